@@ -0,0 +1,133 @@
+//! Routing requests to the database, apart from HTTP so it can be tested on
+//! its own.
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use super::{Db, Field, Fields, Record};
+
+
+/// The body of a `PATCH`.
+#[derive(Deserialize)]
+struct Patch {
+  fields: Fields
+}
+
+
+/// The body of a `DELETE`, when the client says when it deleted the todo.
+#[derive(Deserialize)]
+struct Deletion {
+  at: i64,
+  by: String
+}
+
+
+pub fn error(status: u16, message: &str) -> (u16, String) {
+  (status, json!({ "error": message }).to_string())
+}
+
+
+fn ok(status: u16, value: impl serde::Serialize) -> (u16, String) {
+  match serde_json::to_string(&value) {
+    Ok(text) => { (status, text) }
+    Err(e) => { error(500, &e.to_string()) }
+  }
+}
+
+
+fn now() -> i64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|since| since.as_millis() as i64)
+    .unwrap_or(0)
+}
+
+
+/// A todo's id from a path, if it's an id.
+fn todo_id(path: &str) -> Option<String> {
+  let id = path.strip_prefix("/todos/")?;
+  Uuid::parse_str(id).ok().map(|id| id.to_string())
+}
+
+
+/// The `since` parameter of `/changes`, 0 if it's left out.
+fn since(query: &str) -> Result<i64, String> {
+  match query.split('&').find_map(|pair| pair.strip_prefix("since=")) {
+    Some(since) => { since.parse().map_err(|_| format!("{:?} is not a sequence number", since)) }
+    None => { Ok(0) }
+  }
+}
+
+
+/// Answer a request with its status and JSON body.
+pub fn handle(db: &mut Db, method: &str, url: &str, body: &str) -> (u16, String) {
+  let (path, query) = url.split_once('?').unwrap_or((url, ""));
+  let result =
+    match (method, path) {
+      ("OPTIONS", _) => { return (204, String::new()); }
+      ("GET", "/todos") => { db.todos().map(|todos| ok(200, todos)) }
+      ("POST", "/todos") => {
+        let record:Record =
+          match serde_json::from_str(body) {
+            Ok(record) => { record }
+            Err(e) => { return error(400, &e.to_string()); }
+          };
+        let id =
+          match Uuid::parse_str(&record.id) {
+            Ok(id) => { id.to_string() }
+            Err(_) => { return error(400, &format!("{:?} is not an id", record.id)); }
+          };
+        db.merge(&id, &record.fields).map(|record| ok(201, record))
+      }
+      ("GET", "/changes") => {
+        match since(query) {
+          Ok(since) => { db.changes_since(since).map(|changes| ok(200, changes)) }
+          Err(message) => { return error(400, &message); }
+        }
+      }
+      ("PATCH", path) | ("DELETE", path) if path.starts_with("/todos/") => {
+        let id =
+          match todo_id(path) {
+            Some(id) => { id }
+            None => { return error(404, "there is no such todo"); }
+          };
+        match db.todo(&id) {
+          Ok(Some(_)) => {}
+          Ok(None) => { return error(404, "there is no such todo"); }
+          Err(e) => { return error(500, &e.to_string()); }
+        }
+        let fields =
+          if method == "PATCH" {
+            match serde_json::from_str::<Patch>(body) {
+              Ok(patch) => { patch.fields }
+              Err(e) => { return error(400, &e.to_string()); }
+            }
+          } else {
+            let deletion =
+              if body.trim().is_empty() {
+                Deletion { at: now(), by: "server".to_string() }
+              } else {
+                match serde_json::from_str::<Deletion>(body) {
+                  Ok(deletion) => { deletion }
+                  Err(e) => { return error(400, &e.to_string()); }
+                }
+              };
+            let field = Field { value: Value::Bool(true), at: deletion.at, by: deletion.by };
+            vec![("deleted".to_string(), field)].into_iter().collect()
+          };
+        db
+          .merge(&id, &fields)
+          .map(|record| {
+            if method == "PATCH" {
+              ok(200, record)
+            } else {
+              (204, String::new())
+            }
+          })
+      }
+      (_, "/todos") | (_, "/changes") => { return error(405, "the method is not allowed here"); }
+      _ => { return error(404, "there is nothing here"); }
+    };
+  result.unwrap_or_else(|e| error(500, &e.to_string()))
+}
@@ -0,0 +1,39 @@
+//! Relay collaborators' changes to their todos.
+//!
+//! ```text
+//! mogwai-todo-relay [--addr 127.0.0.1:9001]
+//! ```
+use std::env;
+use std::process;
+use log::{error, info};
+
+use mogwai_todo_server::relay;
+
+
+fn main() {
+  env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+  let mut addr = "127.0.0.1:9001".to_string();
+  let mut args = env::args().skip(1);
+  while let Some(arg) = args.next() {
+    match (arg.as_str(), args.next()) {
+      ("--addr", Some(to)) => { addr = to; }
+      _ => {
+        eprintln!("usage: mogwai-todo-relay [--addr 127.0.0.1:9001]");
+        process::exit(2);
+      }
+    }
+  }
+
+  let relaying =
+    relay(&addr)
+    .unwrap_or_else(|e| {
+      error!("Could not listen on {}: {}", addr, e);
+      process::exit(1);
+    });
+  info!("Relaying rooms at {}/{{room}}", relaying.url());
+  // The relay runs until the process is stopped.
+  loop {
+    std::thread::park();
+  }
+}
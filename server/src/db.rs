@@ -0,0 +1,143 @@
+//! The todos' fields in SQLite, one row per field.
+//!
+//! Every write that lands is numbered by a sequence that only goes up, which
+//! is what clients ask for changes since.
+use std::path::Path;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::{Changes, Field, Fields, Record};
+
+
+pub struct Db {
+  conn: Connection
+}
+
+
+impl Db {
+  pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Db> {
+    Db::init(Connection::open(path)?)
+  }
+
+  pub fn open_in_memory() -> rusqlite::Result<Db> {
+    Db::init(Connection::open_in_memory()?)
+  }
+
+  fn init(conn: Connection) -> rusqlite::Result<Db> {
+    conn.execute_batch(
+      "CREATE TABLE IF NOT EXISTS fields (
+         todo TEXT NOT NULL,
+         name TEXT NOT NULL,
+         value TEXT NOT NULL,
+         at INTEGER NOT NULL,
+         by TEXT NOT NULL,
+         seq INTEGER NOT NULL,
+         PRIMARY KEY (todo, name)
+       );
+       CREATE INDEX IF NOT EXISTS fields_by_seq ON fields (seq);"
+    )?;
+    Ok(Db { conn })
+  }
+
+  /// The todo's fields, if it was ever written.
+  pub fn todo(&self, id: &str) -> rusqlite::Result<Option<Record>> {
+    let mut statement =
+      self
+      .conn
+      .prepare("SELECT name, value, at, by FROM fields WHERE todo = ?1")?;
+    let fields =
+      statement
+      .query_map(params![id], |row| {
+        let value:String = row.get(1)?;
+        Ok((
+          row.get::<_, String>(0)?,
+          Field {
+            // Values were JSON when they were written.
+            value: serde_json::from_str(&value).unwrap_or_default(),
+            at: row.get(2)?,
+            by: row.get(3)?
+          }
+        ))
+      })?
+      .collect::<rusqlite::Result<Fields>>()?;
+    if fields.is_empty() {
+      Ok(None)
+    } else {
+      Ok(Some(Record { id: id.to_string(), fields }))
+    }
+  }
+
+  fn records(&self, ids: Vec<String>) -> rusqlite::Result<Vec<Record>> {
+    let mut records = vec![];
+    for id in ids {
+      records.extend(self.todo(&id)?);
+    }
+    Ok(records)
+  }
+
+  /// The todos that aren't deleted, in the order they were first written.
+  pub fn todos(&self) -> rusqlite::Result<Vec<Record>> {
+    let ids =
+      self
+      .conn
+      .prepare("SELECT todo FROM fields GROUP BY todo ORDER BY MIN(seq)")?
+      .query_map([], |row| row.get(0))?
+      .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(
+      self
+        .records(ids)?
+        .into_iter()
+        .filter(|record| !record.is_deleted())
+        .collect()
+    )
+  }
+
+  /// The todos with fields written after `since`, deleted ones included.
+  pub fn changes_since(&self, since: i64) -> rusqlite::Result<Changes> {
+    let seq = self.seq()?;
+    let ids =
+      self
+      .conn
+      .prepare("SELECT todo FROM fields WHERE seq > ?1 GROUP BY todo ORDER BY MIN(seq)")?
+      .query_map(params![since], |row| row.get(0))?
+      .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(Changes { seq, todos: self.records(ids)? })
+  }
+
+  fn seq(&self) -> rusqlite::Result<i64> {
+    self
+      .conn
+      .query_row("SELECT COALESCE(MAX(seq), 0) FROM fields", [], |row| row.get(0))
+  }
+
+  /// Write each of `fields` that is newer than what's kept, and answer with
+  /// the todo as it is now.
+  pub fn merge(&mut self, id: &str, fields: &Fields) -> rusqlite::Result<Record> {
+    let transaction = self.conn.transaction()?;
+    let seq:i64 =
+      transaction
+      .query_row("SELECT COALESCE(MAX(seq), 0) + 1 FROM fields", [], |row| row.get(0))?;
+    for (name, field) in fields {
+      let kept =
+        transaction
+        .query_row(
+          "SELECT at, by FROM fields WHERE todo = ?1 AND name = ?2",
+          params![id, name],
+          |row| Ok(Field { value: Default::default(), at: row.get(0)?, by: row.get(1)? })
+        )
+        .optional()?;
+      if kept.map(|kept| field.is_newer_than(&kept)).unwrap_or(true) {
+        transaction.execute(
+          "INSERT OR REPLACE INTO fields (todo, name, value, at, by, seq)
+           VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+          params![id, name, field.value.to_string(), field.at, field.by, seq]
+        )?;
+      }
+    }
+    transaction.commit()?;
+    Ok(
+      self
+        .todo(id)?
+        .unwrap_or_else(|| Record { id: id.to_string(), fields: Fields::new() })
+    )
+  }
+}
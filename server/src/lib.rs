@@ -0,0 +1,184 @@
+//! A small server that keeps todos in a SQLite file so that browsers can
+//! sync them.
+//!
+//! The server doesn't know what a todo looks like. It keeps each of a todo's
+//! fields as a JSON value along with a stamp of when and by which client it
+//! was last written, and a write only lands on fields whose stamps are older
+//! than the write's. That makes clients' writes last-writer-wins per field,
+//! and the same writes applied in any order end up the same.
+//!
+//! Deleting a todo writes its `deleted` field, so the deletion syncs like
+//! any other change.
+//!
+//! The API, all JSON:
+//!
+//! * `GET /todos` lists the todos that aren't deleted
+//! * `POST /todos` creates a todo, or merges into it if it exists
+//! * `PATCH /todos/{id}` merges fields into a todo
+//! * `DELETE /todos/{id}` deletes a todo, stamped by the body if there is one
+//! * `GET /changes?since={seq}` lists the todos changed after `seq`, deleted
+//!   ones included, along with the latest `seq`
+//!
+//! The crate also has the relay that passes changes between collaborators
+//! live, see `relay`.
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::thread::{self, JoinHandle};
+use std::sync::Arc;
+use log::{error, info};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+pub mod api;
+pub mod db;
+pub mod relay;
+pub use db::Db;
+pub use relay::{relay, Relaying};
+
+
+/// A field's value and when and by whom it was written.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+  pub value: Value,
+  /// Milliseconds since the epoch, by the writer's clock.
+  pub at: i64,
+  /// The writing client's id, which breaks ties between writes made at the
+  /// same millisecond.
+  pub by: String
+}
+
+
+impl Field {
+  /// Whether this write wins over `other`.
+  pub fn is_newer_than(&self, other: &Field) -> bool {
+    (self.at, &self.by) > (other.at, &other.by)
+  }
+}
+
+
+pub type Fields = BTreeMap<String, Field>;
+
+
+/// A todo as the server keeps it.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+  pub id: String,
+  #[serde(default)]
+  pub fields: Fields
+}
+
+
+impl Record {
+  pub fn is_deleted(&self) -> bool {
+    self
+      .fields
+      .get("deleted")
+      .map(|field| field.value == Value::Bool(true))
+      .unwrap_or(false)
+  }
+}
+
+
+/// What `GET /changes` answers with.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Changes {
+  /// The latest change, to ask for changes since next time.
+  pub seq: i64,
+  pub todos: Vec<Record>
+}
+
+
+fn header(name: &str, value: &str) -> Header {
+  Header::from_bytes(name.as_bytes(), value.as_bytes())
+    .expect("headers are ascii")
+}
+
+
+/// Answer a request, letting pages from any origin call the API.
+fn respond(db: &mut Db, mut request: Request) {
+  let mut body = String::new();
+  let (status, text) =
+    match request.as_reader().read_to_string(&mut body) {
+      Ok(_) => {
+        let method =
+          match request.method() {
+            Method::Get => { "GET" }
+            Method::Post => { "POST" }
+            Method::Patch => { "PATCH" }
+            Method::Delete => { "DELETE" }
+            Method::Options => { "OPTIONS" }
+            _ => { "" }
+          };
+        api::handle(db, method, request.url(), &body)
+      }
+      Err(e) => { api::error(400, &format!("could not read the body: {}", e)) }
+    };
+  info!("{} {} {}", request.method(), request.url(), status);
+  let response =
+    Response::from_string(text)
+    .with_status_code(status)
+    .with_header(header("Content-Type", "application/json"))
+    .with_header(header("Access-Control-Allow-Origin", "*"))
+    .with_header(header("Access-Control-Allow-Methods", "GET, POST, PATCH, DELETE, OPTIONS"))
+    .with_header(header("Access-Control-Allow-Headers", "Content-Type"));
+  request
+    .respond(response)
+    .unwrap_or_else(|e| error!("Could not respond: {}", e));
+}
+
+
+/// A server answering requests on its own thread.
+pub struct Running {
+  pub addr: SocketAddr,
+  server: Arc<Server>,
+  thread: Option<JoinHandle<()>>
+}
+
+
+impl Running {
+  /// The URL the API is at, eg. "http://127.0.0.1:8080".
+  pub fn url(&self) -> String {
+    format!("http://{}", self.addr)
+  }
+
+  pub fn stop(mut self) {
+    self.shut_down();
+  }
+
+  fn shut_down(&mut self) {
+    self.server.unblock();
+    if let Some(thread) = self.thread.take() {
+      thread
+        .join()
+        .unwrap_or_else(|_| error!("The server's thread panicked"));
+    }
+  }
+}
+
+
+impl Drop for Running {
+  fn drop(&mut self) {
+    self.shut_down();
+  }
+}
+
+
+/// Serve the API at `addr` from `db`. A port of 0 picks a free port, which
+/// `Running::addr` tells.
+pub fn serve(addr: &str, mut db: Db) -> Result<Running, String> {
+  let server = Server::http(addr).map_err(|e| e.to_string())?;
+  let addr =
+    server
+    .server_addr()
+    .to_ip()
+    .ok_or_else(|| format!("{} is not an IP address", addr))?;
+  let server = Arc::new(server);
+  let incoming = server.clone();
+  let thread = thread::spawn(move || {
+    for request in incoming.incoming_requests() {
+      respond(&mut db, request);
+    }
+  });
+  Ok(Running { addr, server, thread: Some(thread) })
+}
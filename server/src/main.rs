@@ -0,0 +1,47 @@
+//! Serve the todo sync API.
+//!
+//! ```text
+//! mogwai-todo-server [--db todos.sqlite] [--addr 127.0.0.1:8080]
+//! ```
+use std::env;
+use std::process;
+use log::{error, info};
+
+use mogwai_todo_server::{serve, Db};
+
+
+fn main() {
+  env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+  let mut db_path = "todos.sqlite".to_string();
+  let mut addr = "127.0.0.1:8080".to_string();
+  let mut args = env::args().skip(1);
+  while let Some(arg) = args.next() {
+    match (arg.as_str(), args.next()) {
+      ("--db", Some(path)) => { db_path = path; }
+      ("--addr", Some(to)) => { addr = to; }
+      _ => {
+        eprintln!("usage: mogwai-todo-server [--db todos.sqlite] [--addr 127.0.0.1:8080]");
+        process::exit(2);
+      }
+    }
+  }
+
+  let db =
+    Db::open(&db_path)
+    .unwrap_or_else(|e| {
+      error!("Could not open {}: {}", db_path, e);
+      process::exit(1);
+    });
+  let running =
+    serve(&addr, db)
+    .unwrap_or_else(|e| {
+      error!("Could not listen on {}: {}", addr, e);
+      process::exit(1);
+    });
+  info!("Syncing todos in {} at {}", db_path, running.url());
+  // The server runs until the process is stopped.
+  loop {
+    std::thread::park();
+  }
+}
@@ -0,0 +1,300 @@
+//! A relay that passes collaborators' changes around a room over
+//! WebSockets.
+//!
+//! Clients connect to `ws://{addr}/{room}` and send JSON text messages, each
+//! with a "type":
+//!
+//! * "op" is a change to a todo. It's kept in the room's log and passed on to
+//!   everyone else in the room.
+//! * "presence" says who a client is and which todo they're editing, with
+//!   their "id". It's passed on, and told to whoever joins later.
+//!
+//! Joining replays the room's log and everyone's presence, then sends
+//! `{"type": "caught_up"}`. Leaving tells the room `{"type": "left", "id":
+//! ...}` with the id of the client's last presence.
+//!
+//! Every op carries a stamp that clients settle conflicts with, so the relay
+//! only has to get each op to everyone, and the log makes sure latecomers get
+//! them too. Clients keep the newest write to each field of a todo, so the
+//! log only keeps the newest op to each: it only looks inside an op for its
+//! todo, what it changes and its stamp. Adding a todo writes every field.
+//! Ops it can't make out are kept as they are. Rooms, and their logs, last as
+//! long as the relay.
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use log::{error, info, warn};
+use serde_json::{json, Value};
+use tungstenite::handshake::server::{Request, Response};
+use tungstenite::{Error, Message};
+
+
+/// How long a connection waits for a message before checking on messages
+/// to send it.
+const TICK: Duration = Duration::from_millis(20);
+
+
+/// Someone in a room.
+struct Peer {
+  /// Passes messages to the peer's connection.
+  tx: Sender<String>,
+  /// Their last presence message, if they've sent one.
+  presence: Option<String>,
+  /// The id in their last presence.
+  id: Option<String>
+}
+
+
+/// What an op writes, and when and by whom.
+#[derive(PartialEq)]
+struct Writes {
+  todo: String,
+  /// The field, named after the op that writes it, or `None` for every
+  /// field.
+  field: Option<String>,
+  at: i64,
+  by: String
+}
+
+
+impl Writes {
+  /// What `value`, an op message, writes. Ops look like `{"Add": {"id":
+  /// ...}}`, `{"Remove": id}` or `{"Rename": [id, ...]}`.
+  fn of(value: &Value) -> Option<Writes> {
+    let (name, args) = value["op"].as_object()?.iter().next()?;
+    let (todo, field) =
+      match name.as_str() {
+        "Add" => { (args["id"].as_str()?, None) }
+        "Remove" => { (args.as_str()?, Some(name)) }
+        _ => { (args[0].as_str()?, Some(name)) }
+      };
+    Some(Writes {
+      todo: todo.to_string(),
+      field: field.cloned(),
+      at: value["at"].as_i64()?,
+      by: value["by"].as_str()?.to_string()
+    })
+  }
+
+  /// Whether these writes make `older`'s pointless.
+  fn covers(&self, older: &Writes) -> bool {
+    self.todo == older.todo
+      && (self.field.is_none() || self.field == older.field)
+      && (self.at, &self.by) > (older.at, &older.by)
+  }
+}
+
+
+#[derive(Default)]
+struct Room {
+  /// The ops sent to the room that haven't been overwritten, oldest first,
+  /// with what they write if it could be made out.
+  log: Vec<(Option<Writes>, String)>,
+  peers: HashMap<usize, Peer>
+}
+
+
+impl Room {
+  /// Keep an op for latecomers, dropping those it overwrites. An op that's
+  /// already overwritten isn't kept.
+  fn log(&mut self, value: &Value, text: String) {
+    let may_writes = Writes::of(value);
+    if let Some(writes) = may_writes.as_ref() {
+      let is_overwritten =
+        self
+        .log
+        .iter()
+        .any(|(logged, _)| logged.as_ref().map(|logged| logged.covers(writes)).unwrap_or(false));
+      if is_overwritten {
+        return;
+      }
+      self
+        .log
+        .retain(|(logged, _)| logged.as_ref().map(|logged| !writes.covers(logged)).unwrap_or(true));
+    }
+    self.log.push((may_writes, text));
+  }
+
+  fn broadcast(&self, from: usize, text: &str) {
+    self
+      .peers
+      .iter()
+      .filter(|(id, _)| **id != from)
+      .for_each(|(_, peer)| {
+        // A peer that's gone is removed by its own connection.
+        let _ = peer.tx.send(text.to_string());
+      });
+  }
+}
+
+
+type Rooms = Arc<Mutex<HashMap<String, Room>>>;
+
+
+/// Take in a message from peer `from`.
+fn receive(rooms: &Rooms, name: &str, from: usize, text: String) {
+  let value:Value = serde_json::from_str(&text).unwrap_or(Value::Null);
+  let mut rooms = rooms.lock().expect("a connection panicked");
+  let room =
+    match rooms.get_mut(name) {
+      Some(room) => { room }
+      None => { return; }
+    };
+  match value["type"].as_str() {
+    Some("op") => {
+      room.broadcast(from, &text);
+      room.log(&value, text);
+    }
+    Some("presence") => {
+      room.broadcast(from, &text);
+      if let Some(peer) = room.peers.get_mut(&from) {
+        peer.id = value["id"].as_str().map(String::from);
+        peer.presence = Some(text);
+      }
+    }
+    _ => { warn!("Ignoring a message in {:?}: {}", name, text); }
+  }
+}
+
+
+/// Talk to a newly connected client until they leave or the relay stops.
+fn connect(stream: TcpStream, rooms: Rooms, id: usize, is_stopping: Arc<AtomicBool>) {
+  let mut name = String::new();
+  // The callback's error is tungstenite's own response type.
+  #[allow(clippy::result_large_err)]
+  let callback = |request: &Request, response: Response| {
+    name = request.uri().path().trim_start_matches('/').to_string();
+    Ok(response)
+  };
+  let mut socket =
+    match tungstenite::accept_hdr(stream, callback) {
+      Ok(socket) => { socket }
+      Err(e) => {
+        warn!("Could not open a WebSocket: {}", e);
+        return;
+      }
+    };
+  if let Err(e) = socket.get_ref().set_read_timeout(Some(TICK)) {
+    error!("Could not set a read timeout: {}", e);
+    return;
+  }
+
+  let (tx, rx) = mpsc::channel();
+  {
+    let mut rooms = rooms.lock().expect("a connection panicked");
+    let room = rooms.entry(name.clone()).or_default();
+    room
+      .log
+      .iter()
+      .map(|(_, text)| text)
+      .chain(room.peers.values().filter_map(|peer| peer.presence.as_ref()))
+      .cloned()
+      .chain(Some(json!({ "type": "caught_up" }).to_string()))
+      .for_each(|text| {
+        let _ = tx.send(text);
+      });
+    room.peers.insert(id, Peer { tx, presence: None, id: None });
+  }
+  info!("Joined {:?}", name);
+
+  'talking: while !is_stopping.load(Ordering::SeqCst) {
+    for text in rx.try_iter() {
+      if let Err(e) = socket.send(Message::Text(text)) {
+        warn!("Could not send to {:?}: {}", name, e);
+        break 'talking;
+      }
+    }
+    match socket.read() {
+      Ok(Message::Text(text)) => { receive(&rooms, &name, id, text); }
+      Ok(_) => {}
+      Err(Error::Io(e)) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+      Err(Error::ConnectionClosed) | Err(Error::AlreadyClosed) => { break; }
+      Err(e) => {
+        warn!("Dropping a connection to {:?}: {}", name, e);
+        break;
+      }
+    }
+  }
+
+  let mut rooms = rooms.lock().expect("a connection panicked");
+  if let Some(room) = rooms.get_mut(&name) {
+    let may_peer = room.peers.remove(&id);
+    if let Some(peer_id) = may_peer.and_then(|peer| peer.id) {
+      room.broadcast(id, &json!({ "type": "left", "id": peer_id }).to_string());
+    }
+  }
+  info!("Left {:?}", name);
+}
+
+
+/// A relay accepting connections on its own thread.
+pub struct Relaying {
+  pub addr: SocketAddr,
+  is_stopping: Arc<AtomicBool>,
+  thread: Option<JoinHandle<()>>
+}
+
+
+impl Relaying {
+  /// The URL rooms are under, eg. "ws://127.0.0.1:9001".
+  pub fn url(&self) -> String {
+    format!("ws://{}", self.addr)
+  }
+
+  pub fn stop(mut self) {
+    self.shut_down();
+  }
+
+  fn shut_down(&mut self) {
+    self.is_stopping.store(true, Ordering::SeqCst);
+    // Wakes the thread waiting on the listener, which then sees it's stopping.
+    let _ = TcpStream::connect(self.addr);
+    if let Some(thread) = self.thread.take() {
+      thread
+        .join()
+        .unwrap_or_else(|_| error!("The relay's thread panicked"));
+    }
+  }
+}
+
+
+impl Drop for Relaying {
+  fn drop(&mut self) {
+    self.shut_down();
+  }
+}
+
+
+/// Relay rooms at `addr`. A port of 0 picks a free port, which
+/// `Relaying::addr` tells.
+pub fn relay(addr: &str) -> Result<Relaying, String> {
+  let listener = TcpListener::bind(addr).map_err(|e| e.to_string())?;
+  let addr = listener.local_addr().map_err(|e| e.to_string())?;
+  let is_stopping = Arc::new(AtomicBool::new(false));
+  let stopping = is_stopping.clone();
+  let thread = thread::spawn(move || {
+    let rooms = Rooms::default();
+    let mut next_id = 0;
+    for stream in listener.incoming() {
+      if stopping.load(Ordering::SeqCst) {
+        break;
+      }
+      match stream {
+        Ok(stream) => {
+          let rooms = rooms.clone();
+          let id = next_id;
+          next_id += 1;
+          let stopping = stopping.clone();
+          thread::spawn(move || connect(stream, rooms, id, stopping));
+        }
+        Err(e) => { warn!("Could not accept a connection: {}", e); }
+      }
+    }
+  });
+  Ok(Relaying { addr, is_stopping, thread: Some(thread) })
+}
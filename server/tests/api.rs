@@ -0,0 +1,159 @@
+//! Tests of the API over HTTP on loopback.
+use serde_json::{json, Value};
+
+use mogwai_todo_server::{serve, Changes, Db, Record, Running};
+
+
+const MILK: &str = "0b6f3a8e-5d1c-4a7b-9c2e-1f3d5a7b9c0e";
+const DOG: &str = "7c1e2d3f-4a5b-4c6d-8e7f-9a0b1c2d3e4f";
+
+
+fn start() -> Running {
+  serve("127.0.0.1:0", Db::open_in_memory().unwrap()).unwrap()
+}
+
+
+fn field(value: Value, at: i64, by: &str) -> Value {
+  json!({ "value": value, "at": at, "by": by })
+}
+
+
+fn create(server: &Running, id: &str, title: &str, at: i64) -> Record {
+  let response =
+    ureq::post(&format!("{}/todos", server.url()))
+    .send_json(json!({
+      "id": id,
+      "fields": {
+        "title": field(json!(title), at, "a"),
+        "completed": field(json!(false), at, "a")
+      }
+    }))
+    .unwrap();
+  assert_eq!(response.status(), 201);
+  response.into_json().unwrap()
+}
+
+
+fn patch(server: &Running, id: &str, fields: Value) -> Result<Record, u16> {
+  ureq::patch(&format!("{}/todos/{}", server.url(), id))
+    .send_json(json!({ "fields": fields }))
+    .map(|response| response.into_json().unwrap())
+    .map_err(|e| {
+      match e {
+        ureq::Error::Status(status, _) => { status }
+        e => { panic!("{}", e) }
+      }
+    })
+}
+
+
+fn list(server: &Running) -> Vec<Record> {
+  ureq::get(&format!("{}/todos", server.url()))
+    .call()
+    .unwrap()
+    .into_json()
+    .unwrap()
+}
+
+
+fn changes(server: &Running, since: i64) -> Changes {
+  ureq::get(&format!("{}/changes?since={}", server.url(), since))
+    .call()
+    .unwrap()
+    .into_json()
+    .unwrap()
+}
+
+
+fn title(record: &Record) -> &Value {
+  &record.fields["title"].value
+}
+
+
+#[test]
+fn creates_lists_and_patches_todos() {
+  let server = start();
+  create(&server, MILK, "Buy milk", 100);
+  create(&server, DOG, "Walk the dog", 100);
+  let todos = list(&server);
+  let titles:Vec<&Value> = todos.iter().map(title).collect();
+  assert_eq!(titles, vec![&json!("Buy milk"), &json!("Walk the dog")]);
+
+  let record = patch(&server, MILK, json!({ "completed": field(json!(true), 200, "b") })).unwrap();
+  assert_eq!(record.fields["completed"].value, json!(true));
+  assert_eq!(title(&record), &json!("Buy milk"));
+  assert_eq!(list(&server)[0], record);
+}
+
+
+#[test]
+fn the_last_writer_wins_each_field() {
+  let server = start();
+  create(&server, MILK, "Buy milk", 100);
+  patch(&server, MILK, json!({ "title": field(json!("Buy oat milk"), 300, "a") })).unwrap();
+
+  // A write made earlier but arriving later only lands where it's newer.
+  let record =
+    patch(&server, MILK, json!({
+      "title": field(json!("Buy whole milk"), 200, "b"),
+      "completed": field(json!(true), 200, "b")
+    }))
+    .unwrap();
+  assert_eq!(title(&record), &json!("Buy oat milk"));
+  assert_eq!(record.fields["completed"].value, json!(true));
+
+  // Writes made at the same moment are settled by the client's id.
+  let record = patch(&server, MILK, json!({ "title": field(json!("Buy milk"), 300, "b") })).unwrap();
+  assert_eq!(title(&record), &json!("Buy milk"));
+  let record = patch(&server, MILK, json!({ "title": field(json!("Buy cream"), 300, "a") })).unwrap();
+  assert_eq!(title(&record), &json!("Buy milk"));
+}
+
+
+#[test]
+fn changes_since_include_deletions() {
+  let server = start();
+  create(&server, MILK, "Buy milk", 100);
+  let seq = changes(&server, 0).seq;
+  create(&server, DOG, "Walk the dog", 100);
+  let response =
+    ureq::delete(&format!("{}/todos/{}", server.url(), MILK))
+    .send_json(json!({ "at": 200, "by": "a" }))
+    .unwrap();
+  assert_eq!(response.status(), 204);
+
+  let since = changes(&server, seq);
+  let ids:Vec<&str> = since.todos.iter().map(|record| record.id.as_str()).collect();
+  assert_eq!(ids, vec![DOG, MILK]);
+  assert!(since.todos[1].is_deleted());
+  assert!(changes(&server, since.seq).todos.is_empty());
+  assert_eq!(list(&server).len(), 1);
+}
+
+
+#[test]
+fn unknown_todos_and_bad_bodies_are_refused() {
+  let server = start();
+  assert_eq!(patch(&server, MILK, json!({})), Err(404));
+  assert_eq!(patch(&server, "not-an-id", json!({})), Err(404));
+  let status =
+    match ureq::post(&format!("{}/todos", server.url())).send_string("{\"id\": 3") {
+      Err(ureq::Error::Status(status, _)) => { status }
+      other => { panic!("{:?}", other.map(|response| response.status())) }
+    };
+  assert_eq!(status, 400);
+}
+
+
+#[test]
+fn todos_are_kept_in_the_file() {
+  let path = std::env::temp_dir().join(format!("mogwai-todo-{}.sqlite", std::process::id()));
+  let server = serve("127.0.0.1:0", Db::open(&path).unwrap()).unwrap();
+  create(&server, MILK, "Buy milk", 100);
+  server.stop();
+
+  let server = serve("127.0.0.1:0", Db::open(&path).unwrap()).unwrap();
+  assert_eq!(title(&list(&server)[0]), &json!("Buy milk"));
+  server.stop();
+  std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,139 @@
+//! Tests of the relay with WebSocket clients on loopback.
+use std::net::TcpStream;
+use std::time::Duration;
+use serde_json::{json, Value};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+use mogwai_todo_server::{relay, Relaying};
+
+
+struct Client {
+  socket: WebSocket<MaybeTlsStream<TcpStream>>
+}
+
+
+impl Client {
+  fn join(relaying: &Relaying, room: &str) -> Client {
+    let (socket, _) = tungstenite::connect(format!("{}/{}", relaying.url(), room)).unwrap();
+    if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+      stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    }
+    Client { socket }
+  }
+
+  fn send(&mut self, value: Value) {
+    self.socket.send(Message::Text(value.to_string())).unwrap();
+  }
+
+  fn next(&mut self) -> Value {
+    loop {
+      if let Message::Text(text) = self.socket.read().unwrap() {
+        return serde_json::from_str(&text).unwrap();
+      }
+    }
+  }
+
+  /// Everything up to catching up with the room.
+  fn catch_up(&mut self) -> Vec<Value> {
+    let mut values = vec![];
+    loop {
+      let value = self.next();
+      if value["type"] == "caught_up" {
+        return values;
+      }
+      values.push(value);
+    }
+  }
+}
+
+
+fn op(title: &str) -> Value {
+  json!({ "type": "op", "op": { "Rename": ["0b6f3a8e-5d1c-4a7b-9c2e-1f3d5a7b9c0e", title] }, "at": 1, "by": "a" })
+}
+
+
+fn presence(id: &str, editing: Option<&str>) -> Value {
+  json!({ "type": "presence", "id": id, "name": id.to_uppercase(), "editing": editing })
+}
+
+
+#[test]
+fn ops_go_to_the_rest_of_the_room_and_to_latecomers() {
+  let relaying = relay("127.0.0.1:0").unwrap();
+  let mut a = Client::join(&relaying, "team");
+  let mut b = Client::join(&relaying, "team");
+  assert!(a.catch_up().is_empty());
+  assert!(b.catch_up().is_empty());
+
+  a.send(op("Buy milk"));
+  assert_eq!(b.next(), op("Buy milk"));
+  // A doesn't get its own op back, the next thing it hears is from B.
+  b.send(op("Buy oat milk"));
+  assert_eq!(a.next(), op("Buy oat milk"));
+
+  let mut c = Client::join(&relaying, "team");
+  assert_eq!(c.catch_up(), vec![op("Buy milk"), op("Buy oat milk")]);
+  let mut d = Client::join(&relaying, "elsewhere");
+  assert!(d.catch_up().is_empty());
+}
+
+
+#[test]
+fn latecomers_only_get_the_ops_that_havent_been_overwritten() {
+  let relaying = relay("127.0.0.1:0").unwrap();
+  let mut a = Client::join(&relaying, "team");
+  a.catch_up();
+  let milk = "0b6f3a8e-5d1c-4a7b-9c2e-1f3d5a7b9c0e";
+  let dog = "7c1e9a3b-2f4d-4e6a-8b0c-5d7f9a1b3c5e";
+  let stamped = |op: Value, at: i64| json!({ "type": "op", "op": op, "at": at, "by": "a" });
+  let added = |id: &str, title: &str, at: i64| stamped(json!({ "Add": { "id": id, "title": title } }), at);
+  let renamed = |id: &str, title: &str, at: i64| stamped(json!({ "Rename": [id, title] }), at);
+  let ops = [
+    added(milk, "Buy milk", 1),
+    added(dog, "Walk the dog", 2),
+    renamed(milk, "Buy oat milk", 3),
+    stamped(json!({ "Toggle": [milk, true] }), 4),
+    renamed(milk, "Buy soy milk", 5),
+    // Arrives late, after a newer rename.
+    renamed(milk, "Buy whole milk", 4),
+    stamped(json!({ "Remove": dog }), 6),
+    // Undoing the removal adds the todo again.
+    added(dog, "Walk the dog", 7),
+  ];
+  for op in ops.iter() {
+    a.send(op.clone());
+  }
+  // Presence goes after the ops, so once it's back they've all been logged.
+  a.send(presence("a", None));
+
+  let mut b = Client::join(&relaying, "team");
+  assert_eq!(
+    b.catch_up(),
+    vec![
+      ops[0].clone(),
+      ops[3].clone(),
+      ops[4].clone(),
+      ops[7].clone(),
+      presence("a", None),
+    ]
+  );
+}
+
+
+#[test]
+fn presence_is_told_to_latecomers_and_leaving_is_told_to_all() {
+  let relaying = relay("127.0.0.1:0").unwrap();
+  let mut a = Client::join(&relaying, "team");
+  a.catch_up();
+  a.send(presence("a", None));
+  a.send(presence("a", Some("0b6f3a8e-5d1c-4a7b-9c2e-1f3d5a7b9c0e")));
+
+  let mut b = Client::join(&relaying, "team");
+  assert_eq!(b.catch_up(), vec![presence("a", Some("0b6f3a8e-5d1c-4a7b-9c2e-1f3d5a7b9c0e"))]);
+  b.send(presence("b", None));
+  assert_eq!(a.next(), presence("b", None));
+
+  a.socket.close(None).unwrap();
+  assert_eq!(b.next(), json!({ "type": "left", "id": "a" }));
+}
@@ -1,33 +1,100 @@
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use chrono::NaiveDate;
 use mogwai::prelude::*;
-use web_sys::HashChangeEvent;
+use uuid::Uuid;
+use mogwai::utils::document;
+use wasm_bindgen::JsValue;
+use web_sys::{File, HashChangeEvent, KeyboardEvent, Node, StorageEvent};
 
+use super::collab::{Op, Remote};
+use super::formats::{self, Format, Import};
+use super::quick_add;
+use super::recurrence::Recurrence;
 use super::utils;
-use super::store;
-use super::store::Item;
+use super::model::{Command, Event as ModelEvent, TodoList};
+pub use super::model::{FilterShow, Route, SortBy};
+use super::store::{Item, ListInfo, Priority, TodoStore};
 
+mod collab;
+pub use collab::Collaboration;
 mod item;
 use item::{Todo, TodoIn, TodoOut};
 
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum FilterShow {
-  All,
-  Completed,
-  Active
-}
-
-
 #[derive(Debug)]
 pub enum In {
-  NewTodo(String, bool),
+  /// The user created a todo.
+  NewTodo(Item),
+  /// The user wrote a subtask of a todo, in quick-add syntax.
+  NewSubtask(Uuid, String),
+  /// The user started dragging a todo.
+  DragStart(Uuid),
+  /// The user dropped the todo being dragged on another, below its middle if
+  /// `true`.
+  Drop(Uuid, bool),
+  MoveUp(Uuid),
+  MoveDown(Uuid),
+  /// A todo was restored from the store.
+  LoadTodo(Item),
+  /// The named lists were restored from the store.
+  LoadLists(Vec<ListInfo>),
+  NewList(String),
+  /// The user renamed the list being shown.
+  RenameList(String),
+  /// The user deleted the list being shown, along with its todos.
+  DeleteList,
+  ListSwitcher(HtmlElement),
   NewTodoInput(HtmlElement),
-  Filter(FilterShow),
+  Navigate(Route),
   CompletionToggleInput(HtmlElement),
-  ChangedCompletion(usize, bool),
+  ChangedEditing(Uuid, bool),
+  Toggle(Uuid, bool),
+  Rename(Uuid, String),
+  Reschedule(Uuid, Option<NaiveDate>),
+  Prioritize(Uuid, Priority),
+  Retag(Uuid, Vec<String>),
+  Star(Uuid, bool),
+  Note(Uuid, String),
+  Repeat(Uuid, Option<Recurrence>),
+  /// Show only todos with this tag.
+  PickTag(String),
+  TagPicker(HtmlElement),
+  /// The user typed in the search input.
+  Search(String),
+  SearchInput(HtmlElement),
   ToggleCompleteAll,
   TodoListUl(HtmlElement),
-  Remove(usize),
-  RemoveCompleted
+  Remove(Uuid),
+  RemoveCompleted,
+  Undo,
+  Redo,
+  DismissUndoToast,
+  StoreCorrupted(String),
+  DownloadCorrupted,
+  DismissCorrupted,
+  ToggleExportMenu,
+  /// Whether exports hold only the todos being shown, rather than all.
+  ExportShownOnly(bool),
+  Export(Format),
+  /// The user picked or dropped files to import todos from.
+  ImportFiles(Vec<File>),
+  /// A file to import was read, by name and text.
+  ImportRead(String, String),
+  ImportProblems(HtmlElement),
+  /// The user accepted the import's preview.
+  ConfirmImport,
+  CancelImport,
+  Flush,
+  Synced(Vec<Item>),
+  /// A collaborator's change.
+  Remote(Op),
+  /// The names of collaborators editing each todo.
+  Editors(BTreeMap<Uuid, Vec<String>>),
+  /// The room has been replayed, so it can be told about our todos.
+  CaughtUp,
+  /// The current date, sent on start and when it rolls over.
+  Today(NaiveDate)
 }
 
 
@@ -37,90 +104,600 @@ pub enum Out {
   ShouldShowTodoList(bool),
   NumItems(usize),
   ShouldShowCompleteButton(bool),
-  SelectedFilter(FilterShow)
+  SelectedFilter(FilterShow),
+  Route(Route),
+  ShouldShowCorruptBanner(bool),
+  UndoToast(Option<String>),
+  ActiveList(ListInfo),
+  ShouldShowDeleteList(bool),
+  ClearNewListInput,
+  ShouldShowExportMenu(bool),
+  /// A summary of the import being previewed, if any.
+  ImportPreview(Option<String>)
 }
 
 
+/// The view of a `TodoList`. Messages from the DOM become model commands and
+/// the events the model hands back are rendered onto the todo components.
 pub struct App {
-  next_index: usize,
+  list: TodoList,
+  /// One component per todo, in the same order as the list's items.
   todos: Vec<GizmoComponent<Todo>>,
   todo_input: Option<HtmlInputElement>,
+  search_input: Option<HtmlInputElement>,
   todo_toggle_input: Option<HtmlInputElement>,
   todo_list_ul: Option<HtmlElement>,
+  /// Holds a link per tag in use, built by `render_tag_picker`.
+  tag_picker: Option<HtmlElement>,
+  /// The tags in use and their counts, as last rendered.
+  tag_counts: Vec<(String, usize)>,
+  /// Holds a link per named list, built by `render_lists`.
+  list_switcher: Option<HtmlElement>,
+  /// The todo being dragged, if any.
+  dragging: Option<Uuid>,
+  store: Rc<dyn TodoStore>,
+  corrupted: Option<String>,
+  is_showing_export_menu: bool,
+  /// Whether exports hold only the todos being shown.
+  export_shown_only: bool,
+  /// The todos read from a file, waiting for the user to confirm.
+  pending_import: Option<Import>,
+  /// Holds an item per problem with the pending import, built by
+  /// `render_import_problems`.
+  import_problems: Option<HtmlElement>,
+  /// Whether undo history is saved alongside the todos.
+  persist_history: bool,
+  /// Bumped each time the todo data changes.
+  revision: u64,
+  /// The revision that was last written to the store.
+  saved_revision: u64,
+  /// The room changes are shared with, if any.
+  collaboration: Option<Collaboration>,
+  /// Who's editing each todo, as last heard from the room.
+  editors: BTreeMap<Uuid, Vec<String>>,
 }
 
 
 impl App {
-  pub fn new() -> App {
+  pub fn new(store: Rc<dyn TodoStore>) -> App {
     App {
-      next_index: 0,
+      list: TodoList::new(),
       todos: vec![],
       todo_input: None,
+      search_input: None,
       todo_toggle_input: None,
       todo_list_ul: None,
+      tag_picker: None,
+      tag_counts: vec![],
+      list_switcher: None,
+      dragging: None,
+      store,
+      corrupted: None,
+      is_showing_export_menu: false,
+      export_shown_only: false,
+      pending_import: None,
+      import_problems: None,
+      persist_history: false,
+      revision: 0,
+      saved_revision: 0,
+      collaboration: None,
+      editors: BTreeMap::new(),
     }
   }
 
-  fn num_items_left(&self) -> usize {
+  /// Restore undo history from the store and keep saving it there, so that
+  /// changes can be undone after a reload.
+  pub fn with_persisted_history(mut self) -> App {
+    if let Some(history) = self.store.load_history() {
+      self.list.set_history(history);
+    }
+    self.persist_history = true;
     self
-      .todos
-      .iter()
-      .fold(
-        0,
-        |n, todo| n + todo.with_state(|t| {
-          if t.is_done {
-            0
-          } else {
-            1
-          }
-        })
-      )
   }
 
-  fn are_any_complete(&self) -> bool {
+  /// Share changes with collaborators in a room, and make theirs.
+  pub fn with_collaboration(mut self, collaboration: Collaboration) -> App {
+    self.collaboration = Some(collaboration);
     self
-      .todos
-      .iter()
-      .fold(
-        false,
-        |show, todo| show || todo.with_state(|t| t.is_done)
-      )
   }
 
-  fn are_all_complete(&self) -> bool {
+  /// Write the todos to the store if they changed since the last write.
+  fn flush(&mut self) {
+    if self.revision == self.saved_revision {
+      return;
+    }
+    if let Err(e) = self.store.save(self.list.items()) {
+      error!("Could not store todos: {}", e);
+    }
+    if let Err(e) = self.store.save_lists(self.list.lists()) {
+      error!("Could not store lists: {}", e);
+    }
+    if self.persist_history {
+      if let Err(e) = self.store.save_history(self.list.history()) {
+        error!("Could not store undo history: {}", e);
+      }
+    }
+    // Even on failure we don't retry until the next change, otherwise a full
+    // store would have us logging the same error on every flush.
+    self.saved_revision = self.revision;
+  }
+
+  fn todo_mut(&mut self, id: &Uuid) -> Option<&mut GizmoComponent<Todo>> {
     self
       .todos
+      .iter_mut()
+      .find(|todo| todo.with_state(|t| t.id == *id))
+  }
+
+  /// Create a todo component and put it at `position` in the list.
+  fn insert_todo(&mut self, position: usize, item: &Item, visible: bool, sub: &Subscriber<In>) {
+    let id = item.id;
+    // Turn the new todo into a sub-component.
+    let mut todo = Todo::new(id, item.title.clone());
+    todo.parent = item.parent;
+    let mut component = todo.into_component();
+    // Subscribe to some of its view messages
+    sub.subscribe_filter_map(
+      &component.recv,
+      move |todo_out_msg| {
+        match todo_out_msg {
+          TodoOut::UpdateEditComplete(is_editing, _) => {
+            Some(In::ChangedEditing(id, *is_editing))
+          }
+          TodoOut::Renamed(name) => {
+            Some(In::Rename(id, name.clone()))
+          }
+          TodoOut::Toggled(is_complete) => {
+            Some(In::Toggle(id, *is_complete))
+          }
+          TodoOut::Rescheduled(due) => {
+            Some(In::Reschedule(id, *due))
+          }
+          TodoOut::Reprioritized(priority) => {
+            Some(In::Prioritize(id, *priority))
+          }
+          TodoOut::Retagged(tags) => {
+            Some(In::Retag(id, tags.clone()))
+          }
+          TodoOut::Restarred(starred) => {
+            Some(In::Star(id, *starred))
+          }
+          TodoOut::Renoted(notes) => {
+            Some(In::Note(id, notes.clone()))
+          }
+          TodoOut::Repeated(recurrence) => {
+            Some(In::Repeat(id, recurrence.clone()))
+          }
+          TodoOut::PickedTag(tag) => {
+            Some(In::PickTag(tag.clone()))
+          }
+          TodoOut::AddedSubtask(text) => {
+            Some(In::NewSubtask(id, text.clone()))
+          }
+          TodoOut::DragStarted => {
+            Some(In::DragStart(id))
+          }
+          TodoOut::Dropped(below) => {
+            Some(In::Drop(id, *below))
+          }
+          TodoOut::MovedUp => {
+            Some(In::MoveUp(id))
+          }
+          TodoOut::MovedDown => {
+            Some(In::MoveDown(id))
+          }
+          TodoOut::Remove => {
+            Some(In::Remove(id))
+          }
+          _ => { None }
+        }
+      }
+    );
+    // Build it, append it to our ul and then store it. If the component goes
+    // out of scope it will be dropped and removed from the DOM automacally.
+    component.build();
+    if item.completed {
+      component.update(&TodoIn::SetCompletion(true));
+    }
+    if item.due.is_some() {
+      component.update(&TodoIn::SetDue(item.due));
+    }
+    if item.priority != Priority::None {
+      component.update(&TodoIn::SetPriority(item.priority));
+    }
+    if !item.tags.is_empty() {
+      component.update(&TodoIn::SetTags(item.tags.clone()));
+    }
+    if item.starred {
+      component.update(&TodoIn::SetStarred(true));
+    }
+    if !item.notes.is_empty() {
+      component.update(&TodoIn::SetNotes(item.notes.clone()));
+    }
+    if item.recurrence.is_some() {
+      component.update(&TodoIn::SetRecurrence(item.recurrence.clone()));
+    }
+    if let Some(query) = self.list.query() {
+      component.update(&TodoIn::SetQuery(Some(query.clone())));
+    }
+    if let Some(names) = self.editors.get(&id) {
+      component.update(&TodoIn::SetEditors(names.clone()));
+    }
+    component.update(&TodoIn::SetVisible(visible));
+
+    let position = position.min(self.todos.len());
+    self.todos.insert(position, component);
+    // Add the component to its ul. Appending moves elements that are already
+    // in a ul, so re-appending everything after the new todo puts it in the
+    // right place.
+    self.todos[position..]
       .iter()
-      .fold(
-        true,
-        |complete, todo| complete && todo.with_state(|t| t.is_done)
-      )
+      .for_each(|component| self.append_todo(component));
+  }
+
+  /// Append a todo's element to the end of its parent's subtasks, or to our
+  /// ul if it's a top level todo.
+  fn append_todo(&self, component: &GizmoComponent<Todo>) {
+    let parent = component.with_state(|t| t.parent);
+    let may_subtasks_ul =
+      parent
+      .and_then(|parent| {
+        self
+          .todos
+          .iter()
+          .find(|todo| todo.with_state(|t| t.id == parent))
+      })
+      .and_then(|todo| todo.with_state(|t| t.subtasks_ul.clone()));
+    may_subtasks_ul
+      .as_ref()
+      .or(self.todo_list_ul.as_ref())
+      .into_iter()
+      .for_each(|ul| component.append_to(ul));
+  }
+
+  /// Hand a command to the model and render whatever changed. `origin` is
+  /// the todo whose own view sent the command, if any.
+  fn handle(
+    &mut self,
+    command: Command,
+    origin: Option<Uuid>,
+    tx_view: &Transmitter<Out>,
+    sub: &Subscriber<In>
+  ) {
+    let is_switch = matches!(command, Command::SwitchList(_) | Command::Navigate(_));
+    // Loaded, synced and collaborators' todos are already known elsewhere.
+    let is_shared = !matches!(
+      command,
+      Command::Load(_) | Command::LoadLists(_) | Command::Merge(_) | Command::Patch(_) | Command::Forget(_)
+    );
+    let shown = self.route().list;
+    let events = self.list.handle(command);
+    if events.iter().any(ModelEvent::changes_items) {
+      self.revision += 1;
+    }
+    if is_shared {
+      self
+        .collaboration
+        .iter()
+        .for_each(|collaboration| collaboration.share(&events));
+    }
+    for event in events {
+      self.render(event, origin, tx_view, sub);
+    }
+    self.refresh(tx_view);
+    // Adding, renaming or deleting a list can show another list, or the same
+    // one under a new slug. Navigating got us here already.
+    if !is_switch && self.route().list != shown {
+      window()
+        .location()
+        .set_hash(&self.route().to_hash())
+        .unwrap_or_else(|e| error!("Could not show the list: {:?}", e));
+    }
+  }
+
+  /// Send a message to a todo's view. The todo that sent the command is still
+  /// in the middle of its own update, so its message waits for the next tick.
+  fn update_todo(&mut self, id: Uuid, msg: TodoIn, origin: Option<Uuid>) {
+    let may_todo = self.todo_mut(&id);
+    if origin == Some(id) {
+      may_todo
+        .into_iter()
+        .for_each(|todo| {
+          let tx = todo.trns.clone();
+          timeout(0, move || {
+            tx.send(&msg);
+            false
+          });
+        });
+    } else {
+      may_todo
+        .into_iter()
+        .for_each(|todo| todo.update(&msg));
+    }
+  }
+
+  fn render(
+    &mut self,
+    event: ModelEvent,
+    origin: Option<Uuid>,
+    tx_view: &Transmitter<Out>,
+    sub: &Subscriber<In>
+  ) {
+    match event {
+      ModelEvent::Inserted { position, item, visible } => {
+        self.insert_todo(position, &item, visible, sub);
+      }
+      ModelEvent::Removed(id) => {
+        // Removing the gizmo drops its shared state, transmitters and receivers.
+        // This causes its Drop implementation to run, which removes its
+        // html_element from the parent.
+        self
+          .todos
+          .retain(|todo| todo.with_state(|t| t.id != id));
+      }
+      ModelEvent::Moved { from, to } => {
+        // The elements follow with the Reordered that comes next.
+        if from < self.todos.len() {
+          let component = self.todos.remove(from);
+          let to = to.min(self.todos.len());
+          self.todos.insert(to, component);
+        }
+      }
+      ModelEvent::Renamed(id, name) => {
+        self.update_todo(id, TodoIn::SetName(name), origin);
+      }
+      ModelEvent::Completed(id, is_complete) => {
+        self.update_todo(id, TodoIn::SetCompletion(is_complete), origin);
+      }
+      ModelEvent::Due(id, due) => {
+        self.update_todo(id, TodoIn::SetDue(due), origin);
+      }
+      ModelEvent::Overdue(id, is_overdue) => {
+        self.update_todo(id, TodoIn::SetOverdue(is_overdue), origin);
+      }
+      ModelEvent::Prioritized(id, priority) => {
+        self.update_todo(id, TodoIn::SetPriority(priority), origin);
+      }
+      ModelEvent::Tagged(id, tags) => {
+        self.update_todo(id, TodoIn::SetTags(tags), origin);
+      }
+      ModelEvent::Starred(id, starred) => {
+        self.update_todo(id, TodoIn::SetStarred(starred), origin);
+      }
+      ModelEvent::Noted(id, notes) => {
+        self.update_todo(id, TodoIn::SetNotes(notes), origin);
+      }
+      ModelEvent::Recurring(id, recurrence) => {
+        self.update_todo(id, TodoIn::SetRecurrence(recurrence), origin);
+      }
+      ModelEvent::Visible(id, is_visible) => {
+        self.update_todo(id, TodoIn::SetVisible(is_visible), origin);
+      }
+      ModelEvent::Filtered(show) => {
+        tx_view.send(&Out::SelectedFilter(show));
+        tx_view.send(&Out::Route(self.route()));
+        self.render_tag_picker();
+        self.render_lists();
+      }
+      ModelEvent::TagFiltered(_) | ModelEvent::Sorted(_) => {
+        tx_view.send(&Out::Route(self.route()));
+        self.render_tag_picker();
+        self.render_lists();
+      }
+      ModelEvent::Searched(query) => {
+        tx_view.send(&Out::Route(self.route()));
+        self.render_tag_picker();
+        self.render_lists();
+        self
+          .todos
+          .iter_mut()
+          .for_each(|todo| todo.update(&TodoIn::SetQuery(query.clone())));
+        // While the user is typing their input is ahead of the url, so we
+        // only follow the url when it changed some other way.
+        let value = query.unwrap_or_default();
+        self
+          .search_input
+          .iter()
+          .filter(|input| {
+            let is_focused =
+              document()
+              .active_element()
+              .map(Node::from)
+              .map(|el| input.is_same_node(Some(&el)))
+              .unwrap_or(false);
+            !is_focused && input.value() != value
+          })
+          .for_each(|input| input.set_value(&value));
+      }
+      ModelEvent::Lists(lists) => {
+        self.render_lists();
+        tx_view.send(&Out::ActiveList(self.list.active_list().clone()));
+        tx_view.send(&Out::ShouldShowDeleteList(lists.len() > 1));
+      }
+      ModelEvent::ListSwitched(_) => {
+        self.render_lists();
+        tx_view.send(&Out::ActiveList(self.list.active_list().clone()));
+      }
+      ModelEvent::Reordered(ids) => {
+        self.reorder(&ids);
+      }
+      ModelEvent::Arranged(ids) => {
+        // The elements follow with the Reordered that comes next.
+        let rank:HashMap<Uuid, usize> =
+          ids
+          .iter()
+          .enumerate()
+          .map(|(rank, id)| (*id, rank))
+          .collect();
+        self
+          .todos
+          .sort_by_cached_key(|todo| todo.with_state(|t| rank.get(&t.id).cloned()));
+      }
+      ModelEvent::UndoOffer(may_text) => {
+        tx_view.send(&Out::UndoToast(may_text));
+      }
+    }
+  }
+
+  fn route(&self) -> Route {
+    self.list.route()
+  }
+
+  /// Fill the list switcher with a link per named list. Each link goes back
+  /// to the filter, tag and sort its list was left on.
+  fn render_lists(&self) {
+    let switcher =
+      match self.list_switcher.as_ref() {
+        Some(switcher) => { switcher }
+        None => { return; }
+      };
+    let active = self.list.active_list().id;
+    let render = || -> Result<(), JsValue> {
+      switcher.set_inner_html("");
+      for list in self.list.lists() {
+        let item = document().create_element("li")?;
+        let link = document().create_element("a")?;
+        link.set_class_name(if list.id == active { "list selected" } else { "list" });
+        let to = self.list.route_of(&list.id).unwrap_or_default();
+        link.set_attribute("href", &to.to_hash())?;
+        link.set_text_content(Some(&list.name));
+        item.append_child(&link)?;
+        switcher.append_child(&item)?;
+      }
+      Ok(())
+    };
+    render()
+      .unwrap_or_else(|e| error!("Could not show the lists: {:?}", e));
+  }
+
+  /// Fill the tag picker with a link per tag in use. Following a tag's link
+  /// filters by it within the current route, following the selected tag's
+  /// link stops filtering by tag.
+  fn render_tag_picker(&self) {
+    let picker =
+      match self.tag_picker.as_ref() {
+        Some(picker) => { picker }
+        None => { return; }
+      };
+    let route = self.route();
+    let render = || -> Result<(), JsValue> {
+      picker.set_inner_html("");
+      for (tag, count) in self.tag_counts.iter() {
+        let is_selected = route.tag.as_ref() == Some(tag);
+        let link = document().create_element("a")?;
+        link.set_class_name(if is_selected { "tag selected" } else { "tag" });
+        let to = route.with_tag(if is_selected { None } else { Some(tag.clone()) });
+        link.set_attribute("href", &to.to_hash())?;
+        link.set_attribute("title", &format!("{} {}", count, if *count == 1 { "todo" } else { "todos" }))?;
+        link.set_text_content(Some(&format!("#{}", tag)));
+        picker.append_child(&link)?;
+      }
+      Ok(())
+    };
+    render()
+      .unwrap_or_else(|e| error!("Could not show the tag picker: {:?}", e));
   }
 
-  fn items(&self) -> Vec<Item> {
+  /// Fill the import preview with an item per line of the file that couldn't
+  /// be read.
+  fn render_import_problems(&self) {
+    let list =
+      match self.import_problems.as_ref() {
+        Some(list) => { list }
+        None => { return; }
+      };
+    let problems =
+      self
+      .pending_import
+      .as_ref()
+      .map(|import| import.problems.as_slice())
+      .unwrap_or(&[]);
+    let render = || -> Result<(), JsValue> {
+      list.set_inner_html("");
+      for problem in problems {
+        let item = document().create_element("li")?;
+        item.set_text_content(Some(&problem.to_string()));
+        list.append_child(&item)?;
+      }
+      Ok(())
+    };
+    render()
+      .unwrap_or_else(|e| error!("Could not show the import's problems: {:?}", e));
+  }
+
+  /// What the preview says about an import, eg. "3 new, 1 duplicate, 2
+  /// problems".
+  fn import_summary(name: &str, format: Format, import: &Import) -> String {
+    let plural = |n: usize, one: &str, many: &str| {
+      format!("{} {}", n, if n == 1 { one } else { many })
+    };
+    let mut counts = vec![
+      format!("{} new", import.items.len()),
+      plural(import.duplicates, "duplicate", "duplicates")
+    ];
+    if !import.problems.is_empty() {
+      counts.push(plural(import.problems.len(), "problem", "problems"));
+    }
+    format!("{} ({}): {}", name, format.name(), counts.join(", "))
+  }
+
+  /// Put the todos' elements in their uls in the given order.
+  fn reorder(&self, ids: &[Uuid]) {
+    // Appending an element that is already in the ul moves it to the end.
+    ids
+      .iter()
+      .filter_map(|id| {
+        self
+          .todos
+          .iter()
+          .find(|todo| todo.with_state(|t| t.id == *id))
+      })
+      .for_each(|component| self.append_todo(component));
+  }
+
+  /// Bring the counts, toggle-all checkbox and list visibility up to date.
+  fn refresh(&mut self, tx_view: &Transmitter<Out>) {
+    let tag_counts = self.list.all_tags();
+    if tag_counts != self.tag_counts {
+      self.tag_counts = tag_counts;
+      self.render_tag_picker();
+    }
+    let items_left = self.list.num_items_left();
+    let has_todos = !self.list.list_items().is_empty();
     self
-      .todos
+      .todo_toggle_input
       .iter()
-      .map(|component| component.with_state(|todo| {
-        Item {
-          title: todo.name.clone(),
-          completed: todo.is_done
-        }
-      }))
-      .collect()
+      .for_each(|input| input.set_checked(has_todos && items_left == 0));
+    tx_view.send(&Out::NumItems(items_left));
+    tx_view.send(&Out::ShouldShowCompleteButton(self.list.are_any_complete()));
+    tx_view.send(&Out::ShouldShowTodoList(has_todos));
   }
 
-  pub fn url_to_filter_msg(url: String) -> Option<In> {
-    let ndx =
-      url
-      .find('#')
-      .unwrap_or(0);
-    let (_, hash) = url.split_at(ndx);
-    match hash {
-      "#/" => { Some(In::Filter(FilterShow::All)) }
-      "#/active" => { Some(In::Filter(FilterShow::Active)) }
-      "#/completed" => { Some(In::Filter(FilterShow::Completed)) }
-      _ => { None }
+  /// A new todo from text in quick-add syntax, unless it has no title.
+  fn quick_add(text: &str) -> Option<Item> {
+    let quick = quick_add::parse(text, utils::today());
+    quick
+      .problems
+      .iter()
+      .for_each(|problem| warn!("Quick add: {}", problem));
+    if quick.title.is_empty() {
+      None
+    } else {
+      Some(quick.into_item())
+    }
+  }
+
+  pub fn url_to_route_msg(url: String) -> Option<In> {
+    Route::from_url(&url)
+      .map(In::Navigate)
+  }
+
+  /// The message for what the room asks of the app.
+  pub fn remote_msg(remote: Remote) -> In {
+    match remote {
+      Remote::Apply(op) => { In::Remote(op) }
+      Remote::Editors(editors) => { In::Editors(editors) }
+      Remote::CaughtUp => { In::CaughtUp }
     }
   }
 
@@ -138,6 +715,163 @@ impl App {
       _ => { None }
     }
   }
+
+  /// A link to one of the filters that keeps the rest of the current route.
+  fn filter_link(rx: &Receiver<Out>, show: FilterShow, text: &str) -> GizmoBuilder {
+    let class_show = show.clone();
+    li()
+      .with(
+        a()
+          .rx_class("", rx.branch_filter_map(move |msg| {
+            App::filter_selected(msg, class_show.clone())
+          }))
+          .rx_attribute("href", show.path(), rx.branch_filter_map(move |msg| {
+            match msg {
+              Out::Route(route) => { Some(route.with_filter(show.clone()).to_hash()) }
+              _ => { None }
+            }
+          }))
+          .text(text)
+      )
+  }
+
+  /// The footer's export control, a button that opens a menu with a button
+  /// per format.
+  fn export_menu(tx: &Transmitter<In>, rx: &Receiver<Out>) -> GizmoBuilder {
+    let mut menu =
+      div()
+      .class("export-menu")
+      .with(
+        label()
+          .class("export-shown")
+          .with(
+            input()
+              .attribute("type", "checkbox")
+              .tx_on("change", tx.contra_filter_map(|ev:&Event| {
+                let input = ev.target()?.dyn_into::<HtmlInputElement>().ok()?;
+                Some(In::ExportShownOnly(input.checked()))
+              }))
+          )
+          .with(span().text("Only what's shown"))
+      );
+    for format in Format::ALL.iter().cloned() {
+      menu = menu.with(
+        button()
+          .class("export-format")
+          .attribute("data-format", format.extension())
+          .text(format.name())
+          .tx_on("click", tx.contra_map(move |_:&Event| In::Export(format)))
+      );
+    }
+    div()
+      .rx_class("export", rx.branch_filter_map(|msg| {
+        match msg {
+          Out::ShouldShowExportMenu(should) => {
+            Some(
+              if *should {
+                "export open"
+              } else {
+                "export"
+              }.to_string()
+            )
+          }
+          _ => { None }
+        }
+      }))
+      .with(
+        button()
+          .class("export-toggle")
+          .text("Export")
+          .tx_on("click", tx.contra_map(|_:&Event| In::ToggleExportMenu))
+      )
+      .with(menu)
+  }
+
+  /// The import control, a file input and a preview of what importing the
+  /// picked file would add. Files can be dropped anywhere on the app too.
+  fn import_control(tx: &Transmitter<In>, rx: &Receiver<Out>) -> GizmoBuilder {
+    div()
+      .class("import")
+      .with(
+        label()
+          .class("import-pick")
+          .with(span().text("Import"))
+          .with(
+            input()
+              .class("import-file")
+              .attribute("type", "file")
+              .attribute("accept", ".json,.csv,.md,.markdown,.txt,.ics")
+              .tx_on("change", tx.contra_map(|ev:&Event| {
+                In::ImportFiles(utils::event_input_files(ev))
+              }))
+          )
+      )
+      .with(
+        div()
+          .class("import-preview")
+          .rx_style("display", "none", rx.branch_filter_map(|msg| {
+            match msg {
+              Out::ImportPreview(may_summary) => {
+                Some(
+                  if may_summary.is_some() {
+                    "block"
+                  } else {
+                    "none"
+                  }.to_string()
+                )
+              }
+              _ => { None }
+            }
+          }))
+          .with(
+            p()
+              .class("import-summary")
+              .rx_text("", rx.branch_filter_map(|msg| {
+                match msg {
+                  Out::ImportPreview(Some(summary)) => { Some(summary.clone()) }
+                  _ => { None }
+                }
+              }))
+          )
+          .with(
+            ul()
+              .class("import-problems")
+              .tx_post_build(tx.contra_map(|el:&HtmlElement| In::ImportProblems(el.clone())))
+          )
+          .with(
+            button()
+              .class("import-confirm")
+              .text("Import")
+              .tx_on("click", tx.contra_map(|_:&Event| In::ConfirmImport))
+          )
+          .with(
+            button()
+              .class("import-cancel")
+              .text("Cancel")
+              .tx_on("click", tx.contra_map(|_:&Event| In::CancelImport))
+          )
+      )
+  }
+
+  /// Ctrl+Z undoes and Ctrl+Shift+Z redoes, except while typing in an input
+  /// or the notes where the browser's own text undo should win.
+  fn key_to_history_msg(ev: &Event) -> Option<In> {
+    let kev = ev.dyn_ref::<KeyboardEvent>()?;
+    if !(kev.ctrl_key() || kev.meta_key()) || kev.key().to_lowercase() != "z" {
+      return None;
+    }
+    if utils::is_typing(ev) {
+      return None;
+    }
+    ev.prevent_default();
+    Some(
+      if kev.shift_key() {
+        In::Redo
+      } else {
+        In::Undo
+      }
+    )
+  }
 }
 
 
@@ -147,43 +881,61 @@ impl Component for App {
 
   fn update(&mut self, msg: &In, tx_view: &Transmitter<Out>, sub: &Subscriber<In>) {
     match msg {
-      In::NewTodo(name, complete) => {
-        let index = self.next_index;
-        // Turn the new todo into a sub-component.
-        let mut component = Todo::new(index, name.to_string()).into_component();
-        // Subscribe to some of its view messages
-        sub.subscribe_filter_map(
-          &component.recv,
-          move |todo_out_msg| {
-            match todo_out_msg {
-              TodoOut::UpdateEditComplete(_, is_complete) => {
-                Some(In::ChangedCompletion(index, *is_complete))
-              }
-              TodoOut::Remove => {
-                Some(In::Remove(index))
-              }
-              _ => { None }
-            }
-          }
-        );
-        // Build it, append it to our ul and then store it. If the component goes
-        // out of scope it will be dropped and removed from the DOM automacally.
-        component.build();
-        if *complete {
-          component.update(&TodoIn::SetCompletion(true));
-        }
-        // If we have a ul, add the component to it.
-        self
-          .todo_list_ul
-          .as_ref()
-          .iter()
-          .for_each(|ul| component.append_to(ul));
-        self.todos.push(component);
-        self.next_index += 1;
-
+      In::NewTodo(item) => {
+        self.handle(Command::Add(item.clone()), None, tx_view, sub);
         tx_view.send(&Out::ClearNewTodoInput);
-        tx_view.send(&Out::NumItems(self.todos.len()));
-        tx_view.send(&Out::ShouldShowTodoList(true));
+      }
+      In::NewSubtask(parent, text) => {
+        if let Some(item) = App::quick_add(text) {
+          let item = Item { parent: Some(*parent), ..item };
+          self.handle(Command::Add(item), Some(*parent), tx_view, sub);
+        }
+      }
+      In::DragStart(id) => {
+        self.dragging = Some(*id);
+      }
+      In::Drop(target, below) => {
+        if let Some(id) = self.dragging.take() {
+          let command =
+            if *below {
+              Command::MoveAfter(id, *target)
+            } else {
+              Command::MoveBefore(id, *target)
+            };
+          self.handle(command, None, tx_view, sub);
+        }
+      }
+      In::MoveUp(id) => {
+        self.handle(Command::MoveUp(*id), None, tx_view, sub);
+      }
+      In::MoveDown(id) => {
+        self.handle(Command::MoveDown(*id), None, tx_view, sub);
+      }
+      In::LoadTodo(item) => {
+        self.handle(Command::Load(item.clone()), None, tx_view, sub);
+      }
+      In::LoadLists(lists) => {
+        self.handle(Command::LoadLists(lists.clone()), None, tx_view, sub);
+      }
+      In::NewList(name) => {
+        self.handle(Command::AddList(name.clone()), None, tx_view, sub);
+        tx_view.send(&Out::ClearNewListInput);
+      }
+      In::RenameList(name) => {
+        let id = self.list.active_list().id;
+        self.handle(Command::RenameList(id, name.clone()), None, tx_view, sub);
+        // Puts back the name if it was blank.
+        tx_view.send(&Out::ActiveList(self.list.active_list().clone()));
+      }
+      In::DeleteList => {
+        let id = self.list.active_list().id;
+        self.handle(Command::DeleteList(id), None, tx_view, sub);
+      }
+      In::ListSwitcher(el) => {
+        self.list_switcher = Some(el.clone());
+        self.render_lists();
+        tx_view.send(&Out::ActiveList(self.list.active_list().clone()));
+        tx_view.send(&Out::ShouldShowDeleteList(self.list.lists().len() > 1));
       }
       In::NewTodoInput(el) => {
         let input =
@@ -200,19 +952,18 @@ impl Component for App {
           false
         });
       }
-      In::Filter(show) => {
-        self
-          .todos
-          .iter_mut()
-          .for_each(|component| {
-            let is_done = component.with_state(|t| t.is_done);
-            let is_visible =
-              *show == FilterShow::All
-              || (*show == FilterShow::Completed && is_done)
-              || (*show == FilterShow::Active && !is_done);
-            component.update(&TodoIn::SetVisible(is_visible));
-          });
-        tx_view.send(&Out::SelectedFilter(show.clone()));
+      In::Navigate(route) => {
+        let is_missing =
+          route
+          .list
+          .as_ref()
+          .map(|slug| self.list.list_by_slug(slug).is_none())
+          .unwrap_or(false);
+        if is_missing {
+          warn!("There is no list {:?}", route.list);
+        }
+        // One command, so what's shown is worked out once for the whole url.
+        self.handle(Command::Navigate(route.clone()), None, tx_view, sub);
       }
       In::CompletionToggleInput(el) => {
         self.todo_toggle_input =
@@ -220,79 +971,248 @@ impl Component for App {
           .clone()
           .dyn_into::<HtmlInputElement>()
           .ok();
-        tx_view.send(&Out::ShouldShowCompleteButton(self.are_any_complete()));
+        self.refresh(tx_view);
       }
-      In::ChangedCompletion(_index, _is_complete) => {
-        let items_left = self.num_items_left();
+      In::ChangedEditing(id, is_editing) => {
+        let command =
+          if *is_editing {
+            Command::StartEditing(*id)
+          } else {
+            Command::StopEditing(*id)
+          };
+        self.list.handle(command);
+        let editing = if *is_editing { Some(*id) } else { None };
         self
-          .todo_toggle_input
+          .collaboration
           .iter()
-          .for_each(|input| input.set_checked(items_left == 0));
-        tx_view.send(&Out::NumItems(items_left));
-        tx_view.send(&Out::ShouldShowCompleteButton(self.are_any_complete()));
+          .for_each(|collaboration| collaboration.edit(editing));
+      }
+      In::Toggle(id, is_complete) => {
+        self.handle(Command::SetCompletion(*id, *is_complete), Some(*id), tx_view, sub);
+      }
+      In::Rename(id, name) => {
+        self.handle(Command::Rename(*id, name.clone()), Some(*id), tx_view, sub);
+      }
+      In::Reschedule(id, due) => {
+        self.handle(Command::SetDue(*id, *due), Some(*id), tx_view, sub);
+      }
+      In::Prioritize(id, priority) => {
+        self.handle(Command::SetPriority(*id, *priority), Some(*id), tx_view, sub);
+      }
+      In::Retag(id, tags) => {
+        self.handle(Command::SetTags(*id, tags.clone()), Some(*id), tx_view, sub);
+      }
+      In::Star(id, starred) => {
+        self.handle(Command::SetStarred(*id, *starred), Some(*id), tx_view, sub);
+      }
+      In::Note(id, notes) => {
+        self.handle(Command::SetNotes(*id, notes.clone()), Some(*id), tx_view, sub);
+      }
+      In::Repeat(id, recurrence) => {
+        self.handle(Command::SetRecurrence(*id, recurrence.clone()), Some(*id), tx_view, sub);
+      }
+      In::PickTag(tag) => {
+        // Going through the hash keeps the url bookmarkable, the hashchange
+        // brings us back here as a Navigate.
+        let hash =
+          self
+          .route()
+          .with_tag(Some(tag.clone()))
+          .to_hash();
+        window()
+          .location()
+          .set_hash(&hash)
+          .unwrap_or_else(|e| error!("Could not filter by tag: {:?}", e));
+      }
+      In::TagPicker(el) => {
+        self.tag_picker = Some(el.clone());
+        self.render_tag_picker();
+      }
+      In::Search(text) => {
+        // Like picking a tag this goes through the url, but replaces it so
+        // that every keystroke doesn't end up in the browser's history.
+        let query = Some(text.clone()).filter(|text| !text.trim().is_empty());
+        let hash =
+          self
+          .route()
+          .with_query(query)
+          .to_hash();
+        let location = window().location();
+        let replaced =
+          location
+          .href()
+          .and_then(|href| {
+            let base = href.split('#').next().unwrap_or_default();
+            location.replace(&format!("{}{}", base, hash))
+          });
+        replaced
+          .unwrap_or_else(|e| error!("Could not search: {:?}", e));
+      }
+      In::SearchInput(el) => {
+        self.search_input =
+          el
+          .clone()
+          .dyn_into::<HtmlInputElement>()
+          .ok();
       }
       In::ToggleCompleteAll => {
-        let input =
+        let should_complete =
           self
           .todo_toggle_input
           .as_ref()
-          .unwrap();
-
-        let should_complete = input.checked();
-        for todo in self.todos.iter_mut() {
-          todo.update(&TodoIn::SetCompletion(should_complete));
-        }
-        // We don't have to send here because it makes a round trip into
-        // In::ChangedCompletion via items' child messages
+          .map(|input| input.checked())
+          .unwrap_or(!self.list.are_all_complete());
+        self.handle(Command::SetAllComplete(should_complete), None, tx_view, sub);
       }
       In::TodoListUl(ul) => {
         self.todo_list_ul = Some(ul.clone());
         // If we have todos already created (from local storage), add them to
         // the ul.
+        self.reorder(&self.list.display_order());
+      }
+      In::Remove(id) => {
+        self.handle(Command::Remove(*id), None, tx_view, sub);
+      }
+      In::RemoveCompleted => {
+        self.handle(Command::RemoveCompleted, None, tx_view, sub);
+      }
+      In::Undo => {
+        self.handle(Command::Undo, None, tx_view, sub);
+      }
+      In::Redo => {
+        self.handle(Command::Redo, None, tx_view, sub);
+      }
+      In::DismissUndoToast => {
+        tx_view.send(&Out::UndoToast(None));
+      }
+      In::StoreCorrupted(raw) => {
+        // The raw payload has already been quarantined by the store, we keep
+        // a copy so the user can download it.
+        self.corrupted = Some(raw.clone());
+        tx_view.send(&Out::ShouldShowCorruptBanner(true));
+      }
+      In::DownloadCorrupted => {
         self
-          .todos
+          .corrupted
           .iter()
-          .for_each(|component| component.append_to(ul));
+          .for_each(|raw| {
+            utils::download("todomvc-mogwai-corrupt.json", "application/json", raw)
+              .unwrap_or_else(|e| error!("Could not download corrupt todos: {:?}", e));
+          });
       }
-      In::Remove(index) => {
-        // Removing the gizmo drops its shared state, transmitters and receivers.
-        // This causes its Drop implementation to run, which removes its
-        // html_element from the parent.
-        self
-          .todos
-          .retain(|todo| todo.with_state(|t| t.index != *index));
-        if self.todos.len() == 0 {
-          // Update the toggle input checked state by hand
-          self
-            .todo_toggle_input
-            .iter()
-            .for_each(|input| input.set_checked(!self.are_all_complete()));
-          tx_view.send(&Out::ShouldShowTodoList(false));
+      In::DismissCorrupted => {
+        self.corrupted = None;
+        tx_view.send(&Out::ShouldShowCorruptBanner(false));
+      }
+      In::ToggleExportMenu => {
+        self.is_showing_export_menu = !self.is_showing_export_menu;
+        tx_view.send(&Out::ShouldShowExportMenu(self.is_showing_export_menu));
+      }
+      In::ExportShownOnly(shown_only) => {
+        self.export_shown_only = *shown_only;
+      }
+      In::Export(format) => {
+        // All the todos go in a file named for the app, the ones being shown
+        // in a file named for their list.
+        let (name, items) =
+          if self.export_shown_only {
+            let items:Vec<Item> = self.list.visible_items().into_iter().cloned().collect();
+            (self.list.active_list().slug.clone(), items)
+          } else {
+            ("todos".to_string(), self.list.items().to_vec())
+          };
+        let filename = format!("{}.{}", name, format.extension());
+        utils::download(&filename, format.mime(), &formats::write(*format, &items))
+          .unwrap_or_else(|e| error!("Could not export todos: {:?}", e));
+        self.is_showing_export_menu = false;
+        tx_view.send(&Out::ShouldShowExportMenu(false));
+      }
+      In::ImportFiles(files) => {
+        // One file is previewed at a time.
+        if let Some(file) = files.first() {
+          let (tx_read, rx_read) = txrx::<(String, String)>();
+          sub.subscribe_filter_map(&rx_read, |(name, text):&(String, String)| {
+            Some(In::ImportRead(name.clone(), text.clone()))
+          });
+          let name = file.name();
+          utils::read_file(file, Box::new(move |result| {
+            match result {
+              Ok(text) => { tx_read.send(&(name, text)); }
+              Err(e) => { error!("Could not read {}: {:?}", name, e); }
+            }
+          }))
+          .unwrap_or_else(|e| error!("Could not read the file to import: {:?}", e));
         }
-        tx_view.send(&Out::NumItems(self.num_items_left()));
-        tx_view.send(&Out::ShouldShowCompleteButton(self.are_any_complete()));
       }
-      In::RemoveCompleted => {
-        self
+      In::ImportRead(name, text) => {
+        let format = Format::guess(name, text);
+        let parsed = formats::read(format, text, self.list.today());
+        let import = formats::dedupe(&self.list.list_items(), parsed);
+        tx_view.send(&Out::ImportPreview(Some(App::import_summary(name, format, &import))));
+        self.pending_import = Some(import);
+        self.render_import_problems();
+      }
+      In::ImportProblems(el) => {
+        self.import_problems = Some(el.clone());
+        self.render_import_problems();
+      }
+      In::ConfirmImport => {
+        if let Some(import) = self.pending_import.take() {
+          self.handle(Command::AddAll(import.items), None, tx_view, sub);
+        }
+        tx_view.send(&Out::ImportPreview(None));
+        self.render_import_problems();
+      }
+      In::CancelImport => {
+        self.pending_import = None;
+        tx_view.send(&Out::ImportPreview(None));
+        self.render_import_problems();
+      }
+      In::Flush => {
+        self.flush();
+      }
+      In::Synced(items) => {
+        // Another tab saved its todos, and maybe its lists.
+        if let Some(lists) = self.store.load_lists() {
+          self.handle(Command::LoadLists(lists), None, tx_view, sub);
+        }
+        self.handle(Command::Merge(items.clone()), None, tx_view, sub);
+      }
+      In::Remote(op) => {
+        if let Some(command) = op.command(&self.list) {
+          self.handle(command, None, tx_view, sub);
+        }
+      }
+      In::Editors(editors) => {
+        let ids:Vec<Uuid> =
+          self
           .todos
-          .retain(|todo| todo.with_state(|t| !t.is_done));
+          .iter()
+          .map(|todo| todo.with_state(|t| t.id))
+          .collect();
+        self.editors = editors.clone();
+        for id in ids {
+          let names = self.editors.get(&id).cloned().unwrap_or_default();
+          self.update_todo(id, TodoIn::SetEditors(names), None);
+        }
+      }
+      In::CaughtUp => {
         self
-          .todo_toggle_input
+          .collaboration
           .iter()
-          .for_each(|input| input.set_checked(!self.are_all_complete()));
-        tx_view.send(&Out::NumItems(self.num_items_left()));
-        tx_view.send(&Out::ShouldShowCompleteButton(self.are_any_complete()));
-        tx_view.send(&Out::ShouldShowTodoList(self.num_items_left() > 0));
+          .for_each(|collaboration| collaboration.share_todos(self.list.items()));
+      }
+      In::Today(today) => {
+        // Checked often, but only a new day has anything to render.
+        if *today != self.list.today() {
+          self.handle(Command::SetToday(*today), None, tx_view, sub);
+        }
       }
     };
-
-    // In any case, serialize the current todo items.
-    let items = self.items();
-    store::write_items(items)
-      .expect("Could not store todos");
   }
 
   fn builder(&self, tx: Transmitter<In>, rx: Receiver<Out>) -> GizmoBuilder {
+    let store = self.store.clone();
     let rx_display =
       rx.branch_filter_map(|msg| {
         match msg {
@@ -311,6 +1231,150 @@ impl Component for App {
 
     section()
       .class("todoapp")
+      // Files dragged over any part of the app can be dropped to import them.
+      .tx_on("dragover", tx.contra_filter_map(|ev:&Event| {
+        if utils::is_dragging_files(ev) {
+          ev.prevent_default();
+        }
+        None
+      }))
+      .tx_on("drop", tx.contra_filter_map(|ev:&Event| {
+        if !utils::is_dragging_files(ev) {
+          return None;
+        }
+        ev.prevent_default();
+        Some(In::ImportFiles(utils::dropped_files(ev)))
+      }))
+      .with(
+        div()
+          .class("corrupt-banner")
+          .rx_style("display", "none", rx.branch_filter_map(|msg| {
+            match msg {
+              Out::ShouldShowCorruptBanner(should) => {
+                Some(
+                  if *should {
+                    "block"
+                  } else {
+                    "none"
+                  }.to_string()
+                )
+              }
+              _ => { None }
+            }
+          }))
+          .with(
+            span()
+              .text("Your saved todos could not be read, so we set them aside and started fresh.")
+          )
+          .with(
+            button()
+              .class("corrupt-download")
+              .text("Download saved data")
+              .tx_on("click", tx.contra_map(|_:&Event| In::DownloadCorrupted))
+          )
+          .with(
+            button()
+              .class("corrupt-dismiss")
+              .text("Dismiss")
+              .tx_on("click", tx.contra_map(|_:&Event| In::DismissCorrupted))
+          )
+      )
+      .with(
+        div()
+          .class("undo-toast")
+          .rx_style("display", "none", rx.branch_filter_map(|msg| {
+            match msg {
+              Out::UndoToast(may_text) => {
+                Some(
+                  if may_text.is_some() {
+                    "block"
+                  } else {
+                    "none"
+                  }.to_string()
+                )
+              }
+              _ => { None }
+            }
+          }))
+          .with(
+            span()
+              .rx_text("", rx.branch_filter_map(|msg| {
+                match msg {
+                  Out::UndoToast(Some(text)) => { Some(text.clone()) }
+                  _ => { None }
+                }
+              }))
+          )
+          .with(
+            button()
+              .class("undo")
+              .text("Undo")
+              .tx_on("click", tx.contra_map(|_:&Event| In::Undo))
+          )
+          .with(
+            button()
+              .class("undo-dismiss")
+              .text("×")
+              .tx_on("click", tx.contra_map(|_:&Event| In::DismissUndoToast))
+          )
+      )
+      .with(
+        div()
+          .class("list-switcher")
+          .with(
+            ul()
+              .class("lists")
+              .tx_post_build(tx.contra_map(|el:&HtmlElement| In::ListSwitcher(el.clone())))
+          )
+          .with(
+            input()
+              .class("rename-list")
+              .attribute("title", "Rename this list")
+              .tx_on("change", tx.contra_map(|ev:&Event| {
+                In::RenameList(utils::event_input_value(ev).unwrap())
+              }))
+              .rx_value("", rx.branch_filter_map(|msg| {
+                match msg {
+                  Out::ActiveList(list) => { Some(list.name.clone()) }
+                  _ => { None }
+                }
+              }))
+          )
+          .with(
+            button()
+              .class("delete-list")
+              .text("Delete list")
+              .rx_style("display", "none", rx.branch_filter_map(|msg| {
+                match msg {
+                  Out::ShouldShowDeleteList(should) => {
+                    Some(
+                      if *should {
+                        "inline-block"
+                      } else {
+                        "none"
+                      }.to_string()
+                    )
+                  }
+                  _ => { None }
+                }
+              }))
+              .tx_on("click", tx.contra_map(|_:&Event| In::DeleteList))
+          )
+          .with(
+            input()
+              .class("new-list")
+              .attribute("placeholder", "New list")
+              .tx_on("change", tx.contra_map(|ev:&Event| {
+                In::NewList(utils::event_input_value(ev).unwrap())
+              }))
+              .rx_value("", rx.branch_filter_map(|msg| {
+                match msg {
+                  Out::ClearNewListInput => { Some("".to_string()) }
+                  _ => { None }
+                }
+              }))
+          )
+      )
       .with(
         header()
           .class("header")
@@ -328,11 +1392,7 @@ impl Component for App {
                 tx.contra_filter_map(
                   |ev: &Event| {
                     let todo_name = utils::event_input_value(ev).unwrap();
-                    if todo_name.is_empty() {
-                      None
-                    } else {
-                      Some(In::NewTodo(todo_name, false))
-                    }
+                    App::quick_add(&todo_name).map(In::NewTodo)
                   }
                 )
               )
@@ -344,7 +1404,18 @@ impl Component for App {
               }))
               .tx_post_build(tx.contra_map(|el:&HtmlElement| In::NewTodoInput(el.clone())))
           )
+          .with(
+            input()
+              .class("search")
+              .attribute("type", "search")
+              .attribute("placeholder", "Search")
+              .tx_on("input", tx.contra_map(|ev:&Event| {
+                In::Search(utils::event_input_value(ev).unwrap_or_default())
+              }))
+              .tx_post_build(tx.contra_map(|el:&HtmlElement| In::SearchInput(el.clone())))
+          )
       )
+      .with(App::import_control(&tx, &rx))
       .with(
         section()
           .class("main")
@@ -402,47 +1473,70 @@ impl Component for App {
           .with(
             ul()
               .class("filters")
+              .with(App::filter_link(&rx, FilterShow::All, "All"))
+              .with(App::filter_link(&rx, FilterShow::Active, "Active"))
+              .with(App::filter_link(&rx, FilterShow::Completed, "Completed"))
+              .with(App::filter_link(&rx, FilterShow::Today, "Today"))
+              .with(App::filter_link(&rx, FilterShow::Overdue, "Overdue"))
+              .with(App::filter_link(&rx, FilterShow::Upcoming, "Upcoming"))
               .with(
                 li()
-                  .with(
-                    a()
-                      .rx_class("", rx.branch_filter_map(|msg| {
-                        App::filter_selected(msg, FilterShow::All)
-                      }))
-                      .attribute("href", "#/")
-                      .text("All")
-                  )
-              )
-              .with(
-                li()
-                  .with(
-                    a()
-                      .rx_class("", rx.branch_filter_map(|msg| {
-                        App::filter_selected(msg, FilterShow::Active)
-                      }))
-                      .attribute("href", "#/active")
-                      .text("Active")
-                  )
-              )
-              .with(
-                li()
-                  .with(
-                    a()
-                      .rx_class("", rx.branch_filter_map(|msg| {
-                        App::filter_selected(msg, FilterShow::Completed)
-                      }))
-                      .attribute("href", "#/completed")
-                      .text("Completed")
-                  )
+                  .class("tag-picker")
+                  .tx_post_build(tx.contra_map(|el:&HtmlElement| In::TagPicker(el.clone())))
               )
+              .tx_on_window("beforeunload", tx.contra_map(|_:&Event| In::Flush))
+              .tx_on_window("keydown", tx.contra_filter_map(App::key_to_history_msg))
+              .tx_on_window("storage", tx.contra_filter_map(move |ev:&Event| {
+                let ev:&StorageEvent = ev.dyn_ref::<StorageEvent>()?;
+                match store.external_change(ev)? {
+                  Ok(items) => { Some(In::Synced(items)) }
+                  Err(e) => {
+                    warn!("Ignoring todos from another tab, {}", e);
+                    None
+                  }
+                }
+              }))
               .tx_on_window("hashchange", tx.contra_filter_map(|ev:&Event| {
                 let ev:&HashChangeEvent =
                   ev
                   .dyn_ref::<HashChangeEvent>()
                   .unwrap();
                 let url = ev.new_url();
-                App::url_to_filter_msg(url)
+                App::url_to_route_msg(url)
+              }))
+          )
+          .with(
+            // Toggles sorting by priority, keeping the current filter
+            a()
+              .rx_class("sort-priority", rx.branch_filter_map(|msg| {
+                match msg {
+                  Out::Route(route) => {
+                    Some(
+                      if route.sort == SortBy::Priority {
+                        "sort-priority selected"
+                      } else {
+                        "sort-priority"
+                      }.to_string()
+                    )
+                  }
+                  _ => { None }
+                }
+              }))
+              .rx_attribute("href", "#/?sort=priority", rx.branch_filter_map(|msg| {
+                match msg {
+                  Out::Route(route) => {
+                    let sort =
+                      if route.sort == SortBy::Priority {
+                        SortBy::Added
+                      } else {
+                        SortBy::Priority
+                      };
+                    Some(route.with_sort(sort).to_hash())
+                  }
+                  _ => { None }
+                }
               }))
+              .text("By priority")
           )
           .with(
             button()
@@ -464,6 +1558,7 @@ impl Component for App {
               }))
               .tx_on("click", tx.contra_map(|_:&Event| In::RemoveCompleted))
           )
+          .with(App::export_menu(&tx, &rx))
       )
   }
 }
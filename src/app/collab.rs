@@ -0,0 +1,180 @@
+//! The WebSocket between the app and a room on the relay in `server/`, see
+//! `collab` for how changes are settled.
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use uuid::Uuid;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::closure::Closure;
+use web_sys::{Event, MessageEvent, WebSocket};
+use mogwai::prelude::*;
+
+use crate::collab::{Collab, Message, Op, Remote};
+use crate::model::Event as ModelEvent;
+use crate::store::Item;
+
+
+/// How long to wait before connecting again after losing the relay, in
+/// milliseconds.
+const RECONNECT_AFTER: u32 = 3000;
+
+
+fn now() -> i64 {
+  js_sys::Date::now() as i64
+}
+
+
+/// An open socket. Its message handler has to live as long as it does.
+struct Connection {
+  socket: WebSocket,
+  _on_message: Closure<dyn FnMut(MessageEvent)>
+}
+
+
+type OnRemote = Rc<RefCell<Option<Box<dyn Fn(Remote)>>>>;
+
+
+/// Shares the user's changes with a room and hands back collaborators'.
+/// Messages sent while the relay can't be reached wait until it can.
+#[derive(Clone)]
+pub struct Collaboration {
+  /// The room's URL, eg. "ws://127.0.0.1:9001/team".
+  url: String,
+  collab: Rc<RefCell<Collab>>,
+  connection: Rc<RefCell<Option<Connection>>>,
+  /// Messages waiting for the socket to open, oldest first.
+  outbox: Rc<RefCell<VecDeque<String>>>,
+  on_remote: OnRemote
+}
+
+
+impl Collaboration {
+  /// Join the room at `url` as `name`.
+  pub fn join(url: &str, name: &str) -> Collaboration {
+    let collab = Collab::new(Uuid::new_v4().to_string(), name.to_string());
+    let collaboration = Collaboration {
+      url: url.to_string(),
+      collab: Rc::new(RefCell::new(collab)),
+      connection: Rc::new(RefCell::new(None)),
+      outbox: Rc::new(RefCell::new(VecDeque::new())),
+      on_remote: Rc::new(RefCell::new(None))
+    };
+    collaboration.connect();
+    collaboration
+  }
+
+  /// Be told what to do about each message from the room.
+  pub fn set_on_remote(&self, f: Box<dyn Fn(Remote)>) {
+    *self.on_remote.borrow_mut() = Some(f);
+  }
+
+  fn connect(&self) {
+    let socket =
+      match WebSocket::new(&self.url) {
+        Ok(socket) => { socket }
+        Err(e) => {
+          error!("Could not connect to {}: {:?}", self.url, e);
+          return self.connect_later();
+        }
+      };
+
+    let opened = self.clone();
+    let on_open = Closure::once_into_js(move |_:Event| {
+      // Tell the room who we are, which also sends what's been waiting.
+      let presence = Message::Presence(opened.collab.borrow().me.clone());
+      opened.send(&presence);
+    });
+    socket.set_onopen(Some(on_open.unchecked_ref()));
+    let closed = self.clone();
+    let on_close = Closure::once_into_js(move |_:Event| {
+      warn!("Lost the room at {}, connecting again soon", closed.url);
+      closed.connection.borrow_mut().take();
+      closed.connect_later();
+    });
+    socket.set_onclose(Some(on_close.unchecked_ref()));
+    let received = self.clone();
+    let on_message = Closure::wrap(Box::new(move |ev:MessageEvent| {
+      if let Some(text) = ev.data().as_string() {
+        received.receive(&text);
+      }
+    }) as Box<dyn FnMut(MessageEvent)>);
+    socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+    *self.connection.borrow_mut() = Some(Connection { socket, _on_message: on_message });
+  }
+
+  fn connect_later(&self) {
+    let collaboration = self.clone();
+    timeout(RECONNECT_AFTER, move || {
+      collaboration.connect();
+      false
+    });
+  }
+
+  fn receive(&self, text: &str) {
+    let message =
+      match serde_json::from_str::<Message>(text) {
+        Ok(message) => { message }
+        Err(e) => {
+          warn!("Ignoring a message from the room, {}", e);
+          return;
+        }
+      };
+    let remotes = self.collab.borrow_mut().receive(message);
+    if let Some(f) = self.on_remote.borrow().as_ref() {
+      remotes
+        .into_iter()
+        .for_each(f);
+    }
+  }
+
+  fn send(&self, message: &Message) {
+    match serde_json::to_string(message) {
+      Ok(text) => { self.outbox.borrow_mut().push_back(text); }
+      Err(e) => { error!("Could not write a message for the room: {}", e); }
+    }
+    let connection = self.connection.borrow();
+    let socket =
+      match connection.as_ref() {
+        Some(connection) if connection.socket.ready_state() == WebSocket::OPEN => {
+          &connection.socket
+        }
+        _ => { return; }
+      };
+    loop {
+      let may_text = self.outbox.borrow_mut().pop_front();
+      let text =
+        match may_text {
+          Some(text) => { text }
+          None => { break; }
+        };
+      if let Err(e) = socket.send_with_str(&text) {
+        error!("Could not send to the room: {:?}", e);
+        self.outbox.borrow_mut().push_front(text);
+        break;
+      }
+    }
+  }
+
+  /// Share the changes the model made for the user.
+  pub fn share(&self, events: &[ModelEvent]) {
+    for op in events.iter().filter_map(Op::from_event) {
+      let message = self.collab.borrow_mut().local(op, now());
+      self.send(&message);
+    }
+  }
+
+  /// Share the todos the room hasn't heard of.
+  pub fn share_todos(&self, items: &[Item]) {
+    let messages = self.collab.borrow_mut().share(items, now());
+    messages
+      .iter()
+      .for_each(|message| self.send(message));
+  }
+
+  /// Tell the room which todo the user is editing, if any.
+  pub fn edit(&self, editing: Option<Uuid>) {
+    let message = self.collab.borrow_mut().edit(editing);
+    self.send(&message);
+  }
+}
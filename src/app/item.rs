@@ -1,41 +1,298 @@
+use chrono::NaiveDate;
 use mogwai::prelude::*;
-use web_sys::KeyboardEvent;
+use mogwai::utils::document;
+use uuid::Uuid;
+use wasm_bindgen::JsValue;
+use web_sys::{DragEvent, Element, FocusEvent, HtmlTextAreaElement, KeyboardEvent, MouseEvent, Node};
 
 use super::utils;
+use crate::model;
+use crate::recurrence::{self, Recurrence};
+use crate::search;
+use crate::store::Priority;
 
 
 #[derive(Clone)]
 pub struct Todo {
-  pub index: usize,
+  pub id: Uuid,
   pub is_done: bool,
   pub name: String,
-  is_editing: bool,
+  pub due: Option<NaiveDate>,
+  pub is_overdue: bool,
+  pub priority: Priority,
+  pub tags: Vec<String>,
+  pub starred: bool,
+  pub notes: String,
+  /// Whether the notes panel is open.
+  pub is_showing_notes: bool,
+  pub recurrence: Option<Recurrence>,
+  /// The todo this is a subtask of, if any.
+  pub parent: Option<Uuid>,
+  pub is_editing: bool,
+  /// The names of collaborators editing the todo.
+  pub editors: Vec<String>,
+  /// Holds the li of each of our subtasks.
+  pub subtasks_ul: Option<HtmlElement>,
+  /// The search being shown, highlighted in our label.
+  query: Option<String>,
+  label: Option<HtmlElement>,
   edit_input: Option<HtmlInputElement>,
+  due_input: Option<HtmlInputElement>,
+  tags_input: Option<HtmlInputElement>,
+  recurrence_input: Option<HtmlInputElement>,
+  tags_el: Option<HtmlElement>,
+  subtask_input: Option<HtmlInputElement>,
+  notes_input: Option<HtmlTextAreaElement>,
   toggle_input: Option<HtmlInputElement>,
 }
 
 
 impl Todo {
-  pub fn new(index: usize, name: String) -> Todo {
+  pub fn new(id: Uuid, name: String) -> Todo {
     Todo {
-      index,
+      id,
       name,
       is_done: false,
+      due: None,
+      is_overdue: false,
+      priority: Priority::None,
+      tags: vec![],
+      starred: false,
+      notes: String::new(),
+      is_showing_notes: false,
+      recurrence: None,
+      parent: None,
       is_editing: false,
+      editors: vec![],
+      subtasks_ul: None,
+      query: None,
+      label: None,
       edit_input: None,
+      due_input: None,
+      tags_input: None,
+      recurrence_input: None,
+      tags_el: None,
+      subtask_input: None,
+      notes_input: None,
       toggle_input: None,
     }
   }
+
+  fn list_class(&self) -> String {
+    let class =
+      if self.is_editing {
+        "editing"
+      } else if self.is_done {
+        "completed"
+      } else {
+        ""
+      };
+    let mut classes = vec![class.to_string()];
+    if self.is_overdue {
+      classes.push("overdue".to_string());
+    }
+    if self.priority != Priority::None {
+      classes.push(format!("priority-{}", self.priority.name()));
+    }
+    if self.starred {
+      classes.push("starred".to_string());
+    }
+    if !self.notes.is_empty() {
+      classes.push("has-notes".to_string());
+    }
+    if self.is_showing_notes {
+      classes.push("showing-notes".to_string());
+    }
+    if self.recurrence.is_some() {
+      classes.push("recurring".to_string());
+    }
+    if !self.editors.is_empty() {
+      classes.push("being-edited".to_string());
+    }
+    classes.join(" ")
+  }
+
+  /// Show our name in the label, with the parts that match the search
+  /// marked.
+  fn render_label(&self) {
+    let render = |label: &HtmlElement| -> Result<(), JsValue> {
+      label.set_inner_html("");
+      let ranges =
+        self
+        .query
+        .as_ref()
+        .map(|query| search::find(&self.name, query))
+        .unwrap_or_default();
+      let mut at = 0;
+      for range in ranges {
+        label.append_child(&document().create_text_node(&self.name[at..range.start]))?;
+        let mark = document().create_element("mark")?;
+        mark.set_text_content(Some(&self.name[range.clone()]));
+        label.append_child(&mark)?;
+        at = range.end;
+      }
+      label.append_child(&document().create_text_node(&self.name[at..]))?;
+      Ok(())
+    };
+    self
+      .label
+      .iter()
+      .for_each(|label| {
+        render(label)
+          .unwrap_or_else(|e| error!("Could not show the todo's name: {:?}", e));
+      });
+  }
+
+  /// Let the app and our view know how the todo looks now.
+  fn send_state(&self, tx_view: &Transmitter<TodoOut>) {
+    tx_view.send(&TodoOut::UpdateEditComplete(self.is_editing, self.is_done));
+    tx_view.send(&TodoOut::ListClass(self.list_class()));
+  }
+
+  /// Whether an event happened in the notes textarea.
+  fn is_from_notes(&self, ev: &Event) -> bool {
+    let may_target:Option<Node> =
+      ev
+      .target()
+      .and_then(|target| target.dyn_into::<Node>().ok());
+    self
+      .notes_input
+      .iter()
+      .any(|input| input.is_same_node(may_target.as_ref()))
+  }
+}
+
+
+/// How a due date is shown next to the todo's label.
+fn due_text(due: &Option<NaiveDate>) -> String {
+  due
+    .map(|due| format!("due {}", due.format("%b %-d, %Y")))
+    .unwrap_or_default()
+}
+
+
+/// How a todo's recurrence is shown next to its label.
+fn recurrence_text(recurrence: &Option<Recurrence>) -> String {
+  recurrence
+    .as_ref()
+    .map(|rule| format!("\u{21bb} {}", rule))
+    .unwrap_or_default()
+}
+
+
+/// Who's shown to be editing a todo, eg. "Ann and Bo are editing".
+fn editors_text(editors: &[String]) -> String {
+  match editors {
+    [] => { String::new() }
+    [one] => { format!("{} is editing", one) }
+    [rest @ .., last] => { format!("{} and {} are editing", rest.join(", "), last) }
+  }
+}
+
+
+/// The star control's text.
+fn star_text(starred: bool) -> &'static str {
+  if starred { "\u{2605}" } else { "\u{2606}" }
+}
+
+
+/// Replace the tag chips in `container`, one span per tag.
+fn render_chips(container: &HtmlElement, tags: &[String]) -> Result<(), JsValue> {
+  container.set_inner_html("");
+  for tag in tags {
+    let chip = document().create_element("span")?;
+    chip.set_class_name("tag");
+    chip.set_attribute("data-tag", tag)?;
+    chip.set_text_content(Some(&format!("#{}", tag)));
+    container.append_child(&chip)?;
+  }
+  Ok(())
+}
+
+
+/// Whether a drag event happened over the lower half of the todo's view, the
+/// element that listened for it being the todo's li.
+fn is_over_lower_half(ev: &Event) -> bool {
+  let may_y =
+    ev
+    .dyn_ref::<MouseEvent>()
+    .map(|ev| f64::from(ev.client_y()));
+  let may_rect =
+    ev
+    .current_target()
+    .and_then(|target| target.dyn_into::<Element>().ok())
+    .and_then(|li| li.first_element_child())
+    .map(|view| view.get_bounding_client_rect());
+  match (may_y, may_rect) {
+    (Some(y), Some(rect)) => { y > rect.top() + rect.height() / 2.0 }
+    _ => { false }
+  }
+}
+
+
+/// The value of a date input for a due date.
+fn due_value(due: &Option<NaiveDate>) -> String {
+  due
+    .map(|due| due.format("%Y-%m-%d").to_string())
+    .unwrap_or_default()
 }
 
 
 pub enum TodoIn {
   CompletionToggleInput(HtmlElement),
+  Label(HtmlElement),
   EditInput(HtmlElement),
+  DueInput(HtmlElement),
+  TagsInput(HtmlElement),
+  RecurrenceInput(HtmlElement),
+  TagsEl(HtmlElement),
+  SubtaskInput(HtmlElement),
+  SubtasksUl(HtmlElement),
+  NotesInput(HtmlElement),
   ToggleCompletion,
   SetCompletion(bool),
+  SetName(String),
+  /// Highlight what matches this search in the name.
+  SetQuery(Option<String>),
+  SetDue(Option<NaiveDate>),
+  SetOverdue(bool),
+  SetPriority(Priority),
+  /// The user clicked the priority control.
+  CyclePriority,
+  SetTags(Vec<String>),
+  SetStarred(bool),
+  /// The user clicked the star.
+  ToggleStar,
+  SetNotes(String),
+  /// The user clicked the notes control.
+  ToggleNotes,
+  /// The user edited the notes.
+  ChangedNotes,
+  /// The user edited the tags input.
+  ChangedTags,
+  SetRecurrence(Option<Recurrence>),
+  /// Collaborators are editing the todo, by name.
+  SetEditors(Vec<String>),
+  /// The user edited the recurrence input.
+  ChangedRecurrence,
+  /// The user clicked somewhere on the tag chips.
+  ClickedTag(Event),
+  /// The user picked a date in the due date input.
+  ChangedDue,
+  /// The user entered a subtask.
+  AddSubtask,
+  /// The user started dragging the todo.
+  DragStart(Event),
+  /// Something is being dragged over the todo.
+  DragOver(Event),
+  /// Something was dropped on the todo.
+  Drop(Event),
+  /// A key was pressed while the todo had focus.
+  KeyDown(Event),
   StartEditing,
   StopEditing(Option<Event>),
+  /// One of the edit inputs lost focus.
+  Blurred(Event),
   SetVisible(bool),
   Remove
 }
@@ -44,7 +301,41 @@ pub enum TodoIn {
 #[derive(Clone)]
 pub enum TodoOut {
   UpdateEditComplete(bool, bool),
-  SetName(String),
+  ListClass(String),
+  SetDueText(String),
+  SetPriorityText(String),
+  SetStarText(String),
+  SetRecurrenceText(String),
+  SetEditorsText(String),
+  /// The user renamed the todo.
+  Renamed(String),
+  /// The user toggled the todo's completion.
+  Toggled(bool),
+  /// The user changed or cleared the todo's due date.
+  Rescheduled(Option<NaiveDate>),
+  /// The user picked a new priority.
+  Reprioritized(Priority),
+  /// The user changed the todo's tags.
+  Retagged(Vec<String>),
+  /// The user starred or unstarred the todo.
+  Restarred(bool),
+  /// The user changed the todo's notes.
+  Renoted(String),
+  /// The user made the todo repeat differently, or not at all.
+  Repeated(Option<Recurrence>),
+  /// The user wants to see every todo with this tag.
+  PickedTag(String),
+  /// The user wrote a new subtask of this todo.
+  AddedSubtask(String),
+  /// The user started dragging this todo.
+  DragStarted,
+  /// The user dropped the todo being dragged on this one, below its middle
+  /// if `true`.
+  Dropped(bool),
+  /// The user wants this todo shown one place up.
+  MovedUp,
+  /// The user wants this todo shown one place down.
+  MovedDown,
   SetVisible(bool),
   Remove
 }
@@ -53,17 +344,7 @@ pub enum TodoOut {
 impl TodoOut {
   fn as_list_class(&self) -> Option<String> {
     match self {
-      TodoOut::UpdateEditComplete(editing, completed) => {
-        Some(
-          if *editing {
-            "editing"
-          } else if *completed {
-            "completed"
-          } else {
-            ""
-          }.to_string()
-        )
-      }
+      TodoOut::ListClass(class) => { Some(class.clone()) }
       _ => { None }
     }
   }
@@ -74,7 +355,7 @@ impl Component for Todo {
   type ModelMsg = TodoIn;
   type ViewMsg = TodoOut;
 
-  fn update(&mut self, msg: &TodoIn, tx_view: &Transmitter<TodoOut>, _: &Subscriber<TodoIn>) {
+  fn update(&mut self, msg: &TodoIn, tx_view: &Transmitter<TodoOut>, sub: &Subscriber<TodoIn>) {
     match msg {
       TodoIn::SetVisible(visible) => {
         tx_view.send(&TodoOut::SetVisible(*visible));
@@ -86,6 +367,10 @@ impl Component for Todo {
             .expect("Todo toggle completion input is not an input")
         );
       }
+      TodoIn::Label(el) => {
+        self.label = Some(el.clone());
+        self.render_label();
+      }
       TodoIn::EditInput(el) => {
         self.edit_input = Some(
           el.clone()
@@ -93,9 +378,50 @@ impl Component for Todo {
             .expect("Todo edit input is not an input")
         );
       }
+      TodoIn::TagsInput(el) => {
+        self.tags_input = Some(
+          el.clone()
+            .dyn_into::<HtmlInputElement>()
+            .expect("Todo tags input is not an input")
+        );
+      }
+      TodoIn::RecurrenceInput(el) => {
+        self.recurrence_input = Some(
+          el.clone()
+            .dyn_into::<HtmlInputElement>()
+            .expect("Todo recurrence input is not an input")
+        );
+      }
+      TodoIn::TagsEl(el) => {
+        self.tags_el = Some(el.clone());
+      }
+      TodoIn::SubtaskInput(el) => {
+        self.subtask_input = Some(
+          el.clone()
+            .dyn_into::<HtmlInputElement>()
+            .expect("Todo subtask input is not an input")
+        );
+      }
+      TodoIn::SubtasksUl(el) => {
+        self.subtasks_ul = Some(el.clone());
+      }
+      TodoIn::NotesInput(el) => {
+        self.notes_input = Some(
+          el.clone()
+            .dyn_into::<HtmlTextAreaElement>()
+            .expect("Todo notes input is not a textarea")
+        );
+      }
+      TodoIn::DueInput(el) => {
+        self.due_input = Some(
+          el.clone()
+            .dyn_into::<HtmlInputElement>()
+            .expect("Todo due date input is not an input")
+        );
+      }
       TodoIn::ToggleCompletion => {
-        self.is_done = !self.is_done;
-        tx_view.send(&TodoOut::UpdateEditComplete(self.is_editing, self.is_done));
+        // The app owns the todo's data, it will answer with SetCompletion.
+        tx_view.send(&TodoOut::Toggled(!self.is_done));
       }
       TodoIn::SetCompletion(completed) => {
         self.is_done = *completed;
@@ -103,7 +429,297 @@ impl Component for Todo {
           .toggle_input
           .iter()
           .for_each(|input| input.set_checked(*completed));
-        tx_view.send(&TodoOut::UpdateEditComplete(self.is_editing, self.is_done));
+        self.send_state(tx_view);
+      }
+      TodoIn::SetName(name) => {
+        self.name = name.clone();
+        self
+          .edit_input
+          .iter()
+          .for_each(|input| input.set_value(name));
+        self.render_label();
+      }
+      TodoIn::SetQuery(query) => {
+        self.query = query.clone();
+        self.render_label();
+      }
+      TodoIn::SetDue(due) => {
+        self.due = *due;
+        self
+          .due_input
+          .iter()
+          .for_each(|input| input.set_value(&due_value(due)));
+        tx_view.send(&TodoOut::SetDueText(due_text(due)));
+      }
+      TodoIn::SetOverdue(is_overdue) => {
+        self.is_overdue = *is_overdue;
+        self.send_state(tx_view);
+      }
+      TodoIn::SetPriority(priority) => {
+        self.priority = *priority;
+        tx_view.send(&TodoOut::SetPriorityText(priority.name().to_string()));
+        self.send_state(tx_view);
+      }
+      TodoIn::CyclePriority => {
+        // The app owns the todo's data, it will answer with SetPriority.
+        tx_view.send(&TodoOut::Reprioritized(self.priority.next()));
+      }
+      TodoIn::SetStarred(starred) => {
+        self.starred = *starred;
+        tx_view.send(&TodoOut::SetStarText(star_text(*starred).to_string()));
+        self.send_state(tx_view);
+      }
+      TodoIn::ToggleStar => {
+        tx_view.send(&TodoOut::Restarred(!self.starred));
+      }
+      TodoIn::SetNotes(notes) => {
+        self.notes = notes.clone();
+        self
+          .notes_input
+          .iter()
+          .for_each(|input| input.set_value(notes));
+        self.send_state(tx_view);
+      }
+      TodoIn::ToggleNotes => {
+        self.is_showing_notes = !self.is_showing_notes;
+        if self.is_showing_notes {
+          let may_input = self.notes_input.clone();
+          timeout(1, move || {
+            may_input
+              .iter()
+              .for_each(|input| input.focus().unwrap_or_else(|e| error!("Could not focus notes: {:?}", e)));
+            false
+          });
+        }
+        self.send_state(tx_view);
+      }
+      TodoIn::ChangedNotes => {
+        // The app owns the todo's data, it will answer with SetNotes.
+        self
+          .notes_input
+          .iter()
+          .map(|input| input.value())
+          .filter(|notes| *notes != self.notes)
+          .for_each(|notes| tx_view.send(&TodoOut::Renoted(notes)));
+      }
+      TodoIn::SetTags(tags) => {
+        self.tags = tags.clone();
+        let value =
+          tags
+          .iter()
+          .map(|tag| format!("#{}", tag))
+          .collect::<Vec<_>>()
+          .join(" ");
+        self
+          .tags_input
+          .iter()
+          .for_each(|input| input.set_value(&value));
+        self
+          .tags_el
+          .iter()
+          .for_each(|el| {
+            render_chips(el, tags)
+              .unwrap_or_else(|e| error!("Could not show tags: {:?}", e));
+          });
+      }
+      TodoIn::ChangedTags => {
+        let tags =
+          self
+          .tags_input
+          .as_ref()
+          .map(|input| model::parse_tags(&input.value()))
+          .unwrap_or_default();
+        // The app owns the todo's data, it will answer with SetTags.
+        if tags != self.tags {
+          tx_view.send(&TodoOut::Retagged(tags));
+        }
+      }
+      TodoIn::SetRecurrence(recurrence) => {
+        self.recurrence = recurrence.clone();
+        let value =
+          recurrence
+          .as_ref()
+          .map(|rule| rule.to_string())
+          .unwrap_or_default();
+        self
+          .recurrence_input
+          .iter()
+          .for_each(|input| input.set_value(&value));
+        tx_view.send(&TodoOut::SetRecurrenceText(recurrence_text(recurrence)));
+        self.send_state(tx_view);
+      }
+      TodoIn::SetEditors(editors) => {
+        self.editors = editors.clone();
+        tx_view.send(&TodoOut::SetEditorsText(editors_text(editors)));
+        self.send_state(tx_view);
+      }
+      TodoIn::ChangedRecurrence => {
+        // An empty value stops the todo repeating, anything that isn't a
+        // rule puts back the one we have.
+        let value =
+          self
+          .recurrence_input
+          .as_ref()
+          .map(|input| input.value())
+          .unwrap_or_default();
+        let may_recurrence =
+          if value.trim().is_empty() {
+            Some(None)
+          } else {
+            recurrence::parse(&value).map(Some)
+          };
+        match may_recurrence {
+          Some(recurrence) => {
+            // The app owns the todo's data, it will answer with SetRecurrence.
+            if recurrence != self.recurrence {
+              tx_view.send(&TodoOut::Repeated(recurrence));
+            }
+          }
+          None => {
+            warn!("Could not read how often to repeat: {:?}", value);
+            let recurrence = self.recurrence.clone();
+            self.update(&TodoIn::SetRecurrence(recurrence), tx_view, sub);
+          }
+        }
+      }
+      TodoIn::ClickedTag(ev) => {
+        ev
+          .target()
+          .and_then(|target| target.dyn_into::<Element>().ok())
+          .and_then(|el| el.get_attribute("data-tag"))
+          .into_iter()
+          .for_each(|tag| tx_view.send(&TodoOut::PickedTag(tag)));
+      }
+      TodoIn::ChangedDue => {
+        // An empty value clears the date, anything else the browser couldn't
+        // parse as a date is ignored.
+        let value =
+          self
+          .due_input
+          .as_ref()
+          .map(|input| input.value())
+          .unwrap_or_default();
+        let may_due =
+          if value.is_empty() {
+            Some(None)
+          } else {
+            NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+              .ok()
+              .map(Some)
+          };
+        // The app owns the todo's data, it will answer with SetDue.
+        may_due
+          .into_iter()
+          .filter(|due| *due != self.due)
+          .for_each(|due| tx_view.send(&TodoOut::Rescheduled(due)));
+      }
+      TodoIn::AddSubtask => {
+        // The app creates the subtask and puts it in our subtasks ul. We stay
+        // in editing so that more can be added.
+        let may_text =
+          self
+          .subtask_input
+          .as_ref()
+          .and_then(|input| {
+            let text = utils::input_value(input);
+            input.set_value("");
+            text
+          });
+        may_text
+          .into_iter()
+          .filter(|text| !text.is_empty())
+          .for_each(|text| tx_view.send(&TodoOut::AddedSubtask(text)));
+      }
+      TodoIn::DragStart(ev) => {
+        // Subtasks are inside our li, the innermost todo is the one dragged.
+        ev.stop_propagation();
+        if self.is_editing {
+          // Let the user select text in the edit inputs instead.
+          ev.prevent_default();
+          return;
+        }
+        ev
+          .dyn_ref::<DragEvent>()
+          .and_then(|ev| ev.data_transfer())
+          .into_iter()
+          .for_each(|data| {
+            data.set_effect_allowed("move");
+            // Firefox won't start a drag without some data.
+            data
+              .set_data("text/plain", &self.name)
+              .unwrap_or_else(|e| error!("Could not start dragging: {:?}", e));
+          });
+        tx_view.send(&TodoOut::DragStarted);
+      }
+      TodoIn::DragOver(ev) => {
+        // Files are for the app to import, not for us to reorder.
+        if utils::is_dragging_files(ev) {
+          return;
+        }
+        // Preventing the default is what makes us a drop target.
+        ev.prevent_default();
+        ev.stop_propagation();
+      }
+      TodoIn::Drop(ev) => {
+        if utils::is_dragging_files(ev) {
+          return;
+        }
+        ev.prevent_default();
+        ev.stop_propagation();
+        tx_view.send(&TodoOut::Dropped(is_over_lower_half(ev)));
+      }
+      TodoIn::KeyDown(ev) => {
+        // Alt+Up and Alt+Down move the todo, except while typing.
+        let may_msg =
+          ev
+          .dyn_ref::<KeyboardEvent>()
+          .filter(|kev| kev.alt_key() && !utils::is_typing(ev))
+          .and_then(|kev| {
+            match kev.key().as_str() {
+              "ArrowUp" => { Some(TodoOut::MovedUp) }
+              "ArrowDown" => { Some(TodoOut::MovedDown) }
+              _ => { None }
+            }
+          });
+        if let Some(msg) = may_msg {
+          ev.prevent_default();
+          ev.stop_propagation();
+          tx_view.send(&msg);
+          // Moving our li takes it out of the DOM for a moment, which loses
+          // focus, so we take it back once it has landed.
+          let may_li =
+            ev
+            .current_target()
+            .and_then(|target| target.dyn_into::<HtmlElement>().ok());
+          if let Some(li) = may_li {
+            timeout(1, move || {
+              li
+                .focus()
+                .unwrap_or_else(|e| error!("Could not focus the todo: {:?}", e));
+              false
+            });
+          }
+        }
+      }
+      TodoIn::Blurred(ev) => {
+        // Moving focus between the todo's own inputs keeps editing.
+        let may_related:Option<Node> =
+          ev
+          .dyn_ref::<FocusEvent>()
+          .and_then(|ev| ev.related_target())
+          .and_then(|target| target.dyn_into::<Node>().ok());
+        let to_edit_input =
+          self
+          .edit_input
+          .iter()
+          .chain(self.due_input.iter())
+          .chain(self.tags_input.iter())
+          .chain(self.recurrence_input.iter())
+          .chain(self.subtask_input.iter())
+          .any(|input| input.is_same_node(may_related.as_ref()));
+        if self.is_editing && !to_edit_input {
+          self.update(&TodoIn::StopEditing(None), tx_view, sub);
+        }
       }
       TodoIn::StartEditing => {
         self.is_editing = true;
@@ -119,43 +735,65 @@ impl Component for Todo {
             .unwrap();
           false
         });
-        tx_view.send(&TodoOut::UpdateEditComplete(self.is_editing, self.is_done));
+        self.send_state(tx_view);
+      }
+      TodoIn::StopEditing(Some(ev)) if self.is_from_notes(ev) => {
+        // The notes run over several lines, so Enter is a new line there and
+        // it takes Ctrl+Enter to save them. Escape throws away the changes.
+        // Either way the panel closes, title editing carries on.
+        let kev =
+          ev
+          .dyn_ref::<KeyboardEvent>()
+          .unwrap();
+        let key = kev.key();
+        if key == "Enter" && (kev.ctrl_key() || kev.meta_key()) {
+          self.update(&TodoIn::ChangedNotes, tx_view, sub);
+        } else if key == "Escape" {
+          self
+            .notes_input
+            .iter()
+            .for_each(|input| input.set_value(&self.notes));
+        } else {
+          return;
+        }
+        self.is_showing_notes = false;
+        self.send_state(tx_view);
       }
       TodoIn::StopEditing(may_ev) => {
-        self.is_editing = false;
-
         let input:&HtmlInputElement =
           self
           .edit_input
           .as_ref()
           .unwrap();
 
-        if let Some(ev) = may_ev {
-          // This came from a key event
-          let kev =
-            ev
-            .dyn_ref::<KeyboardEvent>()
-            .unwrap();
-          let key =
-            kev.key();
-          if key == "Enter" {
+        let may_name =
+          if let Some(ev) = may_ev {
+            // This came from a key event, only Enter and Escape stop editing
+            let kev =
+              ev
+              .dyn_ref::<KeyboardEvent>()
+              .unwrap();
+            let key =
+              kev.key();
+            if key == "Enter" {
+              utils::input_value(input)
+            } else if key == "Escape" {
+              input.set_value(&self.name);
+              None
+            } else {
+              return;
+            }
+          } else {
+            // This came from an input change event
             utils::input_value(input)
-              .into_iter()
-              .for_each(|name| self.name = name);
-          } else if key == "Escape" {
-            self
-              .edit_input
-              .iter()
-              .for_each(|input| input.set_value(&self.name));
-          }
-        } else {
-          // This came from an input change event
-          utils::input_value(input)
-            .into_iter()
-            .for_each(|name| self.name = name);
-        }
-        tx_view.send(&TodoOut::SetName(self.name.clone()));
-        tx_view.send(&TodoOut::UpdateEditComplete(self.is_editing, self.is_done));
+          };
+        // The app owns the todo's data, it will answer with SetName.
+        may_name
+          .into_iter()
+          .filter(|name| *name != self.name)
+          .for_each(|name| tx_view.send(&TodoOut::Renamed(name)));
+        self.is_editing = false;
+        self.send_state(tx_view);
       }
       TodoIn::Remove => {
         // A todo cannot remove itself - its gizmo is owned by the parent App.
@@ -168,6 +806,8 @@ impl Component for Todo {
 
   fn builder(&self, tx: Transmitter<TodoIn>, rx: Receiver<TodoOut>) -> GizmoBuilder {
     li()
+      .attribute("draggable", "true")
+      .attribute("tabindex", "0")
       .rx_class("", rx.branch_filter_map(|msg| msg.as_list_class()))
       .rx_style("display", "block", rx.branch_filter_map(|msg| {
         match msg {
@@ -183,6 +823,10 @@ impl Component for Todo {
           _ => { None }
         }
       }))
+      .tx_on("dragstart", tx.contra_map(|ev:&Event| TodoIn::DragStart(ev.clone())))
+      .tx_on("dragover", tx.contra_map(|ev:&Event| TodoIn::DragOver(ev.clone())))
+      .tx_on("drop", tx.contra_map(|ev:&Event| TodoIn::Drop(ev.clone())))
+      .tx_on("keydown", tx.contra_map(|ev:&Event| TodoIn::KeyDown(ev.clone())))
       .with(
         div()
           .class("view")
@@ -200,13 +844,79 @@ impl Component for Todo {
           )
           .with(
             label()
-              .rx_text(&self.name, rx.branch_filter_map(|msg| {
+              .text(&self.name)
+              .tx_post_build(tx.contra_map(|el:&HtmlElement| TodoIn::Label(el.clone())))
+              .tx_on("dblclick", tx.contra_map(|_:&Event| TodoIn::StartEditing))
+          )
+          .with(
+            span()
+              .class("due")
+              .rx_text("", rx.branch_filter_map(|msg| {
                 match msg {
-                  TodoOut::SetName(name) => { Some(name.clone()) }
+                  TodoOut::SetDueText(text) => { Some(text.clone()) }
+                  _ => { None }
+                }
+              }))
+          )
+          .with(
+            span()
+              .class("recurrence")
+              .rx_text("", rx.branch_filter_map(|msg| {
+                match msg {
+                  TodoOut::SetRecurrenceText(text) => { Some(text.clone()) }
+                  _ => { None }
+                }
+              }))
+          )
+          .with(
+            span()
+              .class("editors")
+              .rx_text("", rx.branch_filter_map(|msg| {
+                match msg {
+                  TodoOut::SetEditorsText(text) => { Some(text.clone()) }
                   _ => { None }
                 }
               }))
-              .tx_on("dblclick", tx.contra_map(|_:&Event| TodoIn::StartEditing))
+          )
+          .with(
+            span()
+              .class("tags")
+              .tx_post_build(tx.contra_map(|el:&HtmlElement| TodoIn::TagsEl(el.clone())))
+              .tx_on("click", tx.contra_map(|ev:&Event| TodoIn::ClickedTag(ev.clone())))
+          )
+          .with(
+            button()
+              .class("priority")
+              .attribute("title", "Priority, click to change")
+              .style("cursor", "pointer")
+              .rx_text(Priority::None.name(), rx.branch_filter_map(|msg| {
+                match msg {
+                  TodoOut::SetPriorityText(text) => { Some(text.clone()) }
+                  _ => { None }
+                }
+              }))
+              .tx_on("click", tx.contra_map(|_:&Event| TodoIn::CyclePriority))
+          )
+          .with(
+            button()
+              .class("star")
+              .attribute("title", "Star")
+              .style("cursor", "pointer")
+              .rx_text(star_text(false), rx.branch_filter_map(|msg| {
+                match msg {
+                  TodoOut::SetStarText(text) => { Some(text.clone()) }
+                  _ => { None }
+                }
+              }))
+              .tx_on("click", tx.contra_map(|_:&Event| TodoIn::ToggleStar))
+          )
+          .with(
+            button()
+              .class("notes-toggle")
+              .attribute("title", "Notes")
+              .style("cursor", "pointer")
+              .text("\u{270e}")
+              .tx_on("click", tx.contra_map(|_:&Event| TodoIn::ToggleNotes))
           )
           .with(
             button()
@@ -215,6 +925,21 @@ impl Component for Todo {
               .tx_on("click", tx.contra_map(|_:&Event| TodoIn::Remove))
           )
       )
+      .with(
+        div()
+          .class("notes-panel")
+          .with(
+            textarea()
+              .tx_post_build(
+                tx.contra_map(|el:&HtmlElement| TodoIn::NotesInput(el.clone()))
+              )
+              .class("notes")
+              .attribute("rows", "4")
+              .attribute("placeholder", "Notes, Ctrl+Enter to save")
+              .tx_on("change", tx.contra_map(|_:&Event| TodoIn::ChangedNotes))
+              .tx_on("keyup", tx.contra_map(|ev:&Event| TodoIn::StopEditing(Some(ev.clone()))))
+          )
+      )
       .with(
         input()
           .tx_post_build(
@@ -222,8 +947,58 @@ impl Component for Todo {
           )
           .class("edit")
           .value(&self.name, )
-          .tx_on("blur", tx.contra_map(|_:&Event| TodoIn::StopEditing(None)))
+          .tx_on("blur", tx.contra_map(|ev:&Event| TodoIn::Blurred(ev.clone())))
+          .tx_on("keyup", tx.contra_map(|ev:&Event| TodoIn::StopEditing(Some(ev.clone()))))
+      )
+      .with(
+        input()
+          .tx_post_build(
+            tx.contra_map(|el:&HtmlElement| TodoIn::DueInput(el.clone()))
+          )
+          .class("edit-due")
+          .attribute("type", "date")
+          .attribute("title", "Due date")
+          .tx_on("change", tx.contra_map(|_:&Event| TodoIn::ChangedDue))
+          .tx_on("blur", tx.contra_map(|ev:&Event| TodoIn::Blurred(ev.clone())))
+          .tx_on("keyup", tx.contra_map(|ev:&Event| TodoIn::StopEditing(Some(ev.clone()))))
+      )
+      .with(
+        input()
+          .tx_post_build(
+            tx.contra_map(|el:&HtmlElement| TodoIn::TagsInput(el.clone()))
+          )
+          .class("edit-tags")
+          .attribute("placeholder", "#tags")
+          .tx_on("change", tx.contra_map(|_:&Event| TodoIn::ChangedTags))
+          .tx_on("blur", tx.contra_map(|ev:&Event| TodoIn::Blurred(ev.clone())))
           .tx_on("keyup", tx.contra_map(|ev:&Event| TodoIn::StopEditing(Some(ev.clone()))))
       )
+      .with(
+        input()
+          .tx_post_build(
+            tx.contra_map(|el:&HtmlElement| TodoIn::RecurrenceInput(el.clone()))
+          )
+          .class("edit-recurrence")
+          .attribute("placeholder", "Repeats, eg. every mon, thu")
+          .attribute("title", "How often it repeats")
+          .tx_on("change", tx.contra_map(|_:&Event| TodoIn::ChangedRecurrence))
+          .tx_on("blur", tx.contra_map(|ev:&Event| TodoIn::Blurred(ev.clone())))
+          .tx_on("keyup", tx.contra_map(|ev:&Event| TodoIn::StopEditing(Some(ev.clone()))))
+      )
+      .with(
+        input()
+          .tx_post_build(
+            tx.contra_map(|el:&HtmlElement| TodoIn::SubtaskInput(el.clone()))
+          )
+          .class("new-subtask")
+          .attribute("placeholder", "Add a subtask")
+          .tx_on("change", tx.contra_map(|_:&Event| TodoIn::AddSubtask))
+          .tx_on("blur", tx.contra_map(|ev:&Event| TodoIn::Blurred(ev.clone())))
+      )
+      .with(
+        ul()
+          .class("subtasks")
+          .tx_post_build(tx.contra_map(|el:&HtmlElement| TodoIn::SubtasksUl(el.clone())))
+      )
   }
 }
@@ -0,0 +1,319 @@
+//! Collaborating on todos live, through the relay in `server/`.
+//!
+//! A change travels as an op per todo field it changed. Ops are taken from
+//! the model's events rather than from what the user asked for, so a
+//! collaborator gets exactly what happened here: completing a repeating todo
+//! travels as its completion and its next occurrence, and the collaborator's
+//! model doesn't make another occurrence of its own.
+//!
+//! Every op is stamped like a sync write (see `sync`), except its time never
+//! falls behind the latest stamp seen, so a change made after seeing another
+//! always wins over it whatever the clocks say. Each client keeps every
+//! todo's fields with their stamps and only takes the values of an op that
+//! are newer, which settles conflicts the same way on every client whatever
+//! order the ops arrive in:
+//!
+//! * of two changes to the same field, the one with the later stamp wins,
+//!   and the same stamp is settled by the collaborators' ids
+//! * changes to different fields of a todo both land
+//! * removing a todo writes its `deleted` field, so a todo removed by one
+//!   collaborator stays removed even if another changes it meanwhile, until
+//!   the removal is undone
+//!
+//! Presence tells which todo each collaborator is editing.
+//!
+//! The order of the todos isn't shared, todos from collaborators go at the
+//! end of the list. Like syncing, lists aren't shared either.
+use std::collections::BTreeMap;
+use chrono::NaiveDate;
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+use super::model::{Command, Event, TodoList};
+use super::recurrence::Recurrence;
+use super::store::{Item, Priority};
+use super::sync::{self, Field, Fields};
+
+
+/// A change to a todo. Each mirrors the app's message for making it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Op {
+  Add(Item),
+  Rename(Uuid, String),
+  Toggle(Uuid, bool),
+  Reschedule(Uuid, Option<NaiveDate>),
+  Prioritize(Uuid, Priority),
+  Retag(Uuid, Vec<String>),
+  Star(Uuid, bool),
+  Note(Uuid, String),
+  Repeat(Uuid, Option<Recurrence>),
+  Remove(Uuid)
+}
+
+
+impl Op {
+  /// The op that shares a model event, if the event changed a todo.
+  pub fn from_event(event: &Event) -> Option<Op> {
+    let op =
+      match event {
+        Event::Inserted { item, .. } => { Op::Add(item.clone()) }
+        Event::Renamed(id, title) => { Op::Rename(*id, title.clone()) }
+        Event::Completed(id, completed) => { Op::Toggle(*id, *completed) }
+        Event::Due(id, due) => { Op::Reschedule(*id, *due) }
+        Event::Prioritized(id, priority) => { Op::Prioritize(*id, *priority) }
+        Event::Tagged(id, tags) => { Op::Retag(*id, tags.clone()) }
+        Event::Starred(id, starred) => { Op::Star(*id, *starred) }
+        Event::Noted(id, notes) => { Op::Note(*id, notes.clone()) }
+        Event::Recurring(id, recurrence) => { Op::Repeat(*id, recurrence.clone()) }
+        Event::Removed(id) => { Op::Remove(*id) }
+        _ => { return None; }
+      };
+    Some(op)
+  }
+
+  pub fn id(&self) -> Uuid {
+    match self {
+      Op::Add(item) => { item.id }
+      Op::Rename(id, _)
+      | Op::Toggle(id, _)
+      | Op::Reschedule(id, _)
+      | Op::Prioritize(id, _)
+      | Op::Retag(id, _)
+      | Op::Star(id, _)
+      | Op::Note(id, _)
+      | Op::Repeat(id, _)
+      | Op::Remove(id) => { *id }
+    }
+  }
+
+  /// The fields the op writes, named as in `Item`.
+  fn values(&self) -> Map<String, Value> {
+    let (name, value) =
+      match self {
+        Op::Add(item) => { return sync::values(item); }
+        Op::Rename(_, title) => { ("title", json!(title)) }
+        Op::Toggle(_, completed) => { ("completed", json!(completed)) }
+        Op::Reschedule(_, due) => { ("due", json!(due)) }
+        Op::Prioritize(_, priority) => { ("priority", json!(priority)) }
+        Op::Retag(_, tags) => { ("tags", json!(tags)) }
+        Op::Star(_, starred) => { ("starred", json!(starred)) }
+        Op::Note(_, notes) => { ("notes", json!(notes)) }
+        Op::Repeat(_, recurrence) => { ("recurrence", json!(recurrence)) }
+        Op::Remove(_) => { ("deleted", json!(true)) }
+      };
+    let mut values = Map::new();
+    values.insert(name.to_string(), value);
+    values
+  }
+
+  /// The op that writes `value` to the field `name`, if an op can.
+  fn from_value(id: Uuid, name: &str, value: &Value) -> Option<Op> {
+    let value = value.clone();
+    let op =
+      match name {
+        "title" => { Op::Rename(id, serde_json::from_value(value).ok()?) }
+        "completed" => { Op::Toggle(id, serde_json::from_value(value).ok()?) }
+        "due" => { Op::Reschedule(id, serde_json::from_value(value).ok()?) }
+        "priority" => { Op::Prioritize(id, serde_json::from_value(value).ok()?) }
+        "tags" => { Op::Retag(id, serde_json::from_value(value).ok()?) }
+        "starred" => { Op::Star(id, serde_json::from_value(value).ok()?) }
+        "notes" => { Op::Note(id, serde_json::from_value(value).ok()?) }
+        "recurrence" => { Op::Repeat(id, serde_json::from_value(value).ok()?) }
+        _ => { return None; }
+      };
+    Some(op)
+  }
+
+  /// The command that makes a collaborator's op on `list`, unless it's for
+  /// a todo the list doesn't have.
+  pub fn command(&self, list: &TodoList) -> Option<Command> {
+    match self {
+      Op::Add(item) => { Some(Command::Patch(item.clone())) }
+      Op::Remove(id) => { Some(Command::Forget(*id)) }
+      op => {
+        let mut values =
+          match serde_json::to_value(list.get(&op.id())?) {
+            Ok(Value::Object(values)) => { values }
+            _ => { return None; }
+          };
+        values.extend(op.values());
+        serde_json::from_value(Value::Object(values))
+          .map(Command::Patch)
+          .ok()
+      }
+    }
+  }
+}
+
+
+/// Someone in a room.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Peer {
+  /// Identifies the collaborator's tab, and stamps their ops.
+  pub id: String,
+  pub name: String,
+  /// The todo they're editing, if any.
+  #[serde(default)]
+  pub editing: Option<Uuid>
+}
+
+
+/// What goes over the WebSocket, as the relay knows it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+  /// A change, stamped like a sync write.
+  Op { op: Op, at: i64, by: String },
+  Presence(Peer),
+  /// The collaborator with this id left.
+  Left { id: String },
+  /// The relay has replayed everything the room had before joining.
+  CaughtUp
+}
+
+
+/// What the app should do about a message from the relay.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Remote {
+  /// Make a collaborator's change.
+  Apply(Op),
+  /// The names of who's editing each todo now.
+  Editors(BTreeMap<Uuid, Vec<String>>),
+  /// Share the todos the room doesn't have yet, see `Collab::share`.
+  CaughtUp
+}
+
+
+fn is_live(fields: &Fields) -> bool {
+  fields
+    .get("deleted")
+    .map(|field| field.value == Value::Bool(false))
+    .unwrap_or(false)
+}
+
+
+/// What a collaborator knows of the room.
+#[derive(Clone, Debug)]
+pub struct Collab {
+  pub me: Peer,
+  /// The latest stamp made or seen.
+  clock: i64,
+  /// Every todo's fields, removed ones included.
+  todos: BTreeMap<Uuid, Fields>,
+  /// Everyone else in the room, by id.
+  peers: BTreeMap<String, Peer>
+}
+
+
+impl Collab {
+  pub fn new(id: String, name: String) -> Collab {
+    Collab {
+      me: Peer { id, name, editing: None },
+      clock: 0,
+      todos: BTreeMap::new(),
+      peers: BTreeMap::new()
+    }
+  }
+
+  /// Take in an op's values where they're newer than what's known. Returns
+  /// the ops that bring a todo from how it was to how it is now.
+  fn merge(&mut self, op: &Op, at: i64, by: &str) -> Vec<Op> {
+    self.clock = self.clock.max(at);
+    let id = op.id();
+    let fields = self.todos.entry(id).or_default();
+    let was_live = is_live(fields);
+    let mut changed = vec![];
+    for (name, value) in op.values() {
+      let field = Field { value, at, by: by.to_string() };
+      let may_local = fields.get(&name);
+      if may_local.map(|local| field.is_newer_than(local)).unwrap_or(true) {
+        if may_local.map(|local| local.value != field.value).unwrap_or(true) {
+          changed.push(name.clone());
+        }
+        fields.insert(name, field);
+      }
+    }
+    match (was_live, is_live(fields)) {
+      (false, true) => { sync::item(id, fields).map(Op::Add).into_iter().collect() }
+      (true, false) => { vec![Op::Remove(id)] }
+      (true, true) => {
+        changed
+          .iter()
+          .filter_map(|name| Op::from_value(id, name, &fields[name].value))
+          .collect()
+      }
+      (false, false) => { vec![] }
+    }
+  }
+
+  /// Stamp a change made here at `now`, in milliseconds since the epoch.
+  /// Returns the message that shares it.
+  pub fn local(&mut self, op: Op, now: i64) -> Message {
+    let at = now.max(self.clock + 1);
+    let by = self.me.id.clone();
+    self.merge(&op, at, &by);
+    Message::Op { op, at, by }
+  }
+
+  /// Share the todos the room hasn't heard of, eg. those made before
+  /// joining it. A todo changed here before catching up has been heard of
+  /// only if it's been added, which writes its `deleted` field.
+  pub fn share(&mut self, items: &[Item], now: i64) -> Vec<Message> {
+    let unheard:Vec<Item> =
+      items
+      .iter()
+      .filter(|item| {
+        self
+          .todos
+          .get(&item.id)
+          .map(|fields| !fields.contains_key("deleted"))
+          .unwrap_or(true)
+      })
+      .cloned()
+      .collect();
+    unheard
+      .into_iter()
+      .map(|item| self.local(Op::Add(item), now))
+      .collect()
+  }
+
+  /// Start or stop editing a todo. Returns the message that says so.
+  pub fn edit(&mut self, editing: Option<Uuid>) -> Message {
+    self.me.editing = editing;
+    Message::Presence(self.me.clone())
+  }
+
+  /// The names of who's editing each todo.
+  pub fn editors(&self) -> BTreeMap<Uuid, Vec<String>> {
+    let mut editors:BTreeMap<Uuid, Vec<String>> = BTreeMap::new();
+    for peer in self.peers.values() {
+      if let Some(id) = peer.editing {
+        editors.entry(id).or_default().push(peer.name.clone());
+      }
+    }
+    editors
+  }
+
+  /// Take in a message from the relay.
+  pub fn receive(&mut self, message: Message) -> Vec<Remote> {
+    match message {
+      Message::Op { op, at, by } => {
+        self
+          .merge(&op, at, &by)
+          .into_iter()
+          .map(Remote::Apply)
+          .collect()
+      }
+      Message::Presence(peer) => {
+        self.peers.insert(peer.id.clone(), peer);
+        vec![Remote::Editors(self.editors())]
+      }
+      Message::Left { id } => {
+        self.peers.remove(&id);
+        vec![Remote::Editors(self.editors())]
+      }
+      Message::CaughtUp => { vec![Remote::CaughtUp] }
+    }
+  }
+}
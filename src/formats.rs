@@ -0,0 +1,258 @@
+//! Files that todos are exported to and imported from.
+//!
+//! Each format has a writer and a reader, both pure, so a file the app wrote
+//! reads back as the same todos, as far as the format can hold them:
+//!
+//! * JSON holds everything, it's the store's own layout pretty printed
+//! * CSV holds everything too, one todo per row under a header row
+//! * Markdown is a GitHub-flavored checklist, with subtasks indented under
+//!   their parent, notes as the lines below a todo and the rest of the todo
+//!   written in quick-add syntax after its title
+//! * todo.txt holds completion, priority, tags and due dates
+//! * iCalendar holds todos as VTODO components for calendar clients, with
+//!   everything but stars, lists and completion dates
+//!
+//! Readers don't stop at the first malformed line. They read what they can
+//! and report the rest as `Problem`s with their line numbers.
+//!
+//! Importing leaves out todos that are already in the list, going by their
+//! titles, and gives the rest fresh ids so that importing a file twice, or
+//! into another list, never makes two todos with the same id.
+use std::collections::HashMap;
+use std::fmt;
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+use super::store::Item;
+
+mod csv;
+mod ical;
+mod json;
+mod markdown;
+mod todo_txt;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+  Json,
+  Csv,
+  Markdown,
+  TodoTxt,
+  ICalendar
+}
+
+
+impl Format {
+  pub const ALL: [Format; 5] = [
+    Format::Json,
+    Format::Csv,
+    Format::Markdown,
+    Format::TodoTxt,
+    Format::ICalendar
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Format::Json => { "JSON" }
+      Format::Csv => { "CSV" }
+      Format::Markdown => { "Markdown" }
+      Format::TodoTxt => { "todo.txt" }
+      Format::ICalendar => { "iCalendar" }
+    }
+  }
+
+  pub fn extension(self) -> &'static str {
+    match self {
+      Format::Json => { "json" }
+      Format::Csv => { "csv" }
+      Format::Markdown => { "md" }
+      Format::TodoTxt => { "txt" }
+      Format::ICalendar => { "ics" }
+    }
+  }
+
+  pub fn mime(self) -> &'static str {
+    match self {
+      Format::Json => { "application/json" }
+      Format::Csv => { "text/csv" }
+      Format::Markdown => { "text/markdown" }
+      Format::TodoTxt => { "text/plain" }
+      Format::ICalendar => { "text/calendar" }
+    }
+  }
+
+  /// The format of a file, going by its extension.
+  pub fn from_filename(name: &str) -> Option<Format> {
+    let extension =
+      name
+      .rsplit_once('.')
+      .map(|(_, extension)| extension.to_lowercase())?;
+    match extension.as_str() {
+      "json" => { Some(Format::Json) }
+      "csv" => { Some(Format::Csv) }
+      "md" | "markdown" => { Some(Format::Markdown) }
+      "txt" => { Some(Format::TodoTxt) }
+      "ics" | "ical" => { Some(Format::ICalendar) }
+      _ => { None }
+    }
+  }
+
+  /// The format of a file, going by its extension or else by how it looks.
+  /// Text that looks like nothing else is read as todo.txt, which takes any
+  /// line as a todo.
+  pub fn guess(name: &str, text: &str) -> Format {
+    if let Some(format) = Format::from_filename(name) {
+      return format;
+    }
+    let text = text.trim_start();
+    let first_line = text.lines().next().unwrap_or("").to_lowercase();
+    if text.starts_with('[') || text.starts_with('{') {
+      Format::Json
+    } else if first_line.starts_with("begin:vcalendar") {
+      Format::ICalendar
+    } else if text.lines().any(|line| markdown::is_checklist_item(line.trim())) {
+      Format::Markdown
+    } else if first_line.split(',').any(|column| column.trim() == "title") {
+      Format::Csv
+    } else {
+      Format::TodoTxt
+    }
+  }
+}
+
+
+/// A line of a file that couldn't be read, counting from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+  pub line: usize,
+  pub message: String
+}
+
+
+impl Problem {
+  fn new(line: usize, message: impl Into<String>) -> Problem {
+    Problem { line, message: message.into() }
+  }
+}
+
+
+impl fmt::Display for Problem {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "line {}: {}", self.line, self.message)
+  }
+}
+
+
+/// What a reader made of a file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Parsed {
+  pub items: Vec<Item>,
+  pub problems: Vec<Problem>
+}
+
+
+/// A file's todos, ready to be added to a list.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Import {
+  /// The todos that aren't in the list yet, with fresh ids and each one after
+  /// its parent.
+  pub items: Vec<Item>,
+  /// How many todos were left out as duplicates.
+  pub duplicates: usize,
+  pub problems: Vec<Problem>
+}
+
+
+/// A title as it's compared for duplicates, ignoring case and spacing.
+fn title_key(title: &str) -> String {
+  title
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ")
+    .to_lowercase()
+}
+
+
+/// Sort out what was read into todos to add and duplicates, a duplicate
+/// being a todo with the title of one in `existing` or of one earlier in the
+/// file. Subtasks of a duplicate go under the todo it duplicates.
+pub fn dedupe(existing: &[&Item], parsed: Parsed) -> Import {
+  let mut by_title:HashMap<String, Uuid> =
+    existing
+    .iter()
+    .map(|item| (title_key(&item.title), item.id))
+    .collect();
+  let mut new_ids:HashMap<Uuid, Uuid> = HashMap::new();
+  let mut import = Import { problems: parsed.problems, ..Import::default() };
+  let mut fresh = vec![];
+
+  for item in parsed.items {
+    let key = title_key(&item.title);
+    match by_title.get(&key) {
+      Some(id) => {
+        new_ids.insert(item.id, *id);
+        import.duplicates += 1;
+      }
+      None => {
+        let id = Uuid::new_v4();
+        new_ids.insert(item.id, id);
+        by_title.insert(key, id);
+        fresh.push(Item { id, ..item });
+      }
+    }
+  }
+
+  // Subtasks come after their parents so the parents are there to hold them.
+  let fresh_ids:Vec<Uuid> = fresh.iter().map(|item| item.id).collect();
+  while !fresh.is_empty() {
+    let is_ready = |item: &Item, added: &[Item]| {
+      item
+        .parent
+        .and_then(|parent| new_ids.get(&parent))
+        .filter(|parent| fresh_ids.contains(parent))
+        .map(|parent| added.iter().any(|added| added.id == *parent))
+        .unwrap_or(true)
+    };
+    let may_next = fresh.iter().position(|item| is_ready(item, &import.items));
+    // Subtasks whose parents loop go in as todos of their own.
+    let (at, parent_is_known) =
+      match may_next {
+        Some(at) => { (at, true) }
+        None => { (0, false) }
+      };
+    let mut item = fresh.remove(at);
+    item.parent =
+      item
+      .parent
+      .and_then(|parent| new_ids.get(&parent).cloned())
+      .filter(|_| parent_is_known);
+    import.items.push(item);
+  }
+  import
+}
+
+
+/// Write todos out in `format`. Subtasks should come after their parents, as
+/// they do in the store.
+pub fn write(format: Format, items: &[Item]) -> String {
+  match format {
+    Format::Json => { json::write(items) }
+    Format::Csv => { csv::write(items) }
+    Format::Markdown => { markdown::write(items) }
+    Format::TodoTxt => { todo_txt::write(items) }
+    Format::ICalendar => { ical::write(items) }
+  }
+}
+
+
+/// Read todos from a file in `format`. `today` is used to resolve relative
+/// dates in quick-add syntax.
+pub fn read(format: Format, text: &str, today: NaiveDate) -> Parsed {
+  match format {
+    Format::Json => { json::read(text) }
+    Format::Csv => { csv::read(text) }
+    Format::Markdown => { markdown::read(text, today) }
+    Format::TodoTxt => { todo_txt::read(text) }
+    Format::ICalendar => { ical::read(text) }
+  }
+}
@@ -0,0 +1,269 @@
+//! Todos as comma separated values, one todo per row under a header row.
+//!
+//! Fields are quoted as in RFC 4180 when they need to be, which lets notes
+//! run over several lines. Tags and completion dates are separated by
+//! spaces, a recurrence is written the way the app shows it. Columns are
+//! found by their header, so a spreadsheet can reorder them or leave out all
+//! but the title. A field that a spreadsheet would run as a formula, like
+//! `=HYPERLINK(...)`, is written with a `'` in front, which is taken off
+//! again when reading.
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+use super::{Parsed, Problem};
+use crate::model::parse_tags;
+use crate::recurrence;
+use crate::store::{Item, Priority};
+
+
+const COLUMNS: [&str; 12] = [
+  "id",
+  "title",
+  "completed",
+  "due",
+  "priority",
+  "tags",
+  "starred",
+  "parent",
+  "list",
+  "notes",
+  "recurrence",
+  "completions"
+];
+
+
+fn quoted(field: &str) -> String {
+  if field.contains([',', '"', '\n', '\r']) {
+    format!("\"{}\"", field.replace('"', "\"\""))
+  } else {
+    field.to_string()
+  }
+}
+
+
+/// Whether a spreadsheet would run the field as a formula, were the `'`s in
+/// front of it taken off.
+fn is_formula(field: &str) -> bool {
+  field
+    .trim_start_matches('\'')
+    .starts_with(['=', '+', '-', '@'])
+}
+
+
+fn defused(field: &str) -> String {
+  if is_formula(field) {
+    format!("'{}", field)
+  } else {
+    field.to_string()
+  }
+}
+
+
+/// A field as it was before it was defused.
+fn restored(field: &str) -> &str {
+  if is_formula(field) {
+    field.strip_prefix('\'').unwrap_or(field)
+  } else {
+    field
+  }
+}
+
+
+fn row(fields: &[String]) -> String {
+  let fields:Vec<String> = fields.iter().map(|field| quoted(&defused(field))).collect();
+  format!("{}\r\n", fields.join(","))
+}
+
+
+fn dates(dates: &[NaiveDate]) -> String {
+  dates
+    .iter()
+    .map(|date| date.to_string())
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+
+pub fn write(items: &[Item]) -> String {
+  let header:Vec<String> = COLUMNS.iter().map(|column| column.to_string()).collect();
+  let mut text = row(&header);
+  for item in items {
+    text.push_str(&row(&[
+      item.id.to_string(),
+      item.title.clone(),
+      item.completed.to_string(),
+      item.due.map(|due| due.to_string()).unwrap_or_default(),
+      item.priority.name().to_string(),
+      item.tags.join(" "),
+      item.starred.to_string(),
+      item.parent.map(|parent| parent.to_string()).unwrap_or_default(),
+      item.list.to_string(),
+      item.notes.clone(),
+      item.recurrence.as_ref().map(|rule| rule.to_string()).unwrap_or_default(),
+      dates(&item.completions)
+    ]));
+  }
+  text
+}
+
+
+/// A row of fields and the line it starts on.
+struct Record {
+  line: usize,
+  fields: Vec<String>
+}
+
+
+/// Split the text into records. A quote that is never closed swallows the
+/// rest of the file, so it ends the records with a problem.
+fn records(text: &str) -> (Vec<Record>, Option<Problem>) {
+  let text = text.replace("\r\n", "\n");
+  let mut records = vec![];
+  let mut fields = vec![];
+  let mut field = String::new();
+  let mut line = 1;
+  let mut start = 1;
+  let mut in_quotes = false;
+  let mut chars = text.chars().peekable();
+  while let Some(c) = chars.next() {
+    if c == '\n' {
+      line += 1;
+    }
+    match c {
+      '"' if in_quotes && chars.peek() == Some(&'"') => {
+        chars.next();
+        field.push('"');
+      }
+      '"' if in_quotes => { in_quotes = false; }
+      '"' if field.is_empty() => { in_quotes = true; }
+      ',' if !in_quotes => { fields.push(std::mem::take(&mut field)); }
+      '\n' if !in_quotes => {
+        fields.push(std::mem::take(&mut field));
+        records.push(Record { line: start, fields: std::mem::take(&mut fields) });
+        start = line;
+      }
+      c => { field.push(c); }
+    }
+  }
+  if in_quotes {
+    return (records, Some(Problem::new(start, "a quoted field is never closed")));
+  }
+  if !field.is_empty() || !fields.is_empty() {
+    fields.push(field);
+    records.push(Record { line: start, fields });
+  }
+  (records, None)
+}
+
+
+fn parse_bool(text: &str) -> Result<bool, String> {
+  match text.trim().to_lowercase().as_str() {
+    "" | "false" | "no" | "0" => { Ok(false) }
+    "true" | "yes" | "1" | "x" => { Ok(true) }
+    other => { Err(format!("{:?} is not true or false", other)) }
+  }
+}
+
+
+fn parse_date(text: &str) -> Result<NaiveDate, String> {
+  NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d")
+    .map_err(|_| format!("{:?} is not a date like 2026-11-01", text))
+}
+
+
+fn parse_priority(text: &str) -> Result<Priority, String> {
+  match text.trim().to_lowercase().as_str() {
+    "" | "none" => { Ok(Priority::None) }
+    "low" => { Ok(Priority::Low) }
+    "medium" => { Ok(Priority::Medium) }
+    "high" => { Ok(Priority::High) }
+    other => { Err(format!("{:?} is not a priority", other)) }
+  }
+}
+
+
+fn parse_id(text: &str) -> Result<Uuid, String> {
+  Uuid::parse_str(text.trim())
+    .map_err(|_| format!("{:?} is not an id", text))
+}
+
+
+/// The todo in a row, given which column each header is in.
+fn parse_row(header: &[String], fields: &[String]) -> Result<Item, String> {
+  let field = |name: &str| -> &str {
+    header
+      .iter()
+      .position(|column| column == name)
+      .and_then(|at| fields.get(at))
+      .map(|field| restored(field))
+      .unwrap_or("")
+  };
+  let present = |text: &str| !text.trim().is_empty();
+
+  let title = field("title").trim();
+  if title.is_empty() {
+    return Err("the todo has no title".to_string());
+  }
+  let mut item = Item::new(title.to_string());
+  if present(field("id")) {
+    item.id = parse_id(field("id"))?;
+  }
+  item.completed = parse_bool(field("completed"))?;
+  if present(field("due")) {
+    item.due = Some(parse_date(field("due"))?);
+  }
+  item.priority = parse_priority(field("priority"))?;
+  item.tags = parse_tags(field("tags"));
+  item.starred = parse_bool(field("starred"))?;
+  if present(field("parent")) {
+    item.parent = Some(parse_id(field("parent"))?);
+  }
+  if present(field("list")) {
+    item.list = parse_id(field("list"))?;
+  }
+  item.notes = field("notes").to_string();
+  if present(field("recurrence")) {
+    let text = field("recurrence");
+    item.recurrence = Some(
+      recurrence::parse(text)
+        .ok_or_else(|| format!("{:?} is not a way to repeat", text))?
+    );
+  }
+  item.completions =
+    field("completions")
+    .split_whitespace()
+    .map(parse_date)
+    .collect::<Result<Vec<_>, _>>()?;
+  Ok(item)
+}
+
+
+pub fn read(text: &str) -> Parsed {
+  let (records, may_problem) = records(text);
+  let mut parsed = Parsed::default();
+  let mut records =
+    records
+    .into_iter()
+    .filter(|record| record.fields.iter().any(|field| !field.trim().is_empty()));
+
+  if let Some(first) = records.next() {
+    let header:Vec<String> =
+      first
+      .fields
+      .iter()
+      .map(|column| column.trim().to_lowercase())
+      .collect();
+    if !header.iter().any(|column| column == "title") {
+      parsed.problems.push(Problem::new(first.line, "the header row has no title column"));
+    } else {
+      for record in records {
+        match parse_row(&header, &record.fields) {
+          Ok(item) => { parsed.items.push(item); }
+          Err(message) => { parsed.problems.push(Problem::new(record.line, message)); }
+        }
+      }
+    }
+  }
+  parsed.problems.extend(may_problem);
+  parsed
+}
@@ -0,0 +1,522 @@
+//! Todos as iCalendar `VTODO` components, as in RFC 5545, for calendar
+//! clients:
+//!
+//! ```text
+//! BEGIN:VCALENDAR
+//! VERSION:2.0
+//! PRODID:-//mogwai-todo//EN
+//! BEGIN:VTODO
+//! UID:0b6f3a8e-...
+//! DTSTAMP:20261017T090000Z
+//! SUMMARY:Buy milk
+//! STATUS:NEEDS-ACTION
+//! DUE;VALUE=DATE:20261101
+//! PRIORITY:5
+//! END:VTODO
+//! END:VCALENDAR
+//! ```
+//!
+//! Due dates are written as dates, without a time or timezone, and a due
+//! time read from another app is dropped. Priorities 1 to 4 are high, 5 is
+//! medium and 6 to 9 are low. Tags, notes, subtasks and the simpler repeat
+//! rules go in CATEGORIES, DESCRIPTION, RELATED-TO and RRULE. Lines longer
+//! than 75 bytes are folded and text is escaped as the RFC asks.
+//!
+//! Unlike the other writers this one reads the clock, for the DTSTAMP that
+//! every component must have.
+use std::collections::HashMap;
+use chrono::{NaiveDate, NaiveDateTime, Utc, Weekday};
+use uuid::Uuid;
+
+use super::{Parsed, Problem};
+use crate::model::normalize_tag;
+use crate::recurrence::{self, Recurrence};
+use crate::store::{Item, Priority};
+
+
+/// The longest a line may be, in bytes, before it is folded.
+const LINE_WIDTH: usize = 75;
+
+
+/// Escape text for a TEXT value.
+fn escaped(text: &str) -> String {
+  let mut escaped = String::new();
+  for c in text.chars() {
+    match c {
+      '\\' => { escaped.push_str("\\\\"); }
+      ';' => { escaped.push_str("\\;"); }
+      ',' => { escaped.push_str("\\,"); }
+      '\n' => { escaped.push_str("\\n"); }
+      '\r' => {}
+      c => { escaped.push(c); }
+    }
+  }
+  escaped
+}
+
+
+/// Undo `escaped`.
+fn unescaped(text: &str) -> String {
+  let mut unescaped = String::new();
+  let mut chars = text.chars();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      unescaped.push(c);
+      continue;
+    }
+    match chars.next() {
+      Some('n') | Some('N') => { unescaped.push('\n'); }
+      Some(c) => { unescaped.push(c); }
+      None => { unescaped.push('\\'); }
+    }
+  }
+  unescaped
+}
+
+
+/// Split a TEXT list on the commas that aren't escaped.
+fn split_list(text: &str) -> Vec<String> {
+  let mut parts = vec![];
+  let mut part = String::new();
+  let mut chars = text.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => {
+        part.push(c);
+        part.extend(chars.next());
+      }
+      ',' => { parts.push(unescaped(&std::mem::take(&mut part))); }
+      c => { part.push(c); }
+    }
+  }
+  parts.push(unescaped(&part));
+  parts
+}
+
+
+/// Fold a content line into lines of at most `LINE_WIDTH` bytes, each after
+/// the first starting with a space. Characters are never split.
+fn folded(line: &str) -> String {
+  let mut text = String::new();
+  let mut width = 0;
+  for c in line.chars() {
+    if width + c.len_utf8() > LINE_WIDTH {
+      text.push_str("\r\n ");
+      width = 1;
+    }
+    text.push(c);
+    width += c.len_utf8();
+  }
+  text.push_str("\r\n");
+  text
+}
+
+
+fn day_code(day: Weekday) -> &'static str {
+  match day {
+    Weekday::Mon => { "MO" }
+    Weekday::Tue => { "TU" }
+    Weekday::Wed => { "WE" }
+    Weekday::Thu => { "TH" }
+    Weekday::Fri => { "FR" }
+    Weekday::Sat => { "SA" }
+    Weekday::Sun => { "SU" }
+  }
+}
+
+
+fn from_day_code(code: &str) -> Option<Weekday> {
+  Some(
+    match code {
+      "MO" => { Weekday::Mon }
+      "TU" => { Weekday::Tue }
+      "WE" => { Weekday::Wed }
+      "TH" => { Weekday::Thu }
+      "FR" => { Weekday::Fri }
+      "SA" => { Weekday::Sat }
+      "SU" => { Weekday::Sun }
+      _ => { return None; }
+    }
+  )
+}
+
+
+fn rrule(rule: &Recurrence) -> String {
+  match rule {
+    Recurrence::Daily => { "FREQ=DAILY".to_string() }
+    Recurrence::Weekly { days } => {
+      let days:Vec<&str> = days.iter().map(|day| day_code(*day)).collect();
+      format!("FREQ=WEEKLY;BYDAY={}", days.join(","))
+    }
+    Recurrence::Every { days } => { format!("FREQ=DAILY;INTERVAL={}", days) }
+    Recurrence::Monthly { day } => { format!("FREQ=MONTHLY;BYMONTHDAY={}", day) }
+  }
+}
+
+
+fn every(days: u32) -> Option<Recurrence> {
+  Some(Recurrence::Every { days }).filter(|_| days <= recurrence::MAX_DAYS)
+}
+
+
+/// The rule a RRULE stands for, if it's one the app can repeat by. Like a
+/// rule the user writes, its interval is at most `recurrence::MAX_DAYS`.
+fn parse_rrule(text: &str) -> Option<Recurrence> {
+  let parts:HashMap<String, &str> =
+    text
+    .split(';')
+    .filter_map(|part| part.split_once('='))
+    .map(|(key, value)| (key.trim().to_uppercase(), value.trim()))
+    .collect();
+  let interval:u32 =
+    match parts.get("INTERVAL") {
+      Some(interval) => { interval.parse().ok().filter(|n| *n > 0)? }
+      None => { 1 }
+    };
+  // Anything else, like a COUNT or UNTIL, is more than the app can keep.
+  let mut others:Vec<&str> =
+    parts
+    .keys()
+    .map(|key| key.as_str())
+    .filter(|key| !["FREQ", "INTERVAL", "WKST"].contains(key))
+    .collect();
+  others.sort_unstable();
+  match (parts.get("FREQ")?.to_uppercase().as_str(), others.as_slice()) {
+    ("DAILY", []) if interval == 1 => { Some(Recurrence::Daily) }
+    ("DAILY", []) => { every(interval) }
+    ("WEEKLY", []) => { every(interval.checked_mul(7)?) }
+    ("WEEKLY", ["BYDAY"]) if interval == 1 => {
+      let days =
+        parts["BYDAY"]
+        .split(',')
+        .map(|code| from_day_code(&code.trim().to_uppercase()))
+        .collect::<Option<Vec<_>>>()?;
+      Some(Recurrence::Weekly { days })
+    }
+    ("MONTHLY", ["BYMONTHDAY"]) if interval == 1 => {
+      let day:u32 = parts["BYMONTHDAY"].parse().ok()?;
+      if (1..=31).contains(&day) {
+        Some(Recurrence::Monthly { day })
+      } else {
+        None
+      }
+    }
+    _ => { None }
+  }
+}
+
+
+fn priority_number(priority: Priority) -> Option<u8> {
+  match priority {
+    Priority::High => { Some(1) }
+    Priority::Medium => { Some(5) }
+    Priority::Low => { Some(9) }
+    Priority::None => { None }
+  }
+}
+
+
+fn write_item(item: &Item, stamp: &str, text: &mut String) {
+  let mut lines = vec![
+    "BEGIN:VTODO".to_string(),
+    format!("UID:{}", item.id),
+    format!("DTSTAMP:{}", stamp),
+    format!("SUMMARY:{}", escaped(&item.title)),
+    format!("STATUS:{}", if item.completed { "COMPLETED" } else { "NEEDS-ACTION" })
+  ];
+  if let Some(due) = item.due {
+    lines.push(format!("DUE;VALUE=DATE:{}", due.format("%Y%m%d")));
+  }
+  if let Some(number) = priority_number(item.priority) {
+    lines.push(format!("PRIORITY:{}", number));
+  }
+  if !item.tags.is_empty() {
+    let tags:Vec<String> = item.tags.iter().map(|tag| escaped(tag)).collect();
+    lines.push(format!("CATEGORIES:{}", tags.join(",")));
+  }
+  if !item.notes.is_empty() {
+    lines.push(format!("DESCRIPTION:{}", escaped(&item.notes)));
+  }
+  if let Some(parent) = item.parent {
+    lines.push(format!("RELATED-TO;RELTYPE=PARENT:{}", parent));
+  }
+  if let Some(rule) = item.recurrence.as_ref() {
+    lines.push(format!("RRULE:{}", rrule(rule)));
+  }
+  lines.push("END:VTODO".to_string());
+  lines
+    .iter()
+    .for_each(|line| text.push_str(&folded(line)));
+}
+
+
+pub fn write(items: &[Item]) -> String {
+  let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+  let mut text = String::new();
+  ["BEGIN:VCALENDAR", "VERSION:2.0", "PRODID:-//mogwai-todo//EN"]
+    .iter()
+    .for_each(|line| text.push_str(&folded(line)));
+  items
+    .iter()
+    .for_each(|item| write_item(item, &stamp, &mut text));
+  text.push_str(&folded("END:VCALENDAR"));
+  text
+}
+
+
+/// Join folded lines back up, keeping the number of the line each content
+/// line starts on.
+fn unfolded(text: &str) -> Vec<(usize, String)> {
+  let mut lines:Vec<(usize, String)> = vec![];
+  for (n, line) in text.split('\n').enumerate() {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+      (Some(rest), Some((_, last))) => { last.push_str(rest); }
+      _ if line.trim().is_empty() => {}
+      _ => { lines.push((n + 1, line.to_string())); }
+    }
+  }
+  lines
+}
+
+
+/// A content line, eg. `DUE;VALUE=DATE:20261101`.
+struct Property {
+  name: String,
+  params: Vec<(String, String)>,
+  value: String
+}
+
+
+impl Property {
+  fn param(&self, name: &str) -> Option<&str> {
+    self
+      .params
+      .iter()
+      .find(|(param, _)| param == name)
+      .map(|(_, value)| value.as_str())
+  }
+}
+
+
+/// Split a content line into its name, parameters and value. Parameter
+/// values may be quoted, and quoted values may hold colons and semicolons.
+fn parse_property(line: &str) -> Option<Property> {
+  let mut in_quotes = false;
+  let mut parts = vec![];
+  let mut part = String::new();
+  let mut chars = line.char_indices();
+  let value =
+    loop {
+      let (at, c) = chars.next()?;
+      match c {
+        '"' => { in_quotes = !in_quotes; }
+        ';' if !in_quotes => { parts.push(std::mem::take(&mut part)); }
+        ':' if !in_quotes => {
+          parts.push(part);
+          break line[at + 1..].to_string();
+        }
+        c => { part.push(c); }
+      }
+    };
+  let mut parts = parts.into_iter();
+  let name = parts.next()?.trim().to_uppercase();
+  if name.is_empty() {
+    return None;
+  }
+  let params =
+    parts
+    .filter_map(|param| {
+      let (key, value) = param.split_once('=')?;
+      Some((key.trim().to_uppercase(), value.trim().to_string()))
+    })
+    .collect();
+  Some(Property { name, params, value })
+}
+
+
+/// A date, or the date of a date-time, whatever its timezone.
+fn parse_date(text: &str) -> Result<NaiveDate, String> {
+  let text = text.trim();
+  NaiveDate::parse_from_str(text, "%Y%m%d")
+    .or_else(|_| {
+      NaiveDateTime::parse_from_str(text.trim_end_matches('Z'), "%Y%m%dT%H%M%S")
+        .map(|time| time.date())
+    })
+    .map_err(|_| format!("{:?} is not a date like 20261101", text))
+}
+
+
+fn parse_priority(text: &str) -> Result<Priority, String> {
+  match text.trim().parse::<u8>() {
+    Ok(0) => { Ok(Priority::None) }
+    Ok(1 ..= 4) => { Ok(Priority::High) }
+    Ok(5) => { Ok(Priority::Medium) }
+    Ok(6 ..= 9) => { Ok(Priority::Low) }
+    _ => { Err(format!("{:?} is not a priority from 0 to 9", text)) }
+  }
+}
+
+
+/// A VTODO as it's being read.
+struct Todo {
+  /// The line of its BEGIN.
+  line: usize,
+  item: Item,
+  uid: Option<String>,
+  parent_uid: Option<String>,
+  has_title: bool,
+  /// Whether a property couldn't be read, which leaves the todo out.
+  is_malformed: bool
+}
+
+
+impl Todo {
+  fn new(line: usize) -> Todo {
+    Todo {
+      line,
+      item: Item::new(String::new()),
+      uid: None,
+      parent_uid: None,
+      has_title: false,
+      is_malformed: false
+    }
+  }
+
+  /// Take in a property, or say why it can't be.
+  fn read(&mut self, property: &Property) -> Result<(), String> {
+    let value = property.value.as_str();
+    match property.name.as_str() {
+      "UID" => { self.uid = Some(value.trim().to_string()); }
+      "SUMMARY" => {
+        self.item.title = unescaped(value).trim().to_string();
+        self.has_title = !self.item.title.is_empty();
+      }
+      "STATUS" => {
+        self.item.completed =
+          match value.trim().to_uppercase().as_str() {
+            "COMPLETED" => { true }
+            "NEEDS-ACTION" | "IN-PROCESS" | "CANCELLED" => { false }
+            _ => { return Err(format!("{:?} is not a status of a todo", value)); }
+          };
+      }
+      "COMPLETED" => { self.item.completed = true; }
+      "DUE" => { self.item.due = Some(parse_date(value)?); }
+      "PRIORITY" => { self.item.priority = parse_priority(value)?; }
+      "CATEGORIES" => {
+        for tag in split_list(value).iter().filter_map(|tag| normalize_tag(tag)) {
+          if !self.item.tags.contains(&tag) {
+            self.item.tags.push(tag);
+          }
+        }
+      }
+      "DESCRIPTION" => { self.item.notes = unescaped(value); }
+      "RELATED-TO" => {
+        let is_parent =
+          property
+          .param("RELTYPE")
+          .map(|reltype| reltype.eq_ignore_ascii_case("PARENT"))
+          .unwrap_or(true);
+        if is_parent {
+          self.parent_uid = Some(value.trim().to_string());
+        }
+      }
+      "RRULE" => {
+        // The todo is still worth having without repeating.
+        self.item.recurrence = parse_rrule(value);
+        if self.item.recurrence.is_none() {
+          return Err(format!("the todo can't repeat by {:?}, it was read without repeating", value));
+        }
+      }
+      _ => {}
+    }
+    Ok(())
+  }
+}
+
+
+pub fn read(text: &str) -> Parsed {
+  let mut parsed = Parsed::default();
+  let mut todos:Vec<Todo> = vec![];
+  // The components we're in, and the todo being read if the innermost is
+  // a VTODO.
+  let mut components:Vec<String> = vec![];
+  let mut may_todo:Option<Todo> = None;
+
+  for (number, line) in unfolded(text) {
+    let property =
+      match parse_property(&line) {
+        Some(property) => { property }
+        None => {
+          parsed.problems.push(Problem::new(number, "not a property like \"SUMMARY:Buy milk\""));
+          continue;
+        }
+      };
+    let component = property.value.trim().to_uppercase();
+    match property.name.as_str() {
+      "BEGIN" => {
+        if component == "VTODO" && components.last().map(|c| c == "VCALENDAR").unwrap_or(true) {
+          may_todo = Some(Todo::new(number));
+        }
+        components.push(component);
+      }
+      "END" => {
+        if !components.contains(&component) {
+          parsed.problems.push(Problem::new(number, format!("END:{} has no BEGIN", component)));
+          continue;
+        }
+        while let Some(ended) = components.pop() {
+          if ended == "VTODO" && components.last().map(|c| c == "VCALENDAR").unwrap_or(true) {
+            if let Some(todo) = may_todo.take() {
+              if ended == component {
+                todos.push(todo);
+              } else {
+                parsed.problems.push(Problem::new(todo.line, "the todo is never ended with END:VTODO"));
+              }
+            }
+          }
+          if ended == component {
+            break;
+          }
+        }
+      }
+      _ => {
+        // Properties of alarms and the like inside a todo aren't the todo's.
+        let is_in_todo = components.last().map(|c| c == "VTODO").unwrap_or(false);
+        if let Some(todo) = may_todo.as_mut().filter(|_| is_in_todo) {
+          if let Err(message) = todo.read(&property) {
+            todo.is_malformed = todo.is_malformed || property.name != "RRULE";
+            parsed.problems.push(Problem::new(number, message));
+          }
+        }
+      }
+    }
+  }
+  if let Some(todo) = may_todo {
+    parsed.problems.push(Problem::new(todo.line, "the todo is never ended with END:VTODO"));
+  }
+
+  // UIDs that are ids are kept, others get ids of their own.
+  let mut ids:HashMap<String, Uuid> = HashMap::new();
+  for todo in todos.iter_mut() {
+    if let Some(uid) = todo.uid.as_ref() {
+      if let Ok(id) = Uuid::parse_str(uid) {
+        todo.item.id = id;
+      }
+      ids.insert(uid.clone(), todo.item.id);
+    }
+  }
+  for mut todo in todos {
+    if !todo.has_title {
+      parsed.problems.push(Problem::new(todo.line, "the todo has no title"));
+      continue;
+    }
+    if todo.is_malformed {
+      continue;
+    }
+    todo.item.parent = todo.parent_uid.and_then(|uid| ids.get(&uid).cloned());
+    parsed.items.push(todo.item);
+  }
+  parsed.problems.sort_by_key(|problem| problem.line);
+  parsed
+}
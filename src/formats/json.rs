@@ -0,0 +1,35 @@
+//! Todos as pretty printed JSON, in the store's own layout so that files from
+//! older versions of the app still read.
+use serde_json::Value;
+
+use super::{Parsed, Problem};
+use crate::store::{schema, Item};
+
+
+pub fn write(items: &[Item]) -> String {
+  // Items are plain data, serializing them can't fail.
+  schema::encode_pretty(items)
+    .expect("todos always serialize")
+}
+
+
+pub fn read(text: &str) -> Parsed {
+  // Parsing on its own first tells us where the JSON breaks.
+  if let Err(e) = serde_json::from_str::<Value>(text) {
+    let message = e.to_string();
+    let message = message.split(" at line ").next().unwrap_or(&message);
+    return Parsed {
+      items: vec![],
+      problems: vec![Problem::new(e.line().max(1), message)]
+    };
+  }
+  match schema::decode(text) {
+    Ok(items) => { Parsed { items, problems: vec![] } }
+    Err(e) => {
+      Parsed {
+        items: vec![],
+        problems: vec![Problem::new(1, e)]
+      }
+    }
+  }
+}
@@ -0,0 +1,195 @@
+//! Todos as a GitHub-flavored Markdown checklist:
+//!
+//! ```markdown
+//! - [x] Walk the dog #home !1
+//! - [ ] Buy milk @2026-11-01 *
+//!   Oat, not dairy.
+//!   Two cartons.
+//!   - [ ] Find the oat kind
+//! ```
+//!
+//! A todo's fields other than its title are written in quick-add syntax, and
+//! words of its title that quick-add would read as fields are escaped. Its
+//! notes are the lines indented under it and its subtasks are the todos
+//! indented under it. A note line that would read as a todo, or that starts
+//! with `\`, is written with a `\` in front. Headings are skipped when
+//! reading.
+use std::collections::HashSet;
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+use super::{Parsed, Problem};
+use crate::quick_add;
+use crate::store::{Item, Priority};
+
+
+/// How wide the indentation of a line is, a tab counting as four spaces.
+fn indent_of(line: &str) -> usize {
+  line
+    .chars()
+    .take_while(|c| c.is_whitespace())
+    .map(|c| if c == '\t' { 4 } else { 1 })
+    .sum()
+}
+
+
+/// `line` without up to `width` of its indentation.
+fn dedent(line: &str, width: usize) -> &str {
+  let mut taken = 0;
+  for (at, c) in line.char_indices() {
+    if taken >= width || !c.is_whitespace() {
+      return &line[at..];
+    }
+    taken += if c == '\t' { 4 } else { 1 };
+  }
+  ""
+}
+
+
+/// The text after a todo's checkbox in quick-add syntax.
+fn quick_add_text(item: &Item) -> String {
+  let mut words = vec![quick_add::escape_title(&item.title)];
+  words.extend(item.tags.iter().map(|tag| format!("#{}", tag)));
+  match item.priority {
+    Priority::High => { words.push("!1".to_string()); }
+    Priority::Medium => { words.push("!2".to_string()); }
+    Priority::Low => { words.push("!3".to_string()); }
+    Priority::None => {}
+  }
+  if let Some(due) = item.due {
+    words.push(format!("@{}", due));
+  }
+  if item.starred {
+    words.push("*".to_string());
+  }
+  words.join(" ")
+}
+
+
+fn write_item(items: &[Item], item: &Item, depth: usize, written: &mut HashSet<Uuid>, text: &mut String) {
+  if !written.insert(item.id) {
+    return;
+  }
+  let indent = "  ".repeat(depth);
+  let check = if item.completed { "x" } else { " " };
+  text.push_str(&format!("{}- [{}] {}\n", indent, check, quick_add_text(item)));
+  if !item.notes.is_empty() {
+    for line in item.notes.split('\n') {
+      let content = line.trim_start();
+      let escape =
+        if is_checklist_item(content) || content.starts_with('\\') {
+          "\\"
+        } else {
+          ""
+        };
+      let (space, content) = line.split_at(line.len() - content.len());
+      text.push_str(&format!("{}  {}{}{}\n", indent, space, escape, content));
+    }
+  }
+  items
+    .iter()
+    .filter(|child| child.parent == Some(item.id))
+    .for_each(|child| write_item(items, child, depth + 1, written, text));
+}
+
+
+pub fn write(items: &[Item]) -> String {
+  let ids:HashSet<Uuid> = items.iter().map(|item| item.id).collect();
+  let mut written = HashSet::new();
+  let mut text = String::new();
+  items
+    .iter()
+    .filter(|item| item.parent.map(|parent| !ids.contains(&parent)).unwrap_or(true))
+    .for_each(|item| write_item(items, item, 0, &mut written, &mut text));
+  // Todos whose parents loop are never below a top level todo.
+  items
+    .iter()
+    .for_each(|item| write_item(items, item, 0, &mut written, &mut text));
+  text
+}
+
+
+/// Whether a line, without its indentation, is a checklist item.
+pub fn is_checklist_item(line: &str) -> bool {
+  checklist_item(line).is_some()
+}
+
+
+/// Whether a line is a checklist item, and if so whether it's checked and
+/// the text after its checkbox.
+fn checklist_item(line: &str) -> Option<(bool, &str)> {
+  let rest =
+    line
+    .strip_prefix("- ")
+    .or_else(|| line.strip_prefix("* "))
+    .or_else(|| line.strip_prefix("+ "))?;
+  let (checked, rest) =
+    if let Some(rest) = rest.strip_prefix("[ ]") {
+      (false, rest)
+    } else if let Some(rest) = rest.strip_prefix("[x]").or_else(|| rest.strip_prefix("[X]")) {
+      (true, rest)
+    } else {
+      return None;
+    };
+  if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+    Some((checked, rest.trim()))
+  } else {
+    None
+  }
+}
+
+
+pub fn read(text: &str, today: NaiveDate) -> Parsed {
+  let mut parsed = Parsed::default();
+  // The indentation and id of each todo the next one could be a subtask of.
+  let mut parents:Vec<(usize, Uuid)> = vec![];
+  // The indentation of the latest todo and blank lines since its last note.
+  let mut latest:Option<(usize, usize)> = None;
+
+  for (n, line) in text.lines().enumerate() {
+    let number = n + 1;
+    let indent = indent_of(line);
+    let content = line.trim();
+    if let Some((checked, rest)) = checklist_item(content) {
+      while parents.last().map(|(at, _)| *at >= indent).unwrap_or(false) {
+        parents.pop();
+      }
+      let quick = quick_add::parse(rest, today);
+      parsed
+        .problems
+        .extend(quick.problems.iter().map(|problem| Problem::new(number, problem.to_string())));
+      if quick.title.is_empty() {
+        parsed.problems.push(Problem::new(number, "the todo has no title"));
+        latest = None;
+        continue;
+      }
+      let mut item = quick.into_item();
+      item.completed = checked;
+      item.parent = parents.last().map(|(_, id)| *id);
+      parents.push((indent, item.id));
+      parsed.items.push(item);
+      latest = Some((indent, 0));
+    } else if content.is_empty() {
+      if let Some((_, blanks)) = latest.as_mut() {
+        *blanks += 1;
+      }
+    } else if let Some((at, blanks)) = latest.filter(|(at, _)| indent > *at) {
+      let item = parsed.items.last_mut().expect("the latest todo was read");
+      if !item.notes.is_empty() {
+        item.notes.push_str(&"\n".repeat(blanks + 1));
+      }
+      let note = dedent(line, at + 2).trim_end();
+      let content = note.trim_start();
+      let (space, content) = note.split_at(note.len() - content.len());
+      item.notes.push_str(space);
+      item.notes.push_str(content.strip_prefix('\\').unwrap_or(content));
+      latest = Some((at, 0));
+    } else if indent == 0 && content.starts_with('#') {
+      // A heading, eg. the list's name.
+      latest = None;
+    } else {
+      parsed.problems.push(Problem::new(number, "not a checklist item like \"- [ ] Buy milk\""));
+    }
+  }
+  parsed
+}
@@ -0,0 +1,174 @@
+//! Todos in the todo.txt format, one per line:
+//!
+//! ```text
+//! x Walk the dog +home +pets pri:A
+//! (B) Buy milk +errands due:2026-11-01
+//! ```
+//!
+//! Priorities A, B and C are high, medium and low, and a completed todo
+//! keeps its priority as `pri:`. Tags are written as projects, contexts are
+//! read as tags too. Creation and completion dates are skipped when reading,
+//! as are key:value pairs other than `due:` and `pri:`, which stay in the
+//! title.
+//!
+//! Words of a title that would be read as anything else are written with a
+//! `\` in front, which is taken off again when reading, eg. `\x marks the
+//! spot` or `Email \+1 \@support`.
+use chrono::NaiveDate;
+
+use super::{Parsed, Problem};
+use crate::model::normalize_tag;
+use crate::store::{Item, Priority};
+
+
+fn letter(priority: Priority) -> Option<char> {
+  match priority {
+    Priority::High => { Some('A') }
+    Priority::Medium => { Some('B') }
+    Priority::Low => { Some('C') }
+    Priority::None => { None }
+  }
+}
+
+
+/// The priority of a letter. Letters after C are all low.
+fn from_letter(letter: char) -> Option<Priority> {
+  match letter {
+    'A' => { Some(Priority::High) }
+    'B' => { Some(Priority::Medium) }
+    'C' ..= 'Z' => { Some(Priority::Low) }
+    _ => { None }
+  }
+}
+
+
+/// Whether a word of a title would be read as something else. The first
+/// word can also be taken for a completion, a priority or a date.
+fn is_syntax(word: &str, is_first: bool) -> bool {
+  word.starts_with(|c| ['+', '@', '\\'].contains(&c))
+    || word.starts_with("due:")
+    || word.starts_with("pri:")
+    || (is_first && (word == "x" || parse_letter(word).is_some() || is_date(word)))
+}
+
+
+/// `title` with each word that would be read as something else escaped.
+fn escape_title(title: &str) -> String {
+  title
+    .split_whitespace()
+    .enumerate()
+    .map(|(n, word)| if is_syntax(word, n == 0) { format!("\\{}", word) } else { word.to_string() })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+
+fn write_item(item: &Item) -> String {
+  let mut words = vec![];
+  if item.completed {
+    words.push("x".to_string());
+  } else if let Some(letter) = letter(item.priority) {
+    words.push(format!("({})", letter));
+  }
+  words.push(escape_title(&item.title));
+  words.extend(item.tags.iter().map(|tag| format!("+{}", tag)));
+  if let Some(due) = item.due {
+    words.push(format!("due:{}", due));
+  }
+  if item.completed {
+    if let Some(letter) = letter(item.priority) {
+      words.push(format!("pri:{}", letter));
+    }
+  }
+  words.join(" ")
+}
+
+
+pub fn write(items: &[Item]) -> String {
+  items
+    .iter()
+    .map(|item| format!("{}\n", write_item(item)))
+    .collect()
+}
+
+
+fn is_date(word: &str) -> bool {
+  NaiveDate::parse_from_str(word, "%Y-%m-%d").is_ok()
+}
+
+
+/// A priority written as `(A)`.
+fn parse_letter(word: &str) -> Option<Priority> {
+  let mut chars = word.chars();
+  match (chars.next(), chars.next(), chars.next(), chars.next()) {
+    (Some('('), Some(letter), Some(')'), None) => { from_letter(letter) }
+    _ => { None }
+  }
+}
+
+
+fn read_line(line: &str) -> Result<Item, String> {
+  let mut words = line.split_whitespace().peekable();
+  let completed = words.next_if_eq(&"x").is_some();
+  let mut priority = words.next_if(|word| parse_letter(word).is_some()).and_then(parse_letter);
+  // A completion date then a creation date, or just a creation date.
+  let dates = if completed { 2 } else { 1 };
+  for _ in 0..dates {
+    words.next_if(|word| is_date(word));
+  }
+
+  let mut title = vec![];
+  let mut tags:Vec<String> = vec![];
+  let mut due = None;
+  for word in words {
+    if let Some(word) = word.strip_prefix('\\') {
+      title.push(word);
+    } else if let Some(date) = word.strip_prefix("due:") {
+      due = Some(
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+          .map_err(|_| format!("{:?} is not a date like 2026-11-01", date))?
+      );
+    } else if let Some(letter) = word.strip_prefix("pri:") {
+      let mut chars = letter.chars();
+      priority =
+        match (chars.next(), chars.next()) {
+          (Some(letter), None) => { from_letter(letter) }
+          _ => { None }
+        };
+      if priority.is_none() {
+        return Err(format!("{:?} is not a priority from A to Z", letter));
+      }
+    } else if let Some(tag) = word.strip_prefix('+').or_else(|| word.strip_prefix('@')).and_then(normalize_tag) {
+      if !tags.contains(&tag) {
+        tags.push(tag);
+      }
+    } else {
+      title.push(word);
+    }
+  }
+  if title.is_empty() {
+    return Err("the todo has no title".to_string());
+  }
+  Ok(Item {
+    completed,
+    priority: priority.unwrap_or_default(),
+    tags,
+    due,
+    ..Item::new(title.join(" "))
+  })
+}
+
+
+pub fn read(text: &str) -> Parsed {
+  let mut parsed = Parsed::default();
+  for (n, line) in text.lines().enumerate() {
+    if line.trim().is_empty() {
+      continue;
+    }
+    match read_line(line) {
+      Ok(item) => { parsed.items.push(item); }
+      Err(message) => { parsed.problems.push(Problem::new(n + 1, message)); }
+    }
+  }
+  parsed
+}
@@ -0,0 +1,236 @@
+//! Undo and redo for changes to the todo list.
+//!
+//! Every change a user makes is recorded as a `Change` that knows how to
+//! invert itself. Undoing hands back the inverse of the latest change for the
+//! app to apply, redoing hands back the change itself.
+use std::collections::VecDeque;
+use chrono::NaiveDate;
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use super::recurrence::Recurrence;
+use super::store::{Item, ListInfo, Priority};
+
+
+/// How many changes are remembered before the oldest are forgotten.
+pub const DEFAULT_CAPACITY: usize = 100;
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Change {
+  /// A todo was inserted at `position` in the list.
+  Create { position: usize, item: Item },
+  /// A todo's title changed.
+  Rename { id: Uuid, from: String, to: String },
+  /// A todo's completion changed.
+  Complete { id: Uuid, from: bool, to: bool },
+  /// A todo's due date changed.
+  Due { id: Uuid, from: Option<NaiveDate>, to: Option<NaiveDate> },
+  /// A todo's priority changed.
+  Prioritize { id: Uuid, from: Priority, to: Priority },
+  /// A todo's tags changed.
+  Tag { id: Uuid, from: Vec<String>, to: Vec<String> },
+  /// A todo was starred or unstarred.
+  Star { id: Uuid, from: bool, to: bool },
+  /// A todo's notes changed.
+  Note { id: Uuid, from: String, to: String },
+  /// How often a todo repeats changed.
+  Repeat { id: Uuid, from: Option<Recurrence>, to: Option<Recurrence> },
+  /// The todo at `position` was removed.
+  Remove { position: usize, item: Item },
+  /// A todo was moved from `from` to `to` in the list, `to` being where it
+  /// sits afterwards.
+  Move { id: Uuid, from: usize, to: usize },
+  /// A named list was inserted at `position` among the lists.
+  AddList { position: usize, list: ListInfo },
+  /// A named list was renamed, which can change its slug.
+  RenameList { from: ListInfo, to: ListInfo },
+  /// The named list at `position` was removed. Its todos are removed by
+  /// changes of their own.
+  RemoveList { position: usize, list: ListInfo },
+  /// Several changes made at once, in the order they were applied.
+  Batch(Vec<Change>)
+}
+
+
+impl Change {
+  /// The change that puts things back the way they were.
+  pub fn inverse(&self) -> Change {
+    match self {
+      Change::Create { position, item } => {
+        Change::Remove { position: *position, item: item.clone() }
+      }
+      Change::Rename { id, from, to } => {
+        Change::Rename { id: *id, from: to.clone(), to: from.clone() }
+      }
+      Change::Complete { id, from, to } => {
+        Change::Complete { id: *id, from: *to, to: *from }
+      }
+      Change::Due { id, from, to } => {
+        Change::Due { id: *id, from: *to, to: *from }
+      }
+      Change::Prioritize { id, from, to } => {
+        Change::Prioritize { id: *id, from: *to, to: *from }
+      }
+      Change::Tag { id, from, to } => {
+        Change::Tag { id: *id, from: to.clone(), to: from.clone() }
+      }
+      Change::Star { id, from, to } => {
+        Change::Star { id: *id, from: *to, to: *from }
+      }
+      Change::Note { id, from, to } => {
+        Change::Note { id: *id, from: to.clone(), to: from.clone() }
+      }
+      Change::Repeat { id, from, to } => {
+        Change::Repeat { id: *id, from: to.clone(), to: from.clone() }
+      }
+      Change::Remove { position, item } => {
+        Change::Create { position: *position, item: item.clone() }
+      }
+      Change::Move { id, from, to } => {
+        Change::Move { id: *id, from: *to, to: *from }
+      }
+      Change::AddList { position, list } => {
+        Change::RemoveList { position: *position, list: list.clone() }
+      }
+      Change::RenameList { from, to } => {
+        Change::RenameList { from: to.clone(), to: from.clone() }
+      }
+      Change::RemoveList { position, list } => {
+        Change::AddList { position: *position, list: list.clone() }
+      }
+      Change::Batch(changes) => {
+        Change::Batch(
+          changes
+            .iter()
+            .rev()
+            .map(Change::inverse)
+            .collect()
+        )
+      }
+    }
+  }
+
+  /// Whether this change threw away a todo or a list.
+  pub fn is_destructive(&self) -> bool {
+    match self {
+      Change::Remove { .. } | Change::RemoveList { .. } => { true }
+      Change::Batch(changes) => { changes.iter().any(Change::is_destructive) }
+      _ => { false }
+    }
+  }
+
+  /// A short description for the undo toast.
+  pub fn describe(&self) -> String {
+    match self {
+      Change::Create { item, .. } => { format!("Added \"{}\"", item.title) }
+      Change::Rename { to, .. } => { format!("Renamed to \"{}\"", to) }
+      Change::Complete { to: true, .. } => { "Completed a todo".to_string() }
+      Change::Complete { to: false, .. } => { "Reopened a todo".to_string() }
+      Change::Due { to: Some(due), .. } => { format!("Due {}", due) }
+      Change::Due { to: None, .. } => { "Removed a due date".to_string() }
+      Change::Prioritize { to, .. } => { format!("Changed priority to {}", to.name()) }
+      Change::Tag { .. } => { "Changed tags".to_string() }
+      Change::Star { to: true, .. } => { "Starred a todo".to_string() }
+      Change::Star { to: false, .. } => { "Unstarred a todo".to_string() }
+      Change::Note { to, .. } if to.is_empty() => { "Cleared notes".to_string() }
+      Change::Note { .. } => { "Changed notes".to_string() }
+      Change::Repeat { to: Some(rule), .. } => { format!("Repeats {}", rule) }
+      Change::Repeat { to: None, .. } => { "Stopped repeating".to_string() }
+      Change::Remove { item, .. } => { format!("Deleted \"{}\"", item.title) }
+      Change::Move { .. } => { "Moved a todo".to_string() }
+      Change::AddList { list, .. } => { format!("Added the list \"{}\"", list.name) }
+      Change::RenameList { to, .. } => { format!("Renamed the list to \"{}\"", to.name) }
+      Change::RemoveList { list, .. } => { format!("Deleted the list \"{}\"", list.name) }
+      Change::Batch(changes) => {
+        // Deleting a list takes its todos along, the list is what matters.
+        let may_list =
+          changes
+          .iter()
+          .find(|change| matches!(change, Change::RemoveList { .. }));
+        if let Some(change) = may_list {
+          return change.describe();
+        }
+        let removed =
+          changes
+          .iter()
+          .filter(|change| change.is_destructive())
+          .count();
+        if removed > 0 {
+          format!("Deleted {} {}", removed, if removed == 1 { "todo" } else { "todos" })
+        } else {
+          format!("Changed {} {}", changes.len(), if changes.len() == 1 { "todo" } else { "todos" })
+        }
+      }
+    }
+  }
+}
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct History {
+  capacity: usize,
+  undo: VecDeque<Change>,
+  redo: Vec<Change>
+}
+
+
+impl Default for History {
+  fn default() -> History {
+    History::new(DEFAULT_CAPACITY)
+  }
+}
+
+
+impl History {
+  pub fn new(capacity: usize) -> History {
+    History {
+      capacity,
+      undo: VecDeque::new(),
+      redo: vec![]
+    }
+  }
+
+  /// Remember a change the user just made. This forgets anything that could
+  /// have been redone. Empty batches are ignored.
+  pub fn record(&mut self, change: Change) {
+    if let Change::Batch(changes) = &change {
+      if changes.is_empty() {
+        return;
+      }
+    }
+    self.redo.clear();
+    self.undo.push_back(change);
+    while self.undo.len() > self.capacity {
+      self.undo.pop_front();
+    }
+  }
+
+  /// The change to apply in order to undo the latest change, if any.
+  pub fn undo(&mut self) -> Option<Change> {
+    let change = self.undo.pop_back()?;
+    let inverse = change.inverse();
+    self.redo.push(change);
+    Some(inverse)
+  }
+
+  /// The change to apply in order to redo the latest undone change, if any.
+  pub fn redo(&mut self) -> Option<Change> {
+    let change = self.redo.pop()?;
+    self.undo.push_back(change.clone());
+    Some(change)
+  }
+
+  /// The change that `undo` would revert.
+  pub fn latest(&self) -> Option<&Change> {
+    self.undo.back()
+  }
+
+  pub fn can_undo(&self) -> bool {
+    !self.undo.is_empty()
+  }
+
+  pub fn can_redo(&self) -> bool {
+    !self.redo.is_empty()
+  }
+}
@@ -1,17 +1,30 @@
 #[macro_use]
 extern crate log;
+extern crate chrono;
 extern crate console_log;
 extern crate console_error_panic_hook;
+extern crate js_sys;
 extern crate mogwai;
 extern crate serde;
 extern crate serde_json;
+extern crate uuid;
 
 mod utils;
-mod store;
-
-mod app;
-use app::{App, In};
-
+pub mod collab;
+pub mod formats;
+pub mod history;
+pub mod model;
+pub mod quick_add;
+pub mod recurrence;
+pub mod search;
+pub mod store;
+pub mod sync;
+use store::{IdbStore, Item, LocalStore, StoreError, SyncStore, TodoStore};
+
+pub mod app;
+use app::{App, Collaboration, In};
+
+use std::rc::Rc;
 use log::Level;
 use mogwai::prelude::*;
 use wasm_bindgen::prelude::*;
@@ -34,12 +47,120 @@ pub fn main() -> Result<(), JsValue> {
 
   trace!("Hello from mogwai-todo");
 
-  // Get the any items stored from a previous visit
-  let mut msgs =
-    store::read_items()?
+  run(Rc::new(LocalStore::new(store::KEY)));
+  Ok(())
+}
+
+
+/// Like `main`, but keeps todos in IndexedDB instead of localStorage, which
+/// suits very long lists.
+#[wasm_bindgen]
+pub fn main_indexed_db() -> Result<(), JsValue> {
+  utils::set_panic_hook();
+
+  console_log::init_with_level(Level::Trace)
+    .unwrap();
+
+  IdbStore::open(store::KEY, Box::new(|result| {
+    match result {
+      Ok(store) => { run(Rc::new(store)) }
+      Err(e) => { error!("Could not open IndexedDB: {}", e) }
+    }
+  }));
+  Ok(())
+}
+
+
+/// Like `main`, but also syncs todos through the sync server at `url`, eg.
+/// "http://127.0.0.1:8080". Changes made offline are pushed once the server
+/// can be reached.
+#[wasm_bindgen]
+pub fn main_synced(url: String) -> Result<(), JsValue> {
+  utils::set_panic_hook();
+
+  console_log::init_with_level(Level::Trace)
+    .unwrap();
+
+  let local = Rc::new(LocalStore::new(store::KEY));
+  run(Rc::new(SyncStore::new(local, &url, store::KEY)));
+  Ok(())
+}
+
+
+/// Like `main`, but also shares changes live with everyone in the relay room
+/// at `url`, eg. "ws://127.0.0.1:9001/team", showing them as `name`.
+#[wasm_bindgen]
+pub fn main_shared(url: String, name: String) -> Result<(), JsValue> {
+  utils::set_panic_hook();
+
+  console_log::init_with_level(Level::Trace)
+    .unwrap();
+
+  launch(Rc::new(LocalStore::new(store::KEY)), Some((url, name)));
+  Ok(())
+}
+
+
+/// Run the app, restoring from and persisting into the given store.
+///
+/// This is the entry point for embedders that want their todos kept somewhere
+/// other than localStorage. The app is mounted once the store has loaded.
+pub fn run(store: Rc<dyn TodoStore>) {
+  launch(store, None);
+}
+
+
+/// Run the app, joining the room at the given URL under the given name, if
+/// any, once the store has loaded.
+fn launch(store: Rc<dyn TodoStore>, may_room: Option<(String, String)>) {
+  let app_store = store.clone();
+  store.load(Box::new(move |result| {
+    let may_collaboration =
+      may_room
+      .as_ref()
+      .map(|(url, name)| Collaboration::join(url, name));
+    start(app_store, result, may_collaboration)
+      .unwrap_or_else(|e| error!("Could not start mogwai-todo: {:?}", e));
+  }));
+}
+
+
+fn start(
+  store: Rc<dyn TodoStore>,
+  loaded: Result<Vec<Item>, StoreError>,
+  may_collaboration: Option<Collaboration>
+) -> Result<(), JsValue> {
+  // Get the any items stored from a previous visit. If they can't be read we
+  // set them aside and start with an empty list instead of failing forever.
+  let (items, may_corrupt) =
+    match loaded {
+      Ok(items) => { (items, None) }
+      Err(StoreError::Corrupt { raw, reason }) => {
+        warn!("Starting with no todos, {}", reason);
+        store
+          .quarantine(&raw)
+          .unwrap_or_else(|e| error!("Could not quarantine corrupt todos: {}", e));
+        (vec![], Some(raw))
+      }
+      Err(e) => { return Err(e.into()); }
+    };
+
+  // The date goes first so todos are known to be overdue as they load.
+  let mut msgs = vec![In::Today(utils::today())];
+  // Lists go before their todos, so no todo lands in a list we don't know.
+  store
+    .load_lists()
+    .into_iter()
+    .for_each(|lists| msgs.push(In::LoadLists(lists)));
+  msgs.extend(
+    items
+      .into_iter()
+      .map(In::LoadTodo)
+  );
+
+  may_corrupt
     .into_iter()
-    .map(|item| In::NewTodo(item.title, item.completed))
-    .collect::<Vec<_>>();
+    .for_each(|raw| msgs.push(In::StoreCorrupted(raw)));
 
   // Get the hash for "routing"
   let hash =
@@ -47,13 +168,51 @@ pub fn main() -> Result<(), JsValue> {
     .location()
     .hash()?;
 
-  App::url_to_filter_msg(hash)
+  App::url_to_route_msg(hash)
     .into_iter()
     .for_each(|msg| msgs.push(msg));
 
-  App::new()
-    .into_component()
-    .run_init(msgs)?;
+  let mut app =
+    App::new(store.clone())
+    .with_persisted_history();
+  if let Some(collaboration) = may_collaboration.as_ref() {
+    app = app.with_collaboration(collaboration.clone());
+  }
+  let component = app.into_component();
+
+  // Todos changed elsewhere, eg. on a sync server, are merged in like those
+  // from another tab.
+  let tx_synced = component.trns.clone();
+  store.watch(Box::new(move |items| tx_synced.send(&In::Synced(items))));
+
+  // Collaborators' changes and presence come in as messages too.
+  if let Some(collaboration) = may_collaboration {
+    let tx_remote = component.trns.clone();
+    collaboration.set_on_remote(Box::new(move |remote| tx_remote.send(&App::remote_msg(remote))));
+  }
+
+  // Changes are written to the store in batches, at most every half second,
+  // rather than on every message. The app also flushes on "beforeunload".
+  let tx_flush = component.trns.clone();
+  timeout(500, move || {
+    tx_flush.send(&In::Flush);
+    true
+  });
+
+  // Once a minute is plenty to notice the date rolling over, and the app is
+  // only told when it has.
+  let tx_today = component.trns.clone();
+  let mut last_day = utils::today();
+  timeout(60_000, move || {
+    let today = utils::today();
+    if today != last_day {
+      last_day = today;
+      tx_today.send(&In::Today(today));
+    }
+    true
+  });
+
+  component.run_init(msgs)?;
 
   // The footer has no relation to the rest of the app and is simply a view
   // attached to the body
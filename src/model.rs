@@ -0,0 +1,1633 @@
+//! The todo list with none of the DOM attached.
+//!
+//! `TodoList` holds the todos and applies the rules of TodoMVC to them. The
+//! mogwai components send it `Command`s and render the `Event`s it hands back,
+//! so everything in here builds and tests natively with a plain `cargo test`.
+//!
+//! Todos can have subtasks. The list is kept flat with each subtask pointing
+//! at its parent, and a todo with subtasks is completed exactly when all of
+//! them are.
+//!
+//! Todos are also kept in named lists. Only the active list's todos are
+//! shown and counted, and each list remembers its own filter, tag, search and
+//! sort.
+//!
+//! Completing a repeating todo leaves it completed and adds its next
+//! occurrence, see `recurrence`.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+use super::history::{Change, History};
+use super::recurrence::Recurrence;
+use super::search;
+use super::store::{Item, ListInfo, Priority};
+
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum FilterShow {
+  #[default]
+  All,
+  Completed,
+  Active,
+  /// Open todos due today.
+  Today,
+  /// Open todos past their due date.
+  Overdue,
+  /// Open todos due after today.
+  Upcoming
+}
+
+
+impl FilterShow {
+  /// Whether a todo shows up under this filter on the day `today`.
+  pub fn shows(&self, item: &Item, today: NaiveDate) -> bool {
+    match self {
+      FilterShow::All => { true }
+      FilterShow::Completed => { item.completed }
+      FilterShow::Active => { !item.completed }
+      FilterShow::Today => { !item.completed && item.due == Some(today) }
+      FilterShow::Overdue => { item.is_overdue(today) }
+      FilterShow::Upcoming => {
+        !item.completed && item.due.map(|due| due > today).unwrap_or(false)
+      }
+    }
+  }
+
+  /// The filter a url's hash routes to, if any.
+  pub fn from_url(url: &str) -> Option<FilterShow> {
+    Route::from_url(url)
+      .map(|route| route.filter)
+  }
+
+  fn from_path(path: &str) -> Option<FilterShow> {
+    match path {
+      "#/" => { Some(FilterShow::All) }
+      "#/active" => { Some(FilterShow::Active) }
+      "#/completed" => { Some(FilterShow::Completed) }
+      "#/today" => { Some(FilterShow::Today) }
+      "#/overdue" => { Some(FilterShow::Overdue) }
+      "#/upcoming" => { Some(FilterShow::Upcoming) }
+      _ => { None }
+    }
+  }
+
+  pub fn path(&self) -> &'static str {
+    match self {
+      FilterShow::All => { "#/" }
+      FilterShow::Active => { "#/active" }
+      FilterShow::Completed => { "#/completed" }
+      FilterShow::Today => { "#/today" }
+      FilterShow::Overdue => { "#/overdue" }
+      FilterShow::Upcoming => { "#/upcoming" }
+    }
+  }
+}
+
+
+/// The order todos are shown in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SortBy {
+  /// The order they were added in, which is the order they're saved in.
+  #[default]
+  Added,
+  /// Highest priority first, ties keep the order they were added in.
+  Priority
+}
+
+
+/// A tag as it is stored: without the leading '#', lowercase and made of
+/// letters, digits, '-' and '_'. Anything else isn't a tag.
+pub fn normalize_tag(tag: &str) -> Option<String> {
+  let tag = tag.trim();
+  let tag = tag.strip_prefix('#').unwrap_or(tag);
+  let is_tag =
+    !tag.is_empty()
+    && tag.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+  if is_tag {
+    Some(tag.to_lowercase())
+  } else {
+    None
+  }
+}
+
+
+/// Tags typed by the user, separated by spaces or commas, with or without
+/// their '#'. Invalid tags and repeats are dropped.
+pub fn parse_tags(text: &str) -> Vec<String> {
+  let mut tags:Vec<String> = vec![];
+  text
+    .split(|c: char| c.is_whitespace() || c == ',')
+    .filter_map(normalize_tag)
+    .for_each(|tag| {
+      if !tags.contains(&tag) {
+        tags.push(tag);
+      }
+    });
+  tags
+}
+
+
+/// A list's name as it goes in the url: lowercase ascii letters and digits
+/// with runs of anything else turned into a single '-'.
+pub fn slugify(name: &str) -> String {
+  name
+    .to_lowercase()
+    .split(|c: char| !c.is_ascii_alphanumeric())
+    .filter(|word| !word.is_empty())
+    .collect::<Vec<_>>()
+    .join("-")
+}
+
+
+/// Percent-encode everything but unreserved characters, so a value can sit
+/// in the hash's query.
+fn encode_component(value: &str) -> String {
+  value
+    .bytes()
+    .map(|b| {
+      if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+        (b as char).to_string()
+      } else {
+        format!("%{:02X}", b)
+      }
+    })
+    .collect()
+}
+
+
+fn decode_component(value: &str) -> Option<String> {
+  let bytes = value.as_bytes();
+  let mut decoded = vec![];
+  let mut i = 0;
+  while i < bytes.len() {
+    if bytes[i] == b'%' {
+      let hex = value.get(i + 1..i + 3)?;
+      decoded.push(u8::from_str_radix(hex, 16).ok()?);
+      i += 3;
+    } else {
+      decoded.push(bytes[i]);
+      i += 1;
+    }
+  }
+  String::from_utf8(decoded).ok()
+}
+
+
+/// Everything about the view that lives in the url's hash, eg.
+/// `#/lists/work/active?tag=urgent&q=milk&sort=priority`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Route {
+  /// The slug of the list to show. The first list has no slug in the url.
+  pub list: Option<String>,
+  pub filter: FilterShow,
+  /// Only show todos with this tag.
+  pub tag: Option<String>,
+  /// Only show todos that match this search.
+  pub query: Option<String>,
+  pub sort: SortBy
+}
+
+
+impl Route {
+  /// The route in a url's hash, if it has one we know. Unknown query
+  /// parameters are ignored.
+  pub fn from_url(url: &str) -> Option<Route> {
+    let ndx = url.find('#')?;
+    let (_, hash) = url.split_at(ndx);
+    let (path, query) =
+      match hash.find('?') {
+        Some(ndx) => { (&hash[..ndx], &hash[ndx + 1..]) }
+        None => { (hash, "") }
+      };
+    let (list, filter_path) =
+      match path.strip_prefix("#/lists/") {
+        Some(rest) => {
+          let mut parts = rest.splitn(2, '/');
+          let slug = slugify(parts.next()?);
+          if slug.is_empty() {
+            return None;
+          }
+          (Some(slug), format!("#/{}", parts.next().unwrap_or("")))
+        }
+        None => { (None, path.to_string()) }
+      };
+    let mut route = Route {
+      list,
+      filter: FilterShow::from_path(&filter_path)?,
+      ..Route::default()
+    };
+    for param in query.split('&') {
+      let mut kv = param.splitn(2, '=');
+      match (kv.next(), kv.next()) {
+        (Some("sort"), Some("priority")) => {
+          route.sort = SortBy::Priority;
+        }
+        (Some("tag"), Some(tag)) => {
+          route.tag =
+            decode_component(tag)
+            .as_deref()
+            .and_then(normalize_tag);
+        }
+        (Some("q"), Some(query)) => {
+          route.query =
+            decode_component(query)
+            .filter(|query| !query.trim().is_empty());
+        }
+        _ => {}
+      }
+    }
+    Some(route)
+  }
+
+  pub fn to_hash(&self) -> String {
+    let mut params = vec![];
+    if let Some(tag) = &self.tag {
+      params.push(format!("tag={}", encode_component(tag)));
+    }
+    if let Some(query) = &self.query {
+      params.push(format!("q={}", encode_component(query)));
+    }
+    if self.sort == SortBy::Priority {
+      params.push("sort=priority".to_string());
+    }
+    let path =
+      match &self.list {
+        Some(slug) => { format!("#/lists/{}{}", slug, &self.filter.path()[1..]) }
+        None => { self.filter.path().to_string() }
+      };
+    if params.is_empty() {
+      path
+    } else {
+      format!("{}?{}", path, params.join("&"))
+    }
+  }
+
+  /// This route, in the list with `slug` instead.
+  pub fn with_list(&self, list: Option<String>) -> Route {
+    Route { list, ..self.clone() }
+  }
+
+  /// This route, showing `filter` instead.
+  pub fn with_filter(&self, filter: FilterShow) -> Route {
+    Route { filter, ..self.clone() }
+  }
+
+  /// This route, showing only todos tagged `tag` instead.
+  pub fn with_tag(&self, tag: Option<String>) -> Route {
+    Route { tag, ..self.clone() }
+  }
+
+  /// This route, searching for `query` instead.
+  pub fn with_query(&self, query: Option<String>) -> Route {
+    Route { query, ..self.clone() }
+  }
+
+  /// This route, sorted by `sort` instead.
+  pub fn with_sort(&self, sort: SortBy) -> Route {
+    Route { sort, ..self.clone() }
+  }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+  /// The user created a todo, it goes at the end of the list. Subtasks go in
+  /// their parent's named list, other todos in the active one.
+  Add(Item),
+  /// The user imported todos, they go at the end of the list like `Add`
+  /// puts them and are undone together. Parents come before their subtasks.
+  AddAll(Vec<Item>),
+  /// A todo was restored from the store. This isn't undoable.
+  Load(Item),
+  Rename(Uuid, String),
+  SetCompletion(Uuid, bool),
+  SetAllComplete(bool),
+  SetDue(Uuid, Option<NaiveDate>),
+  SetPriority(Uuid, Priority),
+  SetTags(Uuid, Vec<String>),
+  SetStarred(Uuid, bool),
+  /// Replace a todo's notes. Notes of nothing but whitespace are cleared.
+  SetNotes(Uuid, String),
+  /// Make a todo repeat, or stop it repeating.
+  SetRecurrence(Uuid, Option<Recurrence>),
+  Remove(Uuid),
+  RemoveCompleted,
+  /// Move a todo to just before one of its siblings.
+  MoveBefore(Uuid, Uuid),
+  /// Move a todo to just after one of its siblings.
+  MoveAfter(Uuid, Uuid),
+  /// Move a todo above the sibling shown above it.
+  MoveUp(Uuid),
+  /// Move a todo below the sibling shown below it.
+  MoveDown(Uuid),
+  StartEditing(Uuid),
+  StopEditing(Uuid),
+  Filter(FilterShow),
+  /// Only show todos with the tag, or stop filtering by tag.
+  FilterTag(Option<String>),
+  /// Only show todos that match the search, or stop searching.
+  Search(Option<String>),
+  Sort(SortBy),
+  /// The date changed, which can make todos overdue.
+  SetToday(NaiveDate),
+  Undo,
+  Redo,
+  /// Another copy of the list (eg. in another tab) looks like this now.
+  Merge(Vec<Item>),
+  /// A collaborator's copy of a todo looks like this now, it's added if it's
+  /// new. This isn't undoable.
+  Patch(Item),
+  /// A collaborator removed a todo, its subtasks go with it. This isn't
+  /// undoable.
+  Forget(Uuid),
+  /// Make a named list and switch to it.
+  AddList(String),
+  RenameList(Uuid, String),
+  /// Remove a named list along with its todos. The last list can't go.
+  DeleteList(Uuid),
+  /// Show the named list with this id, as it was last shown.
+  SwitchList(Uuid),
+  /// Show what a url asks for, all at once. The first list has no slug, a
+  /// list that isn't there leaves the active one shown.
+  Navigate(Route),
+  /// The named lists as they were saved, or as another tab has them now.
+  /// This isn't undoable.
+  LoadLists(Vec<ListInfo>)
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+  Inserted { position: usize, item: Item, visible: bool },
+  Removed(Uuid),
+  /// The todo at `from` in the list now sits at `to`.
+  Moved { from: usize, to: usize },
+  Renamed(Uuid, String),
+  Completed(Uuid, bool),
+  Due(Uuid, Option<NaiveDate>),
+  Overdue(Uuid, bool),
+  Prioritized(Uuid, Priority),
+  Tagged(Uuid, Vec<String>),
+  Starred(Uuid, bool),
+  Noted(Uuid, String),
+  Recurring(Uuid, Option<Recurrence>),
+  Visible(Uuid, bool),
+  Filtered(FilterShow),
+  TagFiltered(Option<String>),
+  Searched(Option<String>),
+  Sorted(SortBy),
+  /// The todos should now be shown in this order.
+  Reordered(Vec<Uuid>),
+  /// The todos now sit in the list in this order.
+  Arranged(Vec<Uuid>),
+  /// Offer to undo the latest change, described by the text, or withdraw the
+  /// offer with `None`.
+  UndoOffer(Option<String>),
+  /// The named lists are now these.
+  Lists(Vec<ListInfo>),
+  /// The named list with this id is shown now.
+  ListSwitched(Uuid)
+}
+
+
+impl Event {
+  /// Whether this event changed anything that gets saved.
+  pub fn changes_items(&self) -> bool {
+    matches!(
+      self,
+      Event::Inserted { .. }
+      | Event::Removed(_)
+      | Event::Moved { .. }
+      | Event::Arranged(_)
+      | Event::Renamed(_, _)
+      | Event::Completed(_, _)
+      | Event::Due(_, _)
+      | Event::Prioritized(_, _)
+      | Event::Tagged(_, _)
+      | Event::Starred(_, _)
+      | Event::Noted(_, _)
+      | Event::Recurring(_, _)
+      | Event::Lists(_)
+    )
+  }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct TodoList {
+  items: Vec<Item>,
+  /// The named lists, of which there is always at least one.
+  lists: Vec<ListInfo>,
+  /// The id of the named list being shown.
+  active: Uuid,
+  /// The filter, tag, search and sort of each named list that isn't active.
+  views: HashMap<Uuid, Route>,
+  filter: FilterShow,
+  tag: Option<String>,
+  query: Option<String>,
+  sort: SortBy,
+  today: NaiveDate,
+  history: History,
+  /// Todos being edited by the user, which merges leave alone.
+  editing: HashSet<Uuid>
+}
+
+
+impl Default for TodoList {
+  fn default() -> TodoList {
+    let list = ListInfo::default();
+    TodoList {
+      items: vec![],
+      active: list.id,
+      lists: vec![list],
+      views: HashMap::new(),
+      filter: FilterShow::default(),
+      tag: None,
+      query: None,
+      sort: SortBy::default(),
+      today: NaiveDate::default(),
+      history: History::default(),
+      editing: HashSet::new()
+    }
+  }
+}
+
+
+impl TodoList {
+  pub fn new() -> TodoList {
+    TodoList::default()
+  }
+
+  pub fn items(&self) -> &[Item] {
+    &self.items
+  }
+
+  pub fn get(&self, id: &Uuid) -> Option<&Item> {
+    self
+      .items
+      .iter()
+      .find(|item| item.id == *id)
+  }
+
+  pub fn position_of(&self, id: &Uuid) -> Option<usize> {
+    self
+      .items
+      .iter()
+      .position(|item| item.id == *id)
+  }
+
+  pub fn len(&self) -> usize {
+    self.items.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.items.is_empty()
+  }
+
+  /// The todos in the active named list.
+  pub fn list_items(&self) -> Vec<&Item> {
+    self
+      .items
+      .iter()
+      .filter(|item| item.list == self.active)
+      .collect()
+  }
+
+  pub fn lists(&self) -> &[ListInfo] {
+    &self.lists
+  }
+
+  pub fn active_list(&self) -> &ListInfo {
+    self
+      .lists
+      .iter()
+      .find(|list| list.id == self.active)
+      .unwrap_or(&self.lists[0])
+  }
+
+  pub fn list_by_slug(&self, slug: &str) -> Option<&ListInfo> {
+    self
+      .lists
+      .iter()
+      .find(|list| list.slug == slug)
+  }
+
+  /// Where the named list with `id` was last left: its filter, tag, search
+  /// and sort.
+  pub fn route_of(&self, id: &Uuid) -> Option<Route> {
+    let position =
+      self
+      .lists
+      .iter()
+      .position(|list| list.id == *id)?;
+    let view =
+      if *id == self.active {
+        self.view()
+      } else {
+        self.views.get(id).cloned().unwrap_or_default()
+      };
+    let slug =
+      if position == 0 {
+        None
+      } else {
+        Some(self.lists[position].slug.clone())
+      };
+    Some(view.with_list(slug))
+  }
+
+  /// What is being shown right now.
+  pub fn route(&self) -> Route {
+    let id = self.active_list().id;
+    self
+      .route_of(&id)
+      .unwrap_or_default()
+  }
+
+  pub fn filter(&self) -> &FilterShow {
+    &self.filter
+  }
+
+  pub fn tag(&self) -> Option<&String> {
+    self.tag.as_ref()
+  }
+
+  pub fn query(&self) -> Option<&String> {
+    self.query.as_ref()
+  }
+
+  pub fn sort(&self) -> SortBy {
+    self.sort
+  }
+
+  pub fn today(&self) -> NaiveDate {
+    self.today
+  }
+
+  pub fn history(&self) -> &History {
+    &self.history
+  }
+
+  pub fn set_history(&mut self, history: History) {
+    self.history = history;
+  }
+
+  pub fn is_editing(&self, id: &Uuid) -> bool {
+    self.editing.contains(id)
+  }
+
+  /// The subtasks of the todo with `id`, in the order they were added.
+  pub fn children(&self, id: &Uuid) -> Vec<&Item> {
+    self
+      .items
+      .iter()
+      .filter(|item| item.parent == Some(*id))
+      .collect()
+  }
+
+  pub fn has_children(&self, id: &Uuid) -> bool {
+    self
+      .items
+      .iter()
+      .any(|item| item.parent == Some(*id))
+  }
+
+  /// The ids of the todos above the todo with `id`, nearest first. A todo
+  /// whose parent isn't in the list counts as a top level todo.
+  pub fn ancestors(&self, id: &Uuid) -> Vec<Uuid> {
+    let mut ancestors = vec![];
+    let mut next = self.get(id).and_then(|item| item.parent);
+    while let Some(parent) = next {
+      // Saved data could have parents that loop, we stop going round.
+      if parent == *id || ancestors.contains(&parent) {
+        break;
+      }
+      next =
+        match self.get(&parent) {
+          Some(item) => {
+            ancestors.push(parent);
+            item.parent
+          }
+          None => { None }
+        };
+    }
+    ancestors
+  }
+
+  /// Whether `item` is the todo with `id` or one of its subtasks at any depth.
+  fn is_within(&self, item: &Item, id: &Uuid) -> bool {
+    item.id == *id || self.ancestors(&item.id).contains(id)
+  }
+
+  /// Whether a todo matches the filter and tag.
+  fn matches(&self, item: &Item) -> bool {
+    self.filter.shows(item, self.today)
+      && self.tag.as_ref().map(|tag| item.has_tag(tag)).unwrap_or(true)
+      && self.query.as_ref().map(|query| search::matches(item, query)).unwrap_or(true)
+  }
+
+  /// Whether a todo is shown. A todo is also shown when one of its subtasks
+  /// matches, since that's where the subtask is shown.
+  pub fn is_visible(&self, item: &Item) -> bool {
+    self.visible_ids().contains(&item.id)
+  }
+
+  /// The ids of the todos that are shown, found in one pass: those that match
+  /// and every todo above them, in the active list.
+  fn visible_ids(&self) -> HashSet<Uuid> {
+    let positions:HashMap<Uuid, usize> =
+      self
+      .items
+      .iter()
+      .enumerate()
+      .map(|(position, item)| (item.id, position))
+      .collect();
+    let mut shown = HashSet::new();
+    for item in self.items.iter().filter(|item| self.matches(item)) {
+      let mut next = Some(item);
+      // A todo that's already shown has the todos above it shown too, this
+      // also stops parents that loop.
+      while let Some(item) = next.filter(|item| shown.insert(item.id)) {
+        next =
+          item
+          .parent
+          .and_then(|parent| positions.get(&parent))
+          .map(|position| &self.items[*position]);
+      }
+    }
+    shown.retain(|id| self.items[positions[id]].list == self.active);
+    shown
+  }
+
+  /// Every tag in use in the active list with how many todos have it, by
+  /// name.
+  pub fn all_tags(&self) -> Vec<(String, usize)> {
+    let mut counts = BTreeMap::new();
+    self
+      .list_items()
+      .into_iter()
+      .flat_map(|item| item.tags.iter())
+      .for_each(|tag| *counts.entry(tag.clone()).or_insert(0) += 1);
+    counts
+      .into_iter()
+      .collect()
+  }
+
+  pub fn visible_items(&self) -> Vec<&Item> {
+    let visible = self.visible_ids();
+    self
+      .items
+      .iter()
+      .filter(|item| visible.contains(&item.id))
+      .collect()
+  }
+
+  /// The ids of all todos in the order they are shown, each todo followed
+  /// by its subtasks.
+  pub fn display_order(&self) -> Vec<Uuid> {
+    let top_level =
+      self
+      .items
+      .iter()
+      .filter(|item| item.parent.and_then(|parent| self.get(&parent)).is_none())
+      .collect();
+    let mut order = vec![];
+    self.push_in_order(top_level, &mut order);
+    // Todos whose parents loop never hang below a top level todo.
+    let ordered:HashSet<Uuid> = order.iter().cloned().collect();
+    self
+      .items
+      .iter()
+      .filter(|item| !ordered.contains(&item.id))
+      .for_each(|item| order.push(item.id));
+    order
+  }
+
+  fn push_in_order(&self, mut siblings: Vec<&Item>, order: &mut Vec<Uuid>) {
+    if self.sort == SortBy::Priority {
+      // This sort is stable, so equal priorities stay in the order added.
+      siblings.sort_by_key(|item| std::cmp::Reverse(item.priority));
+    }
+    for item in siblings {
+      order.push(item.id);
+      self.push_in_order(self.children(&item.id), order);
+    }
+  }
+
+  /// How many open todos there are in the active list, not counting todos
+  /// with subtasks as those are done once their subtasks are.
+  pub fn num_items_left(&self) -> usize {
+    self
+      .list_items()
+      .into_iter()
+      .filter(|item| !item.completed && !self.has_children(&item.id))
+      .count()
+  }
+
+  pub fn are_any_complete(&self) -> bool {
+    self
+      .list_items()
+      .into_iter()
+      .any(|item| item.completed)
+  }
+
+  pub fn are_all_complete(&self) -> bool {
+    self
+      .list_items()
+      .into_iter()
+      .all(|item| item.completed)
+  }
+
+  /// Carry out a command, returning what changed.
+  pub fn handle(&mut self, command: Command) -> Vec<Event> {
+    let mut events = self.run(command);
+    let moved =
+      events
+      .iter()
+      .any(|event| matches!(event, Event::Inserted { .. } | Event::Prioritized(_, _)));
+    if self.sort == SortBy::Priority && moved {
+      events.push(Event::Reordered(self.display_order()));
+    }
+    events
+  }
+
+  fn run(&mut self, command: Command) -> Vec<Event> {
+    match command {
+      Command::Add(item) => {
+        let item = self.placed(item);
+        let parent = item.parent;
+        let change = Change::Create { position: self.items.len(), item };
+        self.apply_and_roll_up(vec![change], parent)
+      }
+      Command::AddAll(items) => {
+        // Each is made before the next is placed, so subtasks find their
+        // parents.
+        let mut changes = vec![];
+        let mut events = vec![];
+        for item in items {
+          let item = self.placed(item);
+          let change = Change::Create { position: self.items.len(), item };
+          events.extend(self.apply(&change));
+          changes.push(change);
+        }
+        self.record(Change::Batch(changes), events)
+      }
+      Command::Load(item) => {
+        let mut events = self.apply(&Change::Create { position: self.items.len(), item });
+        events.extend(self.adopt_lists());
+        events
+      }
+      Command::Rename(id, to) => {
+        match self.get(&id) {
+          Some(item) if item.title != to => {
+            let change = Change::Rename { id, from: item.title.clone(), to };
+            self.apply_and_record(change)
+          }
+          _ => { vec![] }
+        }
+      }
+      Command::SetCompletion(id, to) => {
+        // Completing or reopening a todo does the same to its subtasks.
+        let mut changes:Vec<Change> =
+          self
+          .items
+          .iter()
+          .filter(|item| item.completed != to && self.is_within(item, &id))
+          .map(|item| Change::Complete { id: item.id, from: item.completed, to })
+          .collect();
+        if to {
+          changes.extend(self.next_occurrence(&id));
+        }
+        let parent = self.get(&id).and_then(|item| item.parent);
+        self.apply_and_roll_up(changes, parent)
+      }
+      Command::SetAllComplete(to) => {
+        let changes =
+          self
+          .list_items()
+          .into_iter()
+          .filter(|item| item.completed != to)
+          .map(|item| Change::Complete { id: item.id, from: item.completed, to })
+          .collect();
+        self.apply_and_record(Change::Batch(changes))
+      }
+      Command::SetDue(id, to) => {
+        match self.get(&id) {
+          Some(item) if item.due != to => {
+            self.apply_and_record(Change::Due { id, from: item.due, to })
+          }
+          _ => { vec![] }
+        }
+      }
+      Command::SetPriority(id, to) => {
+        match self.get(&id) {
+          Some(item) if item.priority != to => {
+            self.apply_and_record(Change::Prioritize { id, from: item.priority, to })
+          }
+          _ => { vec![] }
+        }
+      }
+      Command::SetTags(id, tags) => {
+        let to = parse_tags(&tags.join(" "));
+        match self.get(&id) {
+          Some(item) if item.tags != to => {
+            self.apply_and_record(Change::Tag { id, from: item.tags.clone(), to })
+          }
+          _ => { vec![] }
+        }
+      }
+      Command::SetStarred(id, to) => {
+        match self.get(&id) {
+          Some(item) if item.starred != to => {
+            self.apply_and_record(Change::Star { id, from: item.starred, to })
+          }
+          _ => { vec![] }
+        }
+      }
+      Command::SetNotes(id, to) => {
+        let to = if to.trim().is_empty() { String::new() } else { to };
+        match self.get(&id) {
+          Some(item) if item.notes != to => {
+            self.apply_and_record(Change::Note { id, from: item.notes.clone(), to })
+          }
+          _ => { vec![] }
+        }
+      }
+      Command::SetRecurrence(id, to) => {
+        match self.get(&id) {
+          Some(item) if item.recurrence != to => {
+            self.apply_and_record(Change::Repeat { id, from: item.recurrence.clone(), to })
+          }
+          _ => { vec![] }
+        }
+      }
+      Command::Remove(id) => {
+        // A todo's subtasks go with it.
+        let changes = self.removals(|item| self.is_within(item, &id));
+        let parent = self.get(&id).and_then(|item| item.parent);
+        self.apply_and_roll_up(changes, parent)
+      }
+      Command::RemoveCompleted => {
+        let changes =
+          self.removals(|item| {
+            let is_done =
+              item.completed
+              || self
+                .ancestors(&item.id)
+                .iter()
+                .any(|id| self.get(id).map(|parent| parent.completed).unwrap_or(false));
+            item.list == self.active && is_done
+          });
+        self.apply_and_record(Change::Batch(changes))
+      }
+      Command::MoveBefore(id, target) => {
+        self.move_next_to(id, target, false)
+      }
+      Command::MoveAfter(id, target) => {
+        self.move_next_to(id, target, true)
+      }
+      Command::MoveUp(id) => {
+        match self.neighbour(&id, false) {
+          Some(target) => { self.move_next_to(id, target, false) }
+          None => { vec![] }
+        }
+      }
+      Command::MoveDown(id) => {
+        match self.neighbour(&id, true) {
+          Some(target) => { self.move_next_to(id, target, true) }
+          None => { vec![] }
+        }
+      }
+      Command::StartEditing(id) => {
+        self.editing.insert(id);
+        vec![]
+      }
+      Command::StopEditing(id) => {
+        self.editing.remove(&id);
+        vec![]
+      }
+      Command::Filter(show) => {
+        self.filter = show.clone();
+        let mut events = self.visibility();
+        events.push(Event::Filtered(show));
+        events
+      }
+      Command::FilterTag(tag) => {
+        self.tag = tag.as_deref().and_then(normalize_tag);
+        let mut events = self.visibility();
+        events.push(Event::TagFiltered(self.tag.clone()));
+        events
+      }
+      Command::Search(query) => {
+        self.query = query.filter(|query| !query.trim().is_empty());
+        let mut events = self.visibility();
+        events.push(Event::Searched(self.query.clone()));
+        events
+      }
+      Command::Sort(sort) => {
+        if sort == self.sort {
+          return vec![];
+        }
+        self.sort = sort;
+        vec![Event::Reordered(self.display_order()), Event::Sorted(sort)]
+      }
+      Command::SetToday(today) => {
+        if today == self.today {
+          return vec![];
+        }
+        self.today = today;
+        let visible = self.visible_ids();
+        self
+          .items
+          .iter()
+          .flat_map(|item| vec![
+            Event::Overdue(item.id, item.is_overdue(today)),
+            Event::Visible(item.id, visible.contains(&item.id))
+          ])
+          .collect()
+      }
+      Command::Undo => {
+        let mut events =
+          self
+          .history
+          .undo()
+          .map(|change| self.apply(&change))
+          .unwrap_or(vec![]);
+        events.push(Event::UndoOffer(None));
+        events
+      }
+      Command::Redo => {
+        let mut events =
+          self
+          .history
+          .redo()
+          .map(|change| self.apply(&change))
+          .unwrap_or(vec![]);
+        events.push(Event::UndoOffer(None));
+        events
+      }
+      Command::Merge(items) => {
+        let mut events = self.merge(items);
+        events.extend(self.adopt_lists());
+        events
+      }
+      Command::Patch(item) => {
+        let change =
+          match self.position_of(&item.id) {
+            Some(position) => {
+              Change::Batch(TodoList::differences(position, &self.items[position], &item))
+            }
+            None => { Change::Create { position: self.items.len(), item } }
+          };
+        let mut events = self.apply(&change);
+        events.extend(self.adopt_lists());
+        events
+      }
+      Command::Forget(id) => {
+        let changes = self.removals(|item| self.is_within(item, &id));
+        self.apply(&Change::Batch(changes))
+      }
+      Command::AddList(name) => {
+        let name = name.trim().to_string();
+        if name.is_empty() {
+          return vec![];
+        }
+        let list = ListInfo {
+          id: Uuid::new_v4(),
+          slug: self.unique_slug(&name, None),
+          name
+        };
+        let id = list.id;
+        let mut events = self.apply_and_record(Change::AddList { position: self.lists.len(), list });
+        events.extend(self.switch_list(id));
+        events
+      }
+      Command::RenameList(id, name) => {
+        let name = name.trim().to_string();
+        match self.lists.iter().find(|list| list.id == id) {
+          Some(from) if !name.is_empty() && from.name != name => {
+            let to = ListInfo {
+              id,
+              slug: self.unique_slug(&name, Some(id)),
+              name
+            };
+            self.apply_and_record(Change::RenameList { from: from.clone(), to })
+          }
+          _ => { vec![] }
+        }
+      }
+      Command::DeleteList(id) => {
+        let may_position =
+          self
+          .lists
+          .iter()
+          .position(|list| list.id == id);
+        match may_position {
+          Some(position) if self.lists.len() > 1 => {
+            let mut changes = self.removals(|item| item.list == id);
+            changes.push(Change::RemoveList { position, list: self.lists[position].clone() });
+            self.apply_and_record(Change::Batch(changes))
+          }
+          _ => { vec![] }
+        }
+      }
+      Command::SwitchList(id) => {
+        self.switch_list(id)
+      }
+      Command::Navigate(route) => {
+        let may_list =
+          match route.list.as_ref() {
+            Some(slug) => { self.list_by_slug(slug) }
+            None => { self.lists.first() }
+          }
+          .map(|list| list.id)
+          .filter(|id| *id != self.active);
+        if let Some(id) = may_list {
+          self.views.insert(self.active, self.view());
+          self.views.remove(&id);
+          self.active = id;
+        }
+        self.filter = route.filter;
+        self.tag = route.tag.as_deref().and_then(normalize_tag);
+        self.query = route.query.filter(|query| !query.trim().is_empty());
+        self.sort = route.sort;
+
+        let mut events = self.visibility();
+        events.extend(may_list.map(Event::ListSwitched));
+        events.extend(vec![
+          Event::Reordered(self.display_order()),
+          Event::Filtered(self.filter.clone()),
+          Event::TagFiltered(self.tag.clone()),
+          Event::Searched(self.query.clone()),
+          Event::Sorted(self.sort)
+        ]);
+        events
+      }
+      Command::LoadLists(lists) => {
+        let mut lists = lists;
+        let mut seen = HashSet::new();
+        lists.retain(|list| seen.insert(list.id));
+        if lists.is_empty() {
+          return vec![];
+        }
+        self.lists = lists;
+        let mut events = self.adopt_lists();
+        if events.is_empty() {
+          events.push(Event::Lists(self.lists.clone()));
+        }
+        if self.lists.iter().all(|list| list.id != self.active) {
+          let first = self.lists[0].id;
+          events.extend(self.switch_list(first));
+        }
+        events
+      }
+    }
+  }
+
+  /// The visible sibling shown just above or below the todo with `id`.
+  fn neighbour(&self, id: &Uuid, below: bool) -> Option<Uuid> {
+    let item = self.get(id)?;
+    let visible = self.visible_ids();
+    let siblings:Vec<Uuid> =
+      self
+      .display_order()
+      .into_iter()
+      .filter(|other| {
+        self
+          .get(other)
+          .map(|other| {
+            other.parent == item.parent
+              && other.list == item.list
+              && (other.id == *id || visible.contains(&other.id))
+          })
+          .unwrap_or(false)
+      })
+      .collect();
+    let at = siblings.iter().position(|other| other == id)?;
+    if below {
+      siblings.get(at + 1).cloned()
+    } else {
+      at.checked_sub(1).map(|at| siblings[at])
+    }
+  }
+
+  /// Move the todo with `id` to just before or after `target`. Todos only
+  /// move among their siblings.
+  fn move_next_to(&mut self, id: Uuid, target: Uuid, after: bool) -> Vec<Event> {
+    let (from, at) =
+      match (self.position_of(&id), self.position_of(&target)) {
+        (Some(from), Some(at)) if from != at => { (from, at) }
+        _ => { return vec![]; }
+      };
+    let (item, other) = (&self.items[from], &self.items[at]);
+    if item.parent != other.parent || item.list != other.list {
+      return vec![];
+    }
+    // Where the target sits once the todo is taken out.
+    let at = if at > from { at - 1 } else { at };
+    let to = if after { at + 1 } else { at };
+    if to == from {
+      return vec![];
+    }
+    self.apply_and_record(Change::Move { id, from, to })
+  }
+
+  /// The active list's filter, tag, search and sort.
+  fn view(&self) -> Route {
+    Route {
+      list: None,
+      filter: self.filter.clone(),
+      tag: self.tag.clone(),
+      query: self.query.clone(),
+      sort: self.sort
+    }
+  }
+
+  /// Show the named list with `id`, putting the current filter, tag, search
+  /// and sort aside until we come back to this one.
+  fn switch_list(&mut self, id: Uuid) -> Vec<Event> {
+    if id == self.active || self.lists.iter().all(|list| list.id != id) {
+      return vec![];
+    }
+    self.views.insert(self.active, self.view());
+    self.active = id;
+    let view =
+      self
+      .views
+      .remove(&id)
+      .unwrap_or_default();
+    self.filter = view.filter;
+    self.tag = view.tag;
+    self.query = view.query;
+    self.sort = view.sort;
+
+    let mut events = self.visibility();
+    events.extend(vec![
+      Event::ListSwitched(id),
+      Event::Reordered(self.display_order()),
+      Event::Filtered(self.filter.clone()),
+      Event::TagFiltered(self.tag.clone()),
+      Event::Searched(self.query.clone()),
+      Event::Sorted(self.sort)
+    ]);
+    events
+  }
+
+  /// A slug for a list called `name` that no list but `except` has.
+  fn unique_slug(&self, name: &str, except: Option<Uuid>) -> String {
+    let base =
+      match slugify(name) {
+        slug if slug.is_empty() => { "list".to_string() }
+        slug => { slug }
+      };
+    let is_taken = |slug: &str| {
+      self
+        .lists
+        .iter()
+        .any(|list| Some(list.id) != except && list.slug == slug)
+    };
+    let mut slug = base.clone();
+    let mut n = 1;
+    while is_taken(&slug) {
+      n += 1;
+      slug = format!("{}-{}", base, n);
+    }
+    slug
+  }
+
+  /// Make up lists for todos that are in a list we don't know about, eg.
+  /// when a store doesn't keep the lists.
+  fn adopt_lists(&mut self) -> Vec<Event> {
+    let mut adopted = false;
+    for position in 0..self.items.len() {
+      let id = self.items[position].list;
+      if self.lists.iter().all(|list| list.id != id) {
+        let name = format!("List {}", self.lists.len() + 1);
+        let slug = self.unique_slug(&name, None);
+        self.lists.push(ListInfo { id, name, slug });
+        adopted = true;
+      }
+    }
+    if adopted {
+      vec![Event::Lists(self.lists.clone())]
+    } else {
+      vec![]
+    }
+  }
+
+  /// Whether each todo is visible.
+  fn visibility(&self) -> Vec<Event> {
+    let visible = self.visible_ids();
+    self
+      .items
+      .iter()
+      .map(|item| Event::Visible(item.id, visible.contains(&item.id)))
+      .collect()
+  }
+
+  /// Whether the todo with `id` and each todo above it are visible.
+  fn visibility_upwards(&self, id: &Uuid) -> Vec<Event> {
+    let visible = self.visible_ids();
+    std::iter::once(*id)
+      .chain(self.ancestors(id))
+      .filter_map(|id| self.get(&id))
+      .map(|item| Event::Visible(item.id, visible.contains(&item.id)))
+      .collect()
+  }
+
+  /// The changes that complete a repeating todo's occurrence: it stops
+  /// repeating and the next one goes in just after it, due on the rule's
+  /// next day and with today in its log. Subtasks aren't carried over.
+  fn next_occurrence(&self, id: &Uuid) -> Vec<Change> {
+    let (position, item) =
+      match self.position_of(id).map(|position| (position, &self.items[position])) {
+        Some((position, item)) if !item.completed => { (position, item) }
+        _ => { return vec![]; }
+      };
+    let rule =
+      match &item.recurrence {
+        Some(rule) => { rule.clone() }
+        None => { return vec![]; }
+      };
+    // A rule with no next day completes like any other todo.
+    let due =
+      match rule.next_due(item.due, self.today) {
+        Some(due) => { due }
+        None => { return vec![]; }
+      };
+    let mut completions = item.completions.clone();
+    completions.push(self.today);
+    let next = Item {
+      id: Uuid::new_v4(),
+      completed: false,
+      due: Some(due),
+      recurrence: Some(rule.clone()),
+      completions,
+      ..item.clone()
+    };
+    vec![
+      Change::Repeat { id: *id, from: Some(rule), to: None },
+      Change::Create { position: position + 1, item: next }
+    ]
+  }
+
+  /// Removals of the todos that match `doomed`, from the back so that each
+  /// recorded position is where the todo sat in the original list.
+  fn removals(&self, doomed: impl Fn(&Item) -> bool) -> Vec<Change> {
+    self
+      .items
+      .iter()
+      .enumerate()
+      .rev()
+      .filter(|(_, item)| doomed(item))
+      .map(|(position, item)| Change::Remove { position, item: item.clone() })
+      .collect()
+  }
+
+  /// Change the todo with `id`, returning it as it is afterwards.
+  fn update_item(&mut self, id: &Uuid, f: impl FnOnce(&mut Item)) -> Option<Item> {
+    let item =
+      self
+      .items
+      .iter_mut()
+      .find(|item| item.id == *id)?;
+    f(item);
+    Some(item.clone())
+  }
+
+  /// Make a change without recording it in the history.
+  fn apply(&mut self, change: &Change) -> Vec<Event> {
+    match change {
+      Change::Create { position, item } => {
+        if self.position_of(&item.id).is_some() {
+          return vec![];
+        }
+        let position = (*position).min(self.items.len());
+        self.items.insert(position, item.clone());
+        let mut events = vec![
+          Event::Inserted { position, item: item.clone(), visible: self.is_visible(item) }
+        ];
+        if item.is_overdue(self.today) {
+          events.push(Event::Overdue(item.id, true));
+        }
+        if let Some(parent) = item.parent {
+          events.extend(self.visibility_upwards(&parent));
+        }
+        events
+      }
+      Change::Move { id, to, .. } => {
+        let from =
+          match self.position_of(id) {
+            Some(from) => { from }
+            None => { return vec![]; }
+          };
+        let item = self.items.remove(from);
+        let to = (*to).min(self.items.len());
+        self.items.insert(to, item);
+        vec![Event::Moved { from, to }, Event::Reordered(self.display_order())]
+      }
+      Change::Rename { id, to, .. } => {
+        self
+          .update_item(id, |item| item.title = to.clone())
+          .map(|_| vec![Event::Renamed(*id, to.clone())])
+          .unwrap_or(vec![])
+      }
+      Change::Complete { id, to, .. } => {
+        self
+          .update_item(id, |item| item.completed = *to)
+          .map(|item| {
+            let mut events = vec![
+              Event::Completed(*id, *to),
+              Event::Overdue(*id, item.is_overdue(self.today))
+            ];
+            events.extend(self.visibility_upwards(id));
+            events
+          })
+          .unwrap_or(vec![])
+      }
+      Change::Due { id, to, .. } => {
+        self
+          .update_item(id, |item| item.due = *to)
+          .map(|item| {
+            let mut events = vec![
+              Event::Due(*id, *to),
+              Event::Overdue(*id, item.is_overdue(self.today))
+            ];
+            events.extend(self.visibility_upwards(id));
+            events
+          })
+          .unwrap_or(vec![])
+      }
+      Change::Prioritize { id, to, .. } => {
+        self
+          .update_item(id, |item| item.priority = *to)
+          .map(|_| vec![Event::Prioritized(*id, *to)])
+          .unwrap_or(vec![])
+      }
+      Change::Tag { id, to, .. } => {
+        self
+          .update_item(id, |item| item.tags = to.clone())
+          .map(|_| {
+            let mut events = vec![Event::Tagged(*id, to.clone())];
+            events.extend(self.visibility_upwards(id));
+            events
+          })
+          .unwrap_or(vec![])
+      }
+      Change::Star { id, to, .. } => {
+        self
+          .update_item(id, |item| item.starred = *to)
+          .map(|_| vec![Event::Starred(*id, *to)])
+          .unwrap_or(vec![])
+      }
+      Change::Note { id, to, .. } => {
+        // Notes are searched, so they can change what's shown.
+        self
+          .update_item(id, |item| item.notes = to.clone())
+          .map(|_| {
+            let mut events = vec![Event::Noted(*id, to.clone())];
+            events.extend(self.visibility_upwards(id));
+            events
+          })
+          .unwrap_or(vec![])
+      }
+      Change::Repeat { id, to, .. } => {
+        self
+          .update_item(id, |item| item.recurrence = to.clone())
+          .map(|_| vec![Event::Recurring(*id, to.clone())])
+          .unwrap_or(vec![])
+      }
+      Change::Remove { item, .. } => {
+        self
+          .position_of(&item.id)
+          .map(|position| {
+            self.items.remove(position);
+            self.editing.remove(&item.id);
+            let mut events = vec![Event::Removed(item.id)];
+            if let Some(parent) = item.parent {
+              events.extend(self.visibility_upwards(&parent));
+            }
+            events
+          })
+          .unwrap_or(vec![])
+      }
+      Change::AddList { position, list } => {
+        if self.lists.iter().any(|other| other.id == list.id) {
+          return vec![];
+        }
+        let position = (*position).min(self.lists.len());
+        self.lists.insert(position, list.clone());
+        vec![Event::Lists(self.lists.clone())]
+      }
+      Change::RenameList { to, .. } => {
+        match self.lists.iter_mut().find(|list| list.id == to.id) {
+          Some(list) => {
+            *list = to.clone();
+            vec![Event::Lists(self.lists.clone())]
+          }
+          None => { vec![] }
+        }
+      }
+      Change::RemoveList { list, .. } => {
+        let may_position =
+          self
+          .lists
+          .iter()
+          .position(|other| other.id == list.id);
+        match may_position {
+          Some(position) if self.lists.len() > 1 => {
+            let mut events = vec![];
+            if list.id == self.active {
+              let next = self.lists[if position == 0 { 1 } else { 0 }].id;
+              events.extend(self.switch_list(next));
+            }
+            self.lists.remove(position);
+            self.views.remove(&list.id);
+            events.push(Event::Lists(self.lists.clone()));
+            events
+          }
+          _ => { vec![] }
+        }
+      }
+      Change::Batch(changes) => {
+        changes
+          .iter()
+          .flat_map(|change| self.apply(change))
+          .collect()
+      }
+    }
+  }
+
+  /// `item` in the named list it's added to: its parent's, or the active one.
+  /// A subtask of a todo that's gone becomes a todo of its own.
+  fn placed(&self, mut item: Item) -> Item {
+    let may_parent_list =
+      item
+      .parent
+      .and_then(|parent| self.get(&parent))
+      .map(|parent| parent.list);
+    if may_parent_list.is_none() {
+      item.parent = None;
+    }
+    item.list = may_parent_list.unwrap_or(self.active);
+    item
+  }
+
+  /// Make a change the user asked for and remember it so it can be undone.
+  fn apply_and_record(&mut self, change: Change) -> Vec<Event> {
+    let events = self.apply(&change);
+    self.record(change, events)
+  }
+
+  /// Make changes the user asked for, then complete or reopen `parent` and
+  /// the todos above it to match their subtasks. It's all remembered as one
+  /// change.
+  fn apply_and_roll_up(&mut self, mut changes: Vec<Change>, parent: Option<Uuid>) -> Vec<Event> {
+    let mut events = self.apply(&Change::Batch(changes.clone()));
+    let upwards:Vec<Uuid> =
+      parent
+      .into_iter()
+      .chain(parent.map(|parent| self.ancestors(&parent)).unwrap_or_default())
+      .collect();
+    for id in upwards {
+      let children = self.children(&id);
+      if children.is_empty() {
+        // Its last subtask was removed, it keeps its own completion.
+        continue;
+      }
+      let to = children.iter().all(|child| child.completed);
+      let from =
+        match self.get(&id) {
+          Some(item) if item.completed != to => { item.completed }
+          _ => { continue; }
+        };
+      let change = Change::Complete { id, from, to };
+      events.extend(self.apply(&change));
+      changes.push(change);
+    }
+    let change =
+      if changes.len() == 1 {
+        changes.remove(0)
+      } else {
+        Change::Batch(changes)
+      };
+    self.record(change, events)
+  }
+
+  /// Remember a change that was just made so it can be undone, offering to
+  /// undo it straight away if it lost data.
+  fn record(&mut self, change: Change, mut events: Vec<Event>) -> Vec<Event> {
+    if events.is_empty() {
+      return events;
+    }
+    let offer =
+      if change.is_destructive() {
+        Some(change.describe())
+      } else {
+        None
+      };
+    self.history.record(change);
+    events.push(Event::UndoOffer(offer));
+    events
+  }
+
+  /// The changes that make `local`, at `position`, look like `remote`. A todo
+  /// that's under another parent or in another named list is put back in its
+  /// place as `remote`.
+  fn differences(position: usize, local: &Item, remote: &Item) -> Vec<Change> {
+    if remote.parent != local.parent || remote.list != local.list {
+      return vec![
+        Change::Remove { position, item: local.clone() },
+        Change::Create { position, item: remote.clone() }
+      ];
+    }
+    let mut changes = vec![];
+    if remote.title != local.title {
+      changes.push(Change::Rename { id: local.id, from: local.title.clone(), to: remote.title.clone() });
+    }
+    if remote.completed != local.completed {
+      changes.push(Change::Complete { id: local.id, from: local.completed, to: remote.completed });
+    }
+    if remote.due != local.due {
+      changes.push(Change::Due { id: local.id, from: local.due, to: remote.due });
+    }
+    if remote.priority != local.priority {
+      changes.push(Change::Prioritize { id: local.id, from: local.priority, to: remote.priority });
+    }
+    if remote.tags != local.tags {
+      changes.push(Change::Tag { id: local.id, from: local.tags.clone(), to: remote.tags.clone() });
+    }
+    if remote.starred != local.starred {
+      changes.push(Change::Star { id: local.id, from: local.starred, to: remote.starred });
+    }
+    if remote.notes != local.notes {
+      changes.push(Change::Note { id: local.id, from: local.notes.clone(), to: remote.notes.clone() });
+    }
+    if remote.recurrence != local.recurrence {
+      changes.push(Change::Repeat { id: local.id, from: local.recurrence.clone(), to: remote.recurrence.clone() });
+    }
+    changes
+  }
+
+  /// Bring the list in line with `items`, and into their order, leaving alone
+  /// any todo that is being edited. These changes were not made by this user
+  /// so they don't go into the undo history.
+  fn merge(&mut self, items: Vec<Item>) -> Vec<Event> {
+    let mut changes = vec![];
+
+    for (position, local) in self.items.iter().enumerate() {
+      if self.editing.contains(&local.id) {
+        continue;
+      }
+      match items.iter().find(|item| item.id == local.id) {
+        Some(remote) => {
+          changes.extend(TodoList::differences(position, local, remote));
+        }
+        None => {
+          changes.push(Change::Remove { position, item: local.clone() });
+        }
+      }
+    }
+
+    let order:Vec<Uuid> = items.iter().map(|item| item.id).collect();
+    for (position, remote) in items.into_iter().enumerate() {
+      if self.position_of(&remote.id).is_none() {
+        changes.push(Change::Create { position, item: remote });
+      }
+    }
+
+    let mut events = self.apply(&Change::Batch(changes));
+    events.extend(self.reorder(&order));
+    events
+  }
+
+  /// Sort the todos with ids in `order` into that order. Todos that aren't in
+  /// it stay after the todo they follow now.
+  fn reorder(&mut self, order: &[Uuid]) -> Vec<Event> {
+    let rank:HashMap<Uuid, usize> =
+      order
+      .iter()
+      .enumerate()
+      .map(|(rank, id)| (*id, rank))
+      .collect();
+    let mut ranks = vec![];
+    for item in self.items.iter() {
+      let previous = ranks.last().cloned().unwrap_or(0);
+      ranks.push(rank.get(&item.id).cloned().unwrap_or(previous));
+    }
+    if ranks.windows(2).all(|pair| pair[0] <= pair[1]) {
+      return vec![];
+    }
+    let mut ranked:Vec<(usize, Item)> =
+      ranks
+      .into_iter()
+      .zip(self.items.drain(..))
+      .collect();
+    ranked.sort_by_key(|(rank, _)| *rank);
+    self.items = ranked.into_iter().map(|(_, item)| item).collect();
+    vec![
+      Event::Arranged(self.items.iter().map(|item| item.id).collect()),
+      Event::Reordered(self.display_order())
+    ]
+  }
+}
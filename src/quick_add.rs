@@ -0,0 +1,165 @@
+//! The quick-add syntax of the new-todo input.
+//!
+//! Words that start with a sigil set the new todo's other fields and the rest
+//! of the words become its title:
+//!
+//! * `#tag` adds a tag
+//! * `!1`, `!2` and `!3` set high, medium and low priority
+//! * `@today`, `@tomorrow`, `@2026-11-01` or a weekday like `@fri` sets the due
+//!   date, a weekday being the next one on or after today
+//! * `*` on its own stars the todo
+//!
+//! A word that starts with a sigil but doesn't parse is left in the title as it
+//! was typed and reported as a `Problem`, so nothing the user typed is lost.
+//! When a field is given more than once the last one wins.
+//!
+//! A word that starts with `\` goes in the title as it is without the `\`, eg.
+//! `Buy \#1 milk` is titled "Buy #1 milk". `escape_title` writes titles that
+//! way.
+use std::fmt;
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+use super::model::normalize_tag;
+use super::store::{Item, Priority};
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+  /// A `#` word that isn't a valid tag, eg. `#` or `#c++`.
+  BadTag(String),
+  /// A `!` word other than `!1`, `!2` and `!3`.
+  BadPriority(String),
+  /// An `@` word that isn't a date we understand.
+  BadDate(String)
+}
+
+
+impl fmt::Display for Problem {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Problem::BadTag(word) => { write!(f, "{:?} is not a tag", word) }
+      Problem::BadPriority(word) => { write!(f, "{:?} is not a priority, use !1, !2 or !3", word) }
+      Problem::BadDate(word) => { write!(f, "{:?} is not a date", word) }
+    }
+  }
+}
+
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QuickAdd {
+  pub title: String,
+  pub tags: Vec<String>,
+  pub priority: Priority,
+  pub due: Option<NaiveDate>,
+  pub starred: bool,
+  /// Words that looked like syntax but weren't, in the order typed.
+  pub problems: Vec<Problem>
+}
+
+
+impl QuickAdd {
+  /// A new todo with everything that was parsed.
+  pub fn into_item(self) -> Item {
+    Item {
+      tags: self.tags,
+      priority: self.priority,
+      due: self.due,
+      starred: self.starred,
+      ..Item::new(self.title)
+    }
+  }
+}
+
+
+fn parse_priority(word: &str) -> Option<Priority> {
+  match word {
+    "!1" => { Some(Priority::High) }
+    "!2" => { Some(Priority::Medium) }
+    "!3" => { Some(Priority::Low) }
+    _ => { None }
+  }
+}
+
+
+/// The date an `@` word (without its `@`) stands for.
+fn parse_date(word: &str, today: NaiveDate) -> Option<NaiveDate> {
+  match word.to_lowercase().as_str() {
+    "today" => { Some(today) }
+    "tomorrow" => { Some(today + Duration::days(1)) }
+    word => {
+      NaiveDate::parse_from_str(word, "%Y-%m-%d")
+        .ok()
+        .or_else(|| {
+          let weekday:Weekday = word.parse().ok()?;
+          let ahead =
+            (7 + weekday.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+          Some(today + Duration::days(i64::from(ahead)))
+        })
+    }
+  }
+}
+
+
+/// Whether `word` would be read as something other than itself.
+fn is_syntax(word: &str) -> bool {
+  word == "*" || word.starts_with(|c| ['#', '!', '@', '\\'].contains(&c))
+}
+
+
+/// `title` as quick-add text that `parse` reads back as that title.
+pub fn escape_title(title: &str) -> String {
+  title
+    .split_whitespace()
+    .map(|word| if is_syntax(word) { format!("\\{}", word) } else { word.to_string() })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+
+/// Parse the text of the new-todo input. `today` is used to resolve relative
+/// dates.
+pub fn parse(text: &str, today: NaiveDate) -> QuickAdd {
+  let mut quick = QuickAdd::default();
+  let mut title = vec![];
+
+  for word in text.split_whitespace() {
+    if let Some(word) = word.strip_prefix('\\') {
+      title.push(word);
+    } else if word == "*" {
+      quick.starred = true;
+    } else if word.starts_with('#') {
+      match normalize_tag(word) {
+        Some(tag) => {
+          if !quick.tags.contains(&tag) {
+            quick.tags.push(tag);
+          }
+        }
+        None => {
+          quick.problems.push(Problem::BadTag(word.to_string()));
+          title.push(word);
+        }
+      }
+    } else if word.starts_with('!') {
+      match parse_priority(word) {
+        Some(priority) => { quick.priority = priority; }
+        None => {
+          quick.problems.push(Problem::BadPriority(word.to_string()));
+          title.push(word);
+        }
+      }
+    } else if let Some(date) = word.strip_prefix('@') {
+      match parse_date(date, today) {
+        Some(due) => { quick.due = Some(due); }
+        None => {
+          quick.problems.push(Problem::BadDate(word.to_string()));
+          title.push(word);
+        }
+      }
+    } else {
+      title.push(word);
+    }
+  }
+
+  quick.title = title.join(" ");
+  quick
+}
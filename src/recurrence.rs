@@ -0,0 +1,166 @@
+//! How often a todo repeats.
+//!
+//! When a repeating todo is completed it stays completed and a copy of it is
+//! added with the next due date, carrying the rule and the dates it was done
+//! on. The next due date comes after the todo's due date, or after today if
+//! the todo had none or was completed late.
+//!
+//! Rules are written and shown as short phrases:
+//!
+//! * `daily` or `every day`
+//! * `weekly on mon, thu` or `every mon thu`
+//! * `every 3 days`, with `weekly` meaning `every 7 days`
+//! * `monthly on 15`, falling on the last day of shorter months
+//!
+//! Intervals are at most `MAX_DAYS` days.
+use std::fmt;
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use serde::{Serialize, Deserialize};
+
+
+/// The longest interval a rule can have, about ten years.
+pub const MAX_DAYS: u32 = 3650;
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Recurrence {
+  Daily,
+  /// On each of these days of the week.
+  Weekly { days: Vec<Weekday> },
+  /// Every so many days.
+  Every { days: u32 },
+  /// On this day of every month.
+  Monthly { day: u32 }
+}
+
+
+/// `day` of the month, or the month's last day if it has fewer days. There's
+/// none past the last date chrono knows.
+fn clamped(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+  (1..=day.clamp(1, 31))
+    .rev()
+    .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+}
+
+
+fn plus_days(date: NaiveDate, days: i64) -> Option<NaiveDate> {
+  date.checked_add_signed(Duration::days(days))
+}
+
+
+impl Recurrence {
+  /// The first day of the rule strictly after `date`, unless that's past the
+  /// last date chrono knows.
+  pub fn after(&self, date: NaiveDate) -> Option<NaiveDate> {
+    match self {
+      Recurrence::Daily => { plus_days(date, 1) }
+      Recurrence::Weekly { days } => {
+        (1..=7)
+          .filter_map(|n| plus_days(date, n))
+          .find(|next| days.contains(&next.weekday()))
+          .or_else(|| plus_days(date, 7))
+      }
+      Recurrence::Every { days } => { plus_days(date, i64::from((*days).max(1))) }
+      Recurrence::Monthly { day } => {
+        let this_month = clamped(date.year(), date.month(), *day)?;
+        if this_month > date {
+          Some(this_month)
+        } else if date.month() == 12 {
+          clamped(date.year() + 1, 1, *day)
+        } else {
+          clamped(date.year(), date.month() + 1, *day)
+        }
+      }
+    }
+  }
+
+  /// When a todo that was due on `due` and completed `today` is due next, if
+  /// ever.
+  pub fn next_due(&self, due: Option<NaiveDate>, today: NaiveDate) -> Option<NaiveDate> {
+    let from =
+      due
+      .map(|due| due.max(today))
+      .unwrap_or(today);
+    self.after(from)
+  }
+}
+
+
+fn weekday_name(day: Weekday) -> String {
+  day.to_string().to_lowercase()
+}
+
+
+impl fmt::Display for Recurrence {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Recurrence::Daily => { write!(f, "daily") }
+      Recurrence::Weekly { days } => {
+        let days:Vec<String> = days.iter().cloned().map(weekday_name).collect();
+        write!(f, "weekly on {}", days.join(", "))
+      }
+      Recurrence::Every { days } => { write!(f, "every {} days", days) }
+      Recurrence::Monthly { day } => { write!(f, "monthly on {}", day) }
+    }
+  }
+}
+
+
+/// The days of the week in `words`, if that's all they are.
+fn parse_weekdays(words: &[&str]) -> Option<Vec<Weekday>> {
+  if words.is_empty() {
+    return None;
+  }
+  let mut days = vec![];
+  for word in words {
+    let day:Weekday = word.parse().ok()?;
+    if !days.contains(&day) {
+      days.push(day);
+    }
+  }
+  days.sort_by_key(|day| day.num_days_from_monday());
+  Some(days)
+}
+
+
+/// A day of the month like `15` or `15th`.
+fn parse_day_of_month(word: &str) -> Option<u32> {
+  let digits = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+  let day:u32 = digits.parse().ok()?;
+  if (1..=31).contains(&day) {
+    Some(day)
+  } else {
+    None
+  }
+}
+
+
+/// Read a rule as the user wrote it, or as `Display` shows it.
+pub fn parse(text: &str) -> Option<Recurrence> {
+  let text = text.to_lowercase();
+  let words:Vec<&str> =
+    text
+    .split(|c: char| c.is_whitespace() || c == ',')
+    .filter(|word| !word.is_empty() && !["on", "the", "and"].contains(word))
+    .collect();
+  match words.as_slice() {
+    ["daily"] | ["every", "day"] => { Some(Recurrence::Daily) }
+    ["weekly"] | ["every", "week"] => { Some(Recurrence::Every { days: 7 }) }
+    ["weekly", days @ ..] | ["every", days @ ..] if parse_weekdays(days).is_some() => {
+      parse_weekdays(days).map(|days| Recurrence::Weekly { days })
+    }
+    ["every", n, "day"] | ["every", n, "days"] => {
+      match n.parse::<u32>().ok()? {
+        0 => { None }
+        1 => { Some(Recurrence::Daily) }
+        days if days > MAX_DAYS => { None }
+        days => { Some(Recurrence::Every { days }) }
+      }
+    }
+    ["monthly", day] => {
+      parse_day_of_month(day).map(|day| Recurrence::Monthly { day })
+    }
+    _ => { None }
+  }
+}
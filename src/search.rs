@@ -0,0 +1,135 @@
+//! Free-text search over todos.
+//!
+//! Searching ignores case and diacritics, so `cafe` finds "Café" whether its
+//! accent was typed as one character or as a combining mark. A query of
+//! several words finds the todos that have every one of them, in their title,
+//! notes or tags. A word's leading `#` is ignored, so `#work` finds todos
+//! tagged "work".
+use std::ops::Range;
+
+use super::store::Item;
+
+
+/// Whether `c` is a combining diacritical mark, like the accent of a
+/// decomposed "é".
+fn is_combining(c: char) -> bool {
+  matches!(c, '\u{0300}'..='\u{036f}' | '\u{1ab0}'..='\u{1aff}' | '\u{1dc0}'..='\u{1dff}' | '\u{20d0}'..='\u{20ff}')
+}
+
+
+/// `c` without its case or diacritics.
+fn fold(c: char) -> char {
+  match c.to_lowercase().next().unwrap_or(c) {
+    'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => { 'a' }
+    'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => { 'c' }
+    'ď' | 'đ' => { 'd' }
+    'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => { 'e' }
+    'ĝ' | 'ğ' | 'ġ' | 'ģ' => { 'g' }
+    'ĥ' | 'ħ' => { 'h' }
+    'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => { 'i' }
+    'ĵ' => { 'j' }
+    'ķ' => { 'k' }
+    'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => { 'l' }
+    'ñ' | 'ń' | 'ņ' | 'ň' => { 'n' }
+    'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => { 'o' }
+    'ŕ' | 'ŗ' | 'ř' => { 'r' }
+    'ś' | 'ŝ' | 'ş' | 'š' | 'ș' => { 's' }
+    'ţ' | 'ť' | 'ŧ' | 'ț' => { 't' }
+    'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => { 'u' }
+    'ŵ' => { 'w' }
+    'ý' | 'ÿ' | 'ŷ' => { 'y' }
+    'ź' | 'ż' | 'ž' => { 'z' }
+    c => { c }
+  }
+}
+
+
+/// The folded characters of `text`, each with the bytes of `text` it came
+/// from. Combining marks belong to the character before them.
+fn folded(text: &str) -> Vec<(char, Range<usize>)> {
+  let mut chars:Vec<(char, Range<usize>)> = vec![];
+  for (at, c) in text.char_indices() {
+    let end = at + c.len_utf8();
+    match chars.last_mut() {
+      Some((_, range)) if is_combining(c) => { range.end = end; }
+      _ if is_combining(c) => {}
+      _ => { chars.push((fold(c), at..end)); }
+    }
+  }
+  chars
+}
+
+
+/// The folded words of a query.
+fn words(query: &str) -> Vec<Vec<char>> {
+  query
+    .split_whitespace()
+    .map(|word| word.strip_prefix('#').unwrap_or(word))
+    .map(|word| folded(word).into_iter().map(|(c, _)| c).collect::<Vec<_>>())
+    .filter(|word| !word.is_empty())
+    .collect()
+}
+
+
+/// Where `word` starts in `chars`, every time it does without overlapping.
+fn occurrences(chars: &[(char, Range<usize>)], word: &[char]) -> Vec<usize> {
+  let mut found = vec![];
+  let mut i = 0;
+  while i + word.len() <= chars.len() {
+    let is_match =
+      chars[i..i + word.len()]
+      .iter()
+      .map(|(c, _)| c)
+      .eq(word.iter());
+    if is_match {
+      found.push(i);
+      i += word.len();
+    } else {
+      i += 1;
+    }
+  }
+  found
+}
+
+
+/// The bytes of `text` that match a word of `query`, in order, with touching
+/// and overlapping matches joined together.
+pub fn find(text: &str, query: &str) -> Vec<Range<usize>> {
+  let chars = folded(text);
+  let mut ranges:Vec<Range<usize>> =
+    words(query)
+    .iter()
+    .flat_map(|word| {
+      occurrences(&chars, word)
+        .into_iter()
+        .map(|i| chars[i].1.start..chars[i + word.len() - 1].1.end)
+        .collect::<Vec<_>>()
+    })
+    .collect();
+  ranges.sort_by_key(|range| range.start);
+
+  let mut joined:Vec<Range<usize>> = vec![];
+  for range in ranges {
+    match joined.last_mut() {
+      Some(last) if range.start <= last.end => {
+        last.end = last.end.max(range.end);
+      }
+      _ => { joined.push(range); }
+    }
+  }
+  joined
+}
+
+
+/// Whether the todo has every word of `query` in its title, notes or tags.
+/// Every todo matches an empty query.
+pub fn matches(item: &Item, query: &str) -> bool {
+  let has = |text: &str, word: &[char]| !occurrences(&folded(text), word).is_empty();
+  words(query)
+    .iter()
+    .all(|word| {
+      has(&item.title, word)
+        || has(&item.notes, word)
+        || item.tags.iter().any(|tag| has(tag, word))
+    })
+}
@@ -1,53 +1,270 @@
-use wasm_bindgen::JsValue;
-use web_sys::Storage;
+use std::fmt;
+use chrono::NaiveDate;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{DomException, StorageEvent};
 use serde::{Serialize, Deserialize};
-use serde_json;
-use mogwai::utils;
+use uuid::Uuid;
 
+use super::history::History;
+use super::recurrence::Recurrence;
 
-#[derive(Serialize, Deserialize)]
+mod idb;
+mod memory;
+pub mod schema;
+mod sync;
+mod web;
+pub use idb::IdbStore;
+pub use memory::MemoryStore;
+pub use sync::{sync_key, SyncStore};
+pub use web::{LocalStore, SessionStore};
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+  #[default]
+  None,
+  Low,
+  Medium,
+  High
+}
+
+
+impl Priority {
+  /// The priority after this one when cycling through them, wrapping from
+  /// high back around to none.
+  pub fn next(self) -> Priority {
+    match self {
+      Priority::None => { Priority::Low }
+      Priority::Low => { Priority::Medium }
+      Priority::Medium => { Priority::High }
+      Priority::High => { Priority::None }
+    }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Priority::None => { "none" }
+      Priority::Low => { "low" }
+      Priority::Medium => { "medium" }
+      Priority::High => { "high" }
+    }
+  }
+}
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Item {
+  /// Identifies the todo across sessions, tabs and exports.
+  pub id: Uuid,
   pub title: String,
-  pub completed: bool
-}
-
-const KEY: &str = "todomvc-mogwai";
-
-pub fn write_items(items: Vec<Item>) -> Result<(), JsValue> {
-  let str_value =
-    serde_json::to_string(&items)
-    .expect("Could not serialize items");
-  utils::window()
-    .local_storage()?
-    .into_iter()
-    .for_each(|storage:Storage| {
-      storage
-        .set_item(KEY, &str_value)
-        .expect("could not store serialized items");
-    });
-  Ok(())
-}
-
-pub fn read_items() -> Result<Vec<Item>, JsValue> {
-  let storage =
-    utils::window()
-    .local_storage()?
-    .expect("Could not get local storage");
-
-  let may_item_str: Option<String> =
-    storage
-    .get_item(KEY)
-    .expect("Error using storage get_item");
-
-  let items =
-    may_item_str
-    .map(|json_str:String| {
-      let items:Vec<Item> =
-        serde_json::from_str(&json_str)
-        .expect("Could not deserialize items");
-      items
-    })
-    .unwrap_or(vec![]);
-
-  Ok(items)
+  pub completed: bool,
+  /// The day the todo should be done by, if any.
+  #[serde(default)]
+  pub due: Option<NaiveDate>,
+  #[serde(default)]
+  pub priority: Priority,
+  /// Labels without their leading '#', eg. "work".
+  #[serde(default)]
+  pub tags: Vec<String>,
+  #[serde(default)]
+  pub starred: bool,
+  /// The todo this is a subtask of, if any.
+  #[serde(default)]
+  pub parent: Option<Uuid>,
+  /// The id of the named list the todo is in.
+  #[serde(default = "Uuid::nil")]
+  pub list: Uuid,
+  /// Free text about the todo, which may run over several lines.
+  #[serde(default)]
+  pub notes: String,
+  /// How often the todo repeats, if it does.
+  #[serde(default)]
+  pub recurrence: Option<Recurrence>,
+  /// The days earlier occurrences of a repeating todo were completed on.
+  #[serde(default)]
+  pub completions: Vec<NaiveDate>
 }
+
+
+impl Item {
+  /// A new, incomplete todo with a fresh id.
+  pub fn new(title: String) -> Item {
+    Item {
+      id: Uuid::new_v4(),
+      title,
+      completed: false,
+      due: None,
+      priority: Priority::None,
+      tags: vec![],
+      starred: false,
+      parent: None,
+      list: Uuid::nil(),
+      notes: String::new(),
+      recurrence: None,
+      completions: vec![]
+    }
+  }
+
+  pub fn has_tag(&self, tag: &str) -> bool {
+    self.tags.iter().any(|t| t == tag)
+  }
+
+  /// Whether the todo is still open after its due date.
+  pub fn is_overdue(&self, today: NaiveDate) -> bool {
+    !self.completed && self.due.map(|due| due < today).unwrap_or(false)
+  }
+}
+
+
+/// One of the named lists that todos are kept in, eg. "Work".
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ListInfo {
+  pub id: Uuid,
+  pub name: String,
+  /// Names the list in the url, eg. "work" in `#/lists/work/active`.
+  pub slug: String
+}
+
+
+impl Default for ListInfo {
+  /// The list that todos saved before there were named lists are in.
+  fn default() -> ListInfo {
+    ListInfo {
+      id: Uuid::nil(),
+      name: "Todos".to_string(),
+      slug: "todos".to_string()
+    }
+  }
+}
+
+
+/// The key our todos are stored under in web storage.
+pub const KEY: &str = "todomvc-mogwai";
+
+
+/// The key undo history is saved under by `TodoStore::save_history`.
+pub fn history_key(key: &str) -> String {
+  format!("{}-history", key)
+}
+
+
+/// The key the named lists are saved under by `TodoStore::save_lists`.
+pub fn lists_key(key: &str) -> String {
+  format!("{}-lists", key)
+}
+
+
+/// The key a corrupted payload is moved to by `TodoStore::quarantine`.
+pub fn quarantine_key(key: &str) -> String {
+  format!("{}-quarantine", key)
+}
+
+
+#[derive(Debug)]
+pub enum StoreError {
+  /// The storage backend could not be reached, eg. storage is disabled.
+  Unavailable(String),
+  /// Saved data exists but could not be understood. `raw` is the payload as
+  /// it was found so it can be set aside and recovered by hand.
+  Corrupt { raw: String, reason: String },
+  /// Items could not be serialized.
+  Serialization(String),
+  /// The backend refused a write because it is full.
+  QuotaExceeded,
+  /// Any other error thrown by the browser.
+  Js(String)
+}
+
+
+impl fmt::Display for StoreError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      StoreError::Unavailable(what) => { write!(f, "{} is unavailable", what) }
+      StoreError::Corrupt { reason, .. } => { write!(f, "saved todos are corrupt: {}", reason) }
+      StoreError::Serialization(reason) => { write!(f, "could not serialize todos: {}", reason) }
+      StoreError::QuotaExceeded => { write!(f, "storage quota exceeded") }
+      StoreError::Js(msg) => { write!(f, "{}", msg) }
+    }
+  }
+}
+
+
+impl From<JsValue> for StoreError {
+  fn from(err: JsValue) -> StoreError {
+    match err.dyn_ref::<DomException>() {
+      Some(ex) if ex.name() == "QuotaExceededError" => { StoreError::QuotaExceeded }
+      Some(ex) => { StoreError::Js(format!("{}: {}", ex.name(), ex.message())) }
+      None => { StoreError::Js(format!("{:?}", err)) }
+    }
+  }
+}
+
+
+impl From<StoreError> for JsValue {
+  fn from(err: StoreError) -> JsValue {
+    JsValue::from_str(&err.to_string())
+  }
+}
+
+
+/// Called with the result of `TodoStore::load`.
+pub type OnLoad = Box<dyn FnOnce(Result<Vec<Item>, StoreError>)>;
+
+
+/// Somewhere to keep todo items between visits.
+///
+/// `App` is handed one of these at construction and calls `save` after each
+/// update. `load` is used once at startup to restore the previous session.
+pub trait TodoStore {
+  /// Load the saved items and hand them to `done`. Synchronous stores call
+  /// `done` before returning, asynchronous ones call it once the items arrive.
+  fn load(&self, done: OnLoad);
+
+  /// Persist the items. Asynchronous stores may return before the write
+  /// lands, in which case a failure is only logged.
+  fn save(&self, items: &[Item]) -> Result<(), StoreError>;
+
+  /// Set aside a payload that `load` reported as `StoreError::Corrupt` so that
+  /// the next `save` does not overwrite it. Stores that cannot hold corrupt
+  /// data have nothing to do here.
+  fn quarantine(&self, _raw: &str) -> Result<(), StoreError> {
+    Ok(())
+  }
+
+  /// If `ev` reports that another tab changed this store's items or lists,
+  /// the items as they are now. Stores that aren't shared between tabs return
+  /// `None`.
+  fn external_change(&self, _ev: &StorageEvent) -> Option<Result<Vec<Item>, StoreError>> {
+    None
+  }
+
+  /// Call `on_change` with the items as they are now whenever they change
+  /// from somewhere other than this app, eg. a sync server. Stores that only
+  /// change when saved do nothing.
+  fn watch(&self, _on_change: Box<dyn Fn(Vec<Item>)>) {}
+
+  /// Undo history saved by `save_history`, if this store keeps any.
+  fn load_history(&self) -> Option<History> {
+    None
+  }
+
+  /// Keep undo history next to the todos. Stores without anywhere to put it
+  /// do nothing.
+  fn save_history(&self, _history: &History) -> Result<(), StoreError> {
+    Ok(())
+  }
+
+  /// The named lists saved by `save_lists`, if this store keeps any. Lists
+  /// that todos are in but that weren't saved come back with made up names.
+  fn load_lists(&self) -> Option<Vec<ListInfo>> {
+    None
+  }
+
+  /// Keep the names and order of the lists next to the todos. Stores without
+  /// anywhere to put them do nothing.
+  fn save_lists(&self, _lists: &[ListInfo]) -> Result<(), StoreError> {
+    Ok(())
+  }
+}
+
@@ -0,0 +1,562 @@
+//! An IndexedDB backed store that keeps one record per todo.
+//!
+//! Records are keyed by the todo's id. The order of the todos, the named
+//! lists and undo history are kept on their own, so unlike web storage,
+//! writes only touch the records whose item changed since the last save,
+//! plus the order if it changed. There is no 5MB cap on the list either.
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+use uuid::Uuid;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen::closure::Closure;
+use web_sys::{
+  Event,
+  IdbDatabase,
+  IdbObjectStoreParameters,
+  IdbOpenDbRequest,
+  IdbRequest,
+  IdbTransaction,
+  IdbTransactionMode
+};
+use mogwai::utils;
+
+use crate::history::History;
+use super::{schema, Item, ListInfo, OnLoad, StoreError, TodoStore};
+
+
+const DB_VERSION: u32 = 2;
+
+/// The object store holding one record per todo, keyed by id.
+const ITEMS: &str = "items";
+
+/// The object store of everything that isn't a todo, like their order.
+const META: &str = "meta";
+
+/// The key in `META` of the todos' ids, in order.
+const ORDER: &str = "order";
+
+/// The key in `META` of the named lists.
+const LISTS: &str = "lists";
+
+/// The key in `META` of the undo history.
+const HISTORY: &str = "history";
+
+/// The object store version 1 kept a record per todo in, keyed by position.
+/// Its todos are moved to `ITEMS` on upgrade.
+const LEGACY_TODOS: &str = "todos";
+
+/// The object store corrupt payloads are moved into.
+const QUARANTINE: &str = "quarantine";
+
+/// The index over each record's completion state.
+pub const COMPLETED_INDEX: &str = "completed";
+
+
+/// Bring an item written at `schema_version` up to date.
+fn migrate_item(schema_version: u32, item: Value) -> Result<Item, String> {
+  let items = schema::migrate(schema_version, Value::Array(vec![item]))?;
+  let mut items:Vec<Item> =
+    serde_json::from_value(items)
+    .map_err(|e| format!("Could not deserialize a record: {}", e))?;
+  items
+    .pop()
+    .ok_or("A record has no item".to_string())
+}
+
+
+/// A single todo as it is stored in the database.
+#[derive(Serialize, Deserialize)]
+struct Record {
+  id: String,
+  /// 1 if the todo is complete, otherwise 0. IndexedDB can't index booleans.
+  completed: u8,
+  /// The schema version `item` was written with.
+  schema_version: u32,
+  item: Value
+}
+
+
+impl Record {
+  fn new(item: &Item) -> Result<Record, StoreError> {
+    Ok(Record {
+      id: item.id.to_string(),
+      completed: if item.completed { 1 } else { 0 },
+      schema_version: schema::SCHEMA_VERSION,
+      item:
+        serde_json::to_value(item)
+        .map_err(|e| StoreError::Serialization(e.to_string()))?
+    })
+  }
+
+  fn to_js(&self) -> Result<JsValue, StoreError> {
+    let json =
+      serde_json::to_string(self)
+      .map_err(|e| StoreError::Serialization(e.to_string()))?;
+    Ok(js_sys::JSON::parse(&json)?)
+  }
+
+  fn to_item(&self) -> Result<Item, String> {
+    migrate_item(self.schema_version, self.item.clone())
+  }
+}
+
+
+/// A todo as version 1 stored it.
+#[derive(Deserialize)]
+struct LegacyRecord {
+  position: u32,
+  schema_version: u32,
+  item: Value
+}
+
+
+/// The JSON of the result of `getAll` on an object store.
+fn stringified(records: &JsValue) -> Result<String, StoreError> {
+  Ok(js_sys::JSON::stringify(records)?.into())
+}
+
+
+/// Turn the result of `getAll` on the items store into items, each with
+/// whether its record was already at the current schema version.
+fn decode_records(records: &JsValue) -> Result<Vec<(Item, bool)>, StoreError> {
+  let raw = stringified(records)?;
+  let corrupt = |reason: String| StoreError::Corrupt { raw: raw.clone(), reason };
+  let records:Vec<Record> =
+    serde_json::from_str(&raw)
+    .map_err(|e| corrupt(format!("Could not parse records: {}", e)))?;
+  records
+    .iter()
+    .map(|record| {
+      record
+        .to_item()
+        .map(|item| (item, record.schema_version == schema::SCHEMA_VERSION))
+    })
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(corrupt)
+}
+
+
+/// Turn the records of version 1 into items, in order.
+fn decode_legacy_records(raw: &str) -> Result<Vec<Item>, String> {
+  let mut records:Vec<LegacyRecord> =
+    serde_json::from_str(raw)
+    .map_err(|e| format!("Could not parse records: {}", e))?;
+  records.sort_by_key(|record| record.position);
+  records
+    .into_iter()
+    .map(|record| migrate_item(record.schema_version, record.item))
+    .collect()
+}
+
+
+/// A value as it's kept in `META`, as JSON.
+fn encode_meta<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, StoreError> {
+  serde_json::to_string(value)
+    .map(|json| JsValue::from_str(&json))
+    .map_err(|e| StoreError::Serialization(e.to_string()))
+}
+
+
+/// The value a `get` on `META` found, if there was one and it can be read.
+fn decode_meta<T: DeserializeOwned>(request: &IdbRequest, key: &str) -> Option<T> {
+  let json = request.result().ok()?.as_string()?;
+  serde_json::from_str(&json)
+    .map_err(|e| warn!("Discarding the unreadable {}: {}", key, e))
+    .ok()
+}
+
+
+/// Put `items` in the order of `order`. Those it doesn't have go at the end.
+fn sort_by_order(items: &mut [Item], order: &[Uuid]) {
+  let positions:HashMap<&Uuid, usize> =
+    order
+    .iter()
+    .enumerate()
+    .map(|(position, id)| (id, position))
+    .collect();
+  items.sort_by_key(|item| positions.get(&item.id).cloned().unwrap_or(usize::MAX));
+}
+
+
+fn store_names(names: &[&str]) -> js_sys::Array {
+  names
+    .iter()
+    .map(|name| JsValue::from_str(name))
+    .collect()
+}
+
+
+fn request_error(request: &IdbRequest) -> StoreError {
+  request
+    .error()
+    .ok()
+    .and_then(|may_ex| may_ex)
+    .map(|ex| StoreError::from(JsValue::from(ex)))
+    .unwrap_or(StoreError::Js("Unknown IndexedDB error".to_string()))
+}
+
+
+/// Wraps a callback so that whichever of a request's success or error handlers
+/// fires first gets to call it.
+struct Once<T>(Rc<RefCell<Option<Box<dyn FnOnce(Result<T, StoreError>)>>>>);
+
+
+impl<T> Clone for Once<T> {
+  fn clone(&self) -> Self {
+    Once(self.0.clone())
+  }
+}
+
+
+impl<T> Once<T> {
+  fn new(f: Box<dyn FnOnce(Result<T, StoreError>)>) -> Once<T> {
+    Once(Rc::new(RefCell::new(Some(f))))
+  }
+
+  fn call(&self, result: Result<T, StoreError>) {
+    let may_f = self.0.borrow_mut().take();
+    may_f
+      .into_iter()
+      .for_each(|f| f(result));
+  }
+}
+
+
+/// Call `on_success` with the request's result, or `done` with its error.
+fn on_request<T: 'static>(
+  request: &IdbRequest,
+  done: Once<T>,
+  on_success: impl FnOnce(JsValue, Once<T>) + 'static
+) {
+  let success_request = request.clone();
+  let success_done = done.clone();
+  let on_success = Closure::once_into_js(move |_:Event| {
+    match success_request.result() {
+      Ok(value) => { on_success(value, success_done) }
+      Err(e) => { success_done.call(Err(e.into())) }
+    }
+  });
+  request.set_onsuccess(Some(on_success.unchecked_ref()));
+
+  let error_request = request.clone();
+  let on_error = Closure::once_into_js(move |_:Event| {
+    done.call(Err(request_error(&error_request)));
+  });
+  request.set_onerror(Some(on_error.unchecked_ref()));
+}
+
+
+fn open_request(name: &str) -> Result<IdbOpenDbRequest, StoreError> {
+  let factory =
+    utils::window()
+    .indexed_db()?
+    .ok_or(StoreError::Unavailable("indexedDB".to_string()))?;
+  Ok(factory.open_with_u32(name, DB_VERSION)?)
+}
+
+
+/// Move the todos of version 1 into `ITEMS` once `getAll` on its store
+/// succeeds, then drop its store. Records that can't be read are quarantined.
+fn move_legacy_records(
+  db: &IdbDatabase,
+  tx: &IdbTransaction,
+  get_all: &IdbRequest
+) -> Result<(), StoreError> {
+  let raw = stringified(&get_all.result()?)?;
+  match decode_legacy_records(&raw) {
+    Ok(items) => {
+      let records = tx.object_store(ITEMS)?;
+      for item in items.iter() {
+        records.put(&Record::new(item)?.to_js()?)?;
+      }
+      let ids:Vec<Uuid> = items.iter().map(|item| item.id).collect();
+      tx.object_store(META)?
+        .put_with_key(&encode_meta(&ids)?, &JsValue::from_str(ORDER))?;
+    }
+    Err(reason) => {
+      warn!("Quarantining todos that can't be moved to the new layout: {}", reason);
+      tx.object_store(QUARANTINE)?
+        .add(&JsValue::from_str(&raw))?;
+    }
+  }
+  db.delete_object_store(LEGACY_TODOS)?;
+  Ok(())
+}
+
+
+/// Create the object stores and indices the first time the database opens,
+/// and move todos kept by an older version.
+fn upgrade(request: &IdbOpenDbRequest) -> Result<(), StoreError> {
+  let db:IdbDatabase =
+    request
+    .result()?
+    .dyn_into()?;
+  let names = db.object_store_names();
+
+  if !names.contains(ITEMS) {
+    let mut params = IdbObjectStoreParameters::new();
+    params.key_path(Some(&JsValue::from_str("id")));
+    let items = db.create_object_store_with_optional_parameters(ITEMS, &params)?;
+    items.create_index_with_str(COMPLETED_INDEX, "completed")?;
+  }
+
+  if !names.contains(META) {
+    db.create_object_store(META)?;
+  }
+
+  if !names.contains(QUARANTINE) {
+    let mut params = IdbObjectStoreParameters::new();
+    params.auto_increment(true);
+    db.create_object_store_with_optional_parameters(QUARANTINE, &params)?;
+  }
+
+  if names.contains(LEGACY_TODOS) {
+    let tx =
+      request
+      .transaction()
+      .ok_or(StoreError::Js("Upgrading without a transaction".to_string()))?;
+    let get_all = tx.object_store(LEGACY_TODOS)?.get_all()?;
+    let moving_request = get_all.clone();
+    let on_success = Closure::once_into_js(move |_:Event| {
+      move_legacy_records(&db, &tx, &moving_request)
+        .unwrap_or_else(|e| error!("Could not move todos to the new layout: {}", e));
+    });
+    get_all.set_onsuccess(Some(on_success.unchecked_ref()));
+  }
+
+  Ok(())
+}
+
+
+/// What was last loaded or saved, used to write only what changed.
+#[derive(Default)]
+struct Snapshot {
+  /// Each stored todo by id. A todo whose record has to be written again,
+  /// eg. because it was migrated, has `None`.
+  items: HashMap<Uuid, Option<Item>>,
+  /// The ids as last stored under `ORDER`.
+  order: Vec<Uuid>
+}
+
+
+impl Snapshot {
+  fn of(items: &[Item]) -> Snapshot {
+    Snapshot {
+      items:
+        items
+        .iter()
+        .map(|item| (item.id, Some(item.clone())))
+        .collect(),
+      order: items.iter().map(|item| item.id).collect()
+    }
+  }
+}
+
+
+/// The requests that load everything, made in one transaction.
+struct Loading {
+  order: IdbRequest,
+  lists: IdbRequest,
+  history: IdbRequest,
+  /// Made last, so the others have succeeded once it has.
+  records: IdbRequest
+}
+
+
+pub struct IdbStore {
+  db: IdbDatabase,
+  saved: Rc<RefCell<Snapshot>>,
+  /// The lists and undo history as of the last load.
+  lists: Rc<RefCell<Option<Vec<ListInfo>>>>,
+  history: Rc<RefCell<Option<History>>>
+}
+
+
+impl IdbStore {
+  /// Open (creating if need be) the database with the given name and hand the
+  /// store to `done` once it is ready.
+  pub fn open(name: &str, done: Box<dyn FnOnce(Result<IdbStore, StoreError>)>) {
+    let done = Once::new(done);
+    let request =
+      match open_request(name) {
+        Ok(request) => { request }
+        Err(e) => { return done.call(Err(e)); }
+      };
+
+    let upgrade_request = request.clone();
+    let on_upgrade = Closure::once_into_js(move |_:Event| {
+      upgrade(&upgrade_request)
+        .unwrap_or_else(|e| error!("Could not upgrade todo database: {}", e));
+    });
+    request.set_onupgradeneeded(Some(on_upgrade.unchecked_ref()));
+
+    on_request(&request, done, |db, done| {
+      done.call(
+        db.dyn_into::<IdbDatabase>()
+          .map(|db| IdbStore {
+            db,
+            saved: Rc::new(RefCell::new(Snapshot::default())),
+            lists: Rc::new(RefCell::new(None)),
+            history: Rc::new(RefCell::new(None))
+          })
+          .map_err(StoreError::from)
+      );
+    });
+  }
+
+  fn get_all(&self) -> Result<Loading, StoreError> {
+    let tx = self.db.transaction_with_str_sequence(&store_names(&[ITEMS, META]))?;
+    let meta = tx.object_store(META)?;
+    Ok(Loading {
+      order: meta.get(&JsValue::from_str(ORDER))?,
+      lists: meta.get(&JsValue::from_str(LISTS))?,
+      history: meta.get(&JsValue::from_str(HISTORY))?,
+      records: tx.object_store(ITEMS)?.get_all()?
+    })
+  }
+
+  /// Keep `value` under `key` in `META`.
+  fn put_meta<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<(), StoreError> {
+    let tx =
+      self
+      .db
+      .transaction_with_str_and_mode(META, IdbTransactionMode::Readwrite)?;
+    tx.object_store(META)?
+      .put_with_key(&encode_meta(value)?, &JsValue::from_str(key))?;
+    let key = key.to_string();
+    let on_error = Closure::once_into_js(move |_:Event| {
+      error!("Could not store the {} in IndexedDB", key);
+    });
+    tx.set_onerror(Some(on_error.unchecked_ref()));
+    Ok(())
+  }
+}
+
+
+impl TodoStore for IdbStore {
+  fn load(&self, done: OnLoad) {
+    let done = Once::new(done);
+    let loading =
+      match self.get_all() {
+        Ok(loading) => { loading }
+        Err(e) => { return done.call(Err(e)); }
+      };
+    let saved = self.saved.clone();
+    let lists = self.lists.clone();
+    let history = self.history.clone();
+    // Requests in a transaction succeed in the order they were made, so the
+    // rest have arrived once the records have.
+    let records_request = loading.records.clone();
+    on_request(&records_request, done, move |records, done| {
+      *lists.borrow_mut() = decode_meta(&loading.lists, LISTS);
+      *history.borrow_mut() = decode_meta(&loading.history, HISTORY);
+      let result =
+        decode_records(&records)
+        .map(|decoded| {
+          let order:Vec<Uuid> = decode_meta(&loading.order, ORDER).unwrap_or_default();
+          // Records that had to be migrated are left out of the snapshot so
+          // the next save writes them back at the current version.
+          let mut snapshot = Snapshot { items: HashMap::new(), order: order.clone() };
+          let mut items = vec![];
+          for (item, is_current) in decoded {
+            snapshot.items.insert(item.id, Some(item.clone()).filter(|_| is_current));
+            items.push(item);
+          }
+          sort_by_order(&mut items, &order);
+          *saved.borrow_mut() = snapshot;
+          items
+        });
+      done.call(result);
+    });
+  }
+
+  fn save(&self, items: &[Item]) -> Result<(), StoreError> {
+    let saved = self.saved.borrow();
+    let changed:Vec<&Item> =
+      items
+      .iter()
+      .filter(|item| saved.items.get(&item.id).map(|stored| stored.as_ref()) != Some(Some(*item)))
+      .collect();
+    let ids:HashSet<&Uuid> = items.iter().map(|item| &item.id).collect();
+    let removed:Vec<&Uuid> =
+      saved
+      .items
+      .keys()
+      .filter(|id| !ids.contains(id))
+      .collect();
+    let is_reordered =
+      saved.order.len() != items.len()
+      || saved.order.iter().zip(items.iter()).any(|(id, item)| *id != item.id);
+    if changed.is_empty() && removed.is_empty() && !is_reordered {
+      return Ok(());
+    }
+
+    let names = store_names(&[ITEMS, META]);
+    let tx =
+      self
+      .db
+      .transaction_with_str_sequence_and_mode(&names, IdbTransactionMode::Readwrite)?;
+    let records = tx.object_store(ITEMS)?;
+    for item in changed {
+      records.put(&Record::new(item)?.to_js()?)?;
+    }
+    for id in removed {
+      records.delete(&JsValue::from_str(&id.to_string()))?;
+    }
+    if is_reordered {
+      let order:Vec<Uuid> = items.iter().map(|item| item.id).collect();
+      tx.object_store(META)?
+        .put_with_key(&encode_meta(&order)?, &JsValue::from_str(ORDER))?;
+    }
+
+    let on_error = Closure::once_into_js(|_:Event| {
+      error!("Could not store todos in IndexedDB");
+    });
+    tx.set_onerror(Some(on_error.unchecked_ref()));
+    // Until the writes land the snapshot stays as it was, so if they fail the
+    // next save writes them again.
+    let snapshot = Snapshot::of(items);
+    let saving = self.saved.clone();
+    let on_complete = Closure::once_into_js(move |_:Event| {
+      *saving.borrow_mut() = snapshot;
+    });
+    tx.set_oncomplete(Some(on_complete.unchecked_ref()));
+    Ok(())
+  }
+
+  fn quarantine(&self, raw: &str) -> Result<(), StoreError> {
+    let names = store_names(&[ITEMS, META, QUARANTINE]);
+    let tx =
+      self
+      .db
+      .transaction_with_str_sequence_and_mode(&names, IdbTransactionMode::Readwrite)?;
+    tx.object_store(QUARANTINE)?
+      .add(&JsValue::from_str(raw))?;
+    tx.object_store(ITEMS)?
+      .clear()?;
+    tx.object_store(META)?
+      .delete(&JsValue::from_str(ORDER))?;
+    *self.saved.borrow_mut() = Snapshot::default();
+    Ok(())
+  }
+
+  fn load_history(&self) -> Option<History> {
+    self.history.borrow().clone()
+  }
+
+  fn save_history(&self, history: &History) -> Result<(), StoreError> {
+    self.put_meta(HISTORY, history)
+  }
+
+  fn load_lists(&self) -> Option<Vec<ListInfo>> {
+    self.lists.borrow().clone()
+  }
+
+  fn save_lists(&self, lists: &[ListInfo]) -> Result<(), StoreError> {
+    self.put_meta(LISTS, lists)
+  }
+}
@@ -0,0 +1,62 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::history::History;
+use super::{Item, ListInfo, OnLoad, StoreError, TodoStore};
+
+
+/// A store that only lives as long as the page (or test) does.
+///
+/// Clones share the same items, so a test can keep a clone around to inspect
+/// what `App` saved.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+  items: Rc<RefCell<Vec<Item>>>,
+  history: Rc<RefCell<Option<History>>>,
+  lists: Rc<RefCell<Option<Vec<ListInfo>>>>
+}
+
+
+impl MemoryStore {
+  pub fn new(items: Vec<Item>) -> MemoryStore {
+    MemoryStore {
+      items: Rc::new(RefCell::new(items)),
+      history: Rc::new(RefCell::new(None)),
+      lists: Rc::new(RefCell::new(None))
+    }
+  }
+
+  pub fn items(&self) -> Vec<Item> {
+    self.items.borrow().clone()
+  }
+}
+
+
+impl TodoStore for MemoryStore {
+  fn load(&self, done: OnLoad) {
+    done(Ok(self.items()))
+  }
+
+  fn save(&self, items: &[Item]) -> Result<(), StoreError> {
+    *self.items.borrow_mut() = items.to_vec();
+    Ok(())
+  }
+
+  fn load_history(&self) -> Option<History> {
+    self.history.borrow().clone()
+  }
+
+  fn save_history(&self, history: &History) -> Result<(), StoreError> {
+    *self.history.borrow_mut() = Some(history.clone());
+    Ok(())
+  }
+
+  fn load_lists(&self) -> Option<Vec<ListInfo>> {
+    self.lists.borrow().clone()
+  }
+
+  fn save_lists(&self, lists: &[ListInfo]) -> Result<(), StoreError> {
+    *self.lists.borrow_mut() = Some(lists.to_vec());
+    Ok(())
+  }
+}
@@ -0,0 +1,235 @@
+//! The on-disk layout of saved todos.
+//!
+//! Items are saved inside an envelope that records which version of the
+//! layout they were written with:
+//!
+//! ```json
+//! { "schema_version": <SCHEMA_VERSION>, "items": [ { "id": "...", "title": "...", ... } ] }
+//! ```
+//!
+//! Payloads written before the envelope existed are a bare JSON array and are
+//! treated as version 0. When loading, the items are passed through each
+//! migration step between their version and `SCHEMA_VERSION` before being
+//! deserialized.
+//!
+//! To change the layout of `Item`, bump `SCHEMA_VERSION` and push a step onto
+//! `MIGRATIONS` that upgrades the previous version's items.
+use serde::{Serialize, Deserialize};
+use serde_json::{self, Value};
+use uuid::Uuid;
+
+use super::Item;
+
+
+/// The version that `encode` writes.
+pub const SCHEMA_VERSION: u32 = 10;
+
+
+/// Upgrades the items array of a payload by exactly one version.
+pub type Migration = fn(Value) -> Result<Value, String>;
+
+
+/// `MIGRATIONS[n]` upgrades items at version `n` to version `n + 1`.
+pub const MIGRATIONS: &[Migration] = &[
+  v0_to_v1,
+  v1_to_v2,
+  v2_to_v3,
+  v3_to_v4,
+  v4_to_v5,
+  v5_to_v6,
+  v6_to_v7,
+  v7_to_v8,
+  v8_to_v9,
+  v9_to_v10
+];
+
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+  schema_version: u32,
+  items: T
+}
+
+
+/// Version 0 was a bare array of items. The items themselves are unchanged in
+/// version 1, they only moved inside the envelope.
+fn v0_to_v1(items: Value) -> Result<Value, String> {
+  if items.is_array() {
+    Ok(items)
+  } else {
+    Err("version 0 items are not an array".to_string())
+  }
+}
+
+
+/// Apply `f` to each item object in a version's items array.
+fn map_items(items: Value, f: impl Fn(&mut serde_json::Map<String, Value>)) -> Result<Value, String> {
+  match items {
+    Value::Array(mut items) => {
+      for item in items.iter_mut() {
+        let obj =
+          item
+          .as_object_mut()
+          .ok_or("item is not an object".to_string())?;
+        f(obj);
+      }
+      Ok(Value::Array(items))
+    }
+    _ => { Err("items are not an array".to_string()) }
+  }
+}
+
+
+/// Version 2 gives every item a stable id.
+fn v1_to_v2(items: Value) -> Result<Value, String> {
+  map_items(items, |item| {
+    item
+      .entry("id")
+      .or_insert_with(|| Value::String(Uuid::new_v4().to_string()));
+  })
+}
+
+
+/// Version 3 adds an optional due date, written as "YYYY-MM-DD".
+fn v2_to_v3(items: Value) -> Result<Value, String> {
+  map_items(items, |item| {
+    item
+      .entry("due")
+      .or_insert(Value::Null);
+  })
+}
+
+
+/// Version 4 adds a priority, one of "none", "low", "medium" or "high".
+fn v3_to_v4(items: Value) -> Result<Value, String> {
+  map_items(items, |item| {
+    item
+      .entry("priority")
+      .or_insert_with(|| Value::String("none".to_string()));
+  })
+}
+
+
+/// Version 5 adds a list of tags.
+fn v4_to_v5(items: Value) -> Result<Value, String> {
+  map_items(items, |item| {
+    item
+      .entry("tags")
+      .or_insert_with(|| Value::Array(vec![]));
+  })
+}
+
+
+/// Version 6 adds a starred flag.
+fn v5_to_v6(items: Value) -> Result<Value, String> {
+  map_items(items, |item| {
+    item
+      .entry("starred")
+      .or_insert(Value::Bool(false));
+  })
+}
+
+
+/// Version 7 adds the id of the todo an item is a subtask of. The tree is
+/// saved flat, parents before their subtasks.
+fn v6_to_v7(items: Value) -> Result<Value, String> {
+  map_items(items, |item| {
+    item
+      .entry("parent")
+      .or_insert(Value::Null);
+  })
+}
+
+
+/// Version 8 puts every item in a named list, by id. Items from before named
+/// lists go in the default list, whose id is nil.
+fn v7_to_v8(items: Value) -> Result<Value, String> {
+  map_items(items, |item| {
+    item
+      .entry("list")
+      .or_insert_with(|| Value::String(Uuid::nil().to_string()));
+  })
+}
+
+
+/// Version 9 adds free text notes, empty for items from before.
+fn v8_to_v9(items: Value) -> Result<Value, String> {
+  map_items(items, |item| {
+    item
+      .entry("notes")
+      .or_insert_with(|| Value::String(String::new()));
+  })
+}
+
+
+/// Version 10 adds a recurrence rule and a log of completions. Items from
+/// before don't repeat and have no log.
+fn v9_to_v10(items: Value) -> Result<Value, String> {
+  map_items(items, |item| {
+    item.entry("recurrence").or_insert(Value::Null);
+    item.entry("completions").or_insert_with(|| Value::Array(vec![]));
+  })
+}
+
+
+/// Serialize items at the current schema version.
+pub fn encode(items: &[Item]) -> Result<String, String> {
+  serde_json::to_string(&Envelope {
+    schema_version: SCHEMA_VERSION,
+    items
+  })
+  .map_err(|e| format!("Could not serialize items: {}", e))
+}
+
+
+/// Serialize items at the current schema version, indented for people to
+/// read.
+pub fn encode_pretty(items: &[Item]) -> Result<String, String> {
+  serde_json::to_string_pretty(&Envelope {
+    schema_version: SCHEMA_VERSION,
+    items
+  })
+  .map_err(|e| format!("Could not serialize items: {}", e))
+}
+
+
+/// Split a payload into its schema version and raw items.
+fn unwrap_envelope(payload: Value) -> Result<(u32, Value), String> {
+  match payload {
+    Value::Array(_) => { Ok((0, payload)) }
+    Value::Object(_) => {
+      let envelope:Envelope<Value> =
+        serde_json::from_value(payload)
+        .map_err(|e| format!("Malformed envelope: {}", e))?;
+      Ok((envelope.schema_version, envelope.items))
+    }
+    _ => { Err("Payload is neither an envelope nor an array".to_string()) }
+  }
+}
+
+
+/// Bring items from `version` up to `SCHEMA_VERSION`.
+pub fn migrate(version: u32, items: Value) -> Result<Value, String> {
+  if version > SCHEMA_VERSION {
+    return Err(format!(
+      "Items were saved with schema version {} but only versions up to {} are understood",
+      version,
+      SCHEMA_VERSION
+    ));
+  }
+  MIGRATIONS[version as usize..]
+    .iter()
+    .try_fold(items, |items, step| step(items))
+}
+
+
+/// Deserialize items saved at any known schema version.
+pub fn decode(json_str: &str) -> Result<Vec<Item>, String> {
+  let payload:Value =
+    serde_json::from_str(json_str)
+    .map_err(|e| format!("Could not parse items: {}", e))?;
+  let (version, items) = unwrap_envelope(payload)?;
+  let items = migrate(version, items)?;
+  serde_json::from_value(items)
+    .map_err(|e| format!("Could not deserialize items: {}", e))
+}
@@ -0,0 +1,192 @@
+//! A store that keeps todos in another store and syncs them with the sync
+//! server in `server/`.
+//!
+//! Saves go to the inner store first, so the app works the same offline,
+//! then to the sync engine, which pushes them as soon as the server answers.
+//! The engine's state, its queue of unpushed changes included, is kept in
+//! localStorage so that changes made offline survive a reload. Named lists
+//! are synced along with the todos.
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::closure::Closure;
+use web_sys::{Event, StorageEvent, XmlHttpRequest};
+use mogwai::prelude::*;
+use mogwai::utils;
+
+use crate::history::History;
+use crate::sync::{Answer, Request, SyncClient, SyncEngine, Transport};
+use super::{Item, ListInfo, OnLoad, StoreError, TodoStore};
+
+
+/// How often to push and pull, in milliseconds.
+const SYNC_EVERY: u32 = 5000;
+
+
+/// The key the sync engine's state is kept under.
+pub fn sync_key(key: &str) -> String {
+  format!("{}-sync", key)
+}
+
+
+fn now() -> i64 {
+  js_sys::Date::now() as i64
+}
+
+
+/// Talks to the server with XMLHttpRequest.
+struct XhrTransport {
+  url: String
+}
+
+
+impl Transport for XhrTransport {
+  fn send(&self, request: Request, done: Box<dyn FnOnce(Answer)>) {
+    let done = Rc::new(RefCell::new(Some(done)));
+    let finish = move |answer: Answer| {
+      if let Some(done) = done.borrow_mut().take() {
+        done(answer);
+      }
+    };
+    let xhr =
+      match XmlHttpRequest::new() {
+        Ok(xhr) => { xhr }
+        Err(e) => { return finish(Err(format!("{:?}", e))); }
+      };
+
+    // Loadend fires once however the request ends, so its closure is always
+    // freed. A status of 0 means there was no answer.
+    let done_xhr = xhr.clone();
+    let done_finish = finish.clone();
+    let on_done = Closure::once_into_js(move |_:Event| {
+      let status = done_xhr.status().unwrap_or(0);
+      if status == 0 {
+        return done_finish(Err("the request failed".to_string()));
+      }
+      let body = done_xhr.response_text().ok().flatten().unwrap_or_default();
+      done_finish(Ok((status, body)));
+    });
+    xhr.set_onloadend(Some(on_done.unchecked_ref()));
+
+    let url = format!("{}{}", self.url, request.path);
+    let sent =
+      xhr
+      .open_with_async(request.method, &url, true)
+      .and_then(|_| xhr.set_request_header("Content-Type", "application/json"))
+      .and_then(|_| xhr.send_with_opt_str(request.body.as_deref()));
+    if let Err(e) = sent {
+      finish(Err(format!("{:?}", e)));
+    }
+  }
+}
+
+
+/// Keeps todos in `inner` and syncs them with the server at `url`.
+pub struct SyncStore {
+  inner: Rc<dyn TodoStore>,
+  client: SyncClient
+}
+
+
+impl SyncStore {
+  /// Sync the todos in `inner` through the server at `url`, eg.
+  /// "http://127.0.0.1:8080". The engine's state is kept under
+  /// `sync_key(key)`.
+  pub fn new(inner: Rc<dyn TodoStore>, url: &str, key: &str) -> SyncStore {
+    let key = sync_key(key);
+    let storage = utils::window().local_storage().ok().flatten();
+    let engine =
+      storage
+      .as_ref()
+      .and_then(|storage| storage.get_item(&key).ok().flatten())
+      .and_then(|json| {
+        serde_json::from_str::<SyncEngine>(&json)
+          .map_err(|e| warn!("Starting sync afresh, {}", e))
+          .ok()
+      })
+      .unwrap_or_else(|| SyncEngine::new(uuid::Uuid::new_v4().to_string()));
+    let transport = XhrTransport { url: url.trim_end_matches('/').to_string() };
+    let client = SyncClient::new(engine, Rc::new(transport));
+    client.set_on_state(Box::new(move |engine| {
+      let written =
+        serde_json::to_string(engine)
+        .map_err(|e| e.to_string())
+        .and_then(|json| {
+          storage
+            .as_ref()
+            .ok_or_else(|| "localStorage is unavailable".to_string())?
+            .set_item(&key, &json)
+            .map_err(|e| format!("{:?}", e))
+        });
+      if let Err(e) = written {
+        error!("Could not keep the sync queue: {}", e);
+      }
+    }));
+
+    let tick = client.clone();
+    timeout(SYNC_EVERY, move || {
+      tick.sync();
+      true
+    });
+    SyncStore { inner, client }
+  }
+}
+
+
+impl TodoStore for SyncStore {
+  fn load(&self, done: OnLoad) {
+    let client = self.client.clone();
+    let inner = self.inner.clone();
+    self.inner.load(Box::new(move |result| {
+      // Todos and lists saved before syncing was turned on are pushed as new,
+      // lists first so they're known before their todos arrive.
+      if let Ok(items) = result.as_ref() {
+        if let Some(lists) = inner.load_lists() {
+          client.save_lists(&lists, now());
+        }
+        client.save(items, now());
+      }
+      client.pull();
+      done(result);
+    }));
+  }
+
+  fn save(&self, items: &[Item]) -> Result<(), StoreError> {
+    self.inner.save(items)?;
+    self.client.save(items, now());
+    Ok(())
+  }
+
+  fn quarantine(&self, raw: &str) -> Result<(), StoreError> {
+    self.inner.quarantine(raw)
+  }
+
+  fn external_change(&self, ev: &StorageEvent) -> Option<Result<Vec<Item>, StoreError>> {
+    self.inner.external_change(ev)
+  }
+
+  fn watch(&self, on_change: Box<dyn Fn(Vec<Item>)>) {
+    self.client.set_on_change(on_change);
+  }
+
+  fn load_history(&self) -> Option<History> {
+    self.inner.load_history()
+  }
+
+  fn save_history(&self, history: &History) -> Result<(), StoreError> {
+    self.inner.save_history(history)
+  }
+
+  /// The lists as synced, once there are any.
+  fn load_lists(&self) -> Option<Vec<ListInfo>> {
+    Some(self.client.lists())
+      .filter(|lists| !lists.is_empty())
+      .or_else(|| self.inner.load_lists())
+  }
+
+  fn save_lists(&self, lists: &[ListInfo]) -> Result<(), StoreError> {
+    self.inner.save_lists(lists)?;
+    self.client.save_lists(lists, now());
+    Ok(())
+  }
+}
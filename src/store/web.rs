@@ -0,0 +1,207 @@
+use web_sys::{Storage, StorageEvent};
+use mogwai::utils;
+
+use crate::history::History;
+use super::{history_key, lists_key, quarantine_key, schema, Item, ListInfo, OnLoad, StoreError, TodoStore};
+
+
+fn local_storage() -> Result<Storage, StoreError> {
+  utils::window()
+    .local_storage()?
+    .ok_or(StoreError::Unavailable("localStorage".to_string()))
+}
+
+
+fn session_storage() -> Result<Storage, StoreError> {
+  utils::window()
+    .session_storage()?
+    .ok_or(StoreError::Unavailable("sessionStorage".to_string()))
+}
+
+
+fn read_items(storage: &Storage, key: &str) -> Result<Vec<Item>, StoreError> {
+  let may_item_str: Option<String> =
+    storage
+    .get_item(key)?;
+
+  may_item_str
+    .map(decode_items)
+    .unwrap_or(Ok(vec![]))
+}
+
+
+fn decode_items(json_str: String) -> Result<Vec<Item>, StoreError> {
+  schema::decode(&json_str)
+    .map_err(|reason| StoreError::Corrupt { raw: json_str, reason })
+}
+
+
+fn write_items(storage: &Storage, key: &str, items: &[Item]) -> Result<(), StoreError> {
+  let str_value =
+    schema::encode(items)
+    .map_err(StoreError::Serialization)?;
+  storage
+    .set_item(key, &str_value)?;
+  Ok(())
+}
+
+
+fn quarantine_items(storage: &Storage, key: &str, raw: &str) -> Result<(), StoreError> {
+  storage
+    .set_item(&quarantine_key(key), raw)?;
+  storage
+    .remove_item(key)?;
+  Ok(())
+}
+
+
+fn read_history(storage: &Storage, key: &str) -> Option<History> {
+  let json_str =
+    storage
+    .get_item(&history_key(key))
+    .ok()??;
+  serde_json::from_str(&json_str)
+    .map_err(|e| warn!("Discarding unreadable undo history: {}", e))
+    .ok()
+}
+
+
+fn write_history(storage: &Storage, key: &str, history: &History) -> Result<(), StoreError> {
+  let str_value =
+    serde_json::to_string(history)
+    .map_err(|e| StoreError::Serialization(e.to_string()))?;
+  storage
+    .set_item(&history_key(key), &str_value)?;
+  Ok(())
+}
+
+
+fn read_lists(storage: &Storage, key: &str) -> Option<Vec<ListInfo>> {
+  let json_str =
+    storage
+    .get_item(&lists_key(key))
+    .ok()??;
+  serde_json::from_str(&json_str)
+    .map_err(|e| warn!("Discarding unreadable lists: {}", e))
+    .ok()
+}
+
+
+fn write_lists(storage: &Storage, key: &str, lists: &[ListInfo]) -> Result<(), StoreError> {
+  let str_value =
+    serde_json::to_string(lists)
+    .map_err(|e| StoreError::Serialization(e.to_string()))?;
+  storage
+    .set_item(&lists_key(key), &str_value)?;
+  Ok(())
+}
+
+
+/// Persists todos in `window.localStorage`, surviving browser restarts.
+pub struct LocalStore {
+  key: String
+}
+
+
+impl LocalStore {
+  pub fn new(key: &str) -> LocalStore {
+    LocalStore {
+      key: key.to_string()
+    }
+  }
+}
+
+
+impl TodoStore for LocalStore {
+  fn load(&self, done: OnLoad) {
+    done(local_storage().and_then(|storage| read_items(&storage, &self.key)))
+  }
+
+  fn save(&self, items: &[Item]) -> Result<(), StoreError> {
+    write_items(&local_storage()?, &self.key, items)
+  }
+
+  fn quarantine(&self, raw: &str) -> Result<(), StoreError> {
+    quarantine_items(&local_storage()?, &self.key, raw)
+  }
+
+  fn load_history(&self) -> Option<History> {
+    read_history(&local_storage().ok()?, &self.key)
+  }
+
+  fn save_history(&self, history: &History) -> Result<(), StoreError> {
+    write_history(&local_storage()?, &self.key, history)
+  }
+
+  fn load_lists(&self) -> Option<Vec<ListInfo>> {
+    read_lists(&local_storage().ok()?, &self.key)
+  }
+
+  fn save_lists(&self, lists: &[ListInfo]) -> Result<(), StoreError> {
+    write_lists(&local_storage()?, &self.key, lists)
+  }
+
+  fn external_change(&self, ev: &StorageEvent) -> Option<Result<Vec<Item>, StoreError>> {
+    // A key of null means another tab cleared all of storage.
+    match ev.key() {
+      Some(key) if key == lists_key(&self.key) => {
+        // The app picks up the lists along with the items.
+        return Some(local_storage().and_then(|storage| read_items(&storage, &self.key)));
+      }
+      Some(key) if key != self.key => { return None; }
+      _ => {}
+    }
+    Some(
+      ev.new_value()
+        .map(decode_items)
+        .unwrap_or(Ok(vec![]))
+    )
+  }
+}
+
+
+/// Persists todos in `window.sessionStorage`, which is scoped to the tab and
+/// cleared when it closes.
+pub struct SessionStore {
+  key: String
+}
+
+
+impl SessionStore {
+  pub fn new(key: &str) -> SessionStore {
+    SessionStore {
+      key: key.to_string()
+    }
+  }
+}
+
+
+impl TodoStore for SessionStore {
+  fn load(&self, done: OnLoad) {
+    done(session_storage().and_then(|storage| read_items(&storage, &self.key)))
+  }
+
+  fn save(&self, items: &[Item]) -> Result<(), StoreError> {
+    write_items(&session_storage()?, &self.key, items)
+  }
+
+  fn quarantine(&self, raw: &str) -> Result<(), StoreError> {
+    quarantine_items(&session_storage()?, &self.key, raw)
+  }
+
+  fn load_history(&self) -> Option<History> {
+    read_history(&session_storage().ok()?, &self.key)
+  }
+
+  fn save_history(&self, history: &History) -> Result<(), StoreError> {
+    write_history(&session_storage()?, &self.key, history)
+  }
+
+  fn load_lists(&self) -> Option<Vec<ListInfo>> {
+    read_lists(&session_storage().ok()?, &self.key)
+  }
+
+  fn save_lists(&self, lists: &[ListInfo]) -> Result<(), StoreError> {
+    write_lists(&session_storage()?, &self.key, lists)
+  }
+}
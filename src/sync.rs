@@ -0,0 +1,584 @@
+//! Syncing todos through the sync server in `server/`.
+//!
+//! The engine keeps each todo as its fields, every field stamped with when
+//! and by which client it was last written. Saving diffs the app's todos
+//! against what the engine knows and queues a change for each todo that
+//! differs, stamped with the time of the save. Queued changes are pushed in
+//! order whenever the server can be reached, so changes made offline land
+//! once it's back. Pulling asks the server for the todos changed since the
+//! last pull.
+//!
+//! A save's stamp never falls behind the latest stamp the engine has made or
+//! seen, like an op's in `collab`, so a change made after seeing another
+//! wins over it even if the other was made on a clock that's ahead.
+//!
+//! Whether the change comes from a push's answer or a pull, a field only
+//! takes a remote write that is newer than its own, the same rule the server
+//! keeps. That's last-writer-wins per field: two clients changing different
+//! fields of a todo both keep their change, and the later of two changes to
+//! the same field wins everywhere.
+//!
+//! Named lists sync the same way, as records with a `kind` of "list" so
+//! they aren't taken for todos.
+//!
+//! The engine and `SyncClient` don't touch the DOM, a `Transport` does the
+//! talking, so they can be tested natively against a server on loopback.
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::rc::Rc;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use super::store::{Item, ListInfo};
+
+
+/// A field's value and when and by whom it was written.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+  pub value: Value,
+  /// Milliseconds since the epoch, by the writer's clock.
+  pub at: i64,
+  /// The writing client's id, which breaks ties.
+  pub by: String
+}
+
+
+impl Field {
+  /// Whether this write wins over `other`.
+  pub fn is_newer_than(&self, other: &Field) -> bool {
+    (self.at, &self.by) > (other.at, &other.by)
+  }
+}
+
+
+pub type Fields = BTreeMap<String, Field>;
+
+
+/// A todo as the server keeps it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+  pub id: Uuid,
+  #[serde(default)]
+  pub fields: Fields
+}
+
+
+/// What the server answers a pull with.
+#[derive(Deserialize)]
+struct Changes {
+  seq: i64,
+  todos: Vec<Record>
+}
+
+
+/// A request for the server, its path relative to the server's URL.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request {
+  pub method: &'static str,
+  pub path: String,
+  pub body: Option<String>
+}
+
+
+/// A change waiting to be pushed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Pending {
+  Create(Record),
+  Patch(Record),
+  Delete { id: Uuid, field: Field }
+}
+
+
+impl Pending {
+  fn request(&self) -> Request {
+    match self {
+      Pending::Create(record) => {
+        Request {
+          method: "POST",
+          path: "/todos".to_string(),
+          body: serde_json::to_string(record).ok()
+        }
+      }
+      Pending::Patch(record) => {
+        Request {
+          method: "PATCH",
+          path: format!("/todos/{}", record.id),
+          body: serde_json::to_string(&serde_json::json!({ "fields": record.fields })).ok()
+        }
+      }
+      Pending::Delete { id, field } => {
+        Request {
+          method: "DELETE",
+          path: format!("/todos/{}", id),
+          body: serde_json::to_string(&serde_json::json!({ "at": field.at, "by": field.by })).ok()
+        }
+      }
+    }
+  }
+}
+
+
+/// Something's fields as JSON, without its id but with whether it's deleted.
+fn values_of(value: &impl Serialize) -> Map<String, Value> {
+  let mut values =
+    match serde_json::to_value(value) {
+      Ok(Value::Object(values)) => { values }
+      _ => { Map::new() }
+    };
+  values.remove("id");
+  values.insert("deleted".to_string(), Value::Bool(false));
+  values
+}
+
+
+/// An item's fields as JSON, without its id but with whether it's deleted.
+pub fn values(item: &Item) -> Map<String, Value> {
+  values_of(item)
+}
+
+
+/// The `kind` of the records of lists. Todos have none.
+const LIST: &str = "list";
+
+
+/// A list's fields as JSON, like `values`.
+fn list_values(list: &ListInfo) -> Map<String, Value> {
+  let mut values = values_of(list);
+  values.insert("kind".to_string(), Value::String(LIST.to_string()));
+  values
+}
+
+
+fn is_list(fields: &Fields) -> bool {
+  fields
+    .get("kind")
+    .map(|field| field.value == Value::String(LIST.to_string()))
+    .unwrap_or(false)
+}
+
+
+fn is_deleted(fields: &Fields) -> bool {
+  fields
+    .get("deleted")
+    .map(|field| field.value == Value::Bool(true))
+    .unwrap_or(false)
+}
+
+
+/// What a record's fields make, if they make one.
+fn from_fields<T: DeserializeOwned>(id: Uuid, fields: &Fields) -> Result<T, serde_json::Error> {
+  let values:Map<String, Value> =
+    fields
+    .iter()
+    .filter(|(name, _)| !["deleted", "kind"].contains(&name.as_str()))
+    .map(|(name, field)| (name.clone(), field.value.clone()))
+    .chain(Some(("id".to_string(), Value::String(id.to_string()))))
+    .collect();
+  serde_json::from_value(Value::Object(values))
+}
+
+
+/// The item that a todo's fields make, if they make one.
+pub fn item(id: Uuid, fields: &Fields) -> Option<Item> {
+  if is_list(fields) {
+    return None;
+  }
+  from_fields(id, fields)
+    .map_err(|e| warn!("Skipping synced todo {}, {}", id, e))
+    .ok()
+}
+
+
+/// The list that a list's fields make, if they make one.
+fn list(id: Uuid, fields: &Fields) -> Option<ListInfo> {
+  from_fields(id, fields)
+    .map_err(|e| warn!("Skipping synced list {}, {}", id, e))
+    .ok()
+}
+
+
+/// What the app last saved of one kind of record, and their order.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Saved {
+  values: HashMap<Uuid, Map<String, Value>>,
+  order: Vec<Uuid>
+}
+
+
+/// What a client knows of the todos and what it has yet to push.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncEngine {
+  /// This client's id, which its writes are stamped with.
+  pub client: String,
+  /// Every todo's and list's fields, deleted ones included.
+  todos: BTreeMap<Uuid, Fields>,
+  /// The order of the todos that aren't deleted.
+  order: Vec<Uuid>,
+  /// The todos' values as the app last saved them.
+  saved: HashMap<Uuid, Map<String, Value>>,
+  /// The lists as the app last saved them, with the order of those that
+  /// aren't deleted.
+  #[serde(default)]
+  lists: Saved,
+  /// Changes not yet pushed, oldest first.
+  outbox: VecDeque<Pending>,
+  /// The server's sequence number as of the last pull.
+  since: i64,
+  /// The latest stamp made or seen.
+  #[serde(default)]
+  clock: i64
+}
+
+
+impl SyncEngine {
+  pub fn new(client: String) -> SyncEngine {
+    SyncEngine { client, ..SyncEngine::default() }
+  }
+
+  /// The todos that aren't deleted, in order.
+  pub fn items(&self) -> Vec<Item> {
+    self
+      .order
+      .iter()
+      .filter_map(|id| item(*id, self.todos.get(id)?))
+      .collect()
+  }
+
+  /// The lists that aren't deleted, in order.
+  pub fn lists(&self) -> Vec<ListInfo> {
+    self
+      .lists
+      .order
+      .iter()
+      .filter_map(|id| list(*id, self.todos.get(id)?))
+      .collect()
+  }
+
+  /// How many changes are waiting to be pushed.
+  pub fn num_pending(&self) -> usize {
+    self.outbox.len()
+  }
+
+  fn field(&self, value: Value, at: i64) -> Field {
+    Field { value, at, by: self.client.clone() }
+  }
+
+  /// The stamp of a write made at `now`, which is after every stamp so far.
+  fn stamp(&mut self, now: i64) -> i64 {
+    self.clock = now.max(self.clock + 1);
+    self.clock
+  }
+
+  /// Queue a change for each of the app's todos that differs from what's
+  /// known. A value only counts as changed if it differs both from what the
+  /// app saved last and from what's known, so values the app hasn't caught
+  /// up with yet, eg. because a todo is being edited, aren't sent back.
+  pub fn record(&mut self, items: &[Item], now: i64) {
+    let records = items.iter().map(|item| (item.id, values(item))).collect();
+    let last = Saved {
+      values: std::mem::take(&mut self.saved),
+      order: std::mem::take(&mut self.order)
+    };
+    let saved = self.record_values(records, last, now);
+    self.saved = saved.values;
+    self.order = saved.order;
+  }
+
+  /// Queue a change for each of the app's lists that differs from what's
+  /// known, like `record`.
+  pub fn record_lists(&mut self, lists: &[ListInfo], now: i64) {
+    let records = lists.iter().map(|list| (list.id, list_values(list))).collect();
+    let last = std::mem::take(&mut self.lists);
+    self.lists = self.record_values(records, last, now);
+  }
+
+  /// Queue the changes in `records` of one kind, given what the app saved of
+  /// them last. Returns what it saved now.
+  fn record_values(
+    &mut self,
+    records: Vec<(Uuid, Map<String, Value>)>,
+    last: Saved,
+    now: i64
+  ) -> Saved {
+    let at = self.stamp(now);
+    let mut saved = HashMap::new();
+    for (id, values) in records.iter() {
+      let may_known = self.todos.get(id);
+      let may_saved = last.values.get(id);
+      let changed:Fields =
+        values
+        .iter()
+        .filter(|(name, value)| {
+          let is_known = may_known.and_then(|fields| fields.get(*name)).map(|field| &field.value == *value);
+          let is_saved = may_saved.and_then(|saved| saved.get(*name)).map(|saved| saved == *value);
+          !is_known.unwrap_or(false) && !is_saved.unwrap_or(false)
+        })
+        .map(|(name, value)| (name.clone(), self.field(value.clone(), at)))
+        .collect();
+      if !changed.is_empty() {
+        let record = Record { id: *id, fields: changed.clone() };
+        self.outbox.push_back(
+          if may_known.is_some() {
+            Pending::Patch(record)
+          } else {
+            Pending::Create(record)
+          }
+        );
+        self.todos.entry(*id).or_default().extend(changed);
+      }
+      saved.insert(*id, values.clone());
+    }
+
+    // Those the app had but doesn't any more were deleted.
+    let deleted:Vec<Uuid> =
+      last
+      .values
+      .keys()
+      .filter(|id| !saved.contains_key(*id))
+      .filter(|id| self.todos.get(*id).map(|fields| !is_deleted(fields)).unwrap_or(false))
+      .cloned()
+      .collect();
+    for id in deleted {
+      let field = self.field(Value::Bool(true), at);
+      self.outbox.push_back(Pending::Delete { id, field: field.clone() });
+      if let Some(fields) = self.todos.get_mut(&id) {
+        fields.insert("deleted".to_string(), field);
+      }
+    }
+
+    // The app's order wins, those it hasn't seen yet keep their place after.
+    let mut order:Vec<Uuid> = records.iter().map(|(id, _)| *id).collect();
+    order.extend(last.order.iter().filter(|id| !saved.contains_key(*id)).cloned());
+    order.retain(|id| self.todos.get(id).map(|fields| !is_deleted(fields)).unwrap_or(false));
+    Saved { values: saved, order }
+  }
+
+  /// Take in a todo from the server, field by field where the server's write
+  /// is newer. Returns whether any value changed.
+  fn merge(&mut self, record: Record) -> bool {
+    let id = record.id;
+    let fields = self.todos.entry(id).or_default();
+    let mut is_changed = false;
+    for (name, remote) in record.fields {
+      self.clock = self.clock.max(remote.at);
+      let is_newer =
+        fields
+        .get(&name)
+        .map(|local| remote.is_newer_than(local))
+        .unwrap_or(true);
+      if is_newer {
+        is_changed = is_changed || fields.get(&name).map(|local| local.value != remote.value).unwrap_or(true);
+        fields.insert(name, remote);
+      }
+    }
+    let is_live = !is_deleted(fields);
+    let order =
+      if is_list(fields) {
+        &mut self.lists.order
+      } else {
+        &mut self.order
+      };
+    let is_in_order = order.contains(&id);
+    if is_live && !is_in_order {
+      order.push(id);
+    } else if !is_live && is_in_order {
+      order.retain(|ordered| *ordered != id);
+    }
+    is_changed
+  }
+
+  /// The oldest change that hasn't been pushed.
+  pub fn next_push(&self) -> Option<Request> {
+    self.outbox.front().map(Pending::request)
+  }
+
+  /// The oldest change was pushed and the server answered with `body`.
+  /// Returns whether any value changed.
+  pub fn pushed(&mut self, body: &str) -> bool {
+    match self.outbox.pop_front() {
+      Some(Pending::Create(_)) | Some(Pending::Patch(_)) => {
+        match serde_json::from_str::<Record>(body) {
+          Ok(record) => { self.merge(record) }
+          Err(e) => {
+            warn!("Could not read the server's todo, {}", e);
+            false
+          }
+        }
+      }
+      _ => { false }
+    }
+  }
+
+  /// The oldest change was refused and will never land, so it's dropped.
+  pub fn refused(&mut self) {
+    self.outbox.pop_front();
+  }
+
+  pub fn pull_request(&self) -> Request {
+    Request { method: "GET", path: format!("/changes?since={}", self.since), body: None }
+  }
+
+  /// The server answered a pull with `body`. Returns whether any value
+  /// changed.
+  pub fn pulled(&mut self, body: &str) -> Result<bool, String> {
+    let changes:Changes = serde_json::from_str(body).map_err(|e| e.to_string())?;
+    let mut is_changed = false;
+    for record in changes.todos {
+      is_changed = self.merge(record) || is_changed;
+    }
+    self.since = changes.seq;
+    Ok(is_changed)
+  }
+}
+
+
+/// The status and body of the server's answer, or why it couldn't be
+/// reached.
+pub type Answer = Result<(u16, String), String>;
+
+
+/// How requests get to the server, eg. by fetching in the browser.
+pub trait Transport {
+  /// Send the request and hand the answer to `done`, now or later.
+  fn send(&self, request: Request, done: Box<dyn FnOnce(Answer)>);
+}
+
+
+/// A callback that can be set after the client is made.
+type Listener<F> = Rc<RefCell<Option<Box<F>>>>;
+
+
+/// Drives a `SyncEngine` over a `Transport`.
+#[derive(Clone)]
+pub struct SyncClient {
+  engine: Rc<RefCell<SyncEngine>>,
+  transport: Rc<dyn Transport>,
+  /// Whether a push is waiting for its answer.
+  is_pushing: Rc<Cell<bool>>,
+  /// Told the todos whenever syncing changes them.
+  on_change: Listener<dyn Fn(Vec<Item>)>,
+  /// Told the engine's state whenever it changes, to keep it.
+  on_state: Listener<dyn Fn(&SyncEngine)>
+}
+
+
+impl SyncClient {
+  pub fn new(engine: SyncEngine, transport: Rc<dyn Transport>) -> SyncClient {
+    SyncClient {
+      engine: Rc::new(RefCell::new(engine)),
+      transport,
+      is_pushing: Rc::new(Cell::new(false)),
+      on_change: Rc::new(RefCell::new(None)),
+      on_state: Rc::new(RefCell::new(None))
+    }
+  }
+
+  pub fn set_on_change(&self, f: Box<dyn Fn(Vec<Item>)>) {
+    *self.on_change.borrow_mut() = Some(f);
+  }
+
+  pub fn set_on_state(&self, f: Box<dyn Fn(&SyncEngine)>) {
+    *self.on_state.borrow_mut() = Some(f);
+  }
+
+  /// The todos as the engine knows them.
+  pub fn items(&self) -> Vec<Item> {
+    self.engine.borrow().items()
+  }
+
+  /// The lists as the engine knows them.
+  pub fn lists(&self) -> Vec<ListInfo> {
+    self.engine.borrow().lists()
+  }
+
+  pub fn num_pending(&self) -> usize {
+    self.engine.borrow().num_pending()
+  }
+
+  fn changed(&self, is_changed: bool) {
+    if let Some(f) = self.on_state.borrow().as_ref() {
+      f(&self.engine.borrow());
+    }
+    if is_changed {
+      let items = self.items();
+      if let Some(f) = self.on_change.borrow().as_ref() {
+        f(items);
+      }
+    }
+  }
+
+  /// Queue the changes in the app's todos and push them.
+  pub fn save(&self, items: &[Item], now: i64) {
+    self.engine.borrow_mut().record(items, now);
+    self.changed(false);
+    self.push();
+  }
+
+  /// Queue the changes in the app's lists and push them.
+  pub fn save_lists(&self, lists: &[ListInfo], now: i64) {
+    self.engine.borrow_mut().record_lists(lists, now);
+    self.changed(false);
+    self.push();
+  }
+
+  /// Push queued changes one at a time, until they're all pushed or the
+  /// server can't be reached.
+  pub fn push(&self) {
+    if self.is_pushing.get() {
+      return;
+    }
+    let request =
+      match self.engine.borrow().next_push() {
+        Some(request) => { request }
+        None => { return; }
+      };
+    self.is_pushing.set(true);
+    let client = self.clone();
+    self.transport.send(request, Box::new(move |answer| {
+      client.is_pushing.set(false);
+      match answer {
+        Ok((status, body)) if status < 300 => {
+          let is_changed = client.engine.borrow_mut().pushed(&body);
+          client.changed(is_changed);
+          client.push();
+        }
+        Ok((status, body)) if status < 500 => {
+          warn!("The sync server refused a change with {}: {}", status, body);
+          client.engine.borrow_mut().refused();
+          client.changed(false);
+          client.push();
+        }
+        // The change stays queued for the next push.
+        Ok((status, body)) => { warn!("The sync server failed with {}: {}", status, body); }
+        Err(e) => { warn!("Could not reach the sync server, {}", e); }
+      }
+    }));
+  }
+
+  /// Pull the todos changed on the server since the last pull.
+  pub fn pull(&self) {
+    let request = self.engine.borrow().pull_request();
+    let client = self.clone();
+    self.transport.send(request, Box::new(move |answer| {
+      let result =
+        match answer {
+          Ok((200, body)) => { client.engine.borrow_mut().pulled(&body) }
+          Ok((status, body)) => { Err(format!("{}: {}", status, body)) }
+          Err(e) => { Err(e) }
+        };
+      match result {
+        Ok(is_changed) => { client.changed(is_changed); }
+        Err(e) => { warn!("Could not pull todos, {}", e); }
+      }
+    }));
+  }
+
+  /// Push what's queued, then pull.
+  pub fn sync(&self) {
+    self.push();
+    self.pull();
+  }
+}
@@ -1,5 +1,8 @@
-use web_sys::{Event, HtmlElement, HtmlInputElement};
-use wasm_bindgen::JsCast;
+use chrono::{Local, NaiveDate};
+use web_sys::{Blob, BlobPropertyBag, DragEvent, Event, File, FileList, FileReader, HtmlAnchorElement, HtmlElement, HtmlInputElement, HtmlTextAreaElement, Url};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use mogwai::utils::{document, timeout};
 
 
 pub fn set_panic_hook() {
@@ -14,6 +17,18 @@ pub fn set_panic_hook() {
 }
 
 
+/// Whether the event happened in an input or textarea, where keys are for
+/// typing.
+pub fn is_typing(ev:&Event) -> bool {
+  ev.target()
+    .map(|target| {
+      target.dyn_ref::<HtmlInputElement>().is_some()
+        || target.dyn_ref::<HtmlTextAreaElement>().is_some()
+    })
+    .unwrap_or(false)
+}
+
+
 pub fn input_value(input:&HtmlElement) -> Option<String> {
   let input:HtmlInputElement =
     input
@@ -42,3 +57,105 @@ pub fn event_input_value(ev:&Event) -> Option<String> {
       .to_string()
   )
 }
+
+
+/// Offer `contents` to the user as a file download named `filename`.
+pub fn download(filename: &str, mime: &str, contents: &str) -> Result<(), JsValue> {
+  let parts = js_sys::Array::of1(&JsValue::from_str(contents));
+  let mut options = BlobPropertyBag::new();
+  options.type_(mime);
+  let blob = Blob::new_with_str_sequence_and_options(&parts, &options)?;
+  let url = Url::create_object_url_with_blob(&blob)?;
+
+  let a:HtmlAnchorElement =
+    document()
+    .create_element("a")?
+    .dyn_into()?;
+  a.set_href(&url);
+  a.set_download(filename);
+  a.click();
+
+  // Some browsers start the download after the click returns, so the url has
+  // to outlive it for a while.
+  timeout(1000, move || {
+    Url::revoke_object_url(&url)
+      .unwrap_or_else(|e| error!("Could not revoke a download's url: {:?}", e));
+    // Never reschedule the timeout
+    false
+  });
+  Ok(())
+}
+
+
+/// Whether the user is dragging files, rather than something on the page.
+pub fn is_dragging_files(ev:&Event) -> bool {
+  ev.dyn_ref::<DragEvent>()
+    .and_then(|ev| ev.data_transfer())
+    .map(|data| data.types().includes(&JsValue::from_str("Files"), 0))
+    .unwrap_or(false)
+}
+
+
+fn file_list(files:Option<FileList>) -> Vec<File> {
+  files
+    .map(|files| (0..files.length()).filter_map(|n| files.get(n)).collect())
+    .unwrap_or_default()
+}
+
+
+/// The files dropped by a drop event.
+pub fn dropped_files(ev:&Event) -> Vec<File> {
+  file_list(
+    ev.dyn_ref::<DragEvent>()
+      .and_then(|ev| ev.data_transfer())
+      .and_then(|data| data.files())
+  )
+}
+
+
+/// The files picked in a file input, which is then cleared so that picking
+/// the same file again still fires "change".
+pub fn event_input_files(ev:&Event) -> Vec<File> {
+  ev.target()
+    .and_then(|target| target.dyn_into::<HtmlInputElement>().ok())
+    .map(|input| {
+      let files = file_list(input.files());
+      input.set_value("");
+      files
+    })
+    .unwrap_or_default()
+}
+
+
+/// Read a file as text, calling `f` with the text once it's read, or with
+/// the error if reading fails or is aborted.
+pub fn read_file(file:&File, f:Box<dyn FnOnce(Result<String, JsValue>)>) -> Result<(), JsValue> {
+  let reader = FileReader::new()?;
+  let on_done_reader = reader.clone();
+  // Only one of load, error and abort fires, and that frees the closure.
+  let on_done = Closure::once_into_js(move |_:Event| {
+    if let Some(error) = on_done_reader.error() {
+      f(Err(error.into()));
+      return;
+    }
+    let text =
+      on_done_reader
+      .result()
+      .and_then(|result| {
+        result
+          .as_string()
+          .ok_or_else(|| JsValue::from_str("the file is not text"))
+      });
+    f(text);
+  });
+  reader.set_onload(Some(on_done.unchecked_ref()));
+  reader.set_onerror(Some(on_done.unchecked_ref()));
+  reader.set_onabort(Some(on_done.unchecked_ref()));
+  reader.read_as_text(file)
+}
+
+
+/// The date where the user is.
+pub fn today() -> NaiveDate {
+  Local::now().date_naive()
+}
@@ -0,0 +1,251 @@
+//! Tests of collaborators settling their changes, with simulated clients
+//! and relay.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+extern crate mogwai_todo;
+use std::collections::BTreeMap;
+use mogwai_todo::collab::{Collab, Message, Op, Remote};
+use mogwai_todo::model::{Command, TodoList};
+use mogwai_todo::recurrence::Recurrence;
+use mogwai_todo::store::{Item, Priority};
+
+
+/// A collaborator with their own copy of the list, as the app keeps it.
+#[derive(Clone)]
+struct Client {
+  collab: Collab,
+  list: TodoList
+}
+
+
+impl Client {
+  fn new(id: &str) -> Client {
+    Client {
+      collab: Collab::new(id.to_string(), id.to_uppercase()),
+      list: TodoList::new()
+    }
+  }
+
+  /// Change the list here, returning the messages that share the change.
+  fn act(&mut self, command: Command, now: i64) -> Vec<Message> {
+    self
+      .list
+      .handle(command)
+      .iter()
+      .filter_map(Op::from_event)
+      .map(|op| self.collab.local(op, now))
+      .collect()
+  }
+
+  /// Take in a message from the relay, making the changes that win.
+  fn receive(&mut self, message: Message) -> Vec<Remote> {
+    let remotes = self.collab.receive(message);
+    for remote in remotes.iter() {
+      if let Remote::Apply(op) = remote {
+        if let Some(command) = op.command(&self.list) {
+          self.list.handle(command);
+        }
+      }
+    }
+    remotes
+  }
+}
+
+
+/// Pass each batch of messages to everyone but its sender, in order.
+fn relay(clients: &mut [Client], batches: Vec<(usize, Vec<Message>)>) {
+  for (from, messages) in batches {
+    for message in messages {
+      for (to, client) in clients.iter_mut().enumerate() {
+        if to != from {
+          client.receive(message.clone());
+        }
+      }
+    }
+  }
+}
+
+
+/// Every order three senders' batches can reach the relay in.
+const ORDERS: [[usize; 3]; 6] = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+
+
+/// The todos everyone has, in the first client's order. Order isn't
+/// shared, so it's not compared.
+fn everyone_has_the_same(clients: &[Client]) -> Vec<Item> {
+  let by_id = |client: &Client| {
+    let mut items = client.list.items().to_vec();
+    items.sort_by_key(|item| item.id);
+    items
+  };
+  let items = by_id(&clients[0]);
+  for client in clients {
+    assert_eq!(by_id(client), items, "{} differs", client.collab.me.id);
+  }
+  clients[0].list.items().to_vec()
+}
+
+
+/// Three clients that all have "Buy milk".
+fn room() -> (Vec<Client>, Item) {
+  let mut clients = vec![Client::new("a"), Client::new("b"), Client::new("c")];
+  let milk = Item::new("Buy milk".to_string());
+  let added = clients[0].act(Command::Add(milk.clone()), 100);
+  relay(&mut clients, vec![(0, added)]);
+  (clients, milk)
+}
+
+
+#[test]
+fn changes_made_at_the_same_time_settle_the_same_in_any_order() {
+  let (clients, milk) = room();
+  let mut settled = vec![];
+  for order in ORDERS.iter() {
+    let mut clients = clients.clone();
+    let mut by_c = clients[2].act(Command::Rename(milk.id, "Buy whole milk".to_string()), 200);
+    by_c.extend(clients[2].act(Command::SetPriority(milk.id, Priority::High), 200));
+    let batches = [
+      (0, clients[0].act(Command::SetCompletion(milk.id, true), 150)),
+      (1, clients[1].act(Command::Rename(milk.id, "Buy oat milk".to_string()), 200)),
+      (2, by_c)
+    ];
+    relay(&mut clients, order.iter().map(|i| batches[*i].clone()).collect());
+    settled.push(everyone_has_the_same(&clients));
+  }
+
+  let item = &settled[0][0];
+  // The renames have the same stamp, so c's id settles it.
+  assert_eq!(item.title, "Buy whole milk");
+  assert!(item.completed);
+  assert_eq!(item.priority, Priority::High);
+  assert!(settled.iter().all(|items| items == &settled[0]));
+}
+
+
+#[test]
+fn a_change_made_after_seeing_another_wins_whatever_the_clocks_say() {
+  let (mut clients, milk) = room();
+  let renamed = clients[1].act(Command::Rename(milk.id, "Buy oat milk".to_string()), 500);
+  relay(&mut clients, vec![(1, renamed)]);
+  // C's clock is behind B's.
+  let renamed = clients[2].act(Command::Rename(milk.id, "Buy soy milk".to_string()), 300);
+  relay(&mut clients, vec![(2, renamed)]);
+  assert_eq!(everyone_has_the_same(&clients)[0].title, "Buy soy milk");
+}
+
+
+#[test]
+fn a_removal_wins_over_changes_made_meanwhile_until_its_undone() {
+  let (clients, milk) = room();
+  for order in [[1, 2], [2, 1]].iter() {
+    let mut clients = clients.clone();
+    let batches = [
+      (0, vec![]),
+      (1, clients[1].act(Command::Remove(milk.id), 200)),
+      (2, clients[2].act(Command::Rename(milk.id, "Buy oat milk".to_string()), 300))
+    ];
+    relay(&mut clients, order.iter().map(|i| batches[*i].clone()).collect());
+    assert!(everyone_has_the_same(&clients).is_empty());
+
+    let undone = clients[1].act(Command::Undo, 400);
+    relay(&mut clients, vec![(1, undone)]);
+    assert_eq!(everyone_has_the_same(&clients).len(), 1);
+  }
+}
+
+
+#[test]
+fn a_completed_repeating_todo_repeats_once() {
+  let (mut clients, _) = room();
+  let plants = Item { recurrence: Some(Recurrence::Daily), ..Item::new("Water the plants".to_string()) };
+  let added = clients[1].act(Command::Add(plants.clone()), 200);
+  relay(&mut clients, vec![(1, added)]);
+
+  let completed = clients[1].act(Command::SetCompletion(plants.id, true), 300);
+  relay(&mut clients, vec![(1, completed)]);
+  let items = everyone_has_the_same(&clients);
+  let titles:Vec<&str> = items.iter().map(|item| item.title.as_str()).collect();
+  assert_eq!(titles, vec!["Buy milk", "Water the plants", "Water the plants"]);
+}
+
+
+#[test]
+fn todos_made_before_joining_are_shared_once_caught_up() {
+  let mut a = Client::new("a");
+  let log = a.act(Command::Add(Item::new("Buy milk".to_string())), 100);
+  let mut d = Client::new("d");
+  d.list.handle(Command::Load(Item::new("Walk the dog".to_string())));
+
+  // D joins, and the relay replays the room's log.
+  for message in log {
+    d.receive(message);
+  }
+  assert_eq!(d.receive(Message::CaughtUp), vec![Remote::CaughtUp]);
+  let items = d.list.items().to_vec();
+  let shared = d.collab.share(&items, 200);
+  assert_eq!(shared.len(), 1);
+  let mut clients = vec![a, d];
+  relay(&mut clients, vec![(1, shared)]);
+  assert_eq!(everyone_has_the_same(&clients).len(), 2);
+  assert!(clients[1].collab.share(&items, 300).is_empty());
+}
+
+
+#[test]
+fn todos_edited_before_catching_up_are_still_shared() {
+  let mut a = Client::new("a");
+  let mut d = Client::new("d");
+  let dog = Item::new("Walk the dog".to_string());
+  d.list.handle(Command::Load(dog.clone()));
+
+  // D renames a todo the room doesn't have before the relay says it's caught
+  // up. The rename goes to a, who can't make it yet.
+  let renamed = d.act(Command::Rename(dog.id, "Walk the dogs".to_string()), 100);
+  assert_eq!(d.receive(Message::CaughtUp), vec![Remote::CaughtUp]);
+  let items = d.list.items().to_vec();
+  let shared = d.collab.share(&items, 200);
+  assert_eq!(shared.len(), 1);
+  for message in renamed.into_iter().chain(shared) {
+    a.receive(message);
+  }
+  let clients = vec![a, d];
+  assert_eq!(everyone_has_the_same(&clients)[0].title, "Walk the dogs");
+}
+
+
+#[test]
+fn presence_tells_who_is_editing_which_todo() {
+  let (mut clients, milk) = room();
+  let editing = clients[0].collab.edit(Some(milk.id));
+  let mut expected = BTreeMap::new();
+  expected.insert(milk.id, vec!["A".to_string()]);
+  assert_eq!(clients[1].receive(editing), vec![Remote::Editors(expected.clone())]);
+
+  let editing = clients[2].collab.edit(Some(milk.id));
+  expected.insert(milk.id, vec!["A".to_string(), "C".to_string()]);
+  assert_eq!(clients[1].receive(editing), vec![Remote::Editors(expected.clone())]);
+
+  expected.insert(milk.id, vec!["C".to_string()]);
+  assert_eq!(clients[1].receive(Message::Left { id: "a".to_string() }), vec![Remote::Editors(expected)]);
+  let stopped = clients[2].collab.edit(None);
+  assert_eq!(clients[1].receive(stopped), vec![Remote::Editors(BTreeMap::new())]);
+}
+
+
+#[test]
+fn messages_are_what_the_relay_expects() {
+  let milk = Item::new("Buy milk".to_string());
+  let mut a = Collab::new("a".to_string(), "Ann".to_string());
+  let message = a.local(Op::Rename(milk.id, "Buy oat milk".to_string()), 100);
+  let json = serde_json::to_value(&message).unwrap();
+  assert_eq!(json["type"], "op");
+  assert_eq!(json["by"], "a");
+  assert_eq!(serde_json::from_value::<Message>(json).unwrap(), message);
+
+  let json = serde_json::to_value(a.edit(Some(milk.id))).unwrap();
+  assert_eq!(json["type"], "presence");
+  assert_eq!(json["id"], "a");
+  let caught_up:Message = serde_json::from_str(r#"{"type": "caught_up"}"#).unwrap();
+  assert_eq!(caught_up, Message::CaughtUp);
+}
@@ -0,0 +1,398 @@
+//! Tests that exported todos read back as they were written.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+extern crate mogwai_todo;
+use chrono::{NaiveDate, Weekday};
+use mogwai_todo::formats::{self, read, write, Format, Parsed, Problem};
+use mogwai_todo::recurrence::Recurrence;
+use mogwai_todo::store::{Item, Priority};
+use uuid::Uuid;
+
+
+fn today() -> NaiveDate {
+  NaiveDate::from_ymd_opt(2026, 10, 17).unwrap()
+}
+
+
+fn date(m: u32, d: u32) -> NaiveDate {
+  NaiveDate::from_ymd_opt(2026, m, d).unwrap()
+}
+
+
+/// A walk, some shopping with a subtask and notes that need quoting, and a
+/// chore that repeats.
+fn items() -> Vec<Item> {
+  let dog = Item {
+    completed: true,
+    priority: Priority::High,
+    tags: vec!["home".to_string(), "pets".to_string()],
+    ..Item::new("Walk the dog".to_string())
+  };
+  let milk = Item {
+    due: Some(date(11, 1)),
+    starred: true,
+    list: Uuid::parse_str("5f3c1a2e-8d4b-4c6a-9e7f-0a1b2c3d4e5f").unwrap(),
+    notes: "Oat, not \"dairy\".\n\nTwo cartons.".to_string(),
+    ..Item::new("Buy milk".to_string())
+  };
+  let oat = Item {
+    parent: Some(milk.id),
+    list: milk.list,
+    priority: Priority::Low,
+    ..Item::new("Find the oat kind".to_string())
+  };
+  let plants = Item {
+    recurrence: Some(Recurrence::Weekly { days: vec![Weekday::Mon, Weekday::Thu] }),
+    completions: vec![date(10, 12), date(10, 15)],
+    due: Some(date(10, 19)),
+    ..Item::new("Water the plants".to_string())
+  };
+  vec![dog, milk, oat, plants]
+}
+
+
+/// What every format keeps of a todo: its title, completion, priority, tags
+/// and due date.
+type Basics = (String, bool, Priority, Vec<String>, Option<NaiveDate>);
+
+
+fn basics(items: &[Item]) -> Vec<Basics> {
+  items
+    .iter()
+    .map(|item| (item.title.clone(), item.completed, item.priority, item.tags.clone(), item.due))
+    .collect()
+}
+
+
+fn round_trip(format: Format, items: &[Item]) -> Vec<Item> {
+  let text = write(format, items);
+  let parsed = read(format, &text, today());
+  assert_eq!(parsed.problems, vec![], "{}:\n{}", format.name(), text);
+  parsed.items
+}
+
+
+#[test]
+fn json_and_csv_keep_everything() {
+  let items = items();
+  assert_eq!(round_trip(Format::Json, &items), items);
+  assert_eq!(round_trip(Format::Csv, &items), items);
+}
+
+
+#[test]
+fn icalendar_keeps_ids_subtasks_notes_and_repeats() {
+  let items = items();
+  let read = round_trip(Format::ICalendar, &items);
+  assert_eq!(basics(&read), basics(&items));
+  let ids:Vec<Uuid> = read.iter().map(|item| item.id).collect();
+  assert_eq!(ids, items.iter().map(|item| item.id).collect::<Vec<_>>());
+  assert_eq!(read[2].parent, Some(read[1].id));
+  assert_eq!(read[1].notes, items[1].notes);
+  assert_eq!(read[3].recurrence, items[3].recurrence);
+}
+
+
+#[test]
+fn markdown_keeps_subtasks_notes_and_stars() {
+  let items = items();
+  let read = round_trip(Format::Markdown, &items);
+  assert_eq!(basics(&read), basics(&items));
+  assert_eq!(read[2].parent, Some(read[1].id));
+  assert_eq!(read[1].notes, items[1].notes);
+  assert!(read[1].starred);
+
+  // Notes that look like todos stay notes.
+  let mut items = items;
+  items[1].notes = "- [ ] not a subtask\n  * [x] nor this\n\\ a backslash".to_string();
+  let read = round_trip(Format::Markdown, &items);
+  assert_eq!(read.len(), items.len());
+  assert_eq!(read[1].notes, items[1].notes);
+}
+
+
+#[test]
+fn todo_txt_keeps_completion_priority_tags_and_due_dates() {
+  let items = items();
+  let read = round_trip(Format::TodoTxt, &items);
+  assert_eq!(basics(&read), basics(&items));
+}
+
+
+/// Titles with words that look like the syntax of todo.txt or quick-add.
+fn titles_like_syntax() -> Vec<Item> {
+  [
+    "x marks the spot",
+    "(A) grade paper",
+    "2026-10-01 retro notes",
+    "Learn C++ +more @home due:friday pri:B",
+    "Ask about #1 and #tags",
+    "Meet @2026-11-01 or @fri",
+    "Say hi! !1 then !!",
+    "Rate it * out of 5",
+    "A \\ path \\x",
+  ]
+    .iter()
+    .map(|title| Item::new(title.to_string()))
+    .collect()
+}
+
+
+#[test]
+fn titles_like_syntax_survive_markdown_and_todo_txt() {
+  let items = titles_like_syntax();
+  for format in [Format::Markdown, Format::TodoTxt] {
+    let read = round_trip(format, &items);
+    assert_eq!(basics(&read), basics(&items), "{}", format.name());
+    assert!(read.iter().all(|item| !item.starred), "{}", format.name());
+  }
+  let completed:Vec<Item> =
+    items
+    .into_iter()
+    .map(|item| Item { completed: true, priority: Priority::Low, ..item })
+    .collect();
+  assert_eq!(basics(&round_trip(Format::TodoTxt, &completed)), basics(&completed));
+}
+
+
+#[test]
+fn writes_each_format_the_way_people_expect() {
+  let items = items();
+  let markdown = write(Format::Markdown, &items);
+  assert!(markdown.starts_with("- [x] Walk the dog #home #pets !1\n- [ ] Buy milk @2026-11-01 *\n"));
+  assert!(markdown.contains("\n  - [ ] Find the oat kind !3\n"));
+
+  let todo_txt = write(Format::TodoTxt, &items);
+  assert!(todo_txt.starts_with("x Walk the dog +home +pets pri:A\nBuy milk due:2026-11-01\n(C) Find"));
+
+  let csv = write(Format::Csv, &items);
+  assert!(csv.starts_with("id,title,completed,due,priority,tags,starred,parent,list,notes,recurrence,completions\r\n"));
+  assert!(csv.contains(",\"Oat, not \"\"dairy\"\".\n\nTwo cartons.\","));
+  assert!(csv.contains(",\"weekly on mon, thu\",2026-10-12 2026-10-15\r\n"));
+
+  assert!(write(Format::Json, &items).contains("\n  \"schema_version\""));
+}
+
+
+#[test]
+fn csv_cells_never_run_as_formulas() {
+  let titles = ["=HYPERLINK(\"http://example.com\")", "+1 more", "-5 degrees", "@home", "'=quoted", "'twas"];
+  let items:Vec<Item> = titles.iter().map(|title| Item::new(title.to_string())).collect();
+  let csv = write(Format::Csv, &items);
+  assert!(csv.contains(",\"'=HYPERLINK(\"\"http://example.com\"\")\","));
+  assert!(csv.contains(",'+1 more,"));
+  assert!(csv.contains(",'-5 degrees,"));
+  assert!(csv.contains(",'@home,"));
+  assert!(csv.contains(",''=quoted,"));
+  assert!(csv.contains(",'twas,"));
+  assert_eq!(round_trip(Format::Csv, &items), items);
+}
+
+
+#[test]
+fn csv_columns_are_found_by_name() {
+  let text = "Title,Done\nBuy milk,yes\n\nWalk the dog,\n";
+  let parsed = read(Format::Csv, text, today());
+  assert_eq!(parsed.problems, vec![]);
+  let titles:Vec<(&str, bool)> =
+    parsed
+    .items
+    .iter()
+    .map(|item| (item.title.as_str(), item.completed))
+    .collect();
+  assert_eq!(titles, vec![("Buy milk", false), ("Walk the dog", false)]);
+
+  let parsed = read(Format::Csv, "title,completed\nBuy milk,yes\n", today());
+  assert!(parsed.items[0].completed);
+}
+
+
+#[test]
+fn malformed_lines_are_reported_with_their_numbers() {
+  let csv = "title,due\nBuy milk,2026-11-01\n\"Walk\nthe dog\",soon\n,2026-11-01\nCall mom,\n";
+  let parsed = read(Format::Csv, csv, today());
+  assert_eq!(parsed.items.len(), 2);
+  assert_eq!(
+    parsed.problems,
+    vec![
+      Problem { line: 3, message: "\"soon\" is not a date like 2026-11-01".to_string() },
+      Problem { line: 5, message: "the todo has no title".to_string() },
+    ]
+  );
+
+  let parsed = read(Format::Csv, "title\nBuy milk\n\"Walk the dog\n", today());
+  assert_eq!(parsed.items.len(), 1);
+  assert_eq!(parsed.problems[0].line, 3);
+
+  let markdown = "# Groceries\n\n- [ ] Buy milk !4\nsome prose\n- [x]\n";
+  let parsed = read(Format::Markdown, markdown, today());
+  assert_eq!(parsed.items.len(), 1);
+  let lines:Vec<usize> = parsed.problems.iter().map(|problem| problem.line).collect();
+  assert_eq!(lines, vec![3, 4, 5]);
+
+  let parsed = read(Format::TodoTxt, "Buy milk\n\nCall mom due:tomorrow\nx \n", today());
+  assert_eq!(parsed.items.len(), 1);
+  let lines:Vec<usize> = parsed.problems.iter().map(|problem| problem.line).collect();
+  assert_eq!(lines, vec![3, 4]);
+
+  let parsed = read(Format::Json, "[\n  {\"title\": \"Buy milk\"\n", today());
+  assert!(parsed.items.is_empty());
+  assert_eq!(parsed.problems[0].line, 3);
+}
+
+
+#[test]
+fn todo_txt_reads_other_apps_files() {
+  let text = "(A) 2026-10-01 Call mom @phone +family\nx 2026-10-16 2026-10-01 Pay rent pri:B\n(D) Someday maybe\n";
+  let parsed = read(Format::TodoTxt, text, today());
+  assert_eq!(parsed.problems, vec![]);
+  let read:Vec<(&str, bool, Priority, Vec<String>)> =
+    parsed
+    .items
+    .iter()
+    .map(|item| (item.title.as_str(), item.completed, item.priority, item.tags.clone()))
+    .collect();
+  assert_eq!(
+    read,
+    vec![
+      ("Call mom", false, Priority::High, vec!["phone".to_string(), "family".to_string()]),
+      ("Pay rent", true, Priority::Medium, vec![]),
+      ("Someday maybe", false, Priority::Low, vec![]),
+    ]
+  );
+}
+
+
+#[test]
+fn markdown_reads_other_bullets_and_nesting() {
+  let text = "* [X] Pack\n    + [ ] Socks\n    + [ ] Toothbrush\n        - [ ] The travel one\n* [ ] Go\n";
+  let parsed = read(Format::Markdown, text, today());
+  assert_eq!(parsed.problems, vec![]);
+  let items = &parsed.items;
+  assert!(items[0].completed);
+  assert_eq!(items[1].parent, Some(items[0].id));
+  assert_eq!(items[2].parent, Some(items[0].id));
+  assert_eq!(items[3].parent, Some(items[2].id));
+  assert_eq!(items[4].parent, None);
+}
+
+
+#[test]
+fn guesses_the_format_from_the_name_or_else_the_text() {
+  assert_eq!(Format::guess("todos.JSON", ""), Format::Json);
+  assert_eq!(Format::guess("groceries.markdown", ""), Format::Markdown);
+  assert_eq!(Format::guess("todo.txt", "- [ ] Buy milk"), Format::TodoTxt);
+  assert_eq!(Format::guess("export", "  [\n]"), Format::Json);
+  assert_eq!(Format::guess("notes", "# Groceries\n- [ ] Buy milk\n"), Format::Markdown);
+  assert_eq!(Format::guess("sheet", "Title,Due\nBuy milk,\n"), Format::Csv);
+  assert_eq!(Format::guess("list", "Buy milk\nCall mom\n"), Format::TodoTxt);
+  assert_eq!(Format::guess("calendar", "BEGIN:VCALENDAR\r\n"), Format::ICalendar);
+}
+
+
+#[test]
+fn imports_leave_out_duplicate_titles_and_get_fresh_ids() {
+  let milk = Item::new("Buy milk".to_string());
+  let text = "- [ ] buy  MILK\n  - [ ] Find the oat kind\n- [ ] Call mom\n- [x] Call Mom\n";
+  let parsed = read(Format::Markdown, text, today());
+  let read_ids:Vec<Uuid> = parsed.items.iter().map(|item| item.id).collect();
+  let import = formats::dedupe(&[&milk], parsed);
+
+  assert_eq!(import.duplicates, 2);
+  let titles:Vec<&str> = import.items.iter().map(|item| item.title.as_str()).collect();
+  assert_eq!(titles, vec!["Find the oat kind", "Call mom"]);
+  assert_eq!(import.items[0].parent, Some(milk.id));
+  assert!(import.items.iter().all(|item| !read_ids.contains(&item.id)));
+}
+
+
+#[test]
+fn imported_subtasks_come_after_their_parents() {
+  let parent = Item::new("Pack".to_string());
+  let child = Item { parent: Some(parent.id), ..Item::new("Socks".to_string()) };
+  let stray = Item { parent: Some(Uuid::new_v4()), ..Item::new("Passport".to_string()) };
+  let parsed = Parsed { items: vec![child, stray, parent], problems: vec![] };
+  let import = formats::dedupe(&[], parsed);
+
+  let titles:Vec<&str> = import.items.iter().map(|item| item.title.as_str()).collect();
+  assert_eq!(titles, vec!["Passport", "Pack", "Socks"]);
+  assert_eq!(import.items[0].parent, None);
+  assert_eq!(import.items[2].parent, Some(import.items[1].id));
+}
+
+
+#[test]
+fn icalendar_folds_long_lines_and_escapes_text() {
+  let title = "Pick up the dry cleaning; shirts, trousers and the \\ long café coat before they close";
+  let item = Item { notes: "Ticket 42.\nAsk for hangers.".to_string(), ..Item::new(title.to_string()) };
+  let text = write(Format::ICalendar, std::slice::from_ref(&item));
+
+  assert!(text.ends_with("END:VCALENDAR\r\n"));
+  let lines:Vec<&str> = text.split("\r\n").collect();
+  assert!(lines.iter().all(|line| line.len() <= 75), "{}", text);
+  assert!(text.contains("SUMMARY:Pick up the dry cleaning\\; shirts\\, trousers and the \\\\ long"));
+  assert!(text.contains("\r\n "));
+  assert!(text.contains("DESCRIPTION:Ticket 42.\\nAsk for hangers."));
+
+  let read = round_trip(Format::ICalendar, &[item]);
+  assert_eq!(read[0].title, title);
+  assert_eq!(read[0].notes, "Ticket 42.\nAsk for hangers.");
+}
+
+
+#[test]
+fn icalendar_reads_other_apps_dates_and_ids() {
+  let text = "BEGIN:VCALENDAR\nVERSION:2.0\n\
+    BEGIN:VTODO\nUID:pack@example.com\nSUMMARY:Pack\nDUE;VALUE=DATE:20261103\nPRIORITY:3\n\
+    BEGIN:VALARM\nACTION:DISPLAY\nDESCRIPTION:Reminder\nEND:VALARM\nEND:VTODO\n\
+    BEGIN:VTODO\nUID:socks@example.com\nSUMMARY:Pack the warm\n\t socks\nDUE:20261101T170000Z\n\
+    RELATED-TO:pack@example.com\nCATEGORIES:Travel,winter\nEND:VTODO\n\
+    BEGIN:VTODO\nSUMMARY:Call the hotel\nDUE;TZID=\"Europe/Berlin\":20261102T090000\n\
+    STATUS:COMPLETED\nRRULE:FREQ=WEEKLY;INTERVAL=2\nEND:VTODO\n\
+    END:VCALENDAR\n";
+  let parsed = read(Format::ICalendar, text, today());
+  assert_eq!(parsed.problems, vec![]);
+  let items = &parsed.items;
+  assert_eq!(basics(items), vec![
+    ("Pack".to_string(), false, Priority::High, vec![], Some(date(11, 3))),
+    ("Pack the warm socks".to_string(), false, Priority::None, vec!["travel".to_string(), "winter".to_string()], Some(date(11, 1))),
+    ("Call the hotel".to_string(), true, Priority::None, vec![], Some(date(11, 2))),
+  ]);
+  assert_eq!(items[0].notes, "");
+  assert_eq!(items[1].parent, Some(items[0].id));
+  assert_eq!(items[2].recurrence, Some(Recurrence::Every { days: 14 }));
+}
+
+
+#[test]
+fn malformed_todos_in_icalendar_are_reported_with_their_lines() {
+  let text = "BEGIN:VCALENDAR\r\n\
+    BEGIN:VTODO\r\nSUMMARY:Buy milk\r\nDUE;VALUE=DATE:soon\r\nEND:VTODO\r\n\
+    BEGIN:VTODO\r\nSTATUS:NEEDS-ACTION\r\nEND:VTODO\r\n\
+    BEGIN:VTODO\r\nSUMMARY:Water the plants\r\nRRULE:FREQ=YEARLY\r\nEND:VTODO\r\n\
+    just some words\r\n\
+    BEGIN:VTODO\r\nSUMMARY:Walk the dog\r\n\
+    END:VCALENDAR\r\n";
+  let parsed = read(Format::ICalendar, text, today());
+  let titles:Vec<&str> = parsed.items.iter().map(|item| item.title.as_str()).collect();
+  assert_eq!(titles, vec!["Water the plants"]);
+  assert_eq!(parsed.items[0].recurrence, None);
+  let lines:Vec<usize> = parsed.problems.iter().map(|problem| problem.line).collect();
+  assert_eq!(lines, vec![4, 6, 11, 13, 14]);
+}
+
+
+#[test]
+fn icalendar_intervals_too_long_to_repeat_by_are_reported() {
+  let text = "BEGIN:VCALENDAR\r\n\
+    BEGIN:VTODO\r\nSUMMARY:Buy milk\r\nRRULE:FREQ=WEEKLY;INTERVAL=1000000000\r\nEND:VTODO\r\n\
+    BEGIN:VTODO\r\nSUMMARY:Walk the dog\r\nRRULE:FREQ=DAILY;INTERVAL=4000000000\r\nEND:VTODO\r\n\
+    BEGIN:VTODO\r\nSUMMARY:Water the plants\r\nRRULE:FREQ=WEEKLY;INTERVAL=522\r\nEND:VTODO\r\n\
+    END:VCALENDAR\r\n";
+  let parsed = read(Format::ICalendar, text, today());
+  assert_eq!(parsed.items.len(), 3);
+  assert!(parsed.items.iter().all(|item| item.recurrence.is_none()));
+  let lines:Vec<usize> = parsed.problems.iter().map(|problem| problem.line).collect();
+  assert_eq!(lines, vec![4, 8, 12]);
+}
@@ -0,0 +1,150 @@
+//! Tests for the undo/redo history.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+extern crate mogwai_todo;
+use chrono::NaiveDate;
+use mogwai_todo::history::{Change, History};
+use mogwai_todo::recurrence::Recurrence;
+use mogwai_todo::store::{Item, ListInfo, Priority};
+
+
+fn item(title: &str) -> Item {
+  Item::new(title.to_string())
+}
+
+
+fn work() -> ListInfo {
+  ListInfo {
+    id: uuid::Uuid::new_v4(),
+    name: "Work".to_string(),
+    slug: "work".to_string()
+  }
+}
+
+
+#[test]
+fn inverse_of_inverse_is_the_change() {
+  let milk = item("Buy milk");
+  let changes = vec![
+    Change::Create { position: 0, item: milk.clone() },
+    Change::Rename { id: milk.id, from: "Buy milk".to_string(), to: "Buy oat milk".to_string() },
+    Change::Complete { id: milk.id, from: false, to: true },
+    Change::Due { id: milk.id, from: None, to: NaiveDate::from_ymd_opt(2026, 11, 1) },
+    Change::Prioritize { id: milk.id, from: Priority::None, to: Priority::High },
+    Change::Tag { id: milk.id, from: vec![], to: vec!["errands".to_string()] },
+    Change::Star { id: milk.id, from: false, to: true },
+    Change::Note { id: milk.id, from: String::new(), to: "Oat\nTwo cartons".to_string() },
+    Change::Repeat { id: milk.id, from: None, to: Some(Recurrence::Daily) },
+    Change::Remove { position: 3, item: milk.clone() },
+    Change::Move { id: milk.id, from: 3, to: 0 },
+    Change::AddList { position: 1, list: work() },
+    Change::RenameList { from: work(), to: ListInfo { name: "Job".to_string(), slug: "job".to_string(), ..work() } },
+    Change::RemoveList { position: 1, list: work() },
+  ];
+  let batch = Change::Batch(changes.clone());
+  for change in changes.into_iter().chain(vec![batch]) {
+    assert_eq!(change.inverse().inverse(), change);
+  }
+}
+
+
+#[test]
+fn batch_inverse_runs_backwards() {
+  let a = item("a");
+  let b = item("b");
+  let batch = Change::Batch(vec![
+    Change::Remove { position: 2, item: b.clone() },
+    Change::Remove { position: 0, item: a.clone() },
+  ]);
+  assert_eq!(
+    batch.inverse(),
+    Change::Batch(vec![
+      Change::Create { position: 0, item: a },
+      Change::Create { position: 2, item: b },
+    ])
+  );
+}
+
+
+#[test]
+fn undo_then_redo() {
+  let milk = item("Buy milk");
+  let create = Change::Create { position: 0, item: milk.clone() };
+  let mut history = History::default();
+  assert_eq!(history.undo(), None);
+
+  history.record(create.clone());
+  assert!(history.can_undo());
+  assert_eq!(history.undo(), Some(create.inverse()));
+  assert!(!history.can_undo());
+  assert!(history.can_redo());
+  assert_eq!(history.redo(), Some(create.clone()));
+  assert_eq!(history.latest(), Some(&create));
+}
+
+
+#[test]
+fn recording_forgets_redo() {
+  let mut history = History::default();
+  history.record(Change::Create { position: 0, item: item("a") });
+  history.undo();
+  history.record(Change::Create { position: 0, item: item("b") });
+  assert!(!history.can_redo());
+}
+
+
+#[test]
+fn capacity_drops_the_oldest_changes() {
+  let mut history = History::new(2);
+  let changes:Vec<Change> =
+    (0 .. 3)
+    .map(|n| Change::Create { position: n, item: item(&n.to_string()) })
+    .collect();
+  changes
+    .iter()
+    .for_each(|change| history.record(change.clone()));
+  assert_eq!(history.undo(), Some(changes[2].inverse()));
+  assert_eq!(history.undo(), Some(changes[1].inverse()));
+  assert_eq!(history.undo(), None);
+}
+
+
+#[test]
+fn empty_batches_are_not_recorded() {
+  let mut history = History::default();
+  history.record(Change::Batch(vec![]));
+  assert!(!history.can_undo());
+}
+
+
+#[test]
+fn only_removals_are_destructive() {
+  let milk = item("Buy milk");
+  assert!(!Change::Create { position: 0, item: milk.clone() }.is_destructive());
+  assert!(Change::Remove { position: 0, item: milk.clone() }.is_destructive());
+  assert!(Change::Batch(vec![Change::Remove { position: 0, item: milk.clone() }]).is_destructive());
+  assert_eq!(Change::Remove { position: 0, item: milk }.describe(), "Deleted \"Buy milk\"");
+}
+
+
+#[test]
+fn deleting_a_list_is_described_by_the_list() {
+  let mut milk = item("Buy milk");
+  milk.list = work().id;
+  let batch = Change::Batch(vec![
+    Change::Remove { position: 0, item: milk },
+    Change::RemoveList { position: 1, list: work() },
+  ]);
+  assert!(batch.is_destructive());
+  assert_eq!(batch.describe(), "Deleted the list \"Work\"");
+}
+
+
+#[test]
+fn history_round_trips_through_json() {
+  let mut history = History::new(10);
+  history.record(Change::Create { position: 0, item: item("a") });
+  let json = serde_json::to_string(&history).unwrap();
+  assert_eq!(serde_json::from_str::<History>(&json).unwrap(), history);
+}
@@ -0,0 +1,1280 @@
+//! Native tests for the DOM-free todo list model.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+extern crate mogwai_todo;
+use chrono::{Duration, NaiveDate};
+use mogwai_todo::model::{self, Command, Event, FilterShow, Route, SortBy, TodoList};
+use mogwai_todo::recurrence::Recurrence;
+use mogwai_todo::store::{Item, ListInfo, Priority};
+use proptest::prelude::*;
+use uuid::Uuid;
+
+
+fn item(title: &str) -> Item {
+  Item::new(title.to_string())
+}
+
+
+fn done(title: &str) -> Item {
+  Item {
+    completed: true,
+    ..item(title)
+  }
+}
+
+
+fn day(d: u32) -> NaiveDate {
+  NaiveDate::from_ymd_opt(2026, 10, d).unwrap()
+}
+
+
+fn due(title: &str, on: NaiveDate) -> Item {
+  Item {
+    due: Some(on),
+    ..item(title)
+  }
+}
+
+
+fn list_of(items: &[Item]) -> TodoList {
+  let mut list = TodoList::new();
+  for item in items {
+    list.handle(Command::Load(item.clone()));
+  }
+  list
+}
+
+
+fn titles(list: &TodoList) -> Vec<&str> {
+  list
+    .items()
+    .iter()
+    .map(|item| item.title.as_str())
+    .collect()
+}
+
+
+#[test]
+fn counts_items_left() {
+  let list = list_of(&[item("a"), done("b"), item("c")]);
+  assert_eq!(list.num_items_left(), 2);
+  assert!(list.are_any_complete());
+  assert!(!list.are_all_complete());
+}
+
+
+#[test]
+fn empty_list_is_all_complete_and_none_complete() {
+  let list = TodoList::new();
+  assert_eq!(list.num_items_left(), 0);
+  assert!(!list.are_any_complete());
+  assert!(list.are_all_complete());
+}
+
+
+#[test]
+fn parses_filters_from_urls() {
+  assert_eq!(FilterShow::from_url("http://localhost/#/"), Some(FilterShow::All));
+  assert_eq!(FilterShow::from_url("http://localhost/#/active"), Some(FilterShow::Active));
+  assert_eq!(FilterShow::from_url("#/completed"), Some(FilterShow::Completed));
+  assert_eq!(FilterShow::from_url("#/today"), Some(FilterShow::Today));
+  assert_eq!(FilterShow::from_url("#/overdue"), Some(FilterShow::Overdue));
+  assert_eq!(FilterShow::from_url("#/upcoming"), Some(FilterShow::Upcoming));
+  assert_eq!(FilterShow::from_url("http://localhost/"), None);
+  assert_eq!(FilterShow::from_url("#/nope"), None);
+}
+
+
+#[test]
+fn routes_carry_the_sort_alongside_the_filter() {
+  let route = Route::from_url("http://localhost/#/active?sort=priority").unwrap();
+  assert_eq!(route, Route { list: None, filter: FilterShow::Active, tag: None, query: None, sort: SortBy::Priority });
+  assert_eq!(route.to_hash(), "#/active?sort=priority");
+  assert_eq!(route.with_sort(SortBy::Added).to_hash(), "#/active");
+  assert_eq!(route.with_filter(FilterShow::All).to_hash(), "#/?sort=priority");
+
+  let route = Route::from_url("#/completed?utm=x&sort=nope").unwrap();
+  assert_eq!(route, Route { list: None, filter: FilterShow::Completed, tag: None, query: None, sort: SortBy::Added });
+  assert_eq!(Route::from_url("#/nope?sort=priority"), None);
+}
+
+
+#[test]
+fn routes_carry_a_tag() {
+  let route = Route::from_url("#/active?tag=work&sort=priority").unwrap();
+  assert_eq!(route.tag, Some("work".to_string()));
+  assert_eq!(route.to_hash(), "#/active?tag=work&sort=priority");
+  assert_eq!(route.with_tag(None).to_hash(), "#/active?sort=priority");
+
+  let route = Route::default().with_tag(Some("café".to_string()));
+  assert_eq!(route.to_hash(), "#/?tag=caf%C3%A9");
+  assert_eq!(Route::from_url(&route.to_hash()), Some(route));
+
+  assert_eq!(Route::from_url("#/?tag=%#nope").unwrap().tag, None);
+  assert_eq!(Route::from_url("#/?tag=%E9").unwrap().tag, None);
+  assert_eq!(Route::from_url("#/?tag=two%20words").unwrap().tag, None);
+}
+
+
+#[test]
+fn routes_carry_a_search() {
+  let route = Route::from_url("#/active?q=caf%C3%A9%20au%20lait&sort=priority").unwrap();
+  assert_eq!(route.query, Some("café au lait".to_string()));
+  assert_eq!(route.to_hash(), "#/active?q=caf%C3%A9%20au%20lait&sort=priority");
+  assert_eq!(route.with_query(None).to_hash(), "#/active?sort=priority");
+  assert_eq!(Route::from_url("#/?q=%20%20").unwrap().query, None);
+  let route = Route::from_url("#/?tag=work").unwrap().with_query(Some("milk".to_string()));
+  assert_eq!(route.to_hash(), "#/?tag=work&q=milk");
+}
+
+
+#[test]
+fn searching_combines_with_the_filter() {
+  let (a, b, c) = (item("Buy milk"), done("Buy bread"), item("Walk the dog"));
+  let mut list = list_of(&[a.clone(), b.clone(), c.clone()]);
+  list.handle(Command::Filter(FilterShow::Active));
+  let events = list.handle(Command::Search(Some("BUY".to_string())));
+  assert!(events.contains(&Event::Visible(a.id, true)));
+  assert!(events.contains(&Event::Visible(b.id, false)));
+  assert!(events.contains(&Event::Visible(c.id, false)));
+  assert_eq!(events.last(), Some(&Event::Searched(Some("BUY".to_string()))));
+  assert_eq!(list.route().to_hash(), "#/active?q=BUY");
+
+  let events = list.handle(Command::Search(Some(" ".to_string())));
+  assert!(events.contains(&Event::Visible(c.id, true)));
+  assert_eq!(list.query(), None);
+}
+
+
+#[test]
+fn parents_show_while_a_subtask_matches_the_search() {
+  let trip = item("Trip");
+  let pack = subtask("Pack the tent", &trip);
+  let mut list = list_of(&[trip.clone(), pack.clone(), item("Other")]);
+  list.handle(Command::Search(Some("tent".to_string())));
+  assert!(list.is_visible(&trip));
+  assert!(list.is_visible(&pack));
+}
+
+
+#[test]
+fn tags_are_normalized() {
+  assert_eq!(model::normalize_tag("#Work"), Some("work".to_string()));
+  assert_eq!(model::normalize_tag(" home-office "), Some("home-office".to_string()));
+  assert_eq!(model::normalize_tag("#"), None);
+  assert_eq!(model::normalize_tag("a b"), None);
+  assert_eq!(model::normalize_tag("c++"), None);
+  assert_eq!(
+    model::parse_tags("#work, home  #WORK bad! x_y"),
+    vec!["work".to_string(), "home".to_string(), "x_y".to_string()]
+  );
+}
+
+
+#[test]
+fn filtering_reports_visibility() {
+  let a = item("a");
+  let b = done("b");
+  let mut list = list_of(&[a.clone(), b.clone()]);
+  let events = list.handle(Command::Filter(FilterShow::Active));
+  assert_eq!(
+    events,
+    vec![
+      Event::Visible(a.id, true),
+      Event::Visible(b.id, false),
+      Event::Filtered(FilterShow::Active)
+    ]
+  );
+  assert_eq!(list.visible_items(), vec![&a]);
+}
+
+
+#[test]
+fn completing_under_a_filter_hides_the_todo() {
+  let a = item("a");
+  let mut list = list_of(std::slice::from_ref(&a));
+  list.handle(Command::Filter(FilterShow::Active));
+  let events = list.handle(Command::SetCompletion(a.id, true));
+  assert!(events.contains(&Event::Completed(a.id, true)));
+  assert!(events.contains(&Event::Visible(a.id, false)));
+}
+
+
+#[test]
+fn date_filters_split_open_todos_by_due_date() {
+  let yesterday = due("yesterday", day(16));
+  let today = due("today", day(17));
+  let tomorrow = due("tomorrow", day(18));
+  let done_yesterday = Item { completed: true, ..due("done", day(16)) };
+  let undated = item("whenever");
+  let mut list = list_of(&[
+    yesterday.clone(), today.clone(), tomorrow.clone(), done_yesterday, undated
+  ]);
+  list.handle(Command::SetToday(day(17)));
+
+  list.handle(Command::Filter(FilterShow::Today));
+  assert_eq!(list.visible_items(), vec![&today]);
+  list.handle(Command::Filter(FilterShow::Overdue));
+  assert_eq!(list.visible_items(), vec![&yesterday]);
+  list.handle(Command::Filter(FilterShow::Upcoming));
+  assert_eq!(list.visible_items(), vec![&tomorrow]);
+}
+
+
+#[test]
+fn overdue_follows_the_date_and_completion() {
+  let a = due("a", day(17));
+  let mut list = list_of(std::slice::from_ref(&a));
+  list.handle(Command::SetToday(day(17)));
+  assert_eq!(list.handle(Command::SetToday(day(17))), vec![]);
+
+  let events = list.handle(Command::SetToday(day(18)));
+  assert!(events.contains(&Event::Overdue(a.id, true)));
+
+  let events = list.handle(Command::SetCompletion(a.id, true));
+  assert!(events.contains(&Event::Overdue(a.id, false)));
+}
+
+
+#[test]
+fn loading_an_overdue_todo_says_so() {
+  let mut list = TodoList::new();
+  list.handle(Command::SetToday(day(17)));
+  let a = due("a", day(1));
+  let events = list.handle(Command::Load(a.clone()));
+  assert_eq!(events[1], Event::Overdue(a.id, true));
+}
+
+
+#[test]
+fn setting_a_due_date_is_undoable() {
+  let a = item("a");
+  let mut list = list_of(std::slice::from_ref(&a));
+  let events = list.handle(Command::SetDue(a.id, Some(day(20))));
+  assert_eq!(events[0], Event::Due(a.id, Some(day(20))));
+  assert_eq!(list.handle(Command::SetDue(a.id, Some(day(20)))), vec![]);
+
+  list.handle(Command::Undo);
+  assert_eq!(list.get(&a.id).unwrap().due, None);
+}
+
+
+fn with_priority(title: &str, priority: Priority) -> Item {
+  Item {
+    priority,
+    ..item(title)
+  }
+}
+
+
+#[test]
+fn priorities_cycle() {
+  let mut priority = Priority::None;
+  let mut seen = vec![];
+  for _ in 0..4 {
+    priority = priority.next();
+    seen.push(priority);
+  }
+  assert_eq!(seen, vec![Priority::Low, Priority::Medium, Priority::High, Priority::None]);
+}
+
+
+#[test]
+fn sorting_by_priority_keeps_ties_in_added_order() {
+  let items = vec![
+    with_priority("a", Priority::Low),
+    with_priority("b", Priority::High),
+    with_priority("c", Priority::None),
+    with_priority("d", Priority::High),
+  ];
+  let mut list = list_of(&items);
+  let ids = |order: &[usize]| order.iter().map(|n| items[*n].id).collect::<Vec<_>>();
+
+  assert_eq!(list.display_order(), ids(&[0, 1, 2, 3]));
+  let events = list.handle(Command::Sort(SortBy::Priority));
+  assert_eq!(events, vec![Event::Reordered(ids(&[1, 3, 0, 2])), Event::Sorted(SortBy::Priority)]);
+  assert_eq!(list.handle(Command::Sort(SortBy::Priority)), vec![]);
+
+  list.handle(Command::Sort(SortBy::Added));
+  assert_eq!(list.display_order(), ids(&[0, 1, 2, 3]));
+}
+
+
+#[test]
+fn changing_priority_reorders_a_sorted_list() {
+  let a = item("a");
+  let b = item("b");
+  let mut list = list_of(&[a.clone(), b.clone()]);
+  let events = list.handle(Command::SetPriority(b.id, Priority::Medium));
+  assert!(!events.iter().any(|event| matches!(event, Event::Reordered(_))));
+
+  list.handle(Command::Sort(SortBy::Priority));
+  list.handle(Command::SetPriority(b.id, Priority::Low));
+  let events = list.handle(Command::SetPriority(a.id, Priority::High));
+  assert_eq!(events.last(), Some(&Event::Reordered(vec![a.id, b.id])));
+
+  list.handle(Command::Undo);
+  assert_eq!(list.display_order(), vec![b.id, a.id]);
+}
+
+
+fn tagged(title: &str, tags: &[&str]) -> Item {
+  Item {
+    tags: tags.iter().map(|tag| tag.to_string()).collect(),
+    ..item(title)
+  }
+}
+
+
+#[test]
+fn tag_filter_combines_with_the_filter() {
+  let report = tagged("report", &["work"]);
+  let done_report = Item { completed: true, ..tagged("done report", &["work"]) };
+  let dishes = tagged("dishes", &["home"]);
+  let mut list = list_of(&[report.clone(), done_report.clone(), dishes]);
+
+  let events = list.handle(Command::FilterTag(Some("#Work".to_string())));
+  assert_eq!(events.last(), Some(&Event::TagFiltered(Some("work".to_string()))));
+  assert_eq!(list.visible_items(), vec![&report, &done_report]);
+
+  list.handle(Command::Filter(FilterShow::Active));
+  assert_eq!(list.visible_items(), vec![&report]);
+
+  list.handle(Command::FilterTag(None));
+  assert_eq!(list.visible_items().len(), 2);
+}
+
+
+#[test]
+fn counts_tags_in_use() {
+  let list = list_of(&[
+    tagged("a", &["work", "urgent"]),
+    tagged("b", &["work"]),
+    tagged("c", &[]),
+  ]);
+  assert_eq!(list.all_tags(), vec![("urgent".to_string(), 1), ("work".to_string(), 2)]);
+}
+
+
+#[test]
+fn retagging_is_normalized_and_undoable() {
+  let a = tagged("a", &["home"]);
+  let mut list = list_of(std::slice::from_ref(&a));
+  list.handle(Command::FilterTag(Some("home".to_string())));
+  let events = list.handle(Command::SetTags(a.id, vec!["#Work".to_string(), "work".to_string()]));
+  assert_eq!(events[0], Event::Tagged(a.id, vec!["work".to_string()]));
+  assert_eq!(events[1], Event::Visible(a.id, false));
+
+  list.handle(Command::Undo);
+  assert_eq!(list.get(&a.id).unwrap().tags, vec!["home".to_string()]);
+}
+
+
+#[test]
+fn adding_appends_and_is_undoable() {
+  let mut list = list_of(&[item("a")]);
+  let b = item("b");
+  let events = list.handle(Command::Add(b.clone()));
+  assert_eq!(events[0], Event::Inserted { position: 1, item: b.clone(), visible: true });
+  assert_eq!(titles(&list), vec!["a", "b"]);
+
+  let events = list.handle(Command::Undo);
+  assert!(events.contains(&Event::Removed(b.id)));
+  assert_eq!(titles(&list), vec!["a"]);
+}
+
+
+#[test]
+fn loading_is_not_undoable() {
+  let mut list = list_of(&[item("a")]);
+  list.handle(Command::Undo);
+  assert_eq!(titles(&list), vec!["a"]);
+}
+
+
+#[test]
+fn renaming_to_the_same_title_does_nothing() {
+  let a = item("a");
+  let mut list = list_of(std::slice::from_ref(&a));
+  assert_eq!(list.handle(Command::Rename(a.id, "a".to_string())), vec![]);
+  assert!(!list.history().can_undo());
+}
+
+
+#[test]
+fn removing_offers_undo_and_restores_position() {
+  let items = vec![item("a"), item("b"), item("c")];
+  let mut list = list_of(&items);
+  let events = list.handle(Command::Remove(items[1].id));
+  assert_eq!(
+    events,
+    vec![
+      Event::Removed(items[1].id),
+      Event::UndoOffer(Some("Deleted \"b\"".to_string()))
+    ]
+  );
+  list.handle(Command::Undo);
+  assert_eq!(titles(&list), vec!["a", "b", "c"]);
+}
+
+
+#[test]
+fn clear_completed_restores_positions_on_undo() {
+  let items = vec![done("a"), item("b"), done("c"), item("d"), done("e")];
+  let mut list = list_of(&items);
+  list.handle(Command::RemoveCompleted);
+  assert_eq!(titles(&list), vec!["b", "d"]);
+  list.handle(Command::Undo);
+  assert_eq!(list.items(), &items[..]);
+  list.handle(Command::Redo);
+  assert_eq!(titles(&list), vec!["b", "d"]);
+}
+
+
+#[test]
+fn set_all_complete_is_one_undo() {
+  let mut list = list_of(&[item("a"), done("b"), item("c")]);
+  list.handle(Command::SetAllComplete(true));
+  assert!(list.are_all_complete());
+  list.handle(Command::Undo);
+  assert_eq!(list.num_items_left(), 2);
+}
+
+
+#[test]
+fn an_import_is_added_and_undone_as_one() {
+  let mut list = list_of(&[item("a")]);
+  list.handle(Command::Add(item("b")));
+  let c = item("c");
+  let d = Item { parent: Some(c.id), ..item("d") };
+  list.handle(Command::AddAll(vec![c.clone(), d]));
+  assert_eq!(titles(&list), vec!["a", "b", "c", "d"]);
+  assert_eq!(list.items()[3].parent, Some(c.id));
+  assert!(list.items().iter().all(|item| item.list == list.items()[0].list));
+
+  list.handle(Command::Undo);
+  assert_eq!(titles(&list), vec!["a", "b"]);
+  list.handle(Command::Redo);
+  assert_eq!(titles(&list), vec!["a", "b", "c", "d"]);
+}
+
+
+#[test]
+fn merge_applies_remote_changes() {
+  let a = item("a");
+  let b = item("b");
+  let c = item("c");
+  let mut list = list_of(&[a.clone(), b.clone()]);
+
+  let renamed_a = Item { title: "A".to_string(), ..a.clone() };
+  list.handle(Command::Merge(vec![renamed_a.clone(), c.clone()]));
+  assert_eq!(list.items(), &[renamed_a, c][..]);
+  assert!(!list.history().can_undo());
+}
+
+
+#[test]
+fn merge_takes_the_other_tabs_nesting_lists_and_order() {
+  let a = item("a");
+  let b = item("b");
+  let c = item("c");
+  let mut list = list_of(&[a.clone(), b.clone(), c.clone()]);
+
+  let other_list = Uuid::new_v4();
+  let nested_b = subtask("b", &c);
+  let moved_a = Item { list: other_list, ..a.clone() };
+  let events = list.handle(Command::Merge(vec![c.clone(), nested_b.clone(), moved_a.clone()]));
+  assert_eq!(list.items(), &[c.clone(), nested_b.clone(), moved_a.clone()][..]);
+  let reorders:Vec<_> =
+    events
+    .iter()
+    .filter(|event| matches!(event, Event::Arranged(_) | Event::Reordered(_) | Event::Moved { .. }))
+    .collect();
+  assert_eq!(
+    reorders,
+    vec![&Event::Arranged(vec![c.id, nested_b.id, moved_a.id]), &Event::Reordered(list.display_order())]
+  );
+  assert!(list.lists().iter().any(|list| list.id == other_list));
+  assert!(!list.history().can_undo());
+}
+
+
+#[test]
+fn merge_leaves_todos_being_edited_alone() {
+  let a = item("a");
+  let mut list = list_of(std::slice::from_ref(&a));
+  list.handle(Command::StartEditing(a.id));
+  list.handle(Command::Merge(vec![]));
+  assert_eq!(titles(&list), vec!["a"]);
+
+  list.handle(Command::StopEditing(a.id));
+  list.handle(Command::Merge(vec![]));
+  assert!(list.is_empty());
+}
+
+
+#[test]
+fn collaborators_changes_patch_todos_without_side_effects() {
+  let a = Item { recurrence: Some(Recurrence::Daily), ..item("a") };
+  let b = item("b");
+  let mut list = list_of(&[a.clone(), b.clone()]);
+
+  // Completing a repeating todo here would make its next occurrence, but a
+  // collaborator's completion brings its own.
+  let events = list.handle(Command::Patch(Item { completed: true, ..a.clone() }));
+  assert!(events.contains(&Event::Completed(a.id, true)));
+  assert_eq!(list.items().len(), 2);
+
+  let c = subtask("c", &b);
+  list.handle(Command::Patch(c.clone()));
+  assert_eq!(titles(&list), vec!["a", "b", "c"]);
+  list.handle(Command::Forget(b.id));
+  assert_eq!(titles(&list), vec!["a"]);
+  assert!(!list.history().can_undo());
+}
+
+
+fn subtask(title: &str, of: &Item) -> Item {
+  Item {
+    parent: Some(of.id),
+    ..item(title)
+  }
+}
+
+
+#[test]
+fn completing_every_subtask_completes_the_parent() {
+  let parent = item("move");
+  let a = subtask("pack", &parent);
+  let b = subtask("drive", &parent);
+  let mut list = list_of(&[parent.clone(), a.clone(), b.clone()]);
+  assert_eq!(list.num_items_left(), 2);
+
+  list.handle(Command::SetCompletion(a.id, true));
+  assert!(!list.get(&parent.id).unwrap().completed);
+  let events = list.handle(Command::SetCompletion(b.id, true));
+  assert!(events.contains(&Event::Completed(parent.id, true)));
+  assert!(list.are_all_complete());
+
+  list.handle(Command::SetCompletion(a.id, false));
+  assert!(!list.get(&parent.id).unwrap().completed);
+  assert_eq!(list.num_items_left(), 1);
+}
+
+
+#[test]
+fn roll_up_reaches_every_ancestor_and_undoes_at_once() {
+  let top = item("top");
+  let middle = subtask("middle", &top);
+  let leaf = subtask("leaf", &middle);
+  let mut list = list_of(&[top.clone(), middle.clone(), leaf.clone()]);
+  assert_eq!(list.ancestors(&leaf.id), vec![middle.id, top.id]);
+
+  list.handle(Command::SetCompletion(leaf.id, true));
+  assert!(list.are_all_complete());
+  list.handle(Command::Undo);
+  assert_eq!(list.items(), &[top, middle, leaf][..]);
+}
+
+
+#[test]
+fn completing_a_parent_completes_its_subtasks() {
+  let parent = item("move");
+  let a = subtask("pack", &parent);
+  let mut list = list_of(&[parent.clone(), a.clone(), item("other")]);
+  list.handle(Command::SetCompletion(parent.id, true));
+  assert!(list.get(&a.id).unwrap().completed);
+  list.handle(Command::SetCompletion(parent.id, false));
+  assert!(!list.get(&a.id).unwrap().completed);
+  assert_eq!(list.num_items_left(), 2);
+}
+
+
+#[test]
+fn adding_an_open_subtask_reopens_the_parent() {
+  let parent = done("move");
+  let mut list = list_of(&[parent.clone(), Item { completed: true, ..subtask("pack", &parent) }]);
+  list.handle(Command::Add(subtask("drive", &parent)));
+  assert!(!list.get(&parent.id).unwrap().completed);
+  assert_eq!(list.children(&parent.id).len(), 2);
+}
+
+
+#[test]
+fn subtasks_of_missing_todos_are_added_at_the_top_level() {
+  let gone = item("gone");
+  let mut list = TodoList::new();
+  list.handle(Command::Add(subtask("orphan", &gone)));
+  assert_eq!(list.items()[0].parent, None);
+}
+
+
+#[test]
+fn removing_a_todo_removes_its_subtasks() {
+  let parent = item("move");
+  let a = subtask("pack", &parent);
+  let b = subtask("drive", &a);
+  let items = vec![parent.clone(), a.clone(), b.clone(), item("other")];
+  let mut list = list_of(&items);
+  let events = list.handle(Command::Remove(parent.id));
+  assert_eq!(titles(&list), vec!["other"]);
+  assert_eq!(events.last(), Some(&Event::UndoOffer(Some("Deleted 3 todos".to_string()))));
+  list.handle(Command::Undo);
+  assert_eq!(list.items(), &items[..]);
+}
+
+
+#[test]
+fn removing_the_last_open_subtask_completes_the_parent() {
+  let parent = item("move");
+  let a = Item { completed: true, ..subtask("pack", &parent) };
+  let b = subtask("drive", &parent);
+  let mut list = list_of(&[parent.clone(), a, b.clone()]);
+  list.handle(Command::Remove(b.id));
+  assert!(list.get(&parent.id).unwrap().completed);
+}
+
+
+#[test]
+fn clear_completed_takes_whole_subtrees() {
+  let open = item("open");
+  let open_done = Item { completed: true, ..subtask("done part", &open) };
+  let open_left = subtask("open part", &open);
+  let closed = done("closed");
+  let closed_part = Item { completed: true, ..subtask("closed part", &closed) };
+  let mut list = list_of(&[open.clone(), open_done, open_left, closed, closed_part]);
+  list.handle(Command::RemoveCompleted);
+  assert_eq!(titles(&list), vec!["open", "open part"]);
+}
+
+
+#[test]
+fn parents_show_while_a_subtask_matches() {
+  let parent = item("move");
+  let a = subtask("pack", &parent);
+  let mut list = list_of(&[parent.clone(), a.clone()]);
+  list.handle(Command::Filter(FilterShow::Completed));
+  assert!(list.visible_items().is_empty());
+
+  let events = list.handle(Command::SetCompletion(a.id, true));
+  assert!(events.contains(&Event::Visible(a.id, true)));
+  assert!(events.contains(&Event::Visible(parent.id, true)));
+  list.handle(Command::Filter(FilterShow::Active));
+  list.handle(Command::Add(subtask("drive", &parent)));
+  let visible:Vec<&str> = list.visible_items().iter().map(|item| item.title.as_str()).collect();
+  assert_eq!(visible, vec!["move", "drive"]);
+}
+
+
+#[test]
+fn subtasks_are_shown_under_their_parent_and_sorted_among_siblings() {
+  let a = item("a");
+  let b = Item { priority: Priority::High, ..item("b") };
+  let a1 = subtask("a1", &a);
+  let a2 = Item { priority: Priority::Low, ..subtask("a2", &a) };
+  let mut list = list_of(&[a.clone(), b.clone(), a1.clone(), a2.clone()]);
+  assert_eq!(list.display_order(), vec![a.id, a1.id, a2.id, b.id]);
+  list.handle(Command::Sort(SortBy::Priority));
+  assert_eq!(list.display_order(), vec![b.id, a.id, a2.id, a1.id]);
+}
+
+
+#[test]
+fn looping_parents_are_still_shown() {
+  let mut a = item("a");
+  let b = subtask("b", &a);
+  a.parent = Some(b.id);
+  let list = list_of(&[a.clone(), b.clone()]);
+  assert_eq!(list.ancestors(&a.id), vec![b.id]);
+  assert_eq!(list.display_order(), vec![a.id, b.id]);
+}
+
+
+#[test]
+fn routes_carry_a_named_list() {
+  let route = Route::from_url("http://localhost/#/lists/work/active?sort=priority").unwrap();
+  assert_eq!(route.list, Some("work".to_string()));
+  assert_eq!(route.filter, FilterShow::Active);
+  assert_eq!(route.sort, SortBy::Priority);
+  assert_eq!(route.to_hash(), "#/lists/work/active?sort=priority");
+  assert_eq!(route.with_filter(FilterShow::All).to_hash(), "#/lists/work/?sort=priority");
+  assert_eq!(Route::from_url("#/lists/work").unwrap().to_hash(), "#/lists/work/");
+  assert_eq!(route.with_list(None).to_hash(), "#/active?sort=priority");
+  assert_eq!(Route::from_url("#/lists/work/nope"), None);
+  assert_eq!(Route::from_url("#/lists//active"), None);
+}
+
+
+#[test]
+fn list_names_are_slugged() {
+  assert_eq!(model::slugify("Work"), "work");
+  assert_eq!(model::slugify("  Weekend   chores! "), "weekend-chores");
+  assert_eq!(model::slugify("Café 2.0"), "caf-2-0");
+  assert_eq!(model::slugify("!!!"), "");
+}
+
+
+fn list_named(list: &TodoList, name: &str) -> ListInfo {
+  list
+    .lists()
+    .iter()
+    .find(|info| info.name == name)
+    .cloned()
+    .unwrap()
+}
+
+
+#[test]
+fn adding_a_list_switches_to_it_with_a_unique_slug() {
+  let mut list = list_of(&[item("a")]);
+  let events = list.handle(Command::AddList(" Work ".to_string()));
+  let work = list_named(&list, "Work");
+  assert!(events.contains(&Event::ListSwitched(work.id)));
+  assert_eq!(list.active_list(), &work);
+  assert_eq!(work.slug, "work");
+  assert_eq!(list.num_items_left(), 0);
+
+  list.handle(Command::AddList("work!".to_string()));
+  assert_eq!(list.lists()[2].slug, "work-2");
+  list.handle(Command::AddList("   ".to_string()));
+  assert_eq!(list.lists().len(), 3);
+}
+
+
+#[test]
+fn todos_are_added_to_the_active_list_and_counted_there() {
+  let mut list = list_of(&[item("a"), done("b")]);
+  let todos = list.active_list().id;
+  list.handle(Command::AddList("Work".to_string()));
+  let added = item("c");
+  list.handle(Command::Add(added.clone()));
+  let work = list_named(&list, "Work").id;
+  assert_eq!(list.get(&added.id).unwrap().list, work);
+  assert_eq!(list.list_items().len(), 1);
+  assert_eq!(list.num_items_left(), 1);
+  assert!(!list.are_any_complete());
+
+  let events = list.handle(Command::SwitchList(todos));
+  assert!(events.contains(&Event::Visible(added.id, false)));
+  assert_eq!(list.num_items_left(), 1);
+  assert!(list.are_any_complete());
+}
+
+
+#[test]
+fn navigating_shows_the_whole_route_at_once() {
+  let a = item("a");
+  let b = done("b");
+  let mut list = list_of(&[a.clone(), b.clone()]);
+  list.handle(Command::AddList("Work".to_string()));
+  let work = list_named(&list, "Work").id;
+
+  let route = Route::from_url("#/completed?q=B&sort=priority").unwrap();
+  let events = list.handle(Command::Navigate(route));
+  assert_eq!(list.active_list().id, list.lists()[0].id);
+  assert_eq!(list.filter(), &FilterShow::Completed);
+  assert_eq!(list.query(), Some(&"B".to_string()));
+  assert_eq!(list.sort(), SortBy::Priority);
+  let visible:Vec<_> =
+    events
+    .iter()
+    .filter(|event| matches!(event, Event::Visible(_, _)))
+    .collect();
+  assert_eq!(visible, vec![&Event::Visible(a.id, false), &Event::Visible(b.id, true)]);
+
+  // A list that isn't there leaves the active one shown.
+  list.handle(Command::Navigate(Route::from_url("#/lists/nope/").unwrap()));
+  assert_ne!(list.active_list().id, work);
+  assert_eq!(list.filter(), &FilterShow::All);
+}
+
+
+#[test]
+fn each_list_remembers_its_filter() {
+  let mut list = list_of(&[item("a")]);
+  let todos = list.active_list().id;
+  list.handle(Command::Filter(FilterShow::Completed));
+  list.handle(Command::AddList("Work".to_string()));
+  assert_eq!(list.filter(), &FilterShow::All);
+  list.handle(Command::Sort(SortBy::Priority));
+  let work = list.active_list().id;
+  assert_eq!(list.route().to_hash(), "#/lists/work/?sort=priority");
+
+  list.handle(Command::SwitchList(todos));
+  assert_eq!(list.filter(), &FilterShow::Completed);
+  assert_eq!(list.sort(), SortBy::Added);
+  assert_eq!(list.route().to_hash(), "#/completed");
+  assert_eq!(list.route_of(&work).unwrap().to_hash(), "#/lists/work/?sort=priority");
+
+  list.handle(Command::SwitchList(work));
+  assert_eq!(list.sort(), SortBy::Priority);
+}
+
+
+#[test]
+fn deleting_a_list_deletes_its_todos_and_undoes_at_once() {
+  let mut list = list_of(&[item("a")]);
+  let todos = list.active_list().clone();
+  list.handle(Command::AddList("Work".to_string()));
+  list.handle(Command::Add(item("b")));
+  list.handle(Command::Add(item("c")));
+  let work = list.active_list().clone();
+
+  let events = list.handle(Command::DeleteList(work.id));
+  assert!(events.contains(&Event::ListSwitched(todos.id)));
+  assert_eq!(list.lists(), &[todos.clone()][..]);
+  assert_eq!(titles(&list), vec!["a"]);
+  assert!(events.contains(&Event::UndoOffer(Some("Deleted the list \"Work\"".to_string()))));
+
+  list.handle(Command::Undo);
+  assert_eq!(list.lists(), &[todos, work.clone()][..]);
+  assert_eq!(titles(&list), vec!["a", "b", "c"]);
+  assert!(list.items()[1..].iter().all(|item| item.list == work.id));
+}
+
+
+#[test]
+fn the_last_list_cannot_be_deleted() {
+  let mut list = list_of(&[item("a")]);
+  let id = list.active_list().id;
+  assert!(list.handle(Command::DeleteList(id)).is_empty());
+  assert_eq!(titles(&list), vec!["a"]);
+}
+
+
+#[test]
+fn loaded_lists_replace_the_default_and_unknown_lists_are_adopted() {
+  let work = ListInfo {
+    id: Uuid::new_v4(),
+    name: "Work".to_string(),
+    slug: "work".to_string()
+  };
+  let mut list = TodoList::new();
+  let events = list.handle(Command::LoadLists(vec![work.clone(), work.clone()]));
+  assert!(events.contains(&Event::Lists(vec![work.clone()])));
+  assert_eq!(list.active_list(), &work);
+
+  let stray = Item { list: Uuid::new_v4(), ..item("stray") };
+  list.handle(Command::Load(Item { list: work.id, ..item("a") }));
+  list.handle(Command::Load(stray.clone()));
+  assert_eq!(list.lists().len(), 2);
+  assert_eq!(list.lists()[1].id, stray.list);
+  assert_eq!(list.lists()[1].name, "List 2");
+  assert_eq!(list.list_by_slug("list-2").map(|info| info.id), Some(stray.list));
+}
+
+
+#[test]
+fn notes_are_undoable_and_blank_notes_are_cleared() {
+  let a = item("a");
+  let mut list = list_of(std::slice::from_ref(&a));
+  let events = list.handle(Command::SetNotes(a.id, "first\nsecond".to_string()));
+  assert_eq!(events[0], Event::Noted(a.id, "first\nsecond".to_string()));
+  assert!(list.handle(Command::SetNotes(a.id, "first\nsecond".to_string())).is_empty());
+
+  list.handle(Command::SetNotes(a.id, " \n ".to_string()));
+  assert_eq!(list.get(&a.id).unwrap().notes, "");
+  list.handle(Command::Undo);
+  assert_eq!(list.get(&a.id).unwrap().notes, "first\nsecond");
+}
+
+
+#[test]
+fn changing_notes_can_change_what_a_search_shows() {
+  let a = item("a");
+  let mut list = list_of(std::slice::from_ref(&a));
+  list.handle(Command::Search(Some("oat".to_string())));
+  let events = list.handle(Command::SetNotes(a.id, "oat milk".to_string()));
+  assert!(events.contains(&Event::Visible(a.id, true)));
+}
+
+
+fn repeating(title: &str, rule: Recurrence) -> Item {
+  Item {
+    recurrence: Some(rule),
+    ..item(title)
+  }
+}
+
+
+#[test]
+fn completing_a_repeating_todo_adds_the_next_one() {
+  let a = Item { due: Some(day(16)), ..repeating("a", Recurrence::Every { days: 3 }) };
+  let b = item("b");
+  let mut list = list_of(&[a.clone(), b]);
+  list.handle(Command::SetToday(day(17)));
+  list.handle(Command::SetCompletion(a.id, true));
+
+  assert_eq!(titles(&list), vec!["a", "a", "b"]);
+  let done = list.get(&a.id).unwrap();
+  assert!(done.completed);
+  assert_eq!(done.recurrence, None);
+  let next = &list.items()[1];
+  assert_ne!(next.id, a.id);
+  assert!(!next.completed);
+  assert_eq!(next.due, Some(day(20)));
+  assert_eq!(next.recurrence, Some(Recurrence::Every { days: 3 }));
+  assert_eq!(next.completions, vec![day(17)]);
+  assert_eq!(list.num_items_left(), 2);
+}
+
+
+#[test]
+fn the_completion_log_carries_over_and_the_series_undoes_at_once() {
+  let a = repeating("a", Recurrence::Daily);
+  let mut list = list_of(std::slice::from_ref(&a));
+  list.handle(Command::SetToday(day(17)));
+  list.handle(Command::SetCompletion(a.id, true));
+  let second = list.items()[1].clone();
+  assert_eq!(second.due, Some(day(18)));
+
+  list.handle(Command::SetToday(day(18)));
+  list.handle(Command::SetCompletion(second.id, true));
+  let third = list.items()[2].clone();
+  assert_eq!(third.completions, vec![day(17), day(18)]);
+  assert_eq!(third.due, Some(day(19)));
+
+  list.handle(Command::Undo);
+  assert_eq!(list.len(), 2);
+  assert!(!list.get(&second.id).unwrap().completed);
+  assert_eq!(list.get(&second.id).unwrap().recurrence, Some(Recurrence::Daily));
+  list.handle(Command::Redo);
+  assert_eq!(list.len(), 3);
+}
+
+
+#[test]
+fn reopening_or_completing_a_done_todo_does_not_repeat() {
+  let a = Item { completed: true, ..repeating("a", Recurrence::Daily) };
+  let mut list = list_of(std::slice::from_ref(&a));
+  list.handle(Command::SetCompletion(a.id, false));
+  list.handle(Command::SetAllComplete(true));
+  assert_eq!(list.len(), 1);
+}
+
+
+#[test]
+fn recurrence_is_undoable_and_merged() {
+  let a = item("a");
+  let mut list = list_of(std::slice::from_ref(&a));
+  let events = list.handle(Command::SetRecurrence(a.id, Some(Recurrence::Daily)));
+  assert_eq!(events[0], Event::Recurring(a.id, Some(Recurrence::Daily)));
+  list.handle(Command::Undo);
+  assert_eq!(list.get(&a.id).unwrap().recurrence, None);
+
+  let remote = Item { recurrence: Some(Recurrence::Monthly { day: 1 }), ..a.clone() };
+  list.handle(Command::Merge(vec![remote]));
+  assert_eq!(list.get(&a.id).unwrap().recurrence, Some(Recurrence::Monthly { day: 1 }));
+}
+
+
+fn ids(items: &[&Item]) -> Vec<Uuid> {
+  items.iter().map(|item| item.id).collect()
+}
+
+
+#[test]
+fn todos_move_before_and_after_their_siblings() {
+  let (a, b, c) = (item("a"), item("b"), item("c"));
+  let mut list = list_of(&[a.clone(), b.clone(), c.clone()]);
+  let events = list.handle(Command::MoveBefore(c.id, a.id));
+  assert_eq!(titles(&list), vec!["c", "a", "b"]);
+  assert_eq!(events[0], Event::Moved { from: 2, to: 0 });
+  assert!(events.contains(&Event::Reordered(vec![c.id, a.id, b.id])));
+
+  list.handle(Command::MoveAfter(c.id, b.id));
+  assert_eq!(titles(&list), vec!["a", "b", "c"]);
+  list.handle(Command::MoveAfter(a.id, b.id));
+  assert_eq!(titles(&list), vec!["b", "a", "c"]);
+  assert!(list.handle(Command::MoveBefore(a.id, c.id)).is_empty());
+  assert!(list.handle(Command::MoveAfter(a.id, a.id)).is_empty());
+}
+
+
+#[test]
+fn moving_is_undoable() {
+  let (a, b) = (item("a"), item("b"));
+  let mut list = list_of(&[a.clone(), b.clone()]);
+  let events = list.handle(Command::MoveUp(b.id));
+  assert!(events.contains(&Event::UndoOffer(None)));
+  assert_eq!(titles(&list), vec!["b", "a"]);
+  list.handle(Command::Undo);
+  assert_eq!(titles(&list), vec!["a", "b"]);
+  list.handle(Command::Redo);
+  assert_eq!(titles(&list), vec!["b", "a"]);
+}
+
+
+#[test]
+fn moving_up_and_down_skips_hidden_siblings() {
+  let (a, b, c) = (item("a"), done("b"), item("c"));
+  let mut list = list_of(&[a.clone(), b.clone(), c.clone()]);
+  list.handle(Command::Filter(FilterShow::Active));
+  list.handle(Command::MoveUp(c.id));
+  assert_eq!(titles(&list), vec!["c", "a", "b"]);
+  list.handle(Command::MoveDown(c.id));
+  assert_eq!(titles(&list), vec!["a", "c", "b"]);
+  assert!(list.handle(Command::MoveDown(c.id)).is_empty());
+  assert!(list.handle(Command::MoveUp(a.id)).is_empty());
+}
+
+
+#[test]
+fn todos_only_move_among_their_siblings() {
+  let a = item("a");
+  let a1 = subtask("a1", &a);
+  let a2 = subtask("a2", &a);
+  let b = item("b");
+  let mut list = list_of(&[a.clone(), a1.clone(), a2.clone(), b.clone()]);
+  assert!(list.handle(Command::MoveBefore(a1.id, b.id)).is_empty());
+  assert!(list.handle(Command::MoveUp(a1.id)).is_empty());
+  list.handle(Command::MoveUp(b.id));
+  assert_eq!(list.display_order(), vec![b.id, a.id, a1.id, a2.id]);
+  list.handle(Command::MoveDown(a1.id));
+  assert_eq!(list.display_order(), vec![b.id, a.id, a2.id, a1.id]);
+  assert_eq!(ids(&list.children(&a.id)), vec![a2.id, a1.id]);
+
+  list.handle(Command::AddList("Work".to_string()));
+  let w = item("w");
+  list.handle(Command::Add(w.clone()));
+  assert!(list.handle(Command::MoveBefore(w.id, b.id)).is_empty());
+}
+
+
+#[derive(Clone, Debug)]
+enum Op {
+  Add(String),
+  AddSubtask(usize, String),
+  Rename(usize, String),
+  Toggle(usize),
+  SetDue(usize, Option<i64>),
+  Prioritize(usize),
+  Tag(usize, Vec<String>),
+  Star(usize, bool),
+  Note(usize, String),
+  Repeat(usize, bool),
+  SetAll(bool),
+  Remove(usize),
+  RemoveCompleted,
+  Move(usize, usize, bool),
+  MoveUp(usize),
+  MoveDown(usize),
+  AddList(String),
+  SwitchList(usize),
+  DeleteList(usize),
+  Undo,
+  Redo
+}
+
+
+fn op() -> impl Strategy<Value = Op> {
+  prop_oneof![
+    "[a-z]{1,8}".prop_map(Op::Add),
+    (any::<usize>(), "[a-z]{1,8}").prop_map(|(n, s)| Op::AddSubtask(n, s)),
+    (any::<usize>(), "[a-z]{1,8}").prop_map(|(n, s)| Op::Rename(n, s)),
+    any::<usize>().prop_map(Op::Toggle),
+    any::<usize>().prop_map(Op::Prioritize),
+    (any::<usize>(), prop::collection::vec("#?[a-c]{1,2}", 0..3)).prop_map(|(n, tags)| Op::Tag(n, tags)),
+    (any::<usize>(), any::<bool>()).prop_map(|(n, to)| Op::Star(n, to)),
+    (any::<usize>(), "[a-z \n]{0,8}").prop_map(|(n, notes)| Op::Note(n, notes)),
+    (any::<usize>(), any::<bool>()).prop_map(|(n, to)| Op::Repeat(n, to)),
+    (any::<usize>(), prop::option::of(-3i64..3)).prop_map(|(n, d)| Op::SetDue(n, d)),
+    any::<bool>().prop_map(Op::SetAll),
+    any::<usize>().prop_map(Op::Remove),
+    Just(Op::RemoveCompleted),
+    (any::<usize>(), any::<usize>(), any::<bool>()).prop_map(|(n, m, after)| Op::Move(n, m, after)),
+    any::<usize>().prop_map(Op::MoveUp),
+    any::<usize>().prop_map(Op::MoveDown),
+    "[A-Za-z ]{1,8}".prop_map(Op::AddList),
+    any::<usize>().prop_map(Op::SwitchList),
+    any::<usize>().prop_map(Op::DeleteList),
+    Just(Op::Undo),
+    Just(Op::Redo),
+  ]
+}
+
+
+/// Turn an op into a command against whatever todos exist right now.
+fn command(list: &TodoList, op: Op) -> Option<Command> {
+  let nth = |n: usize| list.items().get(n % list.len().max(1)).map(|item| item.id);
+  Some(
+    match op {
+      Op::Add(title) => { Command::Add(Item::new(title)) }
+      Op::AddSubtask(n, title) => {
+        Command::Add(Item { parent: Some(nth(n)?), ..Item::new(title) })
+      }
+      Op::Rename(n, title) => { Command::Rename(nth(n)?, title) }
+      Op::Toggle(n) => {
+        let id = nth(n)?;
+        let completed = list.get(&id)?.completed;
+        Command::SetCompletion(id, !completed)
+      }
+      Op::SetDue(n, days) => {
+        let due = days.map(|days| day(17) + Duration::days(days));
+        Command::SetDue(nth(n)?, due)
+      }
+      Op::Prioritize(n) => {
+        let id = nth(n)?;
+        Command::SetPriority(id, list.get(&id)?.priority.next())
+      }
+      Op::Tag(n, tags) => { Command::SetTags(nth(n)?, tags) }
+      Op::Star(n, to) => { Command::SetStarred(nth(n)?, to) }
+      Op::Note(n, notes) => { Command::SetNotes(nth(n)?, notes) }
+      Op::Repeat(n, to) => {
+        let rule = if to { Some(Recurrence::Daily) } else { None };
+        Command::SetRecurrence(nth(n)?, rule)
+      }
+      Op::SetAll(to) => { Command::SetAllComplete(to) }
+      Op::Remove(n) => { Command::Remove(nth(n)?) }
+      Op::RemoveCompleted => { Command::RemoveCompleted }
+      Op::Move(n, m, false) => { Command::MoveBefore(nth(n)?, nth(m)?) }
+      Op::Move(n, m, true) => { Command::MoveAfter(nth(n)?, nth(m)?) }
+      Op::MoveUp(n) => { Command::MoveUp(nth(n)?) }
+      Op::MoveDown(n) => { Command::MoveDown(nth(n)?) }
+      Op::AddList(name) => { Command::AddList(name) }
+      Op::SwitchList(n) => { Command::SwitchList(list.lists()[n % list.lists().len()].id) }
+      Op::DeleteList(n) => { Command::DeleteList(list.lists()[n % list.lists().len()].id) }
+      Op::Undo => { Command::Undo }
+      Op::Redo => { Command::Redo }
+    }
+  )
+}
+
+
+fn run(list: &mut TodoList, ops: Vec<Op>) -> usize {
+  let mut recorded = 0;
+  for op in ops {
+    let undoable = !matches!(op, Op::Undo | Op::Redo);
+    if let Some(cmd) = command(list, op) {
+      let events = list.handle(cmd);
+      if undoable && events.iter().any(Event::changes_items) {
+        recorded += 1;
+      }
+    }
+  }
+  recorded
+}
+
+
+proptest! {
+  #[test]
+  fn counts_always_agree(ops in prop::collection::vec(op(), 0..40)) {
+    let mut list = TodoList::new();
+    run(&mut list, ops);
+    let items = list.list_items();
+    let completed = items.iter().filter(|item| item.completed).count();
+    let open_parents =
+      items
+      .iter()
+      .filter(|item| !item.completed && list.has_children(&item.id))
+      .count();
+    prop_assert_eq!(list.num_items_left() + completed + open_parents, items.len());
+    prop_assert_eq!(list.are_all_complete(), list.num_items_left() == 0);
+    prop_assert_eq!(list.are_any_complete(), completed > 0);
+  }
+
+  #[test]
+  fn parents_are_completed_exactly_when_their_subtasks_are(
+    ops in prop::collection::vec(op(), 0..40)
+  ) {
+    let mut list = TodoList::new();
+    run(&mut list, ops);
+    for item in list.items() {
+      let children = list.children(&item.id);
+      if !children.is_empty() {
+        prop_assert_eq!(item.completed, children.iter().all(|child| child.completed));
+      }
+    }
+  }
+
+  #[test]
+  fn ids_stay_unique(ops in prop::collection::vec(op(), 0..40)) {
+    let mut list = TodoList::new();
+    run(&mut list, ops);
+    let mut ids:Vec<_> = list.items().iter().map(|item| item.id).collect();
+    ids.sort();
+    ids.dedup();
+    prop_assert_eq!(ids.len(), list.len());
+  }
+
+  #[test]
+  fn undoing_everything_restores_the_start(
+    start in prop::collection::vec(("[a-z]{1,8}", any::<bool>()), 0..6),
+    ops in prop::collection::vec(op(), 0..30)
+  ) {
+    let items:Vec<Item> =
+      start
+      .into_iter()
+      .map(|(title, completed)| Item { completed, ..Item::new(title) })
+      .collect();
+    let mut list = list_of(&items);
+    // Undo and redo themselves move back and forth through history, so only
+    // check a run of plain changes.
+    let ops:Vec<Op> =
+      ops
+      .into_iter()
+      .filter(|op| !matches!(op, Op::Undo | Op::Redo))
+      .collect();
+    let recorded = run(&mut list, ops);
+    for _ in 0..recorded {
+      list.handle(Command::Undo);
+    }
+    prop_assert_eq!(list.items(), &items[..]);
+  }
+
+  #[test]
+  fn sorting_shows_every_todo_once(ops in prop::collection::vec(op(), 0..40)) {
+    let mut list = TodoList::new();
+    list.handle(Command::Sort(SortBy::Priority));
+    run(&mut list, ops);
+    let order = list.display_order();
+    let mut sorted_ids = order.clone();
+    sorted_ids.sort();
+    let mut ids:Vec<_> = list.items().iter().map(|item| item.id).collect();
+    ids.sort();
+    prop_assert_eq!(sorted_ids, ids);
+    // Siblings are in priority order, and each todo comes after its parent.
+    // Named lists are sorted on their own, so a later list may not be.
+    let by_priority = list.sort() == SortBy::Priority;
+    for item in list.items() {
+      let priorities:Vec<_> =
+        order
+        .iter()
+        .filter_map(|id| list.get(id))
+        .filter(|sibling| sibling.parent == item.parent)
+        .map(|sibling| sibling.priority)
+        .collect();
+      prop_assert!(!by_priority || priorities.windows(2).all(|pair| pair[0] >= pair[1]));
+      if let Some(parent) = item.parent {
+        let at = |id: &Uuid| order.iter().position(|other| other == id);
+        prop_assert!(at(&parent) < at(&item.id));
+      }
+    }
+  }
+
+  #[test]
+  fn undo_then_redo_is_a_no_op(ops in prop::collection::vec(op(), 1..30)) {
+    let mut list = TodoList::new();
+    run(&mut list, ops);
+    let before = list.items().to_vec();
+    if list.history().can_undo() {
+      list.handle(Command::Undo);
+      list.handle(Command::Redo);
+    }
+    prop_assert_eq!(list.items(), &before[..]);
+  }
+
+  #[test]
+  fn merging_a_copy_converges(
+    ops in prop::collection::vec(op(), 0..30),
+    other_ops in prop::collection::vec(op(), 0..30)
+  ) {
+    let mut list = TodoList::new();
+    run(&mut list, ops);
+    let mut other = list.clone();
+    run(&mut other, other_ops);
+    list.handle(Command::Merge(other.items().to_vec()));
+    prop_assert_eq!(list.items(), other.items());
+  }
+}
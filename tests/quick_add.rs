@@ -0,0 +1,141 @@
+//! Tests for the new-todo input's quick-add syntax.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+extern crate mogwai_todo;
+use chrono::NaiveDate;
+use mogwai_todo::quick_add::{escape_title, parse, Problem};
+use mogwai_todo::store::Priority;
+
+
+/// A Saturday.
+fn today() -> NaiveDate {
+  NaiveDate::from_ymd_opt(2026, 10, 17).unwrap()
+}
+
+
+fn date(m: u32, d: u32) -> Option<NaiveDate> {
+  NaiveDate::from_ymd_opt(2026, m, d)
+}
+
+
+#[test]
+fn plain_text_is_the_title() {
+  let quick = parse("  Buy   milk ", today());
+  assert_eq!(quick.title, "Buy milk");
+  assert!(quick.tags.is_empty());
+  assert_eq!(quick.priority, Priority::None);
+  assert_eq!(quick.due, None);
+  assert!(!quick.starred);
+  assert!(quick.problems.is_empty());
+}
+
+
+#[test]
+fn parses_every_token() {
+  let quick = parse("Send report #Work !1 @tomorrow * #urgent", today());
+  assert_eq!(quick.title, "Send report");
+  assert_eq!(quick.tags, vec!["work".to_string(), "urgent".to_string()]);
+  assert_eq!(quick.priority, Priority::High);
+  assert_eq!(quick.due, date(10, 18));
+  assert!(quick.starred);
+  assert!(quick.problems.is_empty());
+}
+
+
+#[test]
+fn tokens_can_go_anywhere() {
+  let quick = parse("!3 #home water @2026-11-01 the plants", today());
+  assert_eq!(quick.title, "water the plants");
+  assert_eq!(quick.priority, Priority::Low);
+  assert_eq!(quick.due, date(11, 1));
+}
+
+
+#[test]
+fn priorities_count_down_from_one() {
+  assert_eq!(parse("a !1", today()).priority, Priority::High);
+  assert_eq!(parse("a !2", today()).priority, Priority::Medium);
+  assert_eq!(parse("a !3", today()).priority, Priority::Low);
+}
+
+
+#[test]
+fn weekdays_are_the_next_one_on_or_after_today() {
+  assert_eq!(parse("a @sat", today()).due, date(10, 17));
+  assert_eq!(parse("a @sun", today()).due, date(10, 18));
+  assert_eq!(parse("a @fri", today()).due, date(10, 23));
+  assert_eq!(parse("a @Friday", today()).due, date(10, 23));
+  assert_eq!(parse("a @today", today()).due, date(10, 17));
+}
+
+
+#[test]
+fn the_last_of_a_field_wins_and_tags_repeat_once() {
+  let quick = parse("a !1 !3 @today @tomorrow #x #X", today());
+  assert_eq!(quick.priority, Priority::Low);
+  assert_eq!(quick.due, date(10, 18));
+  assert_eq!(quick.tags, vec!["x".to_string()]);
+}
+
+
+#[test]
+fn malformed_tokens_stay_in_the_title() {
+  let quick = parse("learn c++ #c++ !4 @someday @2026-02-30 # ! @", today());
+  assert_eq!(quick.title, "learn c++ #c++ !4 @someday @2026-02-30 # ! @");
+  assert!(quick.tags.is_empty());
+  assert_eq!(quick.priority, Priority::None);
+  assert_eq!(quick.due, None);
+  assert_eq!(
+    quick.problems,
+    vec![
+      Problem::BadTag("#c++".to_string()),
+      Problem::BadPriority("!4".to_string()),
+      Problem::BadDate("@someday".to_string()),
+      Problem::BadDate("@2026-02-30".to_string()),
+      Problem::BadTag("#".to_string()),
+      Problem::BadPriority("!".to_string()),
+      Problem::BadDate("@".to_string()),
+    ]
+  );
+}
+
+
+#[test]
+fn sigils_inside_words_are_just_text() {
+  let quick = parse("email me@example.com re: issue#4 wow!", today());
+  assert_eq!(quick.title, "email me@example.com re: issue#4 wow!");
+  assert!(quick.problems.is_empty());
+}
+
+
+#[test]
+fn only_tokens_leaves_an_empty_title() {
+  let quick = parse("#work !1", today());
+  assert_eq!(quick.title, "");
+  assert_eq!(quick.tags, vec!["work".to_string()]);
+}
+
+
+#[test]
+fn becomes_an_item() {
+  let item = parse("Pay rent @2026-11-01 !1 * #home", today()).into_item();
+  assert_eq!(item.title, "Pay rent");
+  assert_eq!(item.due, date(11, 1));
+  assert_eq!(item.priority, Priority::High);
+  assert!(item.starred);
+  assert_eq!(item.tags, vec!["home".to_string()]);
+  assert!(!item.completed);
+}
+
+
+#[test]
+fn a_backslash_keeps_a_word_in_the_title() {
+  let quick = parse("Buy \\#1 milk \\!1 \\* \\\\o/ #food", today());
+  assert_eq!(quick.title, "Buy #1 milk !1 * \\o/");
+  assert_eq!(quick.tags, vec!["food".to_string()]);
+  assert_eq!(quick.priority, Priority::None);
+  assert!(!quick.starred);
+  assert!(quick.problems.is_empty());
+  assert_eq!(parse(&escape_title(&quick.title), today()).title, quick.title);
+}
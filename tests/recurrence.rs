@@ -0,0 +1,113 @@
+//! Tests for how often repeating todos come round again.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+extern crate mogwai_todo;
+use chrono::{NaiveDate, Weekday};
+use mogwai_todo::recurrence::{parse, Recurrence, MAX_DAYS};
+
+
+fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+  NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+
+/// A Saturday.
+fn today() -> NaiveDate {
+  date(2026, 10, 17)
+}
+
+
+#[test]
+fn daily_and_every_few_days() {
+  assert_eq!(Recurrence::Daily.after(today()), Some(date(2026, 10, 18)));
+  assert_eq!(Recurrence::Every { days: 3 }.after(today()), Some(date(2026, 10, 20)));
+  assert_eq!(Recurrence::Every { days: 0 }.after(today()), Some(date(2026, 10, 18)));
+}
+
+
+#[test]
+fn weekly_on_the_next_chosen_day() {
+  let rule = Recurrence::Weekly { days: vec![Weekday::Mon, Weekday::Thu] };
+  assert_eq!(rule.after(today()), Some(date(2026, 10, 19)));
+  assert_eq!(rule.after(date(2026, 10, 19)), Some(date(2026, 10, 22)));
+  let saturdays = Recurrence::Weekly { days: vec![Weekday::Sat] };
+  assert_eq!(saturdays.after(today()), Some(date(2026, 10, 24)));
+  assert_eq!(Recurrence::Weekly { days: vec![] }.after(today()), Some(date(2026, 10, 24)));
+}
+
+
+#[test]
+fn monthly_falls_on_the_last_day_of_short_months() {
+  let fifteenth = Recurrence::Monthly { day: 15 };
+  assert_eq!(fifteenth.after(date(2026, 10, 14)), Some(date(2026, 10, 15)));
+  assert_eq!(fifteenth.after(date(2026, 10, 15)), Some(date(2026, 11, 15)));
+  assert_eq!(fifteenth.after(date(2026, 12, 20)), Some(date(2027, 1, 15)));
+  let last = Recurrence::Monthly { day: 31 };
+  assert_eq!(last.after(date(2027, 1, 31)), Some(date(2027, 2, 28)));
+  assert_eq!(last.after(date(2027, 2, 28)), Some(date(2027, 3, 31)));
+}
+
+
+#[test]
+fn there_is_no_day_past_the_last_date() {
+  let last = NaiveDate::MAX;
+  assert_eq!(Recurrence::Daily.after(last), None);
+  assert_eq!(Recurrence::Every { days: u32::MAX }.after(today()), None);
+  assert_eq!(Recurrence::Weekly { days: vec![Weekday::Mon] }.after(last), None);
+  assert_eq!(Recurrence::Monthly { day: 1 }.after(last), None);
+}
+
+
+#[test]
+fn the_next_due_date_follows_the_due_date_or_today_if_late() {
+  let rule = Recurrence::Daily;
+  assert_eq!(rule.next_due(None, today()), Some(date(2026, 10, 18)));
+  assert_eq!(rule.next_due(Some(date(2026, 10, 20)), today()), Some(date(2026, 10, 21)));
+  assert_eq!(rule.next_due(Some(date(2026, 10, 1)), today()), Some(date(2026, 10, 18)));
+}
+
+
+#[test]
+fn parses_what_people_write() {
+  assert_eq!(parse("Daily"), Some(Recurrence::Daily));
+  assert_eq!(parse("every day"), Some(Recurrence::Daily));
+  assert_eq!(parse("every 1 day"), Some(Recurrence::Daily));
+  assert_eq!(parse("weekly"), Some(Recurrence::Every { days: 7 }));
+  assert_eq!(parse("every 3 days"), Some(Recurrence::Every { days: 3 }));
+  assert_eq!(
+    parse("every Thursday and Monday"),
+    Some(Recurrence::Weekly { days: vec![Weekday::Mon, Weekday::Thu] })
+  );
+  assert_eq!(parse("monthly on the 15th"), Some(Recurrence::Monthly { day: 15 }));
+  assert_eq!(parse("every 0 days"), None);
+  assert_eq!(parse("every 3650 days"), Some(Recurrence::Every { days: MAX_DAYS }));
+  assert_eq!(parse("every 3651 days"), None);
+  assert_eq!(parse("every 4000000000 days"), None);
+  assert_eq!(parse("monthly on 32"), None);
+  assert_eq!(parse("sometimes"), None);
+  assert_eq!(parse(""), None);
+}
+
+
+#[test]
+fn shown_rules_parse_back() {
+  let rules = vec![
+    Recurrence::Daily,
+    Recurrence::Every { days: 10 },
+    Recurrence::Weekly { days: vec![Weekday::Tue, Weekday::Sun] },
+    Recurrence::Monthly { day: 1 },
+  ];
+  for rule in rules {
+    assert_eq!(parse(&rule.to_string()), Some(rule.clone()), "{}", rule);
+  }
+}
+
+
+#[test]
+fn saves_as_tagged_json() {
+  let rule = Recurrence::Weekly { days: vec![Weekday::Mon] };
+  let json = serde_json::to_string(&rule).unwrap();
+  assert_eq!(json, r#"{"kind":"weekly","days":["Mon"]}"#);
+  assert_eq!(serde_json::from_str::<Recurrence>(&json).unwrap(), rule);
+}
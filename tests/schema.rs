@@ -0,0 +1,189 @@
+//! Tests that todos saved by every past version of the app still load.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+extern crate mogwai_todo;
+use chrono::{NaiveDate, Weekday};
+use mogwai_todo::recurrence::Recurrence;
+use mogwai_todo::store::{Item, Priority};
+use mogwai_todo::store::schema::{self, SCHEMA_VERSION, MIGRATIONS};
+use uuid::Uuid;
+
+
+/// Payloads as they were written by each schema version, oldest first. Every
+/// fixture holds the same two todos.
+const FIXTURES: &[&str] = &[
+  include_str!("fixtures/schema_v0.json"),
+  include_str!("fixtures/schema_v1.json"),
+  include_str!("fixtures/schema_v2.json"),
+  include_str!("fixtures/schema_v3.json"),
+  include_str!("fixtures/schema_v4.json"),
+  include_str!("fixtures/schema_v5.json"),
+  include_str!("fixtures/schema_v6.json"),
+  include_str!("fixtures/schema_v7.json"),
+  include_str!("fixtures/schema_v8.json"),
+  include_str!("fixtures/schema_v9.json"),
+  include_str!("fixtures/schema_v10.json"),
+];
+
+
+/// The parts of an item that every schema version has saved.
+fn summary(items: &[Item]) -> Vec<(String, bool)> {
+  items
+    .iter()
+    .map(|item| (item.title.clone(), item.completed))
+    .collect()
+}
+
+
+fn expected_summary() -> Vec<(String, bool)> {
+  vec![
+    ("Walk the dog".to_string(), true),
+    ("Buy milk".to_string(), false)
+  ]
+}
+
+
+fn load(version: usize) -> Vec<Item> {
+  schema::decode(FIXTURES[version])
+    .unwrap_or_else(|e| panic!("version {} failed to load: {}", version, e))
+}
+
+
+#[test]
+fn every_version_has_a_fixture_and_a_migration() {
+  assert_eq!(FIXTURES.len(), SCHEMA_VERSION as usize + 1);
+  assert_eq!(MIGRATIONS.len(), SCHEMA_VERSION as usize);
+}
+
+
+#[test]
+fn loads_every_past_version() {
+  for version in 0 .. FIXTURES.len() {
+    assert_eq!(summary(&load(version)), expected_summary(), "version {}", version);
+  }
+}
+
+
+#[test]
+fn items_from_before_ids_get_unique_ids() {
+  for version in 0 .. 2 {
+    let items = load(version);
+    assert!(items.iter().all(|item| item.id != Uuid::nil()));
+    assert_ne!(items[0].id, items[1].id, "version {}", version);
+  }
+}
+
+
+#[test]
+fn ids_are_kept_once_saved() {
+  let items = load(2);
+  assert_eq!(items[0].id.to_string(), "0b0ae0f4-4a8e-4f65-9d5b-3f0b1c2d4e01");
+  assert_eq!(items[1].id.to_string(), "7c9e6679-7425-40de-944b-e07fc1f90ae7");
+}
+
+
+#[test]
+fn items_from_before_due_dates_have_none() {
+  for version in 0 .. 3 {
+    assert!(load(version).iter().all(|item| item.due.is_none()), "version {}", version);
+  }
+  let items = load(3);
+  assert_eq!(items[1].due, Some(NaiveDate::from_ymd_opt(2026, 11, 1).unwrap()));
+}
+
+
+#[test]
+fn items_from_before_priorities_have_none() {
+  for version in 0 .. 4 {
+    assert!(load(version).iter().all(|item| item.priority == Priority::None), "version {}", version);
+  }
+  assert_eq!(load(4)[0].priority, Priority::High);
+}
+
+
+#[test]
+fn items_from_before_tags_have_none() {
+  for version in 0 .. 5 {
+    assert!(load(version).iter().all(|item| item.tags.is_empty()), "version {}", version);
+  }
+  assert_eq!(load(5)[0].tags, vec!["home".to_string(), "pets".to_string()]);
+}
+
+
+#[test]
+fn items_from_before_stars_are_not_starred() {
+  for version in 0 .. 6 {
+    assert!(load(version).iter().all(|item| !item.starred), "version {}", version);
+  }
+  assert!(load(6)[1].starred);
+}
+
+
+#[test]
+fn items_from_before_subtasks_have_no_parent() {
+  for version in 0 .. 7 {
+    assert!(load(version).iter().all(|item| item.parent.is_none()), "version {}", version);
+  }
+  let items = load(7);
+  assert_eq!(items[0].parent, None);
+  assert_eq!(items[1].parent, Some(items[0].id));
+}
+
+
+#[test]
+fn items_from_before_named_lists_are_in_the_default_list() {
+  for version in 0 .. 8 {
+    assert!(load(version).iter().all(|item| item.list == Uuid::nil()), "version {}", version);
+  }
+  assert_eq!(load(8)[1].list.to_string(), "5f3c1a2e-8d4b-4c6a-9e7f-0a1b2c3d4e5f");
+}
+
+
+#[test]
+fn items_from_before_notes_have_none() {
+  for version in 0 .. 9 {
+    assert!(load(version).iter().all(|item| item.notes.is_empty()), "version {}", version);
+  }
+  assert_eq!(load(9)[1].notes, "Oat, not dairy.\nTwo cartons.");
+}
+
+
+#[test]
+fn items_from_before_recurrence_dont_repeat() {
+  for version in 0 .. 10 {
+    assert!(
+      load(version).iter().all(|item| item.recurrence.is_none() && item.completions.is_empty()),
+      "version {}", version
+    );
+  }
+  let milk = &load(10)[1];
+  assert_eq!(milk.recurrence, Some(Recurrence::Weekly { days: vec![Weekday::Sun] }));
+  assert_eq!(milk.completions, vec![NaiveDate::from_ymd_opt(2026, 10, 25).unwrap()]);
+}
+
+
+#[test]
+fn encode_writes_the_current_version() {
+  let items = load(SCHEMA_VERSION as usize);
+  let json = schema::encode(&items).unwrap();
+  let value:serde_json::Value = serde_json::from_str(&json).unwrap();
+  assert_eq!(value["schema_version"], SCHEMA_VERSION);
+  assert_eq!(schema::decode(&json).unwrap(), items);
+}
+
+
+#[test]
+fn rejects_versions_from_the_future() {
+  let json = format!(r#"{{"schema_version":{},"items":[]}}"#, SCHEMA_VERSION + 1);
+  assert!(schema::decode(&json).is_err());
+}
+
+
+#[test]
+fn rejects_payloads_that_are_not_items() {
+  assert!(schema::decode("42").is_err());
+  assert!(schema::decode(r#"{"items":[]}"#).is_err());
+  assert!(schema::decode(r#"[{"name":"nope"}]"#).is_err());
+  assert!(schema::decode(r#"[42]"#).is_err());
+}
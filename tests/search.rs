@@ -0,0 +1,72 @@
+//! Tests for searching todos.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+extern crate mogwai_todo;
+use mogwai_todo::search::{find, matches};
+use mogwai_todo::store::Item;
+
+
+fn tagged(title: &str, tags: &[&str]) -> Item {
+  Item {
+    tags: tags.iter().map(|tag| tag.to_string()).collect(),
+    ..Item::new(title.to_string())
+  }
+}
+
+
+#[test]
+fn ignores_case_and_diacritics() {
+  let item = tagged("Crème brûlée at the CAFÉ", &[]);
+  assert!(matches(&item, "creme"));
+  assert!(matches(&item, "BRULEE"));
+  assert!(matches(&item, "café"));
+  assert!(!matches(&item, "cafes"));
+}
+
+
+#[test]
+fn decomposed_accents_match_too() {
+  let item = tagged("Cafe\u{301} au lait", &[]);
+  assert!(matches(&item, "café"));
+  assert!(matches(&item, "cafe"));
+  assert_eq!(find("Cafe\u{301} au lait", "cafe"), vec![0..6]);
+}
+
+
+#[test]
+fn every_word_has_to_match_the_title_or_a_tag() {
+  let item = tagged("Send the report", &["work", "urgent"]);
+  assert!(matches(&item, "report work"));
+  assert!(matches(&item, "  #urg  "));
+  assert!(!matches(&item, "report home"));
+  assert!(matches(&item, ""));
+}
+
+
+#[test]
+fn notes_are_searched_too() {
+  let item = Item {
+    notes: "Ask for the\noat kind".to_string(),
+    ..tagged("Buy milk", &[])
+  };
+  assert!(matches(&item, "milk oat"));
+  assert!(!matches(&item, "dairy"));
+}
+
+
+#[test]
+fn finds_the_bytes_to_highlight() {
+  let text = "Crème and crème";
+  assert_eq!(find(text, "CREME"), vec![0..6, 11..17]);
+  assert_eq!(&text[0..6], "Crème");
+  assert_eq!(find("banana", "ana"), vec![1..4]);
+  assert_eq!(find("milk and bread", "bread milk"), vec![0..4, 9..14]);
+  assert!(find("milk", "oat").is_empty());
+}
+
+
+#[test]
+fn touching_matches_are_joined() {
+  assert_eq!(find("abcdef", "abc cd def"), vec![0..6]);
+}
@@ -0,0 +1,202 @@
+//! Tests of syncing clients through the sync server on loopback.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+extern crate mogwai_todo;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use mogwai_todo::store::{Item, ListInfo, Priority};
+use mogwai_todo::sync::{Answer, Request, SyncClient, SyncEngine, Transport};
+use mogwai_todo_server::{serve, Db, Running};
+
+
+/// Sends requests with ureq, unless it's been taken offline.
+struct Loopback {
+  url: String,
+  is_online: Cell<bool>
+}
+
+
+impl Transport for Loopback {
+  fn send(&self, request: Request, done: Box<dyn FnOnce(Answer)>) {
+    if !self.is_online.get() {
+      return done(Err("offline".to_string()));
+    }
+    let call = ureq::request(request.method, &format!("{}{}", self.url, request.path));
+    let result =
+      match request.body {
+        Some(body) => { call.set("Content-Type", "application/json").send_string(&body) }
+        None => { call.call() }
+      };
+    done(
+      match result {
+        Ok(response) => { Ok((response.status(), response.into_string().unwrap())) }
+        Err(ureq::Error::Status(status, response)) => { Ok((status, response.into_string().unwrap())) }
+        Err(e) => { Err(e.to_string()) }
+      }
+    )
+  }
+}
+
+
+/// A browser tab with its own engine.
+struct Tab {
+  client: SyncClient,
+  transport: Rc<Loopback>,
+  /// The todos the app was last told about.
+  synced: Rc<RefCell<Option<Vec<Item>>>>
+}
+
+
+impl Tab {
+  fn open(server: &Running, name: &str) -> Tab {
+    let transport = Rc::new(Loopback { url: server.url(), is_online: Cell::new(true) });
+    let client = SyncClient::new(SyncEngine::new(name.to_string()), transport.clone());
+    let synced = Rc::new(RefCell::new(None));
+    let on_change = synced.clone();
+    client.set_on_change(Box::new(move |items| *on_change.borrow_mut() = Some(items)));
+    Tab { client, transport, synced }
+  }
+
+  fn go_offline(&self, is_offline: bool) {
+    self.transport.is_online.set(!is_offline);
+  }
+
+  /// Pull and hand back the todos, as the app would merge them.
+  fn pull(&self) -> Vec<Item> {
+    self.client.pull();
+    self.synced.borrow_mut().take().unwrap_or_else(|| self.client.items())
+  }
+}
+
+
+fn start() -> Running {
+  serve("127.0.0.1:0", Db::open_in_memory().unwrap()).unwrap()
+}
+
+
+#[test]
+fn todos_saved_in_one_tab_show_up_in_another() {
+  let server = start();
+  let a = Tab::open(&server, "a");
+  let b = Tab::open(&server, "b");
+  let milk = Item::new("Buy milk".to_string());
+  let dog = Item { completed: true, ..Item::new("Walk the dog".to_string()) };
+  a.client.save(&[milk.clone(), dog.clone()], 100);
+  assert_eq!(a.client.num_pending(), 0);
+
+  let items = b.pull();
+  assert_eq!(items, vec![milk.clone(), dog.clone()]);
+
+  // Deleting syncs too.
+  a.client.save(std::slice::from_ref(&dog), 200);
+  assert_eq!(b.pull(), vec![dog]);
+}
+
+
+#[test]
+fn changes_made_offline_are_queued_until_the_server_answers() {
+  let server = start();
+  let a = Tab::open(&server, "a");
+  let b = Tab::open(&server, "b");
+  let milk = Item::new("Buy milk".to_string());
+  a.go_offline(true);
+  a.client.save(std::slice::from_ref(&milk), 100);
+  let milk = Item { completed: true, ..milk };
+  a.client.save(std::slice::from_ref(&milk), 200);
+  assert_eq!(a.client.num_pending(), 2);
+  assert!(b.pull().is_empty());
+
+  a.go_offline(false);
+  a.client.sync();
+  assert_eq!(a.client.num_pending(), 0);
+  assert_eq!(b.pull(), vec![milk]);
+}
+
+
+#[test]
+fn the_last_writer_wins_each_field() {
+  let server = start();
+  let a = Tab::open(&server, "a");
+  let b = Tab::open(&server, "b");
+  let milk = Item::new("Buy milk".to_string());
+  a.client.save(std::slice::from_ref(&milk), 100);
+  let at_b = b.pull();
+  b.client.save(&at_b, 100);
+
+  // B goes offline and changes the title and priority, then A renames it
+  // later on.
+  b.go_offline(true);
+  b.client.save(&[Item { title: "Buy whole milk".to_string(), priority: Priority::High, ..milk.clone() }], 200);
+  a.client.save(&[Item { title: "Buy oat milk".to_string(), ..milk.clone() }], 300);
+
+  b.go_offline(false);
+  b.client.sync();
+  let at_b = b.client.items();
+  let at_a = a.pull();
+  assert_eq!(at_a, at_b);
+  assert_eq!(at_a[0].title, "Buy oat milk");
+  assert_eq!(at_a[0].priority, Priority::High);
+}
+
+
+#[test]
+fn a_change_made_after_seeing_another_wins_whatever_the_clocks_say() {
+  let server = start();
+  let a = Tab::open(&server, "a");
+  let b = Tab::open(&server, "b");
+  let milk = Item::new("Buy milk".to_string());
+  // A's clock is well ahead of B's.
+  a.client.save(std::slice::from_ref(&milk), 5000);
+  b.client.save(&b.pull(), 100);
+
+  b.client.save(&[Item { title: "Buy oat milk".to_string(), ..milk.clone() }], 200);
+  assert_eq!(a.pull()[0].title, "Buy oat milk");
+}
+
+
+#[test]
+fn values_the_app_has_not_caught_up_with_are_not_sent_back() {
+  let server = start();
+  let a = Tab::open(&server, "a");
+  let b = Tab::open(&server, "b");
+  let milk = Item::new("Buy milk".to_string());
+  a.client.save(std::slice::from_ref(&milk), 100);
+  b.client.save(&b.pull(), 100);
+
+  a.client.save(&[Item { title: "Buy oat milk".to_string(), ..milk.clone() }], 200);
+  b.pull();
+  // B's app is still showing the old title, eg. while it's being edited,
+  // and saves a change to another field.
+  b.client.save(&[Item { starred: true, ..milk.clone() }], 300);
+
+  let at_a = a.pull();
+  assert_eq!(at_a[0].title, "Buy oat milk");
+  assert!(at_a[0].starred);
+}
+
+
+#[test]
+fn lists_sync_alongside_their_todos() {
+  let server = start();
+  let a = Tab::open(&server, "a");
+  let b = Tab::open(&server, "b");
+  let work = ListInfo { id: uuid::Uuid::new_v4(), name: "Work".to_string(), slug: "work".to_string() };
+  let lists = vec![ListInfo::default(), work.clone()];
+  let report = Item { list: work.id, ..Item::new("Send the report".to_string()) };
+  a.client.save_lists(&lists, 100);
+  a.client.save(std::slice::from_ref(&report), 100);
+
+  assert_eq!(b.pull(), vec![report]);
+  assert_eq!(b.client.lists(), lists);
+
+  let renamed = ListInfo { name: "Office".to_string(), slug: "office".to_string(), ..work };
+  b.client.save_lists(&[ListInfo::default(), renamed.clone()], 200);
+  a.pull();
+  assert_eq!(a.client.lists(), vec![ListInfo::default(), renamed]);
+
+  a.client.save_lists(&[ListInfo::default()], 300);
+  b.pull();
+  assert_eq!(b.client.lists(), vec![ListInfo::default()]);
+  assert_eq!(b.client.items().len(), 1);
+}
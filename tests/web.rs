@@ -1,13 +1,950 @@
-//! Test suite for the Web and headless browsers.
+//! End to end tests that mount the app in a headless browser and drive it with
+//! DOM events, following the TodoMVC spec.
+//!
+//! Run them with `wasm-pack test --headless --firefox`.
 
 #![cfg(target_arch = "wasm32")]
 
+extern crate mogwai_todo;
 extern crate wasm_bindgen_test;
+use chrono::NaiveDate;
+use std::cell::RefCell;
+use std::rc::Rc;
+use mogwai_todo::run;
+use mogwai_todo::recurrence::Recurrence;
+use mogwai_todo::store::{self, Item, LocalStore, MemoryStore, TodoStore};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
 use wasm_bindgen_test::*;
+use web_sys::{
+  DataTransfer, Document, DragEvent, DragEventInit, Event, File, HashChangeEvent,
+  HashChangeEventInit, HtmlElement, HtmlInputElement, HtmlTextAreaElement, KeyboardEvent,
+  KeyboardEventInit
+};
 
 wasm_bindgen_test_configure!(run_in_browser);
 
+
+fn document() -> Document {
+  web_sys::window()
+    .unwrap()
+    .document()
+    .unwrap()
+}
+
+
+/// Removes the app from the page when dropped, so every test starts on a
+/// clean body.
+struct Mounted;
+
+
+impl Drop for Mounted {
+  fn drop(&mut self) {
+    for selector in &[".todoapp", "footer.info"] {
+      while let Some(el) = document().query_selector(selector).unwrap() {
+        el.remove();
+      }
+    }
+  }
+}
+
+
+fn mount(store: Rc<dyn TodoStore>) -> Mounted {
+  run(store);
+  Mounted
+}
+
+
+fn mount_with(items: Vec<Item>) -> Mounted {
+  mount(Rc::new(MemoryStore::new(items)))
+}
+
+
+/// Wait for the browser to run any pending timeouts, which is how the app
+/// focuses inputs and answers a todo's own messages.
+async fn tick() {
+  let promise = js_sys::Promise::new(&mut |resolve, _| {
+    web_sys::window()
+      .unwrap()
+      .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, 10)
+      .unwrap();
+  });
+  JsFuture::from(promise)
+    .await
+    .unwrap();
+}
+
+
+fn query(selector: &str) -> HtmlElement {
+  document()
+    .query_selector(&format!(".todoapp {}", selector))
+    .unwrap()
+    .unwrap_or_else(|| panic!("nothing matches {:?}", selector))
+    .dyn_into::<HtmlElement>()
+    .unwrap()
+}
+
+
+fn query_input(selector: &str) -> HtmlInputElement {
+  query(selector)
+    .dyn_into::<HtmlInputElement>()
+    .unwrap()
+}
+
+
+fn todos() -> Vec<HtmlElement> {
+  let list =
+    document()
+    .query_selector_all(".todoapp .todo-list li")
+    .unwrap();
+  (0..list.length())
+    .filter_map(|i| list.item(i))
+    .filter_map(|node| node.dyn_into::<HtmlElement>().ok())
+    .collect()
+}
+
+
+fn todo(n: usize) -> HtmlElement {
+  todos()
+    .into_iter()
+    .nth(n)
+    .unwrap_or_else(|| panic!("there is no todo {}", n))
+}
+
+
+fn within(el: &HtmlElement, selector: &str) -> HtmlElement {
+  el
+    .query_selector(selector)
+    .unwrap()
+    .unwrap()
+    .dyn_into::<HtmlElement>()
+    .unwrap()
+}
+
+
+fn is_shown(el: &HtmlElement) -> bool {
+  !el
+    .get_attribute("style")
+    .unwrap_or_default()
+    .replace(' ', "")
+    .contains("display:none")
+}
+
+
+fn title(el: &HtmlElement) -> String {
+  within(el, "label")
+    .text_content()
+    .unwrap_or_default()
+}
+
+
+fn visible_titles() -> Vec<String> {
+  todos()
+    .iter()
+    .filter(|li| is_shown(li))
+    .map(title)
+    .collect()
+}
+
+
+fn items_left() -> String {
+  query(".todo-count")
+    .text_content()
+    .unwrap_or_default()
+}
+
+
+fn dispatch(el: &HtmlElement, name: &str) {
+  el.dispatch_event(&Event::new(name).unwrap())
+    .unwrap();
+}
+
+
+fn key_up(el: &HtmlElement, key: &str) {
+  let ev =
+    KeyboardEvent::new_with_keyboard_event_init_dict(
+      "keyup",
+      KeyboardEventInit::new().key(key)
+    )
+    .unwrap();
+  el.dispatch_event(&ev)
+    .unwrap();
+}
+
+
+fn add(title: &str) {
+  let input = query_input(".new-todo");
+  input.set_value(title);
+  dispatch(&input, "change");
+}
+
+
+fn navigate(hash: &str) {
+  let ev =
+    HashChangeEvent::new_with_event_init_dict(
+      "hashchange",
+      HashChangeEventInit::new().new_url(&format!("http://localhost/{}", hash))
+    )
+    .unwrap();
+  web_sys::window()
+    .unwrap()
+    .dispatch_event(&ev)
+    .unwrap();
+}
+
+
+async fn start_editing(n: usize, to: &str) -> HtmlInputElement {
+  dispatch(&within(&todo(n), "label"), "dblclick");
+  tick().await;
+  assert!(todo(n).class_list().contains("editing"));
+  let input =
+    within(&todo(n), ".edit")
+    .dyn_into::<HtmlInputElement>()
+    .unwrap();
+  input.set_value(to);
+  input
+}
+
+
+/// Remove everything a test kept in localStorage under `key`.
+fn forget(key: &str) {
+  let storage =
+    web_sys::window()
+    .unwrap()
+    .local_storage()
+    .unwrap()
+    .unwrap();
+  for key in &[key.to_string(), store::history_key(key), store::lists_key(key)] {
+    storage
+      .remove_item(key)
+      .unwrap();
+  }
+}
+
+
+fn item(title: &str, completed: bool) -> Item {
+  Item {
+    completed,
+    ..Item::new(title.to_string())
+  }
+}
+
+
+#[wasm_bindgen_test]
+fn hides_main_and_footer_without_todos() {
+  let _app = mount_with(vec![]);
+  assert!(!is_shown(&query(".main")));
+  assert!(!is_shown(&query(".footer")));
+}
+
+
+#[wasm_bindgen_test]
+fn adding_a_todo_appends_it_and_clears_the_input() {
+  let _app = mount_with(vec![]);
+  add("Walk the dog");
+  add("Buy milk");
+  assert_eq!(visible_titles(), vec!["Walk the dog", "Buy milk"]);
+  assert_eq!(query_input(".new-todo").value(), "");
+  assert!(is_shown(&query(".main")));
+  assert!(is_shown(&query(".footer")));
+}
+
+
+#[wasm_bindgen_test]
+fn blank_todos_are_not_added() {
+  let _app = mount_with(vec![]);
+  add("");
+  assert!(todos().is_empty());
+}
+
+
+#[wasm_bindgen_test]
+fn items_left_is_pluralized() {
+  let _app = mount_with(vec![]);
+  add("one");
+  assert_eq!(items_left(), "1 item left");
+  add("two");
+  assert_eq!(items_left(), "2 items left");
+  within(&todo(0), ".toggle").click();
+  within(&todo(1), ".toggle").click();
+  assert_eq!(items_left(), "0 items left");
+}
+
+
+#[wasm_bindgen_test]
+async fn editing_and_pressing_enter_renames() {
+  let _app = mount_with(vec![item("Walk the dog", false)]);
+  let input = start_editing(0, "Walk the cat").await;
+  key_up(&input, "Enter");
+  tick().await;
+  assert_eq!(visible_titles(), vec!["Walk the cat"]);
+  assert!(!todo(0).class_list().contains("editing"));
+}
+
+
+#[wasm_bindgen_test]
+async fn editing_and_pressing_escape_cancels() {
+  let _app = mount_with(vec![item("Walk the dog", false)]);
+  let input = start_editing(0, "Walk the cat").await;
+  key_up(&input, "Escape");
+  tick().await;
+  assert_eq!(visible_titles(), vec!["Walk the dog"]);
+  assert_eq!(input.value(), "Walk the dog");
+  assert!(!todo(0).class_list().contains("editing"));
+}
+
+
+#[wasm_bindgen_test]
+async fn editing_and_blurring_renames() {
+  let _app = mount_with(vec![item("Walk the dog", false)]);
+  let input = start_editing(0, "Walk the cat").await;
+  dispatch(&input, "blur");
+  tick().await;
+  assert_eq!(visible_titles(), vec!["Walk the cat"]);
+  assert!(!todo(0).class_list().contains("editing"));
+}
+
+
+#[wasm_bindgen_test]
+async fn toggling_marks_a_todo_completed() {
+  let _app = mount_with(vec![item("Walk the dog", false)]);
+  let toggle =
+    within(&todo(0), ".toggle")
+    .dyn_into::<HtmlInputElement>()
+    .unwrap();
+  toggle.click();
+  tick().await;
+  assert!(toggle.checked());
+  assert!(todo(0).class_list().contains("completed"));
+
+  toggle.click();
+  tick().await;
+  assert!(!toggle.checked());
+  assert!(!todo(0).class_list().contains("completed"));
+}
+
+
+#[wasm_bindgen_test]
+fn toggle_all_completes_then_reopens_everything() {
+  let _app = mount_with(vec![item("a", false), item("b", true), item("c", false)]);
+  let toggle_all = query_input(".toggle-all");
+  assert!(!toggle_all.checked());
+
+  toggle_all.click();
+  assert!(todos().iter().all(|li| li.class_list().contains("completed")));
+  assert!(toggle_all.checked());
+  assert_eq!(items_left(), "0 items left");
+
+  toggle_all.click();
+  assert!(todos().iter().all(|li| !li.class_list().contains("completed")));
+  assert!(!toggle_all.checked());
+  assert_eq!(items_left(), "3 items left");
+}
+
+
+#[wasm_bindgen_test]
+async fn toggle_all_follows_individual_todos() {
+  let _app = mount_with(vec![item("a", true), item("b", false)]);
+  let toggle_all = query_input(".toggle-all");
+  assert!(!toggle_all.checked());
+  within(&todo(1), ".toggle").click();
+  tick().await;
+  assert!(toggle_all.checked());
+}
+
+
+#[wasm_bindgen_test]
+fn the_destroy_button_removes_a_todo() {
+  let _app = mount_with(vec![item("a", false), item("b", false)]);
+  within(&todo(0), ".destroy").click();
+  assert_eq!(visible_titles(), vec!["b"]);
+  assert_eq!(items_left(), "1 item left");
+}
+
+
+#[wasm_bindgen_test]
+fn clear_completed_removes_completed_todos_and_hides_itself() {
+  let _app = mount_with(vec![item("a", true), item("b", false), item("c", true)]);
+  let clear = query(".clear-completed");
+  assert!(is_shown(&clear));
+  clear.click();
+  assert_eq!(visible_titles(), vec!["b"]);
+  assert!(!is_shown(&clear));
+}
+
+
+#[wasm_bindgen_test]
+fn hashchange_filters_the_list() {
+  let _app = mount_with(vec![item("a", true), item("b", false)]);
+  let link = |href: &str| query(&format!(".filters a[href='{}']", href));
+
+  navigate("#/active");
+  assert_eq!(visible_titles(), vec!["b"]);
+  assert!(link("#/active").class_list().contains("selected"));
+  assert!(!link("#/").class_list().contains("selected"));
+
+  navigate("#/completed");
+  assert_eq!(visible_titles(), vec!["a"]);
+  assert!(link("#/completed").class_list().contains("selected"));
+
+  navigate("#/");
+  assert_eq!(visible_titles(), vec!["a", "b"]);
+  assert!(link("#/").class_list().contains("selected"));
+}
+
+
+#[wasm_bindgen_test]
+async fn completing_a_todo_hides_it_under_the_active_filter() {
+  let _app = mount_with(vec![item("a", false), item("b", false)]);
+  navigate("#/active");
+  within(&todo(0), ".toggle").click();
+  tick().await;
+  assert_eq!(visible_titles(), vec!["b"]);
+  navigate("#/");
+}
+
+
+#[wasm_bindgen_test]
+fn overdue_todos_are_marked_and_filtered() {
+  let long_ago = NaiveDate::from_ymd_opt(2000, 1, 1);
+  let _app = mount_with(vec![
+    Item { due: long_ago, ..item("File taxes", false) },
+    Item { due: long_ago, ..item("Renew passport", true) },
+    item("Someday", false)
+  ]);
+  assert!(todo(0).class_list().contains("overdue"));
+  assert!(!todo(1).class_list().contains("overdue"));
+  assert_eq!(within(&todo(0), ".due").text_content().unwrap(), "due Jan 1, 2000");
+
+  navigate("#/overdue");
+  assert_eq!(visible_titles(), vec!["File taxes"]);
+  navigate("#/");
+}
+
+
+#[wasm_bindgen_test]
+async fn picking_a_due_date_reschedules() {
+  let _app = mount_with(vec![item("File taxes", false)]);
+  start_editing(0, "File taxes").await;
+  let due =
+    within(&todo(0), ".edit-due")
+    .dyn_into::<HtmlInputElement>()
+    .unwrap();
+  due.set_value("2000-01-01");
+  dispatch(&due, "change");
+  tick().await;
+  assert!(todo(0).class_list().contains("overdue"));
+  assert_eq!(within(&todo(0), ".due").text_content().unwrap(), "due Jan 1, 2000");
+}
+
+
+#[wasm_bindgen_test]
+async fn the_priority_control_cycles_and_sorts_the_list() {
+  let _app = mount_with(vec![item("a", false), item("b", false), item("c", true)]);
+  let priority = within(&todo(1), ".priority");
+  for expected in &["low", "medium", "high"] {
+    priority.click();
+    tick().await;
+    assert_eq!(priority.text_content().unwrap(), *expected);
+  }
+  assert!(todo(1).class_list().contains("priority-high"));
+
+  navigate("#/active?sort=priority");
+  assert_eq!(visible_titles(), vec!["b", "a"]);
+  let sort = query(".sort-priority");
+  assert!(sort.class_list().contains("selected"));
+  assert_eq!(sort.get_attribute("href").unwrap(), "#/active");
+  assert_eq!(
+    query(".filters a.selected").get_attribute("href").unwrap(),
+    "#/active?sort=priority"
+  );
+
+  navigate("#/");
+  assert_eq!(visible_titles(), vec!["a", "b", "c"]);
+}
+
+
+fn tagged(title: &str, tags: &[&str]) -> Item {
+  Item {
+    tags: tags.iter().map(|tag| tag.to_string()).collect(),
+    ..item(title, false)
+  }
+}
+
+
+fn picker_links() -> Vec<(String, String)> {
+  let links =
+    document()
+    .query_selector_all(".todoapp .tag-picker a")
+    .unwrap();
+  (0..links.length())
+    .filter_map(|i| links.item(i))
+    .filter_map(|node| node.dyn_into::<HtmlElement>().ok())
+    .map(|a| (a.text_content().unwrap(), a.get_attribute("href").unwrap()))
+    .collect()
+}
+
+
+#[wasm_bindgen_test]
+fn tags_show_as_chips_and_in_the_tag_picker() {
+  let _app = mount_with(vec![tagged("report", &["work"]), tagged("dishes", &["home", "chores"])]);
+  let chips = within(&todo(1), ".tags").text_content().unwrap();
+  assert_eq!(chips, "#home#chores");
+  assert_eq!(
+    picker_links(),
+    vec![
+      ("#chores".to_string(), "#/?tag=chores".to_string()),
+      ("#home".to_string(), "#/?tag=home".to_string()),
+      ("#work".to_string(), "#/?tag=work".to_string()),
+    ]
+  );
+}
+
+
+#[wasm_bindgen_test]
+fn filtering_by_tag_combines_with_the_filter() {
+  let _app = mount_with(vec![
+    tagged("report", &["work"]),
+    Item { completed: true, ..tagged("slides", &["work"]) },
+    tagged("dishes", &["home"])
+  ]);
+  navigate("#/active?tag=work");
+  assert_eq!(visible_titles(), vec!["report"]);
+  assert_eq!(
+    query(".filters a[href='#/completed?tag=work']").text_content().unwrap(),
+    "Completed"
+  );
+  assert!(picker_links().contains(&("#work".to_string(), "#/active".to_string())));
+  navigate("#/");
+}
+
+
+#[wasm_bindgen_test]
+async fn editing_tags_updates_the_chips() {
+  let _app = mount_with(vec![item("report", false)]);
+  start_editing(0, "report").await;
+  let tags =
+    within(&todo(0), ".edit-tags")
+    .dyn_into::<HtmlInputElement>()
+    .unwrap();
+  tags.set_value("#Work, urgent");
+  dispatch(&tags, "change");
+  tick().await;
+  assert_eq!(within(&todo(0), ".tags").text_content().unwrap(), "#work#urgent");
+  assert_eq!(picker_links().len(), 2);
+}
+
+
+#[wasm_bindgen_test]
+async fn quick_add_syntax_fills_in_the_new_todo() {
+  let _app = mount_with(vec![]);
+  add("File taxes #home !1 @2000-04-15 * #c++");
+  tick().await;
+  let taxes = todo(0);
+  assert_eq!(title(&taxes), "File taxes #c++");
+  assert_eq!(within(&taxes, ".tags").text_content().unwrap(), "#home");
+  assert_eq!(within(&taxes, ".priority").text_content().unwrap(), "high");
+  assert!(taxes.class_list().contains("overdue"));
+  assert!(taxes.class_list().contains("starred"));
+
+  add("#home !2");
+  tick().await;
+  assert_eq!(todos().len(), 1);
+}
+
+
+#[wasm_bindgen_test]
+async fn the_star_control_toggles() {
+  let _app = mount_with(vec![item("a", false)]);
+  let star = within(&todo(0), ".star");
+  star.click();
+  tick().await;
+  assert!(todo(0).class_list().contains("starred"));
+  star.click();
+  tick().await;
+  assert!(!todo(0).class_list().contains("starred"));
+}
+
+
+fn subtask(title: &str, of: &Item) -> Item {
+  Item {
+    parent: Some(of.id),
+    ..item(title, false)
+  }
+}
+
+
+#[wasm_bindgen_test]
+async fn subtasks_nest_and_roll_up_into_their_parent() {
+  let parent = item("move", false);
+  let _app = mount_with(vec![
+    parent.clone(),
+    subtask("pack", &parent),
+    subtask("drive", &parent),
+    item("rest", false)
+  ]);
+  let subtasks = within(&todo(0), ".subtasks");
+  assert_eq!(subtasks.child_element_count(), 2);
+  assert_eq!(visible_titles(), vec!["move", "pack", "drive", "rest"]);
+  assert_eq!(items_left(), "3 items left");
+
+  within(&todo(1), ".toggle").click();
+  tick().await;
+  assert!(!todo(0).class_list().contains("completed"));
+  within(&todo(2), ".toggle").click();
+  tick().await;
+  assert!(todo(0).class_list().contains("completed"));
+  assert_eq!(items_left(), "1 item left");
+
+  within(&todo(2), ".toggle").click();
+  tick().await;
+  assert!(!todo(0).class_list().contains("completed"));
+
+  within(&todo(2), ".toggle").click();
+  tick().await;
+  query(".clear-completed").click();
+  tick().await;
+  assert_eq!(visible_titles(), vec!["rest"]);
+}
+
+
+#[wasm_bindgen_test]
+async fn subtasks_can_be_added_while_editing() {
+  let _app = mount_with(vec![item("move", true)]);
+  start_editing(0, "move").await;
+  let input =
+    within(&todo(0), ".new-subtask")
+    .dyn_into::<HtmlInputElement>()
+    .unwrap();
+  input.set_value("pack #home");
+  dispatch(&input, "change");
+  tick().await;
+  assert_eq!(input.value(), "");
+  assert!(todo(0).class_list().contains("editing"));
+  assert_eq!(within(&todo(0), ".subtasks").child_element_count(), 1);
+  assert_eq!(title(&todo(1)), "pack");
+  assert!(!todo(0).class_list().contains("completed"));
+}
+
+
+fn key_down(el: &HtmlElement, key: &str, alt: bool) {
+  let ev =
+    KeyboardEvent::new_with_keyboard_event_init_dict(
+      "keydown",
+      KeyboardEventInit::new().key(key).alt_key(alt).bubbles(true)
+    )
+    .unwrap();
+  el.dispatch_event(&ev)
+    .unwrap();
+}
+
+
+#[wasm_bindgen_test]
+async fn alt_arrows_move_the_focused_todo() {
+  let store = MemoryStore::new(vec![item("a", false), item("b", false), item("c", false)]);
+  let _app = mount(Rc::new(store.clone()));
+  key_down(&todo(2), "ArrowUp", true);
+  assert_eq!(visible_titles(), vec!["a", "c", "b"]);
+  key_down(&todo(1), "ArrowUp", false);
+  key_down(&todo(0), "ArrowDown", true);
+  assert_eq!(visible_titles(), vec!["c", "a", "b"]);
+  tick().await;
+
+  web_sys::window()
+    .unwrap()
+    .dispatch_event(&Event::new("beforeunload").unwrap())
+    .unwrap();
+  let saved:Vec<String> = store.items().into_iter().map(|item| item.title).collect();
+  assert_eq!(saved, vec!["c", "a", "b"]);
+}
+
+
+#[wasm_bindgen_test]
+fn dropping_a_todo_puts_it_before_the_target() {
+  let _app = mount_with(vec![item("a", false), item("b", false), item("c", false)]);
+  dispatch(&todo(2), "dragstart");
+  dispatch(&todo(0), "drop");
+  assert_eq!(visible_titles(), vec!["c", "a", "b"]);
+  // Dropping without dragging a todo first does nothing.
+  dispatch(&todo(2), "drop");
+  assert_eq!(visible_titles(), vec!["c", "a", "b"]);
+}
+
+
+fn hash() -> String {
+  web_sys::window()
+    .unwrap()
+    .location()
+    .hash()
+    .unwrap()
+}
+
+
+fn list_links() -> Vec<(String, String)> {
+  let links =
+    document()
+    .query_selector_all(".todoapp .list-switcher .lists a")
+    .unwrap();
+  (0..links.length())
+    .filter_map(|i| links.item(i))
+    .filter_map(|node| node.dyn_into::<HtmlElement>().ok())
+    .map(|a| (a.text_content().unwrap(), a.get_attribute("href").unwrap()))
+    .collect()
+}
+
+
+#[wasm_bindgen_test]
+async fn named_lists_keep_their_own_todos_and_filters() {
+  let _app = mount_with(vec![item("a", false)]);
+  assert!(!is_shown(&query(".delete-list")));
+  let new_list = query_input(".new-list");
+  new_list.set_value("Work");
+  dispatch(&new_list, "change");
+  tick().await;
+  assert_eq!(new_list.value(), "");
+  assert_eq!(hash(), "#/lists/work/");
+  assert_eq!(query_input(".rename-list").value(), "Work");
+  assert!(is_shown(&query(".delete-list")));
+  assert!(!is_shown(&query(".main")));
+
+  add("report");
+  assert_eq!(visible_titles(), vec!["report"]);
+  navigate("#/lists/work/completed");
+  assert!(visible_titles().is_empty());
+
+  navigate("#/");
+  assert_eq!(visible_titles(), vec!["a"]);
+  assert_eq!(query_input(".rename-list").value(), "Todos");
+  assert_eq!(
+    list_links(),
+    vec![
+      ("Todos".to_string(), "#/".to_string()),
+      ("Work".to_string(), "#/lists/work/completed".to_string()),
+    ]
+  );
+
+  navigate("#/lists/work/completed");
+  query(".delete-list").click();
+  tick().await;
+  assert_eq!(hash(), "#/");
+  assert_eq!(visible_titles(), vec!["a"]);
+  assert_eq!(list_links().len(), 1);
+}
+
+
+#[wasm_bindgen_test]
+fn searching_from_the_url_highlights_matches() {
+  let _app = mount_with(vec![item("Crème brûlée", false), item("creme fraiche", true), item("milk", false)]);
+  navigate("#/active?q=CREME");
+  assert_eq!(visible_titles(), vec!["Crème brûlée"]);
+  assert_eq!(within(&todo(0), "label mark").text_content().unwrap(), "Crème");
+  assert_eq!(title(&todo(0)), "Crème brûlée");
+  assert_eq!(query_input(".search").value(), "CREME");
+  assert_eq!(query(".filters a[href='#/?q=CREME']").text_content().unwrap(), "All");
+
+  navigate("#/");
+  assert_eq!(visible_titles(), vec!["Crème brûlée", "creme fraiche", "milk"]);
+  assert!(todo(0).query_selector("mark").unwrap().is_none());
+  assert_eq!(query_input(".search").value(), "");
+}
+
+
+#[wasm_bindgen_test]
+async fn typing_a_search_goes_into_the_url() {
+  let _app = mount_with(vec![item("Buy milk", false), item("Walk the dog", false)]);
+  let search = query_input(".search");
+  search.set_value("milk ");
+  dispatch(&search, "input");
+  tick().await;
+  assert_eq!(hash(), "#/?q=milk");
+  assert_eq!(visible_titles(), vec!["Buy milk"]);
+
+  search.set_value("");
+  dispatch(&search, "input");
+  tick().await;
+  assert_eq!(hash(), "#/");
+  assert_eq!(visible_titles(), vec!["Buy milk", "Walk the dog"]);
+}
+
+
 #[wasm_bindgen_test]
-fn pass() {
-    assert_eq!(1 + 1, 2);
+async fn notes_are_multi_line_and_saved_with_ctrl_enter() {
+  let store = MemoryStore::new(vec![item("Buy milk", false)]);
+  let _app = mount(Rc::new(store.clone()));
+  within(&todo(0), ".notes-toggle").click();
+  tick().await;
+  assert!(todo(0).class_list().contains("showing-notes"));
+  let notes =
+    within(&todo(0), ".notes")
+    .dyn_into::<HtmlTextAreaElement>()
+    .unwrap();
+  notes.set_value("Oat, not dairy.\nTwo cartons.");
+  key_up(&notes, "Enter");
+  assert!(todo(0).class_list().contains("showing-notes"));
+
+  let ctrl_enter =
+    KeyboardEvent::new_with_keyboard_event_init_dict(
+      "keyup",
+      KeyboardEventInit::new().key("Enter").ctrl_key(true)
+    )
+    .unwrap();
+  notes.dispatch_event(&ctrl_enter).unwrap();
+  tick().await;
+  assert!(!todo(0).class_list().contains("showing-notes"));
+  assert!(todo(0).class_list().contains("has-notes"));
+
+  within(&todo(0), ".notes-toggle").click();
+  notes.set_value("oops");
+  key_up(&notes, "Escape");
+  tick().await;
+  assert_eq!(notes.value(), "Oat, not dairy.\nTwo cartons.");
+
+  web_sys::window()
+    .unwrap()
+    .dispatch_event(&Event::new("beforeunload").unwrap())
+    .unwrap();
+  assert_eq!(store.items()[0].notes, "Oat, not dairy.\nTwo cartons.");
+}
+
+
+#[wasm_bindgen_test]
+async fn completing_a_repeating_todo_adds_the_next_one() {
+  let store = MemoryStore::new(vec![item("Water the plants", false)]);
+  let _app = mount(Rc::new(store.clone()));
+  let edit = start_editing(0, "Water the plants").await;
+  let repeats =
+    within(&todo(0), ".edit-recurrence")
+    .dyn_into::<HtmlInputElement>()
+    .unwrap();
+  repeats.set_value("every 3 days");
+  dispatch(&repeats, "change");
+  key_up(&edit, "Enter");
+  tick().await;
+  assert!(todo(0).class_list().contains("recurring"));
+  assert_eq!(
+    within(&todo(0), ".recurrence").text_content().unwrap_or_default(),
+    "\u{21bb} every 3 days"
+  );
+
+  within(&todo(0), ".toggle").click();
+  tick().await;
+  assert_eq!(visible_titles(), vec!["Water the plants", "Water the plants"]);
+  assert!(todo(0).class_list().contains("completed"));
+  assert!(!todo(0).class_list().contains("recurring"));
+  assert!(!todo(1).class_list().contains("completed"));
+  assert!(todo(1).class_list().contains("recurring"));
+  assert_eq!(items_left(), "1 item left");
+
+  web_sys::window()
+    .unwrap()
+    .dispatch_event(&Event::new("beforeunload").unwrap())
+    .unwrap();
+  let next = &store.items()[1];
+  assert_eq!(next.recurrence, Some(Recurrence::Every { days: 3 }));
+  assert_eq!(next.completions.len(), 1);
+  assert!(next.due.is_some());
+}
+
+
+#[wasm_bindgen_test]
+async fn the_export_menu_offers_each_format() {
+  let _app = mount_with(vec![item("Buy milk", false)]);
+  let export = query(".footer .export");
+  assert!(!export.class_list().contains("open"));
+  within(&export, ".export-toggle").click();
+  tick().await;
+  assert!(export.class_list().contains("open"));
+
+  let buttons = export.query_selector_all(".export-format").unwrap();
+  let names:Vec<String> =
+    (0..buttons.length())
+    .filter_map(|n| buttons.item(n))
+    .filter_map(|button| button.text_content())
+    .collect();
+  assert_eq!(names, vec!["JSON", "CSV", "Markdown", "todo.txt", "iCalendar"]);
+
+  within(&export, ".export-toggle").click();
+  tick().await;
+  assert!(!export.class_list().contains("open"));
+}
+
+
+#[wasm_bindgen_test]
+async fn dropping_a_file_previews_then_imports_it() {
+  let _app = mount_with(vec![item("Buy milk", false)]);
+  let text = "- [ ] Buy milk\n- [x] Walk the dog\nsome prose\n- [ ] Call mom !1\n";
+  let parts = js_sys::Array::of1(&wasm_bindgen::JsValue::from_str(text));
+  let file = File::new_with_str_sequence(&parts, "chores.md").unwrap();
+  let data = DataTransfer::new().unwrap();
+  data.items().add_with_file(&file).unwrap();
+  let drop = DragEvent::new_with_event_init_dict(
+    "drop",
+    DragEventInit::new().data_transfer(Some(&data)).bubbles(true).cancelable(true)
+  ).unwrap();
+  query(".new-todo").dispatch_event(&drop).unwrap();
+  // Reading the file takes a turn of the event loop or two.
+  tick().await;
+  tick().await;
+
+  let preview = query(".import-preview");
+  assert!(is_shown(&preview));
+  assert_eq!(
+    within(&preview, ".import-summary").text_content().unwrap(),
+    "chores.md (Markdown): 2 new, 1 duplicate, 1 problem"
+  );
+  assert_eq!(
+    within(&preview, ".import-problems li").text_content().unwrap(),
+    "line 3: not a checklist item like \"- [ ] Buy milk\""
+  );
+  assert_eq!(visible_titles(), vec!["Buy milk"]);
+
+  within(&preview, ".import-confirm").click();
+  tick().await;
+  assert!(!is_shown(&preview));
+  assert_eq!(visible_titles(), vec!["Buy milk", "Walk the dog", "Call mom"]);
+  assert_eq!(items_left(), "2 items left");
+}
+
+
+#[wasm_bindgen_test]
+fn restores_todos_from_local_storage() {
+  let key = "todomvc-mogwai-test-restore";
+  let store = LocalStore::new(key);
+  store
+    .save(&[item("Walk the dog", true), item("Buy milk", false)])
+    .unwrap();
+
+  let _app = mount(Rc::new(LocalStore::new(key)));
+  assert_eq!(visible_titles(), vec!["Walk the dog", "Buy milk"]);
+  assert!(todo(0).class_list().contains("completed"));
+  assert!(!todo(1).class_list().contains("completed"));
+  assert_eq!(items_left(), "1 item left");
+
+  forget(key);
+}
+
+
+#[wasm_bindgen_test]
+fn saves_todos_to_local_storage_before_unload() {
+  let key = "todomvc-mogwai-test-save";
+  let _app = mount(Rc::new(LocalStore::new(key)));
+  add("Walk the dog");
+  web_sys::window()
+    .unwrap()
+    .dispatch_event(&Event::new("beforeunload").unwrap())
+    .unwrap();
+
+  let titles = Rc::new(RefCell::new(vec![]));
+  let loaded = titles.clone();
+  LocalStore::new(key).load(Box::new(move |result| {
+    *loaded.borrow_mut() =
+      result
+      .unwrap()
+      .into_iter()
+      .map(|item| item.title)
+      .collect();
+  }));
+  assert_eq!(*titles.borrow(), vec!["Walk the dog"]);
+
+  forget(key);
 }